@@ -0,0 +1,63 @@
+use tonic_build::manual::{Builder, Method, MethodBuilder, Service};
+
+//gRPC stubs for the services of src/iter5/grpc/ezytutors.proto. They are
+//declared here so the build needs no protoc, the messages are written out in
+//src/iter5/grpc/proto.rs. Keep all three in step.
+fn method(name: &str, route: &str, input: &str, output: &str) -> MethodBuilder {
+    Method::builder()
+        .name(name)
+        .route_name(route)
+        .input_type(format!("super::{}", input))
+        .output_type(format!("super::{}", output))
+        .codec_path("tonic::codec::ProstCodec")
+}
+
+fn main() {
+    let tutor_service = Service::builder()
+        .name("TutorService")
+        .package("ezytutors.v1")
+        .method(
+            method(
+                "list_tutors",
+                "ListTutors",
+                "ListTutorsRequest",
+                "TutorList",
+            )
+            .build(),
+        )
+        .method(method("get_tutor", "GetTutor", "TutorId", "Tutor").build())
+        .method(method("create_tutor", "CreateTutor", "NewTutor", "Tutor").build())
+        .method(method("update_tutor", "UpdateTutor", "UpdateTutor", "Tutor").build())
+        .method(method("delete_tutor", "DeleteTutor", "TutorId", "Deleted").build())
+        .build();
+    let course_service = Service::builder()
+        .name("CourseService")
+        .package("ezytutors.v1")
+        .method(
+            method(
+                "list_tutor_courses",
+                "ListTutorCourses",
+                "TutorId",
+                "Course",
+            )
+            .server_streaming()
+            .build(),
+        )
+        .method(
+            method(
+                "list_catalogue",
+                "ListCatalogue",
+                "CatalogueRequest",
+                "Course",
+            )
+            .server_streaming()
+            .build(),
+        )
+        .method(method("get_course", "GetCourse", "CourseId", "Course").build())
+        .method(method("create_course", "CreateCourse", "NewCourse", "Course").build())
+        .method(method("update_course", "UpdateCourse", "UpdateCourse", "Course").build())
+        .method(method("delete_course", "DeleteCourse", "CourseId", "Deleted").build())
+        .build();
+    Builder::new().compile(&[tutor_service, course_service]);
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,28 @@
+/* Baseline schema, matches src/iter5/dbscripts/tutor-course.sql */
+/* "if not exists" so databases created from the dbscripts can adopt migrations */
+
+create table if not exists ezy_tutor_c6 (
+    tutor_id serial primary key,
+    tutor_name varchar(200) not null,
+    tutor_pic_url varchar(200) not null,
+    tutor_profile varchar(2000) not null
+);
+
+create table if not exists ezy_course_c6
+(
+    course_id serial primary key,
+    tutor_id INT not null,
+    course_name varchar(140) not null,
+    course_description varchar(2000),
+    course_format varchar(30),
+    course_structure varchar(200),
+    course_duration varchar(30),
+    course_price INT,
+    course_language varchar(30),
+    course_level varchar(30),
+    posted_time TIMESTAMP default now(),
+    CONSTRAINT fk_tutor
+    FOREIGN KEY(tutor_id)
+        REFERENCES ezy_tutor_c6(tutor_id)
+    ON DELETE cascade
+);
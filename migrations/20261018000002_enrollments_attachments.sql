@@ -0,0 +1,33 @@
+/* Students enrolled in a course, student ids come from the identity provider */
+create table ezy_enrollment_c6
+(
+    course_id INT not null,
+    student_id INT not null,
+    enrolled_time TIMESTAMP default now(),
+    primary key (course_id, student_id),
+    CONSTRAINT fk_course
+    FOREIGN KEY(course_id)
+        REFERENCES ezy_course_c6(course_id)
+    ON DELETE cascade
+);
+
+/* Files attached to a course, the content itself lives in the media store */
+create table ezy_attachment_c6
+(
+    attachment_id serial primary key,
+    course_id INT not null,
+    tutor_id INT not null,
+    file_name varchar(255) not null,
+    mime_type varchar(100) not null,
+    size_bytes BIGINT not null,
+    checksum char(64) not null,
+    storage_key varchar(300) not null,
+    uploaded_time TIMESTAMP not null default now(),
+    CONSTRAINT fk_course
+    FOREIGN KEY(course_id)
+        REFERENCES ezy_course_c6(course_id)
+    ON DELETE cascade,
+    CONSTRAINT uq_course_checksum UNIQUE (course_id, checksum)
+);
+
+create index idx_attachment_tutor on ezy_attachment_c6(tutor_id);
@@ -0,0 +1,9 @@
+/* Token buckets of the postgres rate limit store, shared by all instances.
+   Rows of idle clients are removed by the store itself. */
+create table ezy_rate_limit_c6
+(
+    bucket_key varchar(300) primary key,
+    tokens DOUBLE PRECISION not null,
+    last_allowed BOOLEAN not null,
+    updated_time TIMESTAMPTZ not null default now()
+);
@@ -0,0 +1,52 @@
+/* Webhook subscriptions of partner systems. The secret signs every delivery. */
+create table ezy_webhook_c6
+(
+    webhook_id serial primary key,
+    url varchar(2000) not null,
+    event_types TEXT[] not null,
+    secret varchar(200) not null,
+    active BOOLEAN not null default true,
+    created_time TIMESTAMP not null default now()
+);
+
+/* One event for one subscription. The payload is the exact body that is
+   signed and sent, so redeliveries are byte for byte the same. */
+create table ezy_webhook_delivery_c6
+(
+    delivery_id serial primary key,
+    webhook_id INT not null,
+    event_id varchar(36) not null,
+    event_type varchar(50) not null,
+    payload TEXT not null,
+    status varchar(20) not null default 'pending'
+        CHECK (status IN ('pending', 'delivered', 'dead')),
+    attempts INT not null default 0,
+    next_attempt_time TIMESTAMP not null default now(),
+    created_time TIMESTAMP not null default now(),
+    delivered_time TIMESTAMP,
+    CONSTRAINT fk_webhook
+    FOREIGN KEY(webhook_id)
+        REFERENCES ezy_webhook_c6(webhook_id)
+    ON DELETE cascade
+);
+
+create index idx_webhook_delivery_due on ezy_webhook_delivery_c6(next_attempt_time)
+    where status = 'pending';
+create index idx_webhook_delivery_webhook on ezy_webhook_delivery_c6(webhook_id, delivery_id);
+
+/* Every attempt of a delivery with the receiver's answer */
+create table ezy_webhook_attempt_c6
+(
+    attempt_id serial primary key,
+    delivery_id INT not null,
+    status_code INT,
+    error varchar(500),
+    duration_ms INT not null,
+    attempted_time TIMESTAMP not null default now(),
+    CONSTRAINT fk_delivery
+    FOREIGN KEY(delivery_id)
+        REFERENCES ezy_webhook_delivery_c6(delivery_id)
+    ON DELETE cascade
+);
+
+create index idx_webhook_attempt_delivery on ezy_webhook_attempt_c6(delivery_id);
@@ -0,0 +1,22 @@
+/* Domain events, written in the transaction of the change they describe and
+   published to the sinks by the outbox relay in event_seq order */
+create table ezy_outbox_c6
+(
+    event_seq bigserial primary key,
+    event_id varchar(36) not null unique,
+    event_type varchar(50) not null,
+    /* Events with the same key keep their order in partitioned sinks */
+    partition_key varchar(50) not null,
+    payload TEXT not null,
+    created_time TIMESTAMP not null default now(),
+    published_time TIMESTAMP
+);
+
+create index idx_outbox_unpublished on ezy_outbox_c6(event_seq)
+    where published_time is null;
+create index idx_outbox_published on ezy_outbox_c6(published_time)
+    where published_time is not null;
+
+/* The relay may publish an event again, the webhook sink skips deliveries
+   it already has */
+create unique index idx_webhook_delivery_event on ezy_webhook_delivery_c6(webhook_id, event_id);
@@ -0,0 +1,12 @@
+/* Every instance listens on ezy_outbox to stream changes to its clients.
+   Notifications are sent on commit, in commit order. They only carry the
+   event_seq, payloads can be longer than a notification may be. */
+create function notify_outbox_event() returns trigger as $$
+begin
+    perform pg_notify('ezy_outbox', NEW.event_seq::text);
+    return NEW;
+end;
+$$ language plpgsql;
+
+create trigger trg_outbox_notify after insert on ezy_outbox_c6
+    for each row execute function notify_outbox_event();
@@ -0,0 +1,42 @@
+/* One conversation per student and tutor, started by the student. The
+   course it started about gives the tutor context. */
+create table ezy_conversation_c6
+(
+    conversation_id serial primary key,
+    tutor_id INT not null,
+    student_id INT not null,
+    course_id INT,
+    created_time TIMESTAMP not null default now(),
+    last_message_time TIMESTAMP,
+    CONSTRAINT fk_tutor
+    FOREIGN KEY(tutor_id)
+        REFERENCES ezy_tutor_c6(tutor_id)
+    ON DELETE cascade,
+    CONSTRAINT fk_course
+    FOREIGN KEY(course_id)
+        REFERENCES ezy_course_c6(course_id)
+    ON DELETE set null,
+    CONSTRAINT uq_tutor_student UNIQUE (tutor_id, student_id)
+);
+
+create index idx_conversation_student on ezy_conversation_c6(student_id);
+
+create table ezy_chat_message_c6
+(
+    message_id bigserial primary key,
+    conversation_id INT not null,
+    sender varchar(10) not null check (sender in ('tutor', 'student')),
+    body varchar(1000) not null,
+    /* Chosen by the sending client, a resent message is stored once */
+    client_message_id varchar(64),
+    sent_time TIMESTAMP not null default now(),
+    delivered_time TIMESTAMP,
+    read_time TIMESTAMP,
+    CONSTRAINT fk_conversation
+    FOREIGN KEY(conversation_id)
+        REFERENCES ezy_conversation_c6(conversation_id)
+    ON DELETE cascade,
+    CONSTRAINT uq_client_message UNIQUE (conversation_id, client_message_id)
+);
+
+create index idx_chat_message_conversation on ezy_chat_message_c6(conversation_id, message_id);
@@ -0,0 +1,38 @@
+/* Where and how a tutor or student wants to be notified. Ids come from the
+   identity provider like elsewhere, users without a row get no emails. */
+create table ezy_notification_preference_c6
+(
+    recipient_role varchar(10) not null CHECK (recipient_role IN ('tutor', 'student')),
+    recipient_id INT not null,
+    email varchar(254) not null,
+    locale varchar(10) not null default 'en',
+    /* Kinds of notifications the user doesn't want */
+    muted TEXT[] not null default '{}',
+    updated_time TIMESTAMP not null default now(),
+    primary key (recipient_role, recipient_id)
+);
+
+/* Emails waiting to be sent, queued in the transaction of the change that
+   triggered them. They are rendered when sent from the kind's template in the
+   recipient's locale. */
+create table ezy_notification_c6
+(
+    notification_id serial primary key,
+    kind varchar(50) not null,
+    recipient_role varchar(10) not null,
+    recipient_id INT not null,
+    email varchar(254) not null,
+    locale varchar(10) not null,
+    context TEXT not null,
+    status varchar(20) not null default 'pending'
+        CHECK (status IN ('pending', 'sent', 'dead')),
+    attempts INT not null default 0,
+    last_error varchar(500),
+    next_attempt_time TIMESTAMP not null default now(),
+    created_time TIMESTAMP not null default now(),
+    sent_time TIMESTAMP
+);
+
+create index idx_notification_due on ezy_notification_c6(next_attempt_time)
+    where status = 'pending';
+create index idx_notification_recipient on ezy_notification_c6(recipient_role, recipient_id);
@@ -1,52 +1,379 @@
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
+use clap::Parser;
+use config::{Cli, Config};
 use dotenv::dotenv;
 use errors::EzyTutorError;
-use sqlx::postgres::PgPool;
-use std::env;
 use std::io;
-use std::sync::Mutex;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
+#[path = "../iter5/cache/mod.rs"]
+mod cache;
+#[path = "../iter5/chat/mod.rs"]
+mod chat;
+#[path = "../iter5/config.rs"]
+mod config;
+#[path = "../iter5/consistency.rs"]
+mod consistency;
+#[path = "../iter5/cors.rs"]
+mod cors;
 #[path = "../iter5/dbaccess/mod.rs"]
 mod dbaccess;
 #[path = "../iter5/errors.rs"]
 mod errors;
+#[path = "../iter5/export.rs"]
+mod export;
+#[path = "../iter5/graphql/mod.rs"]
+mod graphql;
+#[path = "../iter5/grpc/mod.rs"]
+mod grpc;
 #[path = "../iter5/handlers/mod.rs"]
 mod handlers;
+#[path = "../iter5/identity.rs"]
+mod identity;
+#[path = "../iter5/lifecycle.rs"]
+mod lifecycle;
+#[path = "../iter5/media/mod.rs"]
+mod media;
+#[path = "../iter5/metrics.rs"]
+mod metrics;
 #[path = "../iter5/models/mod.rs"]
 mod models;
+#[path = "../iter5/notifications/mod.rs"]
+mod notifications;
+#[path = "../iter5/openapi.rs"]
+mod openapi;
+#[path = "../iter5/outbox/mod.rs"]
+mod outbox;
+#[path = "../iter5/poller.rs"]
+mod poller;
+#[path = "../iter5/ratelimit/mod.rs"]
+mod ratelimit;
 #[path = "../iter5/routes.rs"]
 mod routes;
+#[path = "../iter5/security.rs"]
+mod security;
 #[path = "../iter5/state.rs"]
 mod state;
+#[path = "../iter5/streams.rs"]
+mod streams;
+#[path = "../iter5/telemetry.rs"]
+mod telemetry;
+#[path = "../iter5/timeout.rs"]
+mod timeout;
+#[path = "../iter5/versioning.rs"]
+mod versioning;
+#[path = "../iter5/webhooks.rs"]
+mod webhooks;
 
+use identity::AdminToken;
+use lifecycle::Lifecycle;
+use models::v2::PriceCurrency;
 use routes::*;
 use state::AppState;
 
 #[actix_rt::main]
 async fn main() -> io::Result<()> {
     dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-    let db_pool = PgPool::connect(&database_url).await.unwrap();
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(err) => {
+            eprint!("{}", err);
+            process::exit(2);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.redacted().to_toml());
+        return Ok(());
+    }
+    telemetry::init_tracing(config.log.format, config.log.filter.as_deref());
+
+    let db_pool = match dbaccess::pool::create_pool(&config.database) {
+        Ok(pool) => pool,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+    //Request quotas per client, shared by all workers
+    let rate_limiter = config.rate_limit.enabled.then(|| {
+        web::Data::new(ratelimit::rate_limiter_from_config(
+            &config.rate_limit,
+            db_pool.clone(),
+        ))
+    });
+    let replica = match dbaccess::replica::create_replica(&config.database) {
+        Ok(replica) => replica,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+    //Clients that wrote read from the primary while the replica may lag
+    let read_your_writes = replica.as_ref().map(|replica| replica.max_lag());
+    let startup_pool = db_pool.clone();
+    let shutdown_pool = db_pool.clone();
     //Construct AppState
     let shared_data = web::Data::new(AppState {
-        health_check_response: "I'm good, you already asked me".to_string(),
-        visit_count: Mutex::new(0),
+        health_check_response: config.server.health_message.clone(),
         db: db_pool,
+        replica,
+        cache: cache::ReadCache::from_config(&config.cache),
     });
+    let grpc_state = shared_data.clone();
+    let webhook_pool = shared_data.db.clone();
+    let outbox_pool = shared_data.db.clone();
+    let stream_pool = shared_data.db.clone();
+    let chat_pool = shared_data.db.clone();
+    let cache_pool = shared_data.db.clone();
+    let notification_pool = shared_data.db.clone();
+    //Construct media storage for uploaded pictures
+    let media_state = match media::media_state_from_config(&config.media, &config.public_url()) {
+        Ok(media_state) => web::Data::new(media_state),
+        Err(err) => {
+            eprintln!("media storage can't be created: {}", err);
+            process::exit(2);
+        }
+    };
+    let admin_token = web::Data::new(AdminToken(config.admin.token.clone()));
+    let price_currency = web::Data::new(PriceCurrency(config.api.price_currency.clone()));
+    //Unprefixed paths of the first release, None once they are switched off
+    let legacy_aliases = config
+        .api
+        .legacy_aliases
+        .then(|| Arc::new(versioning::LegacyAliases::new(&config.api)));
+    let lifecycle = web::Data::new(Lifecycle::default());
+    let server_lifecycle = lifecycle.clone();
+    //Sends recorded events to subscribers until shutdown starts
+    if config.webhooks.enabled {
+        match webhooks::WebhookSender::new(&config.webhooks) {
+            Ok(sender) => lifecycle.spawn(poller::run(sender, webhook_pool, lifecycle.clone())),
+            Err(err) => {
+                eprintln!("webhook client can't be created: {}", err);
+                process::exit(2);
+            }
+        }
+    }
+    let webhooks_enabled = config.webhooks.enabled;
+    //Sends the emails queued by changes until shutdown starts
+    if config.notifications.enabled {
+        let notifier =
+            notifications::mailer_from_config(&config.notifications).and_then(|mailer| {
+                notifications::Notifier::new(mailer, &config.notifications, &config.public_url())
+            });
+        match notifier {
+            Ok(notifier) => {
+                lifecycle.spawn(poller::run(notifier, notification_pool, lifecycle.clone()))
+            }
+            Err(err) => {
+                eprintln!("notifications can't be sent: {}", err);
+                process::exit(2);
+            }
+        }
+    }
+    //Publishes the events recorded by changes, one instance at a time
+    if config.outbox.enabled {
+        match outbox::relay_from_config(&config.outbox, outbox_pool.clone()) {
+            Ok(relay) => lifecycle.spawn(relay.run(outbox_pool, lifecycle.clone())),
+            Err(err) => {
+                eprintln!("outbox relay can't be created: {}", err);
+                process::exit(2);
+            }
+        }
+    }
+    //Fed by the notifications of every instance's changes. Not a job, it
+    //ends when the pool is closed after the jobs.
+    let streams_enabled = config.streams.enabled;
+    let event_hub = web::Data::new(streams::EventHub::from_config(&config.streams));
+    let stream_listener =
+        streams_enabled.then(|| actix_rt::spawn(streams::listen(event_hub.clone(), stream_pool)));
+    //Drops cached reads changed by any instance or tutorctl, ends like the
+    //streams. Replica reads follow the changes with the cache disabled too.
+    let cache_listener = (config.cache.enabled || read_your_writes.is_some())
+        .then(|| actix_rt::spawn(cache::listen(shared_data.clone(), cache_pool)));
+    //Passes chat frames to the sockets connected here, ends like the streams
+    let chat_enabled = config.chat.enabled;
+    let chat_hub = web::Data::new(chat::ChatHub::from_config(&config.chat));
+    let chat_listener =
+        chat_enabled.then(|| actix_rt::spawn(chat::listen(chat_hub.clone(), chat_pool)));
+    let features = config.features.clone();
+    let graphql = config.graphql.clone();
+    let graphql_schema = web::Data::new(graphql::Graphql::from_config(&config.graphql));
+    let request_timeout = Duration::from_secs(config.server.request_timeout_secs);
+    let cors = Arc::new(cors::Cors::new(config.cors.clone()));
+    let security_headers = Arc::new(security::SecurityHeaders::new(&config.security_headers));
     //Construct App and configure routes
     let app = move || {
+        let cors = cors.clone();
+        let security_headers = security_headers.clone();
         App::new()
+            .wrap(from_fn(move |req, next| {
+                timeout::request_timeout_middleware(request_timeout, req, next)
+            }))
+            .wrap(from_fn(move |req, next| {
+                consistency::read_your_writes_middleware(read_your_writes, req, next)
+            }))
+            .wrap(from_fn(ratelimit::rate_limit_middleware))
+            .wrap(from_fn(move |req, next| {
+                cors::cors_middleware(cors.clone(), req, next)
+            }))
+            .wrap(from_fn(move |req, next| {
+                security::security_headers_middleware(security_headers.clone(), req, next)
+            }))
+            .wrap(from_fn(metrics::metrics_middleware))
+            .wrap(from_fn(telemetry::request_id_middleware))
             .app_data(shared_data.clone())
+            .app_data(media_state.clone())
+            .app_data(admin_token.clone())
+            .app_data(price_currency.clone())
+            .app_data(server_lifecycle.clone())
             .app_data(web::JsonConfig::default().error_handler(|_err, _req| {
                 EzyTutorError::InvalidInput("please provide valid JSON input".to_string()).into()
             }))
             .configure(general_routes)
-            .configure(course_routes)
-            .configure(tutor_routes)
+            .configure(|cfg| {
+                if let Some(rate_limiter) = &rate_limiter {
+                    cfg.app_data(rate_limiter.clone());
+                }
+                if features.metrics {
+                    metrics_routes(cfg);
+                }
+                if features.api_docs {
+                    docs_routes(cfg);
+                }
+                //Before the /admin scope, which would answer its paths with 404
+                if streams_enabled {
+                    cfg.app_data(event_hub.clone());
+                    stream_routes(cfg);
+                }
+                if chat_enabled {
+                    cfg.app_data(chat_hub.clone());
+                    chat_routes(cfg);
+                }
+                if webhooks_enabled {
+                    webhook_routes(cfg);
+                }
+                if features.exports {
+                    admin_routes(cfg);
+                }
+                if graphql.enabled {
+                    cfg.app_data(graphql_schema.clone());
+                    graphql_routes(cfg, graphql.max_body_bytes);
+                }
+                if graphql.enabled && graphql.playground {
+                    graphql_playground_routes(cfg);
+                }
+            })
+            .configure(notification_routes)
+            .configure(api_routes)
+            .configure(media_routes)
+            .configure(|cfg| {
+                if let Some(aliases) = &legacy_aliases {
+                    legacy_routes(cfg, aliases.clone());
+                }
+            })
     };
     //start HTTP server
-    let host_port = env::var("HOST_PORT").expect("HOST:PORT address is not set in .env file");
-    println!("listening on {}", &host_port);
-    HttpServer::new(app).bind(&host_port)?.run().await
-    
+    let delay = Duration::from_secs(config.server.shutdown_delay_secs);
+    let grace = Duration::from_secs(config.server.shutdown_grace_secs);
+    let mut server = HttpServer::new(app)
+        .disable_signals()
+        .shutdown_timeout(config.server.shutdown_grace_secs);
+    if let Some(workers) = config.server.workers {
+        server = server.workers(workers);
+    }
+    for address in &config.server.bind {
+        server = server.bind(address)?;
+        tracing::info!(address = %address, "listening");
+    }
+    let server = server.run();
+    //gRPC runs on the main arbiter and is stopped once the HTTP server is
+    let (stop_grpc, grpc_stopped) = tokio::sync::oneshot::channel::<()>();
+    let grpc_server = if config.grpc.enabled {
+        let listener = tokio::net::TcpListener::bind(&config.grpc.bind).await?;
+        tracing::info!(address = %config.grpc.bind, "gRPC listening");
+        Some(actix_rt::spawn(async move {
+            let shutdown = async {
+                let _ = grpc_stopped.await;
+            };
+            if let Err(err) = grpc::serve(listener, grpc_state, shutdown).await {
+                tracing::error!(error = %err, "gRPC server failed");
+            }
+        }))
+    } else {
+        None
+    };
+    //Signals are handled here instead of by actix, so readiness can report
+    //draining while the workers finish the requests in flight
+    let handle = server.handle();
+    //The server is already up for liveness probes while the database is
+    //still starting, readiness fails until it answers
+    let database_config = config.database.clone();
+    let startup_handle = server.handle();
+    let startup_failed = Arc::new(AtomicBool::new(false));
+    let startup_result = startup_failed.clone();
+    actix_rt::spawn(async move {
+        match dbaccess::pool::wait_for_db(&startup_pool, &database_config).await {
+            Ok(()) => tracing::info!("database connection established"),
+            Err(err) => {
+                tracing::error!(error = %err, "giving up on the database");
+                startup_result.store(true, Ordering::SeqCst);
+                startup_handle.stop(false).await;
+            }
+        }
+    });
+    let shutdown_lifecycle = lifecycle.clone();
+    actix_rt::spawn(async move {
+        shutdown_signal().await;
+        lifecycle::shutdown(&shutdown_lifecycle, handle, delay, grace).await;
+    });
+    server.await?;
+    //Started by the shutdown task after a signal, otherwise it starts now
+    lifecycle.deadline(grace);
+    let _ = stop_grpc.send(());
+    if let Some(grpc_server) = grpc_server {
+        let remaining = lifecycle.remaining();
+        if actix_rt::time::timeout(remaining, grpc_server)
+            .await
+            .is_err()
+        {
+            tracing::warn!("gRPC calls still running at the end of the grace period");
+        }
+    }
+    //Stopped by a signal, the database failing at startup or the
+    //server itself, make sure no job still needs a connection. Polling jobs
+    //stop once draining, which only a signal has started so far.
+    lifecycle.start_draining();
+    lifecycle.wait_for_jobs().await;
+    shutdown_pool.close().await;
+    //They still hold their connections, which can't be released once main returns
+    for listener in [stream_listener, chat_listener, cache_listener]
+        .into_iter()
+        .flatten()
+    {
+        let _ = listener.await;
+    }
+    tracing::info!("shutdown complete");
+    if startup_failed.load(Ordering::SeqCst) {
+        return Err(io::Error::other("database not reachable"));
+    }
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_rt::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler failed");
+        tokio::select! {
+            _ = actix_rt::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = actix_rt::signal::ctrl_c().await;
 }
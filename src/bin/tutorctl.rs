@@ -0,0 +1,77 @@
+use clap::Parser;
+use config::{Cli, Config};
+use ctl::CtlArgs;
+use dotenv::dotenv;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process;
+
+//Shared with the server, which uses the rest of them
+#[allow(dead_code)]
+#[path = "../iter5/config.rs"]
+mod config;
+#[path = "../iter5/ctl/mod.rs"]
+mod ctl;
+#[allow(dead_code)]
+#[path = "../iter5/dbaccess/mod.rs"]
+mod dbaccess;
+#[allow(dead_code)]
+#[path = "../iter5/errors.rs"]
+mod errors;
+#[allow(dead_code)]
+#[path = "../iter5/metrics.rs"]
+mod metrics;
+#[allow(dead_code)]
+#[path = "../iter5/models/mod.rs"]
+mod models;
+#[allow(dead_code)]
+#[path = "../iter5/telemetry.rs"]
+mod telemetry;
+
+//Asks on the terminal, without one only --yes confirms
+fn ask(question: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        eprintln!("{} Pass --yes to confirm without a terminal.", question);
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok();
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+#[actix_rt::main]
+async fn main() {
+    dotenv().ok();
+    let args = CtlArgs::parse();
+    let cli = Cli {
+        config: args.config.clone(),
+        database_url: args.database_url.clone(),
+        ..Cli::default()
+    };
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(err) => {
+            eprint!("{}", err);
+            process::exit(2);
+        }
+    };
+    let pool = match dbaccess::pool::create_pool(&config.database) {
+        Ok(pool) => pool,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+    let yes = args.yes;
+    let mut confirm = |question: &str| yes || ask(question);
+    let result = ctl::run(args.command, &pool, &mut confirm).await;
+    pool.close().await;
+    match result {
+        Ok(output) => print!("{}", output.render(args.output)),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
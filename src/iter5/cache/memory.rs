@@ -0,0 +1,123 @@
+use super::CacheStore;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    value: Bytes,
+    expires: Instant,
+    //Position in the recency order
+    used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    //Least recently used first
+    order: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.used);
+            entry.used = self.clock;
+            self.order.insert(self.clock, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+        }
+    }
+}
+
+//Per process LRU, each instance caches on its own
+pub struct MemoryCacheStore {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl MemoryCacheStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryCacheStore {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryCacheStore {
+    async fn get(&self, key: &str) -> Option<Bytes> {
+        let mut lru = self.lru.lock().unwrap();
+        let entry = lru.entries.get(key)?;
+        if entry.expires <= Instant::now() {
+            lru.remove(key);
+            return None;
+        }
+        let value = entry.value.clone();
+        lru.touch(key);
+        Some(value)
+    }
+
+    async fn put(&self, key: &str, value: Bytes, ttl: Duration) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(key);
+        lru.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires: Instant::now() + ttl,
+                used: 0,
+            },
+        );
+        lru.touch(key);
+        while lru.entries.len() > self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+    }
+
+    async fn remove(&self, keys: &[String]) {
+        let mut lru = self.lru.lock().unwrap();
+        for key in keys {
+            lru.remove(key);
+        }
+    }
+
+    async fn clear(&self) {
+        *self.lru.lock().unwrap() = Lru::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn least_recently_used_entry_is_evicted() {
+        let store = MemoryCacheStore::new(2);
+        let ttl = Duration::from_secs(60);
+        store.put("a", Bytes::from_static(b"1"), ttl).await;
+        store.put("b", Bytes::from_static(b"2"), ttl).await;
+        //Reading a makes b the least recently used entry
+        assert!(store.get("a").await.is_some());
+        store.put("c", Bytes::from_static(b"3"), ttl).await;
+        assert!(store.get("b").await.is_none());
+        assert_eq!(store.get("a").await.unwrap(), "1");
+        assert_eq!(store.get("c").await.unwrap(), "3");
+
+        store
+            .put("d", Bytes::from_static(b"4"), Duration::ZERO)
+            .await;
+        assert!(store.get("d").await.is_none());
+    }
+}
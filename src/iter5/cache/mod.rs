@@ -0,0 +1,333 @@
+pub mod memory;
+
+use crate::config::CacheConfig;
+#[cfg(test)]
+use crate::dbaccess::cache::notify_cache;
+use crate::dbaccess::cache::CACHE_CHANNEL;
+use crate::errors::EzyTutorError;
+use crate::metrics::metrics;
+pub use crate::models::cache::CacheKey;
+use crate::state::AppState;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::web::{self, Bytes};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::postgres::{PgListener, PgPool};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+//Storage of serialized entries. A shared cache (e.g. Redis) implements this
+//as well, entries are JSON so any instance can read them.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Bytes>;
+    async fn put(&self, key: &str, value: Bytes, ttl: Duration);
+    async fn remove(&self, keys: &[String]);
+    async fn clear(&self);
+}
+
+//Read-through cache in front of the dbaccess reads. The keys a change
+//touches are dropped once it is committed, see listen.
+pub struct ReadCache {
+    store: Option<Arc<dyn CacheStore>>,
+    ttl: Duration,
+    //Bumped on every invalidation. A value loaded while an invalidation ran
+    //may already be outdated and is not stored.
+    invalidations: AtomicU64,
+    last_invalidation: Mutex<Option<Instant>>,
+}
+
+impl Default for ReadCache {
+    fn default() -> Self {
+        ReadCache::from_config(&CacheConfig::default())
+    }
+}
+
+impl ReadCache {
+    pub fn new(store: Arc<dyn CacheStore>, ttl: Duration) -> Self {
+        ReadCache {
+            store: Some(store),
+            ttl,
+            invalidations: AtomicU64::new(0),
+            last_invalidation: Mutex::new(None),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        ReadCache {
+            store: None,
+            ttl: Duration::ZERO,
+            invalidations: AtomicU64::new(0),
+            last_invalidation: Mutex::new(None),
+        }
+    }
+
+    pub fn from_config(config: &CacheConfig) -> Self {
+        if !config.enabled {
+            return ReadCache::disabled();
+        }
+        ReadCache::new(
+            Arc::new(memory::MemoryCacheStore::new(config.capacity)),
+            Duration::from_secs(config.ttl_secs),
+        )
+    }
+
+    pub async fn get_or_load<T, F, Fut>(&self, key: CacheKey, load: F) -> Result<T, EzyTutorError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, EzyTutorError>>,
+    {
+        let Some(store) = &self.store else {
+            return load().await;
+        };
+        let cache_key = key.to_string();
+        if let Some(cached) = store.get(&cache_key).await {
+            //An entry written by another version may not parse, load it again then
+            if let Ok(value) = serde_json::from_slice(&cached) {
+                self.count(key, "hit");
+                return Ok(value);
+            }
+        }
+        self.count(key, "miss");
+        let invalidations = self.invalidations.load(Ordering::SeqCst);
+        let value = load().await?;
+        if self.invalidations.load(Ordering::SeqCst) == invalidations {
+            if let Ok(serialized) = serde_json::to_vec(&value) {
+                store.put(&cache_key, serialized.into(), self.ttl).await;
+            }
+        }
+        Ok(value)
+    }
+
+    pub async fn invalidate(&self, keys: &[CacheKey]) {
+        *self.last_invalidation.lock().unwrap() = Some(Instant::now());
+        let Some(store) = &self.store else {
+            return;
+        };
+        self.invalidations.fetch_add(1, Ordering::SeqCst);
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        store.remove(&keys).await;
+    }
+
+    //Changes may have been missed, nothing stored can be trusted
+    pub async fn clear(&self) {
+        *self.last_invalidation.lock().unwrap() = Some(Instant::now());
+        let Some(store) = &self.store else {
+            return;
+        };
+        self.invalidations.fetch_add(1, Ordering::SeqCst);
+        store.clear().await;
+    }
+
+    //True if anything was written within the window, tracked with the cache
+    //disabled as well
+    pub fn invalidated_within(&self, window: Duration) -> bool {
+        self.last_invalidation
+            .lock()
+            .unwrap()
+            .is_some_and(|last| last.elapsed() < window)
+    }
+
+    //Clients may keep cached reads as long as we do
+    pub fn cache_control(&self) -> CacheControl {
+        if self.store.is_none() {
+            return CacheControl(vec![CacheDirective::NoCache]);
+        }
+        CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(self.ttl.as_secs() as u32),
+        ])
+    }
+
+    fn count(&self, key: CacheKey, result: &str) {
+        metrics()
+            .cache_requests
+            .with_label_values(&[key.label(), result])
+            .inc();
+    }
+}
+
+//Drops the keys of the changes committed by every instance and tutorctl
+//until the pool is closed at shutdown, this instance's own included
+pub async fn listen(state: web::Data<AppState>, pool: PgPool) {
+    let mut listener = loop {
+        match connect_listener(&pool).await {
+            Ok(listener) => break listener,
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(error = %err, "cache notifications not available");
+                actix_rt::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    };
+    while !pool.is_closed() {
+        match listener.try_recv().await {
+            Ok(Some(notification)) => {
+                let keys: Option<Vec<CacheKey>> = notification
+                    .payload()
+                    .split(' ')
+                    .map(CacheKey::parse)
+                    .collect();
+                match keys {
+                    Some(keys) => state.cache.invalidate(&keys).await,
+                    None => {
+                        tracing::warn!(payload = notification.payload(), "unexpected notification");
+                        state.cache.clear().await;
+                    }
+                }
+            }
+            //Reconnected, whatever was committed in between is lost
+            Ok(None) => {
+                tracing::warn!("cache notifications reconnected");
+                state.cache.clear().await;
+            }
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(error = %err, "cache notifications failed");
+                state.cache.clear().await;
+                actix_rt::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn connect_listener(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CACHE_CHANNEL).await?;
+    Ok(listener)
+}
+
+//Returns once the listener is notified, later changes reach the cache. It
+//ends when the pool is closed.
+#[cfg(test)]
+pub async fn start_listening(
+    state: web::Data<AppState>,
+    pool: PgPool,
+) -> actix_rt::task::JoinHandle<()> {
+    let listener = actix_rt::spawn(listen(state.clone(), pool.clone()));
+    let mut conn = pool.acquire().await.unwrap();
+    while !state.cache.invalidated_within(Duration::from_secs(60)) {
+        notify_cache(&mut conn, &[CacheKey::Tutor(0)])
+            .await
+            .unwrap();
+        actix_rt::time::sleep(Duration::from_millis(20)).await;
+    }
+    listener
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbaccess::tutor::*;
+    use crate::models::tutor::NewTutor;
+    use dotenv::dotenv;
+    use std::env;
+    use std::sync::atomic::AtomicUsize;
+
+    #[actix_rt::test]
+    async fn reads_are_cached_until_invalidated() {
+        let cache = ReadCache::default();
+        let loads = AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(vec!["Alex".to_string()])
+        };
+
+        let first: Vec<String> = cache.get_or_load(CacheKey::Tutor(1), load).await.unwrap();
+        let second: Vec<String> = cache.get_or_load(CacheKey::Tutor(1), load).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        //Other keys are not affected by the invalidation
+        let _: Vec<String> = cache.get_or_load(CacheKey::Tutor(2), load).await.unwrap();
+        cache.invalidate(&[CacheKey::Tutor(1)]).await;
+        let _: Vec<String> = cache.get_or_load(CacheKey::Tutor(1), load).await.unwrap();
+        let _: Vec<String> = cache.get_or_load(CacheKey::Tutor(2), load).await.unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_rt::test]
+    async fn errors_are_not_cached() {
+        let cache = ReadCache::default();
+        let result: Result<Vec<String>, _> = cache
+            .get_or_load(CacheKey::AllTutors, || async {
+                Err(EzyTutorError::NotFound("no tutors".into()))
+            })
+            .await;
+        assert!(result.is_err());
+        let tutors: Vec<String> = cache
+            .get_or_load(CacheKey::AllTutors, || async { Ok(vec!["Alex".into()]) })
+            .await
+            .unwrap();
+        assert_eq!(tutors.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn committed_changes_drop_their_keys() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool.clone(),
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let listener = start_listening(state.clone(), pool.clone()).await;
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Cached tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic10".into(),
+                tutor_profile: "Changed behind the cache".into(),
+            },
+        )
+        .await
+        .unwrap();
+        let tutor_id = tutor.tutor_id;
+        let cached = || {
+            state
+                .cache
+                .get_or_load(CacheKey::Tutor(tutor_id), || async {
+                    get_tutor_details_db(&pool, tutor_id).await
+                })
+        };
+        cached().await.unwrap();
+
+        //As tutorctl would, without going through a handler
+        update_tutor_pic_url_db(&pool, tutor_id, "http://s3.amazon.aws.com/pic11")
+            .await
+            .unwrap();
+        let mut tutor = cached().await.unwrap();
+        for _ in 0..100 {
+            if tutor.tutor_pic_url.ends_with("pic11") {
+                break;
+            }
+            actix_rt::time::sleep(Duration::from_millis(50)).await;
+            tutor = cached().await.unwrap();
+        }
+        delete_tutor_db(&pool, tutor_id).await.unwrap();
+        pool.close().await;
+        listener.await.unwrap();
+        assert_eq!(tutor.tutor_pic_url, "http://s3.amazon.aws.com/pic11");
+    }
+
+    #[test]
+    fn keys_parse_from_their_names() {
+        for key in [
+            CacheKey::AllTutors,
+            CacheKey::Tutor(7),
+            CacheKey::CoursesForTutor(7),
+        ] {
+            assert_eq!(CacheKey::parse(&key.to_string()), Some(key));
+        }
+        assert_eq!(CacheKey::parse("tutors/x"), None);
+        assert_eq!(CacheKey::parse("courses"), None);
+    }
+}
@@ -0,0 +1,449 @@
+pub mod ws;
+
+use crate::config::ChatConfig;
+use crate::dbaccess::chat::*;
+use crate::errors::EzyTutorError;
+use crate::lifecycle::Lifecycle;
+use crate::models::chat::*;
+use actix_http::ws::CloseCode;
+use actix_web::web;
+use sqlx::postgres::{PgListener, PgPool};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use ws::{Incoming, WsReceiver, WsSender};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+//How soon sockets close once shutdown starts
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+//Typing indicators of a conversation are passed on at most this often
+const TYPING_INTERVAL: Duration = Duration::from_secs(2);
+//Frames waiting for a session, a client that falls further behind is
+//disconnected and reloads the history when it reconnects
+const SESSION_CAPACITY: usize = 64;
+
+type SessionSender = mpsc::Sender<ServerFrame>;
+
+//Sockets connected to this instance, by participant. Several per participant
+//for clients open in more than one place.
+pub struct ChatHub {
+    sessions: Mutex<HashMap<Participant, Vec<(u64, SessionSender)>>>,
+    next_session: AtomicU64,
+    heartbeat: Duration,
+    client_timeout: Duration,
+    max_frame_bytes: usize,
+}
+
+impl ChatHub {
+    pub fn from_config(config: &ChatConfig) -> Self {
+        ChatHub {
+            sessions: Mutex::new(HashMap::new()),
+            next_session: AtomicU64::new(0),
+            heartbeat: Duration::from_secs(config.heartbeat_secs),
+            client_timeout: Duration::from_secs(config.client_timeout_secs),
+            max_frame_bytes: config.max_frame_bytes,
+        }
+    }
+
+    pub fn max_frame_bytes(&self) -> usize {
+        self.max_frame_bytes
+    }
+
+    fn register(&self, participant: Participant) -> (u64, mpsc::Receiver<ServerFrame>) {
+        let session_id = self.next_session.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(SESSION_CAPACITY);
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions
+            .entry(participant)
+            .or_default()
+            .push((session_id, sender));
+        (session_id, receiver)
+    }
+
+    fn unregister(&self, participant: Participant, session_id: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(participant_sessions) = sessions.get_mut(&participant) {
+            participant_sessions.retain(|(id, _)| *id != session_id);
+            if participant_sessions.is_empty() {
+                sessions.remove(&participant);
+            }
+        }
+    }
+
+    //False if the participant has no session here. Sessions that can't take
+    //the frame are dropped, which closes their socket.
+    fn deliver(&self, participant: Participant, frame: &ServerFrame) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(participant_sessions) = sessions.get_mut(&participant) else {
+            return false;
+        };
+        participant_sessions.retain(|(_, sender)| sender.try_send(frame.clone()).is_ok());
+        if participant_sessions.is_empty() {
+            sessions.remove(&participant);
+            return false;
+        }
+        true
+    }
+
+    //Notifications were lost, every client reloads what it shows
+    fn resync(&self) {
+        let sessions = self.sessions.lock().unwrap();
+        for (_, sender) in sessions.values().flatten() {
+            let _ = sender.try_send(ServerFrame::Resync);
+        }
+    }
+
+    //Passes a frame to the participants connected here. A new message that
+    //reached its recipient is delivered.
+    async fn dispatch(&self, pool: &PgPool, notice: ChatNotice) {
+        let connected: Vec<Participant> = notice
+            .recipients()
+            .into_iter()
+            .filter(|participant| self.deliver(*participant, &notice.frame))
+            .collect();
+        let ServerFrame::Message { message } = &notice.frame else {
+            return;
+        };
+        let Some(recipient) = ChatRole::parse(&message.sender).map(|sender| sender.other()) else {
+            return;
+        };
+        if message.delivered_time.is_some()
+            || !connected
+                .iter()
+                .any(|participant| participant.role == recipient)
+        {
+            return;
+        }
+        let delivered = async {
+            let conversation = get_conversation_db(pool, message.conversation_id).await?;
+            mark_delivered_db(pool, &conversation, recipient, message.message_id).await
+        };
+        if let Err(err) = delivered.await {
+            tracing::warn!(error = %err, message_id = message.message_id, "delivery not recorded");
+        }
+    }
+}
+
+//Passes the frames of all instances to the sessions here until the pool is
+//closed at shutdown
+pub async fn listen(hub: web::Data<ChatHub>, pool: PgPool) {
+    let mut listener = loop {
+        match connect_listener(&pool).await {
+            Ok(listener) => break listener,
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(error = %err, "chat notifications not available");
+                actix_rt::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    };
+    while !pool.is_closed() {
+        match listener.try_recv().await {
+            Ok(Some(notification)) => {
+                match serde_json::from_str::<ChatNotice>(notification.payload()) {
+                    Ok(notice) => hub.dispatch(&pool, notice).await,
+                    Err(err) => tracing::warn!(error = %err, "unexpected chat notification"),
+                }
+            }
+            //Reconnected, whatever was sent in between is lost
+            Ok(None) => {
+                tracing::warn!("chat notifications reconnected");
+                hub.resync();
+            }
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(error = %err, "chat notifications failed");
+                hub.resync();
+                actix_rt::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn connect_listener(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHAT_CHANNEL).await?;
+    Ok(listener)
+}
+
+//One socket of a participant, for all of their conversations
+struct Session {
+    participant: Participant,
+    pool: PgPool,
+    //Checked once per socket, participants of a conversation don't change
+    conversations: HashMap<i32, Conversation>,
+    last_typing: HashMap<i32, Instant>,
+}
+
+impl Session {
+    async fn conversation(&mut self, conversation_id: i32) -> Result<Conversation, EzyTutorError> {
+        if let Some(conversation) = self.conversations.get(&conversation_id) {
+            return Ok(conversation.clone());
+        }
+        let conversation = get_conversation_db(&self.pool, conversation_id).await?;
+        if !conversation.includes(self.participant) {
+            return Err(EzyTutorError::Forbidden(
+                "only its participants may use a conversation".into(),
+            ));
+        }
+        self.conversations
+            .insert(conversation_id, conversation.clone());
+        Ok(conversation)
+    }
+
+    async fn handle(&mut self, frame: ClientFrame) -> Result<(), EzyTutorError> {
+        let role = self.participant.role;
+        match frame {
+            ClientFrame::Send {
+                conversation_id,
+                body,
+                client_message_id,
+            } => {
+                validate_message(&body, client_message_id.as_deref())
+                    .map_err(EzyTutorError::InvalidInput)?;
+                let conversation = self.conversation(conversation_id).await?;
+                post_chat_message_db(
+                    &self.pool,
+                    &conversation,
+                    role,
+                    &body,
+                    client_message_id.as_deref(),
+                )
+                .await?;
+            }
+            ClientFrame::Typing { conversation_id } => {
+                let recent = self
+                    .last_typing
+                    .get(&conversation_id)
+                    .is_some_and(|typed| typed.elapsed() < TYPING_INTERVAL);
+                if !recent {
+                    let conversation = self.conversation(conversation_id).await?;
+                    notify_typing_db(&self.pool, &conversation, role).await?;
+                    self.last_typing.insert(conversation_id, Instant::now());
+                }
+            }
+            ClientFrame::Read {
+                conversation_id,
+                message_id,
+            } => {
+                let conversation = self.conversation(conversation_id).await?;
+                mark_read_db(&self.pool, &conversation, role, message_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn send_frame(sender: &WsSender, frame: &ServerFrame) -> bool {
+    let text = serde_json::to_string(frame).expect("chat frames serialize to JSON");
+    sender.text(text).await
+}
+
+//Runs the socket until the client leaves, stops answering pings, falls too
+//far behind or shutdown starts
+pub async fn run_session(
+    hub: web::Data<ChatHub>,
+    pool: PgPool,
+    lifecycle: web::Data<Lifecycle>,
+    participant: Participant,
+    sender: WsSender,
+    mut receiver: WsReceiver,
+) {
+    let (session_id, mut frames) = hub.register(participant);
+    let mut session = Session {
+        participant,
+        pool,
+        conversations: HashMap::new(),
+        last_typing: HashMap::new(),
+    };
+    let mut ticker = actix_rt::time::interval(DRAIN_CHECK_INTERVAL);
+    let mut last_heard = Instant::now();
+    let mut last_ping = Instant::now();
+    let close = loop {
+        tokio::select! {
+            incoming = receiver.recv() => match incoming {
+                Some(Ok(Incoming::Text(text))) => {
+                    last_heard = Instant::now();
+                    let frame = match serde_json::from_str::<ClientFrame>(&text) {
+                        Ok(frame) => frame,
+                        Err(err) => {
+                            let error = ServerFrame::Error {
+                                error: format!("invalid frame: {}", err),
+                                client_message_id: None,
+                            };
+                            send_frame(&sender, &error).await;
+                            continue;
+                        }
+                    };
+                    let client_message_id = match &frame {
+                        ClientFrame::Send { client_message_id, .. } => client_message_id.clone(),
+                        _ => None,
+                    };
+                    if let Err(err) = session.handle(frame).await {
+                        let error = ServerFrame::Error {
+                            error: err.client_message(),
+                            client_message_id,
+                        };
+                        send_frame(&sender, &error).await;
+                    }
+                }
+                Some(Ok(Incoming::Binary)) => {
+                    break Some((CloseCode::Unsupported, "only text frames are supported"));
+                }
+                Some(Ok(Incoming::Ping(data))) => {
+                    last_heard = Instant::now();
+                    sender.pong(data).await;
+                }
+                Some(Ok(Incoming::Pong)) => last_heard = Instant::now(),
+                Some(Ok(Incoming::Close)) | None => break Some((CloseCode::Normal, "closed")),
+                Some(Err(err)) => {
+                    tracing::info!(error = %err, "chat socket failed");
+                    break Some((CloseCode::Protocol, "invalid frame"));
+                }
+            },
+            frame = frames.recv() => match frame {
+                Some(frame) => {
+                    if !send_frame(&sender, &frame).await {
+                        break None;
+                    }
+                }
+                None => break Some((CloseCode::Policy, "too far behind, reconnect")),
+            },
+            _ = ticker.tick() => {
+                if lifecycle.is_draining() {
+                    break Some((CloseCode::Restart, "server restarting"));
+                }
+                if last_heard.elapsed() > hub.client_timeout {
+                    break Some((CloseCode::Away, "no answer to pings"));
+                }
+                if last_ping.elapsed() >= hub.heartbeat {
+                    last_ping = Instant::now();
+                    sender.ping().await;
+                }
+            }
+        }
+    };
+    hub.unregister(participant, session_id);
+    if let Some((code, description)) = close {
+        sender.close(code, description).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tutor(id: i32) -> Participant {
+        Participant {
+            role: ChatRole::Tutor,
+            id,
+        }
+    }
+
+    fn student(id: i32) -> Participant {
+        Participant {
+            role: ChatRole::Student,
+            id,
+        }
+    }
+
+    fn typing(sender: ChatRole) -> ServerFrame {
+        ServerFrame::Typing {
+            conversation_id: 1,
+            sender,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn frames_reach_every_session_of_their_recipients() {
+        let hub = ChatHub::from_config(&ChatConfig::default());
+        let (_, mut phone) = hub.register(student(7));
+        let (laptop_id, mut laptop) = hub.register(student(7));
+        let (_, mut tutor_frames) = hub.register(tutor(3));
+        let (_, mut other_tutor) = hub.register(tutor(4));
+
+        let notice = ChatNotice {
+            tutor_id: 3,
+            student_id: 7,
+            recipient: Some(ChatRole::Student),
+            frame: typing(ChatRole::Tutor),
+        };
+        assert_eq!(notice.recipients(), vec![student(7)]);
+        for participant in notice.recipients() {
+            assert!(hub.deliver(participant, &notice.frame));
+        }
+        assert_eq!(phone.try_recv().unwrap(), typing(ChatRole::Tutor));
+        assert_eq!(laptop.try_recv().unwrap(), typing(ChatRole::Tutor));
+        assert!(tutor_frames.try_recv().is_err());
+
+        let both = ChatNotice {
+            recipient: None,
+            ..notice
+        };
+        assert_eq!(both.recipients(), vec![tutor(3), student(7)]);
+        hub.unregister(student(7), laptop_id);
+        for participant in both.recipients() {
+            assert!(hub.deliver(participant, &both.frame));
+        }
+        assert!(phone.try_recv().is_ok());
+        assert!(laptop.try_recv().is_err());
+        assert!(tutor_frames.try_recv().is_ok());
+        assert!(other_tutor.try_recv().is_err());
+        assert!(!hub.deliver(student(8), &both.frame));
+    }
+
+    #[actix_rt::test]
+    async fn sessions_falling_behind_are_dropped() {
+        let hub = ChatHub::from_config(&ChatConfig::default());
+        let (_, mut frames) = hub.register(tutor(3));
+        for _ in 0..SESSION_CAPACITY {
+            assert!(hub.deliver(tutor(3), &typing(ChatRole::Student)));
+        }
+        assert!(!hub.deliver(tutor(3), &typing(ChatRole::Student)));
+        for _ in 0..SESSION_CAPACITY {
+            frames.recv().await.unwrap();
+        }
+        //Its sender is gone, which tells the session to close the socket
+        assert!(frames.recv().await.is_none());
+    }
+
+    #[test]
+    fn messages_are_validated() {
+        assert!(validate_message("Hello", Some("c1")).is_ok());
+        assert!(validate_message(&"a".repeat(MAX_MESSAGE_CHARS), None).is_ok());
+        assert_eq!(
+            validate_message(" \n", None).unwrap_err(),
+            "body must not be empty"
+        );
+        assert!(validate_message(&"a".repeat(MAX_MESSAGE_CHARS + 1), None)
+            .unwrap_err()
+            .contains("longer than"));
+        assert!(validate_message("Hello", Some(""))
+            .unwrap_err()
+            .contains("client_message_id"));
+        assert!(validate_message("Hello", Some(&"c".repeat(65)))
+            .unwrap_err()
+            .contains("client_message_id"));
+    }
+
+    #[test]
+    fn client_frames_are_tagged_by_type() {
+        let frame: ClientFrame =
+            serde_json::from_str(r#"{"type":"read","conversation_id":2,"message_id":9}"#).unwrap();
+        assert!(matches!(
+            frame,
+            ClientFrame::Read {
+                conversation_id: 2,
+                message_id: 9
+            }
+        ));
+        assert!(serde_json::from_str::<ClientFrame>(
+            r#"{"type":"typing","conversation_id":2,"sender":"tutor"}"#
+        )
+        .is_err());
+        let resync = serde_json::to_string(&ServerFrame::Resync).unwrap();
+        assert_eq!(resync, r#"{"type":"resync"}"#);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::errors::EzyTutorError;
+use actix_codec::{Decoder, Encoder};
+use actix_http::ws::{self, CloseCode, CloseReason, Codec, Frame, Item, Message};
+use actix_web::body::{BodyStream, BoxBody};
+use actix_web::web::{self, Bytes, BytesMut};
+use actix_web::{HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+//Frames queued for a client that doesn't read, the session ends beyond it
+const OUTGOING_CAPACITY: usize = 64;
+
+//What the session gets from the client, fragmented messages are reassembled
+#[derive(Debug)]
+pub enum Incoming {
+    Text(String),
+    Binary,
+    Ping(Bytes),
+    Pong,
+    Close,
+}
+
+//Sending half, encoded into the response body
+#[derive(Clone)]
+pub struct WsSender {
+    sender: mpsc::Sender<Message>,
+}
+
+//Receiving half, decoded from the request payload. Cancel safe: bytes read
+//are kept until they form a frame.
+pub struct WsReceiver {
+    payload: web::Payload,
+    codec: Codec,
+    buffer: BytesMut,
+    fragments: Option<(bool, BytesMut)>,
+    max_message_bytes: usize,
+}
+
+//Upgrades the request, the response has to be returned for the socket to open
+pub fn upgrade(
+    req: &HttpRequest,
+    payload: web::Payload,
+    max_frame_bytes: usize,
+) -> Result<(HttpResponse, WsSender, WsReceiver), EzyTutorError> {
+    let mut handshake = ws::handshake(req.head())
+        .map_err(|err| EzyTutorError::InvalidInput(format!("WebSocket handshake: {}", err)))?;
+    let (sender, receiver) = mpsc::channel(OUTGOING_CAPACITY);
+    let body = futures_util::stream::unfold(
+        (receiver, Codec::new()),
+        |(mut receiver, mut codec)| async move {
+            let message = receiver.recv().await?;
+            let mut encoded = BytesMut::new();
+            let chunk = codec
+                .encode(message, &mut encoded)
+                .map(|()| encoded.freeze())
+                .map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()));
+            Some((chunk, (receiver, codec)))
+        },
+    );
+    let response = handshake
+        .message_body(BoxBody::new(BodyStream::new(body)))
+        .map_err(|err| EzyTutorError::ActixError(err.to_string()))?;
+    Ok((
+        HttpResponse::from(response),
+        WsSender { sender },
+        WsReceiver {
+            payload,
+            codec: Codec::new().max_size(max_frame_bytes),
+            buffer: BytesMut::new(),
+            fragments: None,
+            max_message_bytes: max_frame_bytes,
+        },
+    ))
+}
+
+impl WsSender {
+    //False once the client is gone
+    pub async fn text(&self, text: String) -> bool {
+        self.sender.send(Message::Text(text.into())).await.is_ok()
+    }
+
+    pub async fn ping(&self) -> bool {
+        self.sender
+            .send(Message::Ping(Bytes::from_static(b"")))
+            .await
+            .is_ok()
+    }
+
+    pub async fn pong(&self, data: Bytes) -> bool {
+        self.sender.send(Message::Pong(data)).await.is_ok()
+    }
+
+    //The response ends after the close frame
+    pub async fn close(self, code: CloseCode, description: &str) {
+        let reason = CloseReason {
+            code,
+            description: Some(description.to_string()),
+        };
+        let _ = self.sender.send(Message::Close(Some(reason))).await;
+    }
+}
+
+impl WsReceiver {
+    //None once the client closed the connection
+    pub async fn recv(&mut self) -> Option<Result<Incoming, EzyTutorError>> {
+        loop {
+            match self.codec.decode(&mut self.buffer) {
+                Ok(Some(frame)) => match self.assemble(frame) {
+                    Ok(Some(incoming)) => return Some(Ok(incoming)),
+                    Ok(None) => continue,
+                    Err(err) => return Some(Err(err)),
+                },
+                Ok(None) => {}
+                Err(err) => {
+                    return Some(Err(EzyTutorError::InvalidInput(format!(
+                        "WebSocket frame: {}",
+                        err
+                    ))))
+                }
+            }
+            match self.payload.next().await? {
+                Ok(chunk) => self.buffer.extend_from_slice(&chunk),
+                Err(err) => return Some(Err(EzyTutorError::ActixError(err.to_string()))),
+            }
+        }
+    }
+
+    fn assemble(&mut self, frame: Frame) -> Result<Option<Incoming>, EzyTutorError> {
+        let complete = |text: bool, data: Bytes| {
+            if !text {
+                return Ok(Incoming::Binary);
+            }
+            String::from_utf8(data.to_vec())
+                .map(Incoming::Text)
+                .map_err(|_| EzyTutorError::InvalidInput("text frame is not UTF-8".into()))
+        };
+        match frame {
+            Frame::Text(data) => complete(true, data).map(Some),
+            Frame::Binary(_) => Ok(Some(Incoming::Binary)),
+            Frame::Ping(data) => Ok(Some(Incoming::Ping(data))),
+            Frame::Pong(_) => Ok(Some(Incoming::Pong)),
+            Frame::Close(_) => Ok(Some(Incoming::Close)),
+            Frame::Continuation(Item::FirstText(data)) => {
+                self.fragments = Some((true, BytesMut::from(&data[..])));
+                Ok(None)
+            }
+            Frame::Continuation(Item::FirstBinary(data)) => {
+                self.fragments = Some((false, BytesMut::from(&data[..])));
+                Ok(None)
+            }
+            Frame::Continuation(Item::Continue(data)) => match &mut self.fragments {
+                Some((_, fragments)) if fragments.len() + data.len() <= self.max_message_bytes => {
+                    fragments.extend_from_slice(&data);
+                    Ok(None)
+                }
+                Some(_) => Err(EzyTutorError::PayloadTooLarge(format!(
+                    "messages must not be larger than {} bytes",
+                    self.max_message_bytes
+                ))),
+                None => Err(EzyTutorError::InvalidInput(
+                    "continuation without a first frame".into(),
+                )),
+            },
+            Frame::Continuation(Item::Last(data)) => match self.fragments.take() {
+                Some((text, mut fragments)) => {
+                    fragments.extend_from_slice(&data);
+                    complete(text, fragments.freeze()).map(Some)
+                }
+                None => Err(EzyTutorError::InvalidInput(
+                    "continuation without a first frame".into(),
+                )),
+            },
+        }
+    }
+}
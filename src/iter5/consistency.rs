@@ -0,0 +1,112 @@
+use crate::errors::EzyTutorError;
+use actix_web::body::MessageBody;
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Unix time until which the client's reads go to the primary
+pub const READ_PRIMARY_COOKIE: &str = "ezy_read_primary_until";
+
+//What a read needs to see. Extracted by the read handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    //Any replica will do, it may lag behind the primary
+    Eventual,
+    //The client wrote recently and has to see its own writes
+    ReadYourWrites,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl FromRequest for Consistency {
+    type Error = EzyTutorError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let wrote_recently = req
+            .cookie(READ_PRIMARY_COOKIE)
+            .and_then(|cookie| cookie.value().parse::<u64>().ok())
+            .is_some_and(|until| until > unix_now());
+        ready(Ok(if wrote_recently {
+            Consistency::ReadYourWrites
+        } else {
+            Consistency::Eventual
+        }))
+    }
+}
+
+//After a successful write the client gets a cookie sending its reads to the
+//primary for as long as the replica may lag behind. Without a replica (None)
+//there is nothing to do.
+pub async fn read_your_writes_middleware(
+    window: Option<Duration>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let is_write = ![Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method());
+    let mut res = next.call(req).await?;
+    if let Some(window) = window.filter(|_| is_write && res.status().is_success()) {
+        let cookie = Cookie::build(
+            READ_PRIMARY_COOKIE,
+            (unix_now() + window.as_secs()).to_string(),
+        )
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(window.as_secs() as i64))
+        .finish();
+        res.response_mut().add_cookie(&cookie)?;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn consistency_handler(consistency: Consistency) -> HttpResponse {
+        HttpResponse::Ok().body(format!("{:?}", consistency))
+    }
+
+    #[actix_rt::test]
+    async fn reads_after_a_write_see_the_primary() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(|req, next| {
+                    read_your_writes_middleware(Some(Duration::from_secs(5)), req, next)
+                }))
+                .route("/tutors/", web::get().to(consistency_handler))
+                .route("/tutors/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/tutors/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.response().cookies().next().is_none());
+        assert_eq!(test::read_body(resp).await, "Eventual");
+
+        let req = test::TestRequest::post().uri("/tutors/").to_request();
+        let resp = test::call_service(&app, req).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        assert_eq!(cookie.name(), READ_PRIMARY_COOKIE);
+        assert_eq!(cookie.max_age(), Some(time::Duration::seconds(5)));
+
+        let req = test::TestRequest::get()
+            .uri("/tutors/")
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "ReadYourWrites");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::config::CorsConfig;
+use crate::errors::EzyTutorError;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, ResponseError};
+use std::sync::Arc;
+
+//Answers preflight requests for every route before routing, actix itself
+//would reply 404 or 405 to the OPTIONS request
+pub struct Cors {
+    config: CorsConfig,
+}
+
+impl Cors {
+    pub fn new(config: CorsConfig) -> Self {
+        Cors { config }
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.config
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    fn allows_header(&self, name: &str) -> bool {
+        self.config
+            .allowed_headers
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(name))
+    }
+
+    fn insert_headers(&self, origin: &HeaderValue, headers: &mut HeaderMap) {
+        //Credentials require the origin itself instead of the wildcard
+        let any_origin = self.config.allowed_origins.iter().any(|o| o == "*");
+        if any_origin && !self.config.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+        } else {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        }
+        if self.config.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    //Checks the method and headers announced by the browser
+    fn preflight(
+        &self,
+        req: &ServiceRequest,
+        origin: &HeaderValue,
+    ) -> Result<HttpResponse, EzyTutorError> {
+        let method = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| method.to_str().ok())
+            .unwrap_or_default();
+        if !self
+            .config
+            .allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method))
+        {
+            return Err(EzyTutorError::Forbidden(format!(
+                "method {} is not allowed for cross origin requests",
+                method
+            )));
+        }
+        let requested_headers = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|headers| headers.to_str().ok())
+            .unwrap_or_default();
+        let requested_headers: Vec<&str> = requested_headers
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect();
+        if let Some(name) = requested_headers
+            .iter()
+            .find(|name| !self.allows_header(name))
+        {
+            return Err(EzyTutorError::Forbidden(format!(
+                "header {} is not allowed for cross origin requests",
+                name
+            )));
+        }
+
+        let mut response = HttpResponse::NoContent();
+        response
+            .insert_header((
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                self.config.allowed_methods.join(", "),
+            ))
+            .insert_header((header::ACCESS_CONTROL_MAX_AGE, self.config.max_age_secs));
+        if !requested_headers.is_empty() {
+            response.insert_header((
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                requested_headers.join(", "),
+            ));
+        }
+        let mut response = response.finish();
+        self.insert_headers(origin, response.headers_mut());
+        Ok(response)
+    }
+}
+
+fn is_preflight(req: &ServiceRequest) -> bool {
+    req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+fn append_vary(headers: &mut HeaderMap) {
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+}
+
+//Requests without an Origin header or from origins that are not allowed pass
+//without CORS headers, the browser then refuses to hand out the response
+pub async fn cors_middleware(
+    cors: Arc<Cors>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|origin| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| cors.allows_origin(origin))
+        })
+        .cloned();
+
+    if is_preflight(&req) && req.headers().contains_key(header::ORIGIN) {
+        let result = match &origin {
+            Some(origin) => cors.preflight(&req, origin),
+            None => Err(EzyTutorError::Forbidden(
+                "origin is not allowed for cross origin requests".into(),
+            )),
+        };
+        let mut response = result.unwrap_or_else(|err| err.error_response());
+        append_vary(response.headers_mut());
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    match next.call(req).await {
+        Ok(mut res) => {
+            if let Some(origin) = &origin {
+                cors.insert_headers(origin, res.headers_mut());
+                if !cors.config.exposed_headers.is_empty() {
+                    res.headers_mut().insert(
+                        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                        HeaderValue::from_str(&cors.config.exposed_headers.join(", ")).unwrap(),
+                    );
+                }
+            }
+            append_vary(res.headers_mut());
+            Ok(res.map_into_left_body())
+        }
+        //Errors of inner middleware (timeouts) have to be readable as well
+        Err(err) => {
+            let mut response = err.error_response();
+            if let Some(origin) = &origin {
+                cors.insert_headers(origin, response.headers_mut());
+            }
+            append_vary(response.headers_mut());
+            Err(InternalError::from_response(err.to_string(), response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+
+    fn cors() -> Arc<Cors> {
+        Arc::new(Cors::new(CorsConfig {
+            allowed_origins: vec!["https://app.ezytutors.example".to_string()],
+            allow_credentials: true,
+            ..CorsConfig::default()
+        }))
+    }
+
+    #[actix_rt::test]
+    async fn preflight_is_answered_for_allowed_origins_only() {
+        let cors = cors();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(move |req, next| {
+                    cors_middleware(cors.clone(), req, next)
+                }))
+                .route("/tutors/{tutor_id}", web::put().to(HttpResponse::Ok)),
+        )
+        .await;
+        let preflight = |origin: &str, headers: &str| {
+            test::TestRequest::default()
+                .method(Method::OPTIONS)
+                .uri("/tutors/1")
+                .insert_header((header::ORIGIN, origin))
+                .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "PUT"))
+                .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, headers))
+                .to_request()
+        };
+
+        let resp = test::call_service(
+            &app,
+            preflight("https://app.ezytutors.example", "Content-Type, X-Tutor-Id"),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let headers = resp.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.ezytutors.example"
+        );
+        assert_eq!(
+            headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap(),
+            "Content-Type, X-Tutor-Id"
+        );
+        assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+
+        let resp =
+            test::call_service(&app, preflight("https://evil.example", "Content-Type")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let resp = test::call_service(
+            &app,
+            preflight("https://app.ezytutors.example", "X-Unknown"),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn responses_expose_headers_to_allowed_origins() {
+        let cors = cors();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(move |req, next| {
+                    cors_middleware(cors.clone(), req, next)
+                }))
+                .route("/tutors/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/tutors/")
+            .insert_header((header::ORIGIN, "https://app.ezytutors.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.ezytutors.example"
+        );
+        assert!(headers
+            .get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("x-request-id"));
+        assert_eq!(headers.get(header::VARY).unwrap(), "Origin");
+
+        let req = test::TestRequest::get()
+            .uri("/tutors/")
+            .insert_header((header::ORIGIN, "https://evil.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
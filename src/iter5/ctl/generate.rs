@@ -0,0 +1,833 @@
+use super::output::Output;
+use crate::dbaccess::bulk::{copy_line, BulkLoad};
+use crate::errors::EzyTutorError;
+use crate::models::chat::ChatRole;
+use crate::models::course::CreateCourse;
+use crate::models::tutor::NewTutor;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use clap::Args;
+use sqlx::postgres::PgPool;
+use std::collections::HashSet;
+use std::time::Instant;
+
+#[derive(Args, Debug, Clone)]
+pub struct GenerateArgs {
+    #[arg(long, default_value_t = 100)]
+    pub tutors: u32,
+    //Spread unevenly, a few tutors get many of them
+    #[arg(long, default_value_t = 1000)]
+    pub courses: u32,
+    //Generated student ids are 1 to this, like ids of the identity provider
+    #[arg(long, default_value_t = 1000)]
+    pub students: u32,
+    //On average, a course gets between none and twice as many
+    #[arg(long, default_value_t = 5)]
+    pub enrollments_per_course: u32,
+    //On average, with different students
+    #[arg(long, default_value_t = 2)]
+    pub conversations_per_tutor: u32,
+    //On average, every conversation has at least one
+    #[arg(long, default_value_t = 6)]
+    pub messages_per_conversation: u32,
+    //The same seed and sizes give the same data, only the ids may differ
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+}
+
+const FIRST_NAMES: &[&str] = &[
+    "Merlene", "Frank", "Alex", "Priya", "Jonas", "Amara", "Wei", "Sofia", "Mateo", "Hana",
+    "Oliver", "Fatima", "Lukas", "Chloe", "Ravi", "Ingrid", "Kwame", "Elena", "Tomás", "Yuki",
+    "Noah", "Zara", "Emil", "Aisha", "Pierre", "Maya", "Viktor", "Leila", "Sam", "Greta",
+];
+const LAST_NAMES: &[&str] = &[
+    "Woods",
+    "Müller",
+    "Okafor",
+    "Sharma",
+    "Kowalski",
+    "Nguyen",
+    "García",
+    "Rossi",
+    "Tanaka",
+    "Berg",
+    "Dubois",
+    "Haddad",
+    "O'Brien",
+    "Kim",
+    "Novak",
+    "Silva",
+    "Andersen",
+    "Mensah",
+    "Costa",
+    "Fischer",
+    "Patel",
+    "Moreau",
+    "Ivanova",
+    "Lindqvist",
+    "Chen",
+];
+const SUBJECTS: &[&str] = &[
+    "Rust",
+    "Python",
+    "Accounting",
+    "Nuclear physics",
+    "Photography",
+    "Guitar",
+    "Spanish",
+    "Statistics",
+    "Machine learning",
+    "Web design",
+    "Creative writing",
+    "Organic chemistry",
+    "Calculus",
+    "Marketing",
+    "Film making",
+    "Yoga",
+    "Chess",
+    "Public speaking",
+    "Data engineering",
+    "Italian cooking",
+    "Watercolour painting",
+    "Personal finance",
+];
+const COURSE_NAMES: &[&str] = &[
+    "{} for beginners",
+    "Introduction to {}",
+    "{} in practice",
+    "Advanced {}",
+    "{} masterclass",
+    "{} crash course",
+    "{} fundamentals",
+    "Mastering {}",
+    "{} for professionals",
+    "Weekend {} workshop",
+];
+const DESCRIPTIONS: &[&str] = &[
+    "A hands-on course on {}, with exercises after every lesson.",
+    "Everything you need to get going with {}, explained step by step.",
+    "Take your {} skills to the next level with real world projects.",
+    "Small groups, lots of practice and personal feedback on your {}.",
+    "The theory behind {} and how to apply it from day one.",
+];
+const DESCRIPTION_ENDINGS: &[&str] = &[
+    "No prior knowledge needed.",
+    "Bring your own laptop.",
+    "Includes a certificate of completion.",
+    "Recordings of every session are available afterwards.",
+    "Suited for self-paced learners.",
+    "",
+];
+const PROFILE_LINES: &[&str] = &[
+    "Patient, structured and always happy to answer questions.",
+    "Previously worked in the industry before turning to teaching.",
+    "Believes the best way to learn is by doing.",
+    "Has helped hundreds of students pass their exams.",
+    "Teaches in small groups and one to one.",
+];
+const FORMATS: &[&str] = &["online", "in person", "video", "ebook", "hybrid"];
+const STRUCTURES: &[&str] = &[
+    "weekly live sessions",
+    "self-paced lessons",
+    "lectures and exercises",
+    "projects with reviews",
+    "one to one sessions",
+];
+const LEVELS: &[&str] = &["Beginner", "Intermediate", "Advanced"];
+//Repeated to make some languages more common
+const LANGUAGES: &[&str] = &[
+    "English",
+    "English",
+    "English",
+    "English",
+    "English",
+    "English",
+    "German",
+    "German",
+    "Spanish",
+    "Spanish",
+    "French",
+    "Hindi",
+    "Japanese",
+    "Portuguese",
+];
+const STUDENT_MESSAGES: &[&str] = &[
+    "Hi, is this course suitable for complete beginners?",
+    "Could you tell me more about the schedule?",
+    "Is there a discount for students?",
+    "I missed the last session, is there a recording?",
+    "Thanks, that explanation really helped!",
+    "How much time should I plan per week?",
+    "Can I switch to the next level after this course?",
+    "I'm stuck on the second exercise, any hints?",
+];
+const TUTOR_MESSAGES: &[&str] = &[
+    "Hi! Yes, no prior knowledge is needed.",
+    "Sessions are twice a week, details are in the course description.",
+    "The recording is online, I just sent you the link.",
+    "Glad to hear that, keep going!",
+    "Plan for about three hours per week including exercises.",
+    "Sure, let's go through it together next session.",
+    "Thanks for your question, I'll cover it in the next lesson.",
+];
+
+//Generated times fall into the two years from here on
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 1, 1)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .expect("valid date")
+}
+const SPAN_SECS: u64 = 2 * 365 * 24 * 3600;
+const DAY_SECS: u64 = 24 * 3600;
+
+//Every record draws from its own stream, so each can be generated on its own
+#[derive(Clone, Copy)]
+enum Stream {
+    Owner = 1,
+    Tutor,
+    Course,
+    Posted,
+    Enrollments,
+    Conversations,
+    Messages,
+}
+
+//SplitMix64, small and stable across releases, so seeds keep their data
+struct Rng(u64);
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    fn new(seed: u64, stream: Stream, index: u64) -> Rng {
+        Rng(mix(mix(seed ^ stream as u64) ^ index))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.0)
+    }
+
+    //0 up to but not including n, which must not be 0
+    fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.unit() < probability
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+
+    //Between none and twice the average
+    fn around(&mut self, average: u32) -> u64 {
+        self.below(2 * u64::from(average) + 1)
+    }
+
+    fn secs(&mut self, max_secs: u64) -> Duration {
+        Duration::seconds(self.below(max_secs) as i64)
+    }
+
+    //count different numbers below n with Floyd's algorithm
+    fn distinct(&mut self, n: u64, count: u64) -> Vec<u64> {
+        let mut seen = HashSet::new();
+        let mut numbers = Vec::new();
+        for upper in n - count..n {
+            let number = self.below(upper + 1);
+            let number = if seen.contains(&number) {
+                upper
+            } else {
+                number
+            };
+            seen.insert(number);
+            numbers.push(number);
+        }
+        numbers
+    }
+}
+
+struct GeneratedConversation {
+    student_id: i32,
+    course: Option<u64>,
+    created_time: NaiveDateTime,
+}
+
+struct GeneratedMessage {
+    sender: ChatRole,
+    body: &'static str,
+    sent_time: NaiveDateTime,
+    delivered_time: NaiveDateTime,
+    read_time: Option<NaiveDateTime>,
+}
+
+//First ids of the reserved ranges, records are numbered from 0
+#[derive(Clone, Copy)]
+struct Ids {
+    tutor: i64,
+    course: i64,
+    conversation: i64,
+}
+
+//Courses of a tutor and conversations of a tutor are consecutive, tutor t
+//has courses first_course[t] up to first_course[t + 1]
+struct Plan {
+    args: GenerateArgs,
+    first_course: Vec<u64>,
+    first_conversation: Vec<u64>,
+}
+
+impl Plan {
+    fn new(args: &GenerateArgs) -> Result<Plan, EzyTutorError> {
+        if args.courses > 0 && args.tutors == 0 {
+            return Err(EzyTutorError::InvalidInput(
+                "courses need at least one tutor".into(),
+            ));
+        }
+        if args.students > i32::MAX as u32 {
+            return Err(EzyTutorError::InvalidInput(format!(
+                "students must not be more than {}",
+                i32::MAX
+            )));
+        }
+        let tutors = args.tutors as usize;
+        //Squaring skews owners towards the first tutors
+        let mut courses_per_tutor = vec![0u64; tutors];
+        for course in 0..u64::from(args.courses) {
+            let u = Rng::new(args.seed, Stream::Owner, course).unit();
+            let tutor = ((u * u * tutors as f64) as usize).min(tutors - 1);
+            courses_per_tutor[tutor] += 1;
+        }
+        let mut plan = Plan {
+            args: args.clone(),
+            first_course: prefix_sums(courses_per_tutor),
+            first_conversation: Vec::new(),
+        };
+        let conversations_per_tutor = (0..tutors)
+            .map(|tutor| plan.conversations(tutor).len() as u64)
+            .collect();
+        plan.first_conversation = prefix_sums(conversations_per_tutor);
+        Ok(plan)
+    }
+
+    fn course_count(&self) -> u64 {
+        *self.first_course.last().unwrap_or(&0)
+    }
+
+    fn conversation_count(&self) -> u64 {
+        *self.first_conversation.last().unwrap_or(&0)
+    }
+
+    fn tutor(&self, tutor: usize) -> NewTutor {
+        let mut rng = Rng::new(self.args.seed, Stream::Tutor, tutor as u64);
+        let first_name = rng.pick(FIRST_NAMES);
+        let last_name = rng.pick(LAST_NAMES);
+        let subject = rng.pick(SUBJECTS);
+        NewTutor {
+            tutor_name: format!("{} {}", first_name, last_name),
+            tutor_pic_url: format!(
+                "https://pics.example.com/tutors/{}.jpg",
+                rng.next_u64() % 1_000_000
+            ),
+            tutor_profile: format!(
+                "{} has taught {} for {} years. {}",
+                first_name,
+                subject.to_lowercase(),
+                2 + rng.below(25),
+                rng.pick(PROFILE_LINES)
+            ),
+        }
+    }
+
+    fn course(&self, course: u64, tutor_id: i32) -> CreateCourse {
+        let mut rng = Rng::new(self.args.seed, Stream::Course, course);
+        let subject = rng.pick(SUBJECTS);
+        let format = rng.pick(FORMATS);
+        let description = format!(
+            "{} {}",
+            rng.pick(DESCRIPTIONS)
+                .replace("{}", &subject.to_lowercase()),
+            rng.pick(DESCRIPTION_ENDINGS)
+        );
+        let course_price = match rng.below(10) {
+            0 => None,
+            1 => Some(0),
+            _ => Some(5 * (2 + rng.below(100) as i32)),
+        };
+        CreateCourse {
+            tutor_id,
+            course_name: rng.pick(COURSE_NAMES).replace("{}", subject),
+            course_description: Some(description.trim_end().to_string()),
+            course_format: Some(format.to_string()),
+            course_structure: Some(format!(
+                "{} modules, {}",
+                2 + rng.below(12),
+                rng.pick(STRUCTURES)
+            )),
+            course_duration: Some(if rng.chance(0.5) {
+                format!("{} weeks", 1 + rng.below(16))
+            } else {
+                format!("{} hours", 2 + rng.below(60))
+            }),
+            course_price,
+            course_language: Some(rng.pick(LANGUAGES).to_string()),
+            course_level: (!rng.chance(0.1)).then(|| rng.pick(LEVELS).to_string()),
+        }
+    }
+
+    fn posted_time(&self, course: u64) -> NaiveDateTime {
+        epoch() + Rng::new(self.args.seed, Stream::Posted, course).secs(SPAN_SECS)
+    }
+
+    //Student ids with their enrollment times
+    fn enrollments(&self, course: u64) -> Vec<(i32, NaiveDateTime)> {
+        let mut rng = Rng::new(self.args.seed, Stream::Enrollments, course);
+        let students = u64::from(self.args.students);
+        let count = rng.around(self.args.enrollments_per_course).min(students);
+        let posted_time = self.posted_time(course);
+        rng.distinct(students, count)
+            .into_iter()
+            .map(|student| (student as i32 + 1, posted_time + rng.secs(180 * DAY_SECS)))
+            .collect()
+    }
+
+    fn conversations(&self, tutor: usize) -> Vec<GeneratedConversation> {
+        let mut rng = Rng::new(self.args.seed, Stream::Conversations, tutor as u64);
+        let students = u64::from(self.args.students);
+        let count = rng.around(self.args.conversations_per_tutor).min(students);
+        let (first_course, end_course) = (self.first_course[tutor], self.first_course[tutor + 1]);
+        rng.distinct(students, count)
+            .into_iter()
+            .map(|student| {
+                //Mostly started about one of the tutor's courses
+                let course = (end_course > first_course && rng.chance(0.8))
+                    .then(|| first_course + rng.below(end_course - first_course));
+                let created_time = match course {
+                    Some(course) => self.posted_time(course) + rng.secs(180 * DAY_SECS),
+                    None => epoch() + rng.secs(SPAN_SECS),
+                };
+                GeneratedConversation {
+                    student_id: student as i32 + 1,
+                    course,
+                    created_time,
+                }
+            })
+            .collect()
+    }
+
+    //Students start conversations, the last message may still be unread
+    fn messages(&self, conversation: u64, created_time: NaiveDateTime) -> Vec<GeneratedMessage> {
+        let mut rng = Rng::new(self.args.seed, Stream::Messages, conversation);
+        let count = rng.around(self.args.messages_per_conversation).max(1);
+        let mut sender = ChatRole::Student;
+        let mut sent_time = created_time;
+        (0..count)
+            .map(|index| {
+                if index > 0 && rng.chance(0.7) {
+                    sender = sender.other();
+                }
+                sent_time += Duration::seconds(30) + rng.secs(6 * 3600);
+                let body = match sender {
+                    ChatRole::Student => rng.pick(STUDENT_MESSAGES),
+                    ChatRole::Tutor => rng.pick(TUTOR_MESSAGES),
+                };
+                let delivered_time = sent_time + Duration::seconds(1) + rng.secs(5);
+                let read = index + 1 < count || rng.chance(0.5);
+                GeneratedMessage {
+                    sender,
+                    body,
+                    sent_time,
+                    delivered_time,
+                    read_time: read.then(|| delivered_time + rng.secs(2 * 3600)),
+                }
+            })
+            .collect()
+    }
+
+    fn tutor_lines(&self, ids: Ids) -> impl Iterator<Item = String> + '_ {
+        (0..self.args.tutors as usize).map(move |tutor| {
+            let new_tutor = self.tutor(tutor);
+            copy_line(&[
+                Some(&(ids.tutor + tutor as i64).to_string()),
+                Some(&new_tutor.tutor_name),
+                Some(&new_tutor.tutor_pic_url),
+                Some(&new_tutor.tutor_profile),
+            ])
+        })
+    }
+
+    fn course_lines(&self, ids: Ids) -> impl Iterator<Item = String> + '_ {
+        (0..self.args.tutors as usize).flat_map(move |tutor| {
+            let tutor_id = (ids.tutor + tutor as i64) as i32;
+            (self.first_course[tutor]..self.first_course[tutor + 1]).map(move |course| {
+                let new_course = self.course(course, tutor_id);
+                copy_line(&[
+                    Some(&(ids.course + course as i64).to_string()),
+                    Some(&tutor_id.to_string()),
+                    Some(&new_course.course_name),
+                    new_course.course_description.as_deref(),
+                    new_course.course_format.as_deref(),
+                    new_course.course_structure.as_deref(),
+                    new_course.course_duration.as_deref(),
+                    new_course
+                        .course_price
+                        .map(|price| price.to_string())
+                        .as_deref(),
+                    new_course.course_language.as_deref(),
+                    new_course.course_level.as_deref(),
+                    Some(&timestamp(self.posted_time(course))),
+                ])
+            })
+        })
+    }
+
+    fn enrollment_lines(&self, ids: Ids) -> impl Iterator<Item = String> + '_ {
+        (0..self.course_count()).flat_map(move |course| {
+            let course_id = (ids.course + course as i64).to_string();
+            self.enrollments(course)
+                .into_iter()
+                .map(move |(student_id, enrolled_time)| {
+                    copy_line(&[
+                        Some(&course_id),
+                        Some(&student_id.to_string()),
+                        Some(&timestamp(enrolled_time)),
+                    ])
+                })
+        })
+    }
+
+    fn conversation_lines(&self, ids: Ids) -> impl Iterator<Item = String> + '_ {
+        (0..self.args.tutors as usize).flat_map(move |tutor| {
+            let first = self.first_conversation[tutor];
+            self.conversations(tutor)
+                .into_iter()
+                .zip(first..)
+                .map(move |(conversation, index)| {
+                    let messages = self.messages(index, conversation.created_time);
+                    let last_message_time = messages.last().map(|message| message.sent_time);
+                    copy_line(&[
+                        Some(&(ids.conversation + index as i64).to_string()),
+                        Some(&(ids.tutor + tutor as i64).to_string()),
+                        Some(&conversation.student_id.to_string()),
+                        conversation
+                            .course
+                            .map(|course| (ids.course + course as i64).to_string())
+                            .as_deref(),
+                        Some(&timestamp(conversation.created_time)),
+                        last_message_time.map(timestamp).as_deref(),
+                    ])
+                })
+        })
+    }
+
+    fn message_lines(&self, ids: Ids) -> impl Iterator<Item = String> + '_ {
+        (0..self.args.tutors as usize).flat_map(move |tutor| {
+            let first = self.first_conversation[tutor];
+            self.conversations(tutor).into_iter().zip(first..).flat_map(
+                move |(conversation, index)| {
+                    let conversation_id = (ids.conversation + index as i64).to_string();
+                    self.messages(index, conversation.created_time)
+                        .into_iter()
+                        .map(move |message| {
+                            copy_line(&[
+                                Some(&conversation_id),
+                                Some(message.sender.as_str()),
+                                Some(message.body),
+                                Some(&timestamp(message.sent_time)),
+                                Some(&timestamp(message.delivered_time)),
+                                message.read_time.map(timestamp).as_deref(),
+                            ])
+                        })
+                },
+            )
+        })
+    }
+}
+
+fn prefix_sums(counts: Vec<u64>) -> Vec<u64> {
+    let mut sums = Vec::with_capacity(counts.len() + 1);
+    let mut sum = 0;
+    sums.push(sum);
+    for count in counts {
+        sum += count;
+        sums.push(sum);
+    }
+    sums
+}
+
+fn timestamp(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+//Adds to what is already there, generated tutors get new ids
+pub async fn generate(args: &GenerateArgs, pool: &PgPool) -> Result<Output, EzyTutorError> {
+    let started = Instant::now();
+    let plan = Plan::new(args)?;
+    let mut load = BulkLoad::begin(pool).await?;
+    let ids = Ids {
+        tutor: load
+            .reserve_ids("ezy_tutor_c6", "tutor_id", i64::from(args.tutors))
+            .await?,
+        course: load
+            .reserve_ids("ezy_course_c6", "course_id", plan.course_count() as i64)
+            .await?,
+        conversation: load
+            .reserve_ids(
+                "ezy_conversation_c6",
+                "conversation_id",
+                plan.conversation_count() as i64,
+            )
+            .await?,
+    };
+    let tutors = load
+        .copy(
+            "ezy_tutor_c6",
+            &["tutor_id", "tutor_name", "tutor_pic_url", "tutor_profile"],
+            plan.tutor_lines(ids),
+        )
+        .await?;
+    let courses = load
+        .copy(
+            "ezy_course_c6",
+            &[
+                "course_id",
+                "tutor_id",
+                "course_name",
+                "course_description",
+                "course_format",
+                "course_structure",
+                "course_duration",
+                "course_price",
+                "course_language",
+                "course_level",
+                "posted_time",
+            ],
+            plan.course_lines(ids),
+        )
+        .await?;
+    let enrollments = load
+        .copy(
+            "ezy_enrollment_c6",
+            &["course_id", "student_id", "enrolled_time"],
+            plan.enrollment_lines(ids),
+        )
+        .await?;
+    let conversations = load
+        .copy(
+            "ezy_conversation_c6",
+            &[
+                "conversation_id",
+                "tutor_id",
+                "student_id",
+                "course_id",
+                "created_time",
+                "last_message_time",
+            ],
+            plan.conversation_lines(ids),
+        )
+        .await?;
+    let messages = load
+        .copy(
+            "ezy_chat_message_c6",
+            &[
+                "conversation_id",
+                "sender",
+                "body",
+                "sent_time",
+                "delivered_time",
+                "read_time",
+            ],
+            plan.message_lines(ids),
+        )
+        .await?;
+    load.commit().await?;
+    Ok(Output::Message(format!(
+        "Generated {} tutors from id {}, {} courses, {} enrollments, {} conversations and {} messages in {:.1}s",
+        tutors,
+        ids.tutor,
+        courses,
+        enrollments,
+        conversations,
+        messages,
+        started.elapsed().as_secs_f64()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::validate_message;
+    use dotenv::dotenv;
+    use std::env;
+
+    fn args(seed: u64) -> GenerateArgs {
+        GenerateArgs {
+            tutors: 40,
+            courses: 400,
+            students: 30,
+            enrollments_per_course: 6,
+            conversations_per_tutor: 3,
+            messages_per_conversation: 5,
+            seed,
+        }
+    }
+
+    const IDS: Ids = Ids {
+        tutor: 1,
+        course: 1,
+        conversation: 1,
+    };
+
+    fn lines(plan: &Plan) -> Vec<String> {
+        plan.tutor_lines(IDS)
+            .chain(plan.course_lines(IDS))
+            .chain(plan.enrollment_lines(IDS))
+            .chain(plan.conversation_lines(IDS))
+            .chain(plan.message_lines(IDS))
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_data() {
+        let plan = Plan::new(&args(7)).unwrap();
+        assert_eq!(lines(&plan), lines(&Plan::new(&args(7)).unwrap()));
+        assert_ne!(lines(&plan), lines(&Plan::new(&args(8)).unwrap()));
+        assert_eq!(plan.course_count(), 400);
+        //Skewed, but not all on one tutor
+        let most = (0..40)
+            .map(|tutor| plan.first_course[tutor + 1] - plan.first_course[tutor])
+            .max()
+            .unwrap();
+        assert!(most > 10 && most < 200, "{}", most);
+    }
+
+    #[test]
+    fn generated_records_fit_the_schema() {
+        let plan = Plan::new(&args(1)).unwrap();
+        for tutor in 0..40 {
+            let new_tutor = plan.tutor(tutor);
+            assert!(new_tutor.tutor_name.chars().count() <= 200);
+            assert!(new_tutor.tutor_pic_url.chars().count() <= 200);
+            assert!(new_tutor.tutor_profile.chars().count() <= 2000);
+
+            let conversations = plan.conversations(tutor);
+            let students: HashSet<i32> = conversations.iter().map(|c| c.student_id).collect();
+            assert_eq!(students.len(), conversations.len());
+            let courses = plan.first_course[tutor]..plan.first_course[tutor + 1];
+            for (conversation, index) in conversations.iter().zip(plan.first_conversation[tutor]..)
+            {
+                assert!((1..=30).contains(&conversation.student_id));
+                assert!(conversation
+                    .course
+                    .is_none_or(|course| courses.contains(&course)));
+                let messages = plan.messages(index, conversation.created_time);
+                assert_eq!(messages[0].sender, ChatRole::Student);
+                let mut previous = conversation.created_time;
+                for message in &messages {
+                    validate_message(message.body, None).unwrap();
+                    assert!(message.sent_time > previous);
+                    assert!(message
+                        .read_time
+                        .is_none_or(|read| read >= message.delivered_time));
+                    previous = message.sent_time;
+                }
+            }
+        }
+        for course in 0..plan.course_count() {
+            plan.course(course, 1).validate().unwrap();
+            let enrollments = plan.enrollments(course);
+            let students: HashSet<i32> = enrollments.iter().map(|(student, _)| *student).collect();
+            assert_eq!(students.len(), enrollments.len());
+            assert!(enrollments.len() <= 12);
+            assert!(enrollments
+                .iter()
+                .all(|(student, time)| (1..=30).contains(student)
+                    && *time >= plan.posted_time(course)));
+        }
+    }
+
+    #[test]
+    fn courses_need_tutors() {
+        let no_tutors = GenerateArgs {
+            tutors: 0,
+            ..args(1)
+        };
+        assert!(matches!(
+            Plan::new(&no_tutors),
+            Err(EzyTutorError::InvalidInput(_))
+        ));
+        let nothing = GenerateArgs {
+            tutors: 0,
+            courses: 0,
+            ..args(1)
+        };
+        assert!(lines(&Plan::new(&nothing).unwrap()).is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn generated_data_is_loaded_with_fresh_ids() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let small = GenerateArgs {
+            tutors: 3,
+            courses: 12,
+            students: 10,
+            ..args(3)
+        };
+        let plan = Plan::new(&small).unwrap();
+        let first_tutor = match generate(&small, &pool).await.unwrap() {
+            Output::Message(message) => message
+                .split_whitespace()
+                .nth(5)
+                .and_then(|id| id.trim_end_matches(',').parse::<i32>().ok())
+                .unwrap(),
+            other => panic!("unexpected {:?}", other),
+        };
+        let tutor_ids: Vec<i32> = (first_tutor..first_tutor + 3).collect();
+        let (courses, enrollments, conversations, messages): (i64, i64, i64, i64) = sqlx::query_as(
+            "SELECT (SELECT count(*) FROM ezy_course_c6 WHERE tutor_id = ANY($1)),
+                (SELECT count(*) FROM ezy_enrollment_c6 e JOIN ezy_course_c6 c USING (course_id)
+                    WHERE c.tutor_id = ANY($1)),
+                (SELECT count(*) FROM ezy_conversation_c6 WHERE tutor_id = ANY($1)),
+                (SELECT count(*) FROM ezy_chat_message_c6 m JOIN ezy_conversation_c6 c
+                    USING (conversation_id) WHERE c.tutor_id = ANY($1))",
+        )
+        .bind(&tutor_ids)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(courses, 12);
+        let expected_enrollments: usize =
+            (0..12).map(|course| plan.enrollments(course).len()).sum();
+        assert_eq!(enrollments, expected_enrollments as i64);
+        assert_eq!(conversations as u64, plan.conversation_count());
+        assert!(messages >= conversations);
+
+        //Serial ids continue after the generated ones
+        let next = crate::dbaccess::tutor::post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "After generating".into(),
+                tutor_pic_url: "http://pic".into(),
+                tutor_profile: "Next".into(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(next.tutor_id > first_tutor + 2);
+        sqlx::query("DELETE FROM ezy_tutor_c6 WHERE tutor_id = ANY($1) OR tutor_id = $2")
+            .bind(&tutor_ids)
+            .bind(next.tutor_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
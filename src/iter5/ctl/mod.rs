@@ -0,0 +1,574 @@
+pub mod generate;
+pub mod output;
+
+use crate::dbaccess::course::*;
+use crate::dbaccess::health::{get_pending_migrations_db, MIGRATOR};
+use crate::dbaccess::tutor::*;
+use crate::errors::EzyTutorError;
+use crate::models::course::{Course, CourseFilter, CreateCourse, UpdateCourse};
+use crate::models::tutor::{NewTutor, UpdateTutor};
+use clap::{Args, Parser, Subcommand};
+use generate::GenerateArgs;
+use output::{MigrationRow, Output, OutputFormat};
+use sqlx::postgres::PgPool;
+use std::path::PathBuf;
+
+//Changes go through the same queries as the API, so they record events and
+//queue notifications. Cached reads of running servers expire on their own.
+#[derive(Parser, Debug)]
+#[command(
+    name = "tutorctl",
+    about = "Manage EzyTutors tutors and courses in the database"
+)]
+pub struct CtlArgs {
+    //Same file and environment as the server, only the database is used
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+    #[arg(long, value_name = "URL", global = true)]
+    pub database_url: Option<String>,
+    #[arg(long, short, value_enum, default_value_t, global = true)]
+    pub output: OutputFormat,
+    //Answer yes to confirmations, needed without a terminal
+    #[arg(long, short, global = true)]
+    pub yes: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(subcommand, about = "List, show, create, update and delete tutors")]
+    Tutors(TutorCommand),
+    #[command(
+        subcommand,
+        about = "List, show, create, update, delete and reassign courses"
+    )]
+    Courses(CourseCommand),
+    #[command(about = "Apply pending migrations, or list them with --status")]
+    Migrate {
+        #[arg(long)]
+        status: bool,
+    },
+    #[command(about = "Add the demo tutors and courses to an empty database")]
+    Seed,
+    #[command(
+        about = "Bulk load generated tutors, courses, enrollments and chats, without events"
+    )]
+    Generate(GenerateArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TutorCommand {
+    List {
+        //Part of the name, compared case-insensitively
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        //Tutors with a larger id, for the next page
+        #[arg(long, value_name = "TUTOR_ID")]
+        after: Option<i32>,
+    },
+    Show {
+        tutor_id: i32,
+    },
+    Create {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        pic_url: String,
+        #[arg(long)]
+        profile: String,
+    },
+    Update {
+        tutor_id: i32,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        pic_url: Option<String>,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    //Takes the tutor's courses with it
+    Delete {
+        tutor_id: i32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CourseCommand {
+    List {
+        #[arg(long, value_name = "TUTOR_ID")]
+        tutor: Option<i32>,
+        #[arg(long)]
+        language: Option<String>,
+        #[arg(long)]
+        level: Option<String>,
+        //Part of the name, compared case-insensitively
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        //Courses with a larger id, for the next page
+        #[arg(long, value_name = "COURSE_ID")]
+        after: Option<i32>,
+    },
+    Show {
+        tutor_id: i32,
+        course_id: i32,
+    },
+    Create {
+        tutor_id: i32,
+        #[arg(long)]
+        name: String,
+        #[command(flatten)]
+        fields: CourseFields,
+    },
+    Update {
+        tutor_id: i32,
+        course_id: i32,
+        #[arg(long)]
+        name: Option<String>,
+        #[command(flatten)]
+        fields: CourseFields,
+    },
+    Delete {
+        tutor_id: i32,
+        course_id: i32,
+    },
+    #[command(about = "Move courses to another tutor, the enrollments stay")]
+    Reassign {
+        #[arg(long, value_name = "TUTOR_ID")]
+        from: i32,
+        #[arg(long, value_name = "TUTOR_ID")]
+        to: i32,
+        #[arg(required_unless_present = "all")]
+        course_ids: Vec<i32>,
+        //Every course of the tutor
+        #[arg(long, conflicts_with = "course_ids")]
+        all: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CourseFields {
+    #[arg(long)]
+    description: Option<String>,
+    #[arg(long)]
+    format: Option<String>,
+    #[arg(long)]
+    structure: Option<String>,
+    #[arg(long)]
+    duration: Option<String>,
+    #[arg(long)]
+    price: Option<i32>,
+    #[arg(long)]
+    language: Option<String>,
+    #[arg(long)]
+    level: Option<String>,
+}
+
+//Asked before destructive changes, false leaves everything as it is
+pub type Confirm<'a> = &'a mut dyn FnMut(&str) -> bool;
+
+pub async fn run(
+    command: Command,
+    pool: &PgPool,
+    confirm: Confirm<'_>,
+) -> Result<Output, EzyTutorError> {
+    match command {
+        Command::Tutors(command) => run_tutor_command(command, pool, confirm).await,
+        Command::Courses(command) => run_course_command(command, pool, confirm).await,
+        Command::Migrate { status } => {
+            if !status {
+                MIGRATOR
+                    .run(pool)
+                    .await
+                    .map_err(|err| EzyTutorError::DBError(err.to_string()))?;
+            }
+            let pending = get_pending_migrations_db(pool).await?;
+            let migrations = MIGRATOR
+                .iter()
+                .filter(|migration| !migration.migration_type.is_down_migration())
+                .map(|migration| MigrationRow {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    applied: !pending.contains(&migration.version),
+                })
+                .collect();
+            Ok(Output::Migrations(migrations))
+        }
+        Command::Seed => seed(pool).await,
+        Command::Generate(args) => generate::generate(&args, pool).await,
+    }
+}
+
+fn cancelled() -> EzyTutorError {
+    EzyTutorError::InvalidInput("cancelled, nothing was changed".into())
+}
+
+async fn run_tutor_command(
+    command: TutorCommand,
+    pool: &PgPool,
+    confirm: Confirm<'_>,
+) -> Result<Output, EzyTutorError> {
+    let tutor = match command {
+        TutorCommand::List { name, limit, after } => {
+            let tutors = get_tutors_page_db(pool, name.as_deref(), after, limit).await?;
+            return Ok(Output::Tutors(tutors));
+        }
+        TutorCommand::Show { tutor_id } => get_tutor_details_db(pool, tutor_id).await?,
+        TutorCommand::Create {
+            name,
+            pic_url,
+            profile,
+        } => {
+            let new_tutor = NewTutor {
+                tutor_name: name,
+                tutor_pic_url: pic_url,
+                tutor_profile: profile,
+            };
+            post_new_tutor_db(pool, new_tutor).await?
+        }
+        TutorCommand::Update {
+            tutor_id,
+            name,
+            pic_url,
+            profile,
+        } => {
+            let update_tutor = UpdateTutor {
+                tutor_name: name,
+                tutor_pic_url: pic_url,
+                tutor_profile: profile,
+            };
+            update_tutor_details_db(pool, tutor_id, update_tutor).await?
+        }
+        TutorCommand::Delete { tutor_id } => {
+            let tutor = get_tutor_details_db(pool, tutor_id).await?;
+            let courses = get_courses_for_tutor_db(pool, tutor_id).await?;
+            let question = format!(
+                "Delete tutor {} {:?} and their {} course(s)?",
+                tutor.tutor_id,
+                tutor.tutor_name,
+                courses.len()
+            );
+            if !confirm(&question) {
+                return Err(cancelled());
+            }
+            delete_tutor_db(pool, tutor_id).await?;
+            return Ok(Output::Message(format!(
+                "Deleted tutor {} and {} course(s)",
+                tutor_id,
+                courses.len()
+            )));
+        }
+    };
+    Ok(Output::Tutors(vec![tutor]))
+}
+
+async fn run_course_command(
+    command: CourseCommand,
+    pool: &PgPool,
+    confirm: Confirm<'_>,
+) -> Result<Output, EzyTutorError> {
+    let course = match command {
+        CourseCommand::List {
+            tutor,
+            language,
+            level,
+            name,
+            limit,
+            after,
+        } => {
+            let filter = CourseFilter {
+                tutor_id: tutor,
+                course_name: name,
+                course_level: level,
+                course_language: language,
+                ..CourseFilter::default()
+            };
+            let courses = get_courses_page_db(pool, &filter, after, limit).await?;
+            return Ok(Output::Courses(courses));
+        }
+        CourseCommand::Show {
+            tutor_id,
+            course_id,
+        } => get_course_details_db(pool, tutor_id, course_id).await?,
+        CourseCommand::Create {
+            tutor_id,
+            name,
+            fields,
+        } => {
+            let new_course = CreateCourse {
+                tutor_id,
+                course_name: name,
+                course_description: fields.description,
+                course_format: fields.format,
+                course_structure: fields.structure,
+                course_duration: fields.duration,
+                course_price: fields.price,
+                course_language: fields.language,
+                course_level: fields.level,
+            };
+            new_course.validate().map_err(EzyTutorError::InvalidInput)?;
+            //Fails on the foreign key otherwise
+            get_tutor_details_db(pool, tutor_id).await?;
+            post_new_course_db(pool, new_course).await?
+        }
+        CourseCommand::Update {
+            tutor_id,
+            course_id,
+            name,
+            fields,
+        } => {
+            let update_course = UpdateCourse {
+                course_name: name,
+                course_description: fields.description,
+                course_format: fields.format,
+                course_structure: fields.structure,
+                course_duration: fields.duration,
+                course_price: fields.price,
+                course_language: fields.language,
+                course_level: fields.level,
+            };
+            update_course_details_db(pool, tutor_id, course_id, update_course).await?
+        }
+        CourseCommand::Delete {
+            tutor_id,
+            course_id,
+        } => {
+            let course = get_course_details_db(pool, tutor_id, course_id).await?;
+            let question = format!(
+                "Delete course {} {:?} of tutor {}?",
+                course.course_id, course.course_name, tutor_id
+            );
+            if !confirm(&question) {
+                return Err(cancelled());
+            }
+            delete_course_db(pool, tutor_id, course_id).await?;
+            return Ok(Output::Message(format!("Deleted course {}", course_id)));
+        }
+        CourseCommand::Reassign {
+            from,
+            to,
+            course_ids,
+            all,
+        } => {
+            let target = get_tutor_details_db(pool, to).await?;
+            let count = if all {
+                get_courses_for_tutor_db(pool, from).await?.len()
+            } else {
+                course_ids.len()
+            };
+            if count == 0 {
+                return Ok(Output::Courses(vec![]));
+            }
+            let question = format!(
+                "Move {} course(s) from tutor {} to tutor {} {:?}?",
+                count, from, to, target.tutor_name
+            );
+            if !confirm(&question) {
+                return Err(cancelled());
+            }
+            let course_ids = (!all).then_some(course_ids.as_slice());
+            let courses = reassign_courses_db(pool, from, to, course_ids).await?;
+            return Ok(Output::Courses(courses));
+        }
+    };
+    Ok(Output::Courses(vec![course]))
+}
+
+//The rows of src/iter5/dbscripts/tutor-course.sql, with new ids
+async fn seed(pool: &PgPool) -> Result<Output, EzyTutorError> {
+    if !get_tutors_page_db(pool, None, None, 1).await?.is_empty() {
+        return Err(EzyTutorError::InvalidInput(
+            "the database has tutors already, seed only fills empty ones".into(),
+        ));
+    }
+    let tutors = [
+        (
+            "Merlene",
+            "http://s3.amazon.aws.com/pic1",
+            "Merlene is an experienced finance professional",
+        ),
+        (
+            "Frank",
+            "http://s3.amazon.aws.com/pic2",
+            "Frank is an expert nuclear engineer",
+        ),
+    ];
+    let mut tutor_ids = Vec::new();
+    for (name, pic_url, profile) in tutors {
+        let new_tutor = NewTutor {
+            tutor_name: name.into(),
+            tutor_pic_url: pic_url.into(),
+            tutor_profile: profile.into(),
+        };
+        tutor_ids.push(post_new_tutor_db(pool, new_tutor).await?.tutor_id);
+    }
+    let mut courses: Vec<Course> = Vec::new();
+    for (name, format, level) in [
+        ("First course", None, Some("Beginner")),
+        ("Second course", Some("ebook"), None),
+    ] {
+        let new_course = CreateCourse {
+            tutor_id: tutor_ids[0],
+            course_name: name.into(),
+            course_description: None,
+            course_format: format.map(String::from),
+            course_structure: None,
+            course_duration: None,
+            course_price: None,
+            course_language: None,
+            course_level: level.map(String::from),
+        };
+        courses.push(post_new_course_db(pool, new_course).await?);
+    }
+    Ok(Output::Message(format!(
+        "Added {} tutors and {} courses",
+        tutor_ids.len(),
+        courses.len()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotenv::dotenv;
+    use std::env;
+
+    fn command(args: &[&str]) -> Command {
+        let args = std::iter::once("tutorctl").chain(args.iter().copied());
+        CtlArgs::try_parse_from(args).unwrap().command
+    }
+
+    async fn created_tutor(pool: &PgPool, name: &str) -> i32 {
+        let args = [
+            "tutors",
+            "create",
+            "--name",
+            name,
+            "--pic-url",
+            "http://pic",
+            "--profile",
+            "Moves",
+        ];
+        match run(command(&args), pool, &mut |_| unreachable!()).await {
+            Ok(Output::Tutors(tutors)) => tutors[0].tutor_id,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reassigning_needs_course_ids_or_all() {
+        let parse = |args: &[&str]| {
+            CtlArgs::try_parse_from(std::iter::once("tutorctl").chain(args.iter().copied()))
+        };
+        assert!(parse(&["courses", "reassign", "--from", "1", "--to", "2"]).is_err());
+        assert!(parse(&["courses", "reassign", "--from", "1", "--to", "2", "3", "--all"]).is_err());
+        let args = parse(&[
+            "courses", "reassign", "--from", "1", "--to", "2", "3", "4", "-y",
+        ]);
+        let args = args.unwrap();
+        assert!(args.yes);
+        assert!(matches!(
+            args.command,
+            Command::Courses(CourseCommand::Reassign { course_ids, all: false, .. })
+                if course_ids == [3, 4]
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn courses_move_between_tutors_once_confirmed() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let from = created_tutor(&pool, "Leaving tutor").await;
+        let to = created_tutor(&pool, "Staying tutor").await;
+        let from_id = from.to_string();
+        let to_id = to.to_string();
+        let mut course_ids = Vec::new();
+        for name in ["Moved course", "Also moved"] {
+            let args = [
+                "courses", "create", &from_id, "--name", name, "--price", "50",
+            ];
+            match run(command(&args), &pool, &mut |_| unreachable!()).await {
+                Ok(Output::Courses(courses)) => course_ids.push(courses[0].course_id),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        let args = ["courses", "create", &from_id, "--name", " "];
+        assert!(matches!(
+            run(command(&args), &pool, &mut |_| unreachable!()).await,
+            Err(EzyTutorError::InvalidInput(_))
+        ));
+
+        //Declined, nothing moves
+        let mut questions = Vec::new();
+        let reassign = [
+            "courses", "reassign", "--from", &from_id, "--to", &to_id, "--all",
+        ];
+        let declined = run(command(&reassign), &pool, &mut |question| {
+            questions.push(question.to_string());
+            false
+        })
+        .await;
+        assert!(matches!(declined, Err(EzyTutorError::InvalidInput(_))));
+        assert_eq!(
+            questions,
+            [format!(
+                "Move 2 course(s) from tutor {} to tutor {} \"Staying tutor\"?",
+                from, to
+            )]
+        );
+        assert_eq!(
+            get_courses_for_tutor_db(&pool, from).await.unwrap().len(),
+            2
+        );
+
+        //An unknown course fails the whole move
+        let unknown = [
+            "courses",
+            "reassign",
+            "--from",
+            &from_id,
+            "--to",
+            &to_id,
+            "2147483647",
+        ];
+        let unknown = run(command(&unknown), &pool, &mut |_| true).await;
+        assert!(matches!(unknown, Err(EzyTutorError::NotFound(_))));
+        assert_eq!(
+            get_courses_for_tutor_db(&pool, from).await.unwrap().len(),
+            2
+        );
+
+        let moved = match run(command(&reassign), &pool, &mut |_| true).await {
+            Ok(Output::Courses(courses)) => courses,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(moved.len(), 2);
+        assert!(moved.iter().all(|course| course.tutor_id == to));
+        assert!(get_courses_for_tutor_db(&pool, from)
+            .await
+            .unwrap()
+            .is_empty());
+        let moved_ids: Vec<i32> = get_courses_for_tutor_db(&pool, to)
+            .await
+            .unwrap()
+            .iter()
+            .map(|course| course.course_id)
+            .collect();
+        assert_eq!(moved_ids, course_ids);
+
+        for tutor_id in [&from_id, &to_id] {
+            let delete = ["tutors", "delete", tutor_id.as_str()];
+            let deleted = run(command(&delete), &pool, &mut |_| true).await;
+            assert!(matches!(deleted, Ok(Output::Message(_))));
+        }
+        assert!(matches!(
+            run(command(&["tutors", "show", &to_id]), &pool, &mut |_| true).await,
+            Err(EzyTutorError::NotFound(_))
+        ));
+    }
+}
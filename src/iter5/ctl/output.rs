@@ -0,0 +1,225 @@
+use crate::models::course::Course;
+use crate::models::tutor::Tutor;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+//Longer cells are cut in tables, JSON has everything
+const MAX_CELL_CHARS: usize = 40;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+//Migration as listed by `migrate --status`
+#[derive(Serialize, Debug, Clone)]
+pub struct MigrationRow {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+//Result of a command, printed in the chosen format
+#[derive(Debug)]
+pub enum Output {
+    Tutors(Vec<Tutor>),
+    Courses(Vec<Course>),
+    Migrations(Vec<MigrationRow>),
+    Message(String),
+}
+
+impl Output {
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Table => self.table().to_string(),
+            OutputFormat::Json => {
+                let value = match self {
+                    Output::Tutors(tutors) => serde_json::to_value(tutors),
+                    Output::Courses(courses) => serde_json::to_value(courses),
+                    Output::Migrations(migrations) => serde_json::to_value(migrations),
+                    Output::Message(message) => Ok(serde_json::json!({ "message": message })),
+                };
+                let value = value.expect("models serialize to JSON");
+                serde_json::to_string_pretty(&value).expect("JSON values serialize") + "\n"
+            }
+        }
+    }
+
+    fn table(&self) -> Table {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        match self {
+            Output::Tutors(tutors) => Table {
+                headers: vec!["ID", "NAME", "PICTURE", "PROFILE"],
+                rows: tutors
+                    .iter()
+                    .map(|tutor| {
+                        vec![
+                            tutor.tutor_id.to_string(),
+                            tutor.tutor_name.clone(),
+                            tutor.tutor_pic_url.clone(),
+                            tutor.tutor_profile.clone(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Output::Courses(courses) => Table {
+                headers: vec![
+                    "ID", "TUTOR", "NAME", "FORMAT", "LEVEL", "LANGUAGE", "PRICE", "POSTED",
+                ],
+                rows: courses
+                    .iter()
+                    .map(|course| {
+                        vec![
+                            course.course_id.to_string(),
+                            course.tutor_id.to_string(),
+                            course.course_name.clone(),
+                            optional(&course.course_format),
+                            optional(&course.course_level),
+                            optional(&course.course_language),
+                            course
+                                .course_price
+                                .map(|price| price.to_string())
+                                .unwrap_or_default(),
+                            course
+                                .posted_time
+                                .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                                .unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Output::Migrations(migrations) => Table {
+                headers: vec!["VERSION", "DESCRIPTION", "STATUS"],
+                rows: migrations
+                    .iter()
+                    .map(|migration| {
+                        vec![
+                            migration.version.to_string(),
+                            migration.description.clone(),
+                            if migration.applied {
+                                "applied".to_string()
+                            } else {
+                                "pending".to_string()
+                            },
+                        ]
+                    })
+                    .collect(),
+            },
+            Output::Message(message) => Table {
+                headers: vec![],
+                rows: vec![vec![message.clone()]],
+            },
+        }
+    }
+}
+
+//Columns padded to their widest cell, a message is a table without headers
+struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+fn cell(value: &str) -> String {
+    //Line breaks would split the row
+    let value = value.replace(['\n', '\r', '\t'], " ");
+    if value.chars().count() > MAX_CELL_CHARS {
+        let cut: String = value.chars().take(MAX_CELL_CHARS - 1).collect();
+        format!("{}…", cut)
+    } else {
+        value
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.headers.is_empty() {
+            for row in &self.rows {
+                writeln!(f, "{}", row.join(" "))?;
+            }
+            return Ok(());
+        }
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|value| cell(value)).collect())
+            .collect();
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.len()).collect();
+        for row in &rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
+            }
+        }
+        let headers: Vec<String> = self
+            .headers
+            .iter()
+            .map(|header| header.to_string())
+            .collect();
+        for row in std::iter::once(&headers).chain(&rows) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| {
+                    let padding = width - value.chars().count();
+                    format!("{}{}", value, " ".repeat(padding))
+                })
+                .collect();
+            writeln!(f, "{}", line.join("  ").trim_end())?;
+        }
+        if rows.is_empty() {
+            writeln!(f, "(none)")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tutor(tutor_id: i32, tutor_profile: &str) -> Tutor {
+        Tutor {
+            tutor_id,
+            tutor_name: "Merlene".into(),
+            tutor_pic_url: "http://s3.amazon.aws.com/pic1".into(),
+            tutor_profile: tutor_profile.into(),
+        }
+    }
+
+    #[test]
+    fn tables_are_aligned_and_cut() {
+        let output = Output::Tutors(vec![
+            tutor(1, "Finance"),
+            tutor(
+                12,
+                "Merlene is an experienced finance professional\nand teaches accounting",
+            ),
+        ]);
+        assert_eq!(
+            output.render(OutputFormat::Table),
+            "ID  NAME     PICTURE                        PROFILE\n\
+             1   Merlene  http://s3.amazon.aws.com/pic1  Finance\n\
+             12  Merlene  http://s3.amazon.aws.com/pic1  Merlene is an experienced finance profe…\n"
+        );
+        assert_eq!(
+            Output::Courses(vec![]).render(OutputFormat::Table),
+            "ID  TUTOR  NAME  FORMAT  LEVEL  LANGUAGE  PRICE  POSTED\n(none)\n"
+        );
+    }
+
+    #[test]
+    fn json_has_the_whole_records() {
+        let long_profile = "x".repeat(100);
+        let output = Output::Tutors(vec![tutor(1, &long_profile)]);
+        let json: serde_json::Value =
+            serde_json::from_str(&output.render(OutputFormat::Json)).unwrap();
+        assert_eq!(json[0]["tutor_profile"], long_profile);
+        let message = Output::Message("Deleted 1 tutor".into()).render(OutputFormat::Json);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&message).unwrap(),
+            serde_json::json!({ "message": "Deleted 1 tutor" })
+        );
+    }
+}
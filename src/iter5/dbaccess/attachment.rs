@@ -0,0 +1,157 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::attachment::{Attachment, NewAttachment};
+use sqlx::postgres::PgPool;
+use tracing::instrument;
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_attachments_for_course_db(
+    pool: &PgPool,
+    course_id: i32,
+) -> Result<Vec<Attachment>, EzyTutorError> {
+    let _timer = query_timer("get_attachments_for_course");
+    //Prepare SQL Statement
+    let attachment_rows = sqlx::query_as!(
+        Attachment,
+        "SELECT * FROM ezy_attachment_c6 WHERE course_id = $1 ORDER BY attachment_id",
+        course_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(attachment_rows)
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_attachment_details_db(
+    pool: &PgPool,
+    course_id: i32,
+    attachment_id: i32,
+) -> Result<Attachment, EzyTutorError> {
+    let _timer = query_timer("get_attachment_details");
+    //Prepare SQL Statement
+    let attachment_row = sqlx::query_as!(
+        Attachment,
+        "SELECT * FROM ezy_attachment_c6 WHERE course_id = $1 AND attachment_id = $2",
+        course_id,
+        attachment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    attachment_row.ok_or_else(|| EzyTutorError::NotFound("Attachment id not found".into()))
+}
+
+//Whether an attachment refers to the stored content. Identical files of a
+//course share their storage key.
+#[instrument(level = "debug", skip(pool))]
+pub async fn storage_key_in_use_db(
+    pool: &PgPool,
+    storage_key: &str,
+) -> Result<bool, EzyTutorError> {
+    let _timer = query_timer("storage_key_in_use");
+    let in_use = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM ezy_attachment_c6 WHERE storage_key = $1) as "in_use!""#,
+        storage_key
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(in_use)
+}
+
+#[instrument(level = "debug", skip(pool, new_attachment, quota_bytes))]
+pub async fn post_new_attachment_db(
+    pool: &PgPool,
+    new_attachment: NewAttachment,
+    quota_bytes: i64,
+) -> Result<Attachment, EzyTutorError> {
+    let _timer = query_timer("post_new_attachment");
+    let mut tx = pool.begin().await?;
+    //Lock the tutor row so concurrent uploads can't both squeeze under the quota
+    sqlx::query!(
+        "SELECT tutor_id FROM ezy_tutor_c6 WHERE tutor_id = $1 FOR UPDATE",
+        new_attachment.tutor_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".into()))?;
+
+    let used_bytes = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(size_bytes), 0)::BIGINT as "used!"
+        FROM ezy_attachment_c6 WHERE tutor_id = $1"#,
+        new_attachment.tutor_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if used_bytes + new_attachment.size_bytes > quota_bytes {
+        return Err(EzyTutorError::PayloadTooLarge(format!(
+            "attachment would exceed the storage quota of {} bytes ({} bytes used)",
+            quota_bytes, used_bytes
+        )));
+    }
+
+    let attachment_row = sqlx::query_as!(
+        Attachment,
+        "INSERT INTO ezy_attachment_c6
+        (course_id, tutor_id, file_name, mime_type, size_bytes, checksum, storage_key)
+        VALUES ($1,$2,$3,$4,$5,$6,$7) RETURNING *",
+        new_attachment.course_id,
+        new_attachment.tutor_id,
+        new_attachment.file_name,
+        new_attachment.mime_type,
+        new_attachment.size_bytes,
+        new_attachment.checksum,
+        new_attachment.storage_key
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            EzyTutorError::InvalidInput("this file is already attached to the course".into())
+        }
+        err => err.into(),
+    })?;
+    tx.commit().await?;
+
+    Ok(attachment_row)
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn delete_attachment_db(
+    pool: &PgPool,
+    course_id: i32,
+    attachment_id: i32,
+) -> Result<Attachment, EzyTutorError> {
+    let _timer = query_timer("delete_attachment");
+    //Prepare SQL Statement
+    let attachment_row = sqlx::query_as!(
+        Attachment,
+        "DELETE FROM ezy_attachment_c6 WHERE course_id = $1 AND attachment_id = $2 RETURNING *",
+        course_id,
+        attachment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    attachment_row.ok_or_else(|| EzyTutorError::NotFound("Attachment id not found".into()))
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn is_student_enrolled_db(
+    pool: &PgPool,
+    course_id: i32,
+    student_id: i32,
+) -> Result<bool, EzyTutorError> {
+    let _timer = query_timer("is_student_enrolled");
+    let enrolled = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM ezy_enrollment_c6
+        WHERE course_id = $1 AND student_id = $2) as "enrolled!""#,
+        course_id,
+        student_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(enrolled)
+}
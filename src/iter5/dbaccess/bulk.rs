@@ -0,0 +1,151 @@
+use crate::dbaccess::cache::notify_cache;
+use crate::errors::EzyTutorError;
+use crate::models::cache::CacheKey;
+use sqlx::postgres::PgPool;
+use sqlx::{Postgres, Transaction};
+use tracing::instrument;
+
+//Tables filled by bulk loads, in the order their foreign keys need
+const BULK_TABLES: &str = "ezy_tutor_c6, ezy_course_c6, ezy_enrollment_c6, \
+                           ezy_conversation_c6, ezy_chat_message_c6";
+
+//Rows are sent to COPY in chunks of about this size
+const COPY_CHUNK_BYTES: usize = 1 << 20;
+
+//COPY inside one transaction, nothing is visible until commit. Rows skip the
+//outbox, so bulk loads send no events, webhooks or notifications.
+pub struct BulkLoad {
+    tx: Transaction<'static, Postgres>,
+}
+
+impl BulkLoad {
+    //Other writers wait until the load is committed, readers don't
+    #[instrument(level = "debug", skip(pool))]
+    pub async fn begin(pool: &PgPool) -> Result<Self, EzyTutorError> {
+        let mut tx = pool.begin().await?;
+        //Loads take longer than any request may
+        sqlx::query("SET LOCAL statement_timeout = 0")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!("LOCK TABLE {} IN EXCLUSIVE MODE", BULK_TABLES))
+            .execute(&mut *tx)
+            .await?;
+        Ok(BulkLoad { tx })
+    }
+
+    //First of `count` ids nobody else gets from the serial column. Rows
+    //inserted with explicit ids are skipped as well.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn reserve_ids(
+        &mut self,
+        table: &'static str,
+        column: &'static str,
+        count: i64,
+    ) -> Result<i64, EzyTutorError> {
+        let first: i64 = sqlx::query_scalar(&format!(
+            "SELECT greatest(nextval(pg_get_serial_sequence($1, $2)),
+                (SELECT coalesce(max({}), 0) + 1 FROM {}))::bigint",
+            column, table
+        ))
+        .bind(table)
+        .bind(column)
+        .fetch_one(&mut *self.tx)
+        .await?;
+        let last = first + count - 1;
+        if last > i64::from(i32::MAX) {
+            return Err(EzyTutorError::InvalidInput(format!(
+                "{} ids would go past {}",
+                table,
+                i32::MAX
+            )));
+        }
+        sqlx::query("SELECT setval(pg_get_serial_sequence($1, $2), $3)")
+            .bind(table)
+            .bind(column)
+            .bind(last.max(first))
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(first)
+    }
+
+    //Lines come from copy_line. Returns the number of rows copied.
+    #[instrument(level = "debug", skip(self, lines))]
+    pub async fn copy(
+        &mut self,
+        table: &'static str,
+        columns: &[&str],
+        lines: impl Iterator<Item = String>,
+    ) -> Result<u64, EzyTutorError> {
+        let statement = format!("COPY {} ({}) FROM STDIN", table, columns.join(", "));
+        let mut copy = self.tx.copy_in_raw(&statement).await?;
+        let mut chunk = String::with_capacity(COPY_CHUNK_BYTES + 4096);
+        for line in lines {
+            chunk.push_str(&line);
+            if chunk.len() >= COPY_CHUNK_BYTES {
+                if let Err(err) = copy.send(chunk.as_bytes()).await {
+                    copy.abort("sending rows failed").await.ok();
+                    return Err(err.into());
+                }
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            if let Err(err) = copy.send(chunk.as_bytes()).await {
+                copy.abort("sending rows failed").await.ok();
+                return Err(err.into());
+            }
+        }
+        Ok(copy.finish().await?)
+    }
+
+    //Fresh statistics, so the planner knows how large the tables became.
+    //Loads only add tutors, cached reads of the existing ones stay valid.
+    pub async fn commit(self) -> Result<(), EzyTutorError> {
+        let mut tx = self.tx;
+        sqlx::query(&format!("ANALYZE {}", BULK_TABLES))
+            .execute(&mut *tx)
+            .await?;
+        notify_cache(&mut tx, &[CacheKey::AllTutors]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+//One row in COPY's text format, None is NULL
+pub fn copy_line(fields: &[Option<&str>]) -> String {
+    let mut line = String::new();
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            line.push('\t');
+        }
+        match field {
+            None => line.push_str("\\N"),
+            Some(value) => {
+                for c in value.chars() {
+                    match c {
+                        '\\' => line.push_str("\\\\"),
+                        '\t' => line.push_str("\\t"),
+                        '\n' => line.push_str("\\n"),
+                        '\r' => line.push_str("\\r"),
+                        c => line.push(c),
+                    }
+                }
+            }
+        }
+    }
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_lines_escape_separators_and_mark_nulls() {
+        assert_eq!(
+            copy_line(&[Some("1"), None, Some("a\tb\nc\\d\re"), Some("")]),
+            "1\t\\N\ta\\tb\\nc\\\\d\\re\t\n"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use crate::models::cache::CacheKey;
+use sqlx::postgres::PgConnection;
+
+//Every instance listens on it and drops the keys from its cache
+pub const CACHE_CHANNEL: &str = "ezy_cache";
+
+//Sent when the transaction commits, not at all if it doesn't. Changes of
+//cached reads send it here, whether they come from the server or tutorctl.
+pub async fn notify_cache(conn: &mut PgConnection, keys: &[CacheKey]) -> Result<(), sqlx::Error> {
+    let payload = keys
+        .iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    sqlx::query!("SELECT pg_notify($1, $2)", CACHE_CHANNEL, payload)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::chat::*;
+use sqlx::postgres::{PgConnection, PgPool};
+use tracing::instrument;
+
+//Every instance listens on it and passes the frames to the participants
+//connected there
+pub const CHAT_CHANNEL: &str = "ezy_chat";
+
+//Sent when the transaction commits, not at all if it doesn't
+pub async fn notify_chat(conn: &mut PgConnection, notice: &ChatNotice) -> Result<(), sqlx::Error> {
+    let payload = serde_json::to_string(notice).expect("chat notices serialize to JSON");
+    sqlx::query!("SELECT pg_notify($1, $2)", CHAT_CHANNEL, payload)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+//The existing conversation of the student with the tutor if there is one,
+//which gets the course if it had none
+#[instrument(level = "debug", skip(pool))]
+pub async fn start_conversation_db(
+    pool: &PgPool,
+    student_id: i32,
+    new_conversation: &NewConversation,
+) -> Result<Conversation, EzyTutorError> {
+    let _timer = query_timer("start_conversation");
+    let conversation_row = sqlx::query_as!(
+        Conversation,
+        "INSERT INTO ezy_conversation_c6 (tutor_id, student_id, course_id) VALUES ($1, $2, $3)
+        ON CONFLICT (tutor_id, student_id) DO UPDATE
+        SET course_id = COALESCE(ezy_conversation_c6.course_id, EXCLUDED.course_id)
+        RETURNING conversation_id, tutor_id, student_id, course_id, created_time,
+        last_message_time",
+        new_conversation.tutor_id,
+        student_id,
+        new_conversation.course_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(conversation_row)
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_conversation_db(
+    pool: &PgPool,
+    conversation_id: i32,
+) -> Result<Conversation, EzyTutorError> {
+    let _timer = query_timer("get_conversation");
+    let conversation_row = sqlx::query_as!(
+        Conversation,
+        "SELECT conversation_id, tutor_id, student_id, course_id, created_time, last_message_time
+        FROM ezy_conversation_c6 WHERE conversation_id = $1",
+        conversation_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    conversation_row.ok_or_else(|| EzyTutorError::NotFound("Conversation id not found".into()))
+}
+
+//Latest conversations first
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_conversations_db(
+    pool: &PgPool,
+    participant: Participant,
+) -> Result<Vec<ConversationSummary>, EzyTutorError> {
+    let _timer = query_timer("get_conversations");
+    let conversation_rows = sqlx::query_as!(
+        ConversationSummary,
+        r#"SELECT c.conversation_id, c.tutor_id, c.student_id, c.course_id, c.created_time,
+        c.last_message_time,
+        (SELECT count(*) FROM ezy_chat_message_c6 m
+        WHERE m.conversation_id = c.conversation_id AND m.sender <> $2 AND m.read_time IS NULL)
+        AS "unread!"
+        FROM ezy_conversation_c6 c
+        WHERE CASE WHEN $2 = 'tutor' THEN c.tutor_id ELSE c.student_id END = $1
+        ORDER BY c.last_message_time DESC NULLS LAST, c.conversation_id DESC"#,
+        participant.id,
+        participant.role.as_str()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(conversation_rows)
+}
+
+//A message resent with the same client_message_id is stored once, its first
+//copy is returned and sent to the participants again
+#[instrument(level = "debug", skip(pool, conversation, body))]
+pub async fn post_chat_message_db(
+    pool: &PgPool,
+    conversation: &Conversation,
+    sender: ChatRole,
+    body: &str,
+    client_message_id: Option<&str>,
+) -> Result<ChatMessage, EzyTutorError> {
+    let _timer = query_timer("post_chat_message");
+    let mut tx = pool.begin().await?;
+    let inserted = sqlx::query_as!(
+        ChatMessage,
+        "INSERT INTO ezy_chat_message_c6 (conversation_id, sender, body, client_message_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (conversation_id, client_message_id) DO NOTHING
+        RETURNING message_id, conversation_id, sender, body, client_message_id, sent_time,
+        delivered_time, read_time",
+        conversation.conversation_id,
+        sender.as_str(),
+        body,
+        client_message_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let message = match inserted {
+        Some(message) => {
+            sqlx::query!(
+                "UPDATE ezy_conversation_c6 SET last_message_time = $2 WHERE conversation_id = $1",
+                conversation.conversation_id,
+                message.sent_time
+            )
+            .execute(&mut *tx)
+            .await?;
+            message
+        }
+        None => {
+            sqlx::query_as!(
+                ChatMessage,
+                "SELECT message_id, conversation_id, sender, body, client_message_id, sent_time,
+                delivered_time, read_time
+                FROM ezy_chat_message_c6 WHERE conversation_id = $1 AND client_message_id = $2",
+                conversation.conversation_id,
+                client_message_id
+            )
+            .fetch_one(&mut *tx)
+            .await?
+        }
+    };
+    let frame = ServerFrame::Message {
+        message: message.clone(),
+    };
+    notify_chat(&mut tx, &ChatNotice::to_both(conversation, frame)).await?;
+    tx.commit().await?;
+
+    Ok(message)
+}
+
+//Typing indicators are not stored, they only go to the other participant
+#[instrument(level = "debug", skip(pool, conversation))]
+pub async fn notify_typing_db(
+    pool: &PgPool,
+    conversation: &Conversation,
+    sender: ChatRole,
+) -> Result<(), EzyTutorError> {
+    let _timer = query_timer("notify_typing");
+    let mut conn = pool.acquire().await?;
+    let frame = ServerFrame::Typing {
+        conversation_id: conversation.conversation_id,
+        sender,
+    };
+    let notice = ChatNotice {
+        recipient: Some(sender.other()),
+        ..ChatNotice::to_both(conversation, frame)
+    };
+    notify_chat(&mut conn, &notice).await?;
+
+    Ok(())
+}
+
+//Newest first, a page ends before the message_id given
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_chat_messages_db(
+    pool: &PgPool,
+    conversation_id: i32,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<ChatMessage>, EzyTutorError> {
+    let _timer = query_timer("get_chat_messages");
+    let message_rows = sqlx::query_as!(
+        ChatMessage,
+        "SELECT message_id, conversation_id, sender, body, client_message_id, sent_time,
+        delivered_time, read_time
+        FROM ezy_chat_message_c6
+        WHERE conversation_id = $1 AND ($2::bigint IS NULL OR message_id < $2)
+        ORDER BY message_id DESC LIMIT $3",
+        conversation_id,
+        before,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(message_rows)
+}
+
+//Marks the messages to the recipient up to message_id as delivered. Only the
+//first instance to do so tells the participants.
+#[instrument(level = "debug", skip(pool, conversation))]
+pub async fn mark_delivered_db(
+    pool: &PgPool,
+    conversation: &Conversation,
+    recipient: ChatRole,
+    up_to: i64,
+) -> Result<Vec<i64>, EzyTutorError> {
+    let _timer = query_timer("mark_delivered");
+    let mut tx = pool.begin().await?;
+    let mut message_ids = sqlx::query_scalar!(
+        "UPDATE ezy_chat_message_c6 SET delivered_time = now()
+        WHERE conversation_id = $1 AND sender <> $2 AND message_id <= $3
+        AND delivered_time IS NULL
+        RETURNING message_id",
+        conversation.conversation_id,
+        recipient.as_str(),
+        up_to
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    if !message_ids.is_empty() {
+        message_ids.sort_unstable();
+        let frame = ServerFrame::Delivered {
+            conversation_id: conversation.conversation_id,
+            message_ids: message_ids.clone(),
+        };
+        notify_chat(&mut tx, &ChatNotice::to_both(conversation, frame)).await?;
+    }
+    tx.commit().await?;
+
+    Ok(message_ids)
+}
+
+//Read messages count as delivered as well
+#[instrument(level = "debug", skip(pool, conversation))]
+pub async fn mark_read_db(
+    pool: &PgPool,
+    conversation: &Conversation,
+    reader: ChatRole,
+    up_to: i64,
+) -> Result<Vec<i64>, EzyTutorError> {
+    let _timer = query_timer("mark_read");
+    let mut tx = pool.begin().await?;
+    let mut message_ids = sqlx::query_scalar!(
+        "UPDATE ezy_chat_message_c6
+        SET read_time = now(), delivered_time = COALESCE(delivered_time, now())
+        WHERE conversation_id = $1 AND sender <> $2 AND message_id <= $3 AND read_time IS NULL
+        RETURNING message_id",
+        conversation.conversation_id,
+        reader.as_str(),
+        up_to
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    if !message_ids.is_empty() {
+        message_ids.sort_unstable();
+        let frame = ServerFrame::Read {
+            conversation_id: conversation.conversation_id,
+            message_ids: message_ids.clone(),
+        };
+        notify_chat(&mut tx, &ChatNotice::to_both(conversation, frame)).await?;
+    }
+    tx.commit().await?;
+
+    Ok(message_ids)
+}
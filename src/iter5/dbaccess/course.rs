@@ -1,11 +1,21 @@
+use crate::dbaccess::cache::notify_cache;
+use crate::dbaccess::notification::queue_notifications_db;
+use crate::dbaccess::outbox::record_event;
 use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::cache::CacheKey;
 use crate::models::course::*;
-use sqlx::postgres::PgPool;
+use crate::models::event::Event;
+use sqlx::postgres::{PgConnection, PgPool};
+use sqlx::Connection;
+use tracing::instrument;
 
+#[instrument(level = "debug", skip(pool))]
 pub async fn get_courses_for_tutor_db(
     pool: &PgPool,
     tutor_id: i32,
 ) -> Result<Vec<Course>, EzyTutorError> {
+    let _timer = query_timer("get_courses_for_tutor");
     //prepare sql statement
     let course_rows = sqlx::query_as!(
         Course,
@@ -18,11 +28,79 @@ pub async fn get_courses_for_tutor_db(
     Ok(course_rows)
 }
 
+//Courses of several tutors with one query, for loading a relation in batches
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_courses_for_tutors_db(
+    pool: &PgPool,
+    tutor_ids: &[i32],
+) -> Result<Vec<Course>, EzyTutorError> {
+    let _timer = query_timer("get_courses_for_tutors");
+    let course_rows = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6 WHERE tutor_id = ANY($1) ORDER BY course_id",
+        tutor_ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(course_rows)
+}
+
+//Keyset page of courses ordered by id, limit + 1 rows tell whether there are more
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_courses_page_db(
+    pool: &PgPool,
+    filter: &CourseFilter,
+    after: Option<i32>,
+    limit: i64,
+) -> Result<Vec<Course>, EzyTutorError> {
+    let _timer = query_timer("get_courses_page");
+    let course_rows = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6
+        WHERE ($1::int IS NULL OR tutor_id = $1)
+        AND ($2::text IS NULL OR course_name ILIKE $2)
+        AND ($3::text IS NULL OR lower(course_level) = lower($3))
+        AND ($4::text IS NULL OR course_language = $4)
+        AND ($5::text IS NULL OR course_format = $5)
+        AND ($6::int IS NULL OR course_price >= $6)
+        AND ($7::int IS NULL OR course_price <= $7)
+        AND ($8::timestamp IS NULL OR posted_time >= $8)
+        AND ($9::int IS NULL OR course_id > $9)
+        ORDER BY course_id LIMIT $10",
+        filter.tutor_id,
+        filter.course_name.as_deref().map(contains_pattern),
+        filter.course_level,
+        filter.course_language,
+        filter.course_format,
+        filter.min_price,
+        filter.max_price,
+        filter.posted_after,
+        after,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(course_rows)
+}
+
+//ILIKE pattern matching the value anywhere, wildcards typed by users match themselves
+pub fn contains_pattern(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[instrument(level = "debug", skip(pool))]
 pub async fn get_course_details_db(
     pool: &PgPool,
     tutor_id: i32,
     course_id: i32,
 ) -> Result<Course, EzyTutorError> {
+    let _timer = query_timer("get_course_details");
     //prepare sql statement
     let course_row = sqlx::query_as!(
         Course,
@@ -39,10 +117,13 @@ pub async fn get_course_details_db(
     }
 }
 
+#[instrument(level = "debug", skip(pool, new_course))]
 pub async fn post_new_course_db(
     pool: &PgPool,
     new_course: CreateCourse,
 ) -> Result<Course, EzyTutorError> {
+    let _timer = query_timer("post_new_course");
+    let mut tx = pool.begin().await?;
     //posted time is not set, because in database.sql it is set to posted_time TIMESTAMP default now()
     let course_row = sqlx::query_as!(
         Course,
@@ -63,33 +144,160 @@ pub async fn post_new_course_db(
         new_course.course_structure,
         new_course.course_price
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *tx)
     .await?;
+    let event = Event::course_created(&course_row);
+    record_event(&mut tx, &event).await?;
+    queue_notifications_db(&mut tx, &event).await?;
+    notify_cache(&mut tx, &[CacheKey::CoursesForTutor(course_row.tutor_id)]).await?;
+    tx.commit().await?;
     //returning Course
     Ok(course_row)
 }
+//Each row runs in its own savepoint, so in best effort mode a failing row
+//only rolls back itself. Nothing is committed for dry runs or failed atomic imports.
+#[instrument(level = "debug", skip(pool, rows, params))]
+pub async fn import_courses_db(
+    pool: &PgPool,
+    rows: Vec<Result<CreateCourse, String>>,
+    params: &ImportParams,
+) -> Result<ImportReport, EzyTutorError> {
+    let _timer = query_timer("import_courses");
+    let mut results: Vec<ImportRowResult> = rows
+        .iter()
+        .enumerate()
+        .map(|(index, row)| ImportRowResult {
+            row: index + 1,
+            course_id: None,
+            error: row.as_ref().err().cloned(),
+        })
+        .collect();
+    let has_invalid_rows = results.iter().any(|result| result.error.is_some());
+
+    let mut tx = pool.begin().await?;
+    if !(params.mode == ImportMode::Atomic && has_invalid_rows) {
+        for (row, result) in rows.into_iter().zip(results.iter_mut()) {
+            let Ok(course) = row else {
+                continue;
+            };
+            let mut savepoint = Connection::begin(&mut *tx).await?;
+            match insert_course(&mut savepoint, &course).await {
+                Ok(course_id) => {
+                    savepoint.commit().await?;
+                    result.course_id = Some(course_id);
+                }
+                Err(err) => {
+                    savepoint.rollback().await?;
+                    result.error = Some(match err {
+                        sqlx::Error::Database(db_err) => db_err.message().to_string(),
+                        _ => "database error".into(),
+                    });
+                    if params.mode == ImportMode::Atomic {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+    let commit = !params.dry_run && (params.mode == ImportMode::BestEffort || failed == 0);
+    if commit {
+        tx.commit().await?;
+    } else {
+        tx.rollback().await?;
+        for result in results.iter_mut() {
+            result.course_id = None;
+        }
+    }
+    Ok(ImportReport {
+        mode: params.mode,
+        dry_run: params.dry_run,
+        created: results
+            .iter()
+            .filter(|result| result.course_id.is_some())
+            .count(),
+        failed,
+        rows: results,
+    })
+}
+
+//The event is rolled back with the row if the savepoint is. Imports queue
+//no notifications, the tutor would get one per row.
+async fn insert_course(conn: &mut PgConnection, course: &CreateCourse) -> Result<i32, sqlx::Error> {
+    let course = sqlx::query_as!(
+        Course,
+        "INSERT INTO ezy_course_c6
+        (tutor_id, course_name, course_description, course_duration,
+        course_level, course_format, course_language, course_structure,
+        course_price) VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9)
+        returning *",
+        course.tutor_id,
+        course.course_name,
+        course.course_description,
+        course.course_duration,
+        course.course_level,
+        course.course_format,
+        course.course_language,
+        course.course_structure,
+        course.course_price
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    record_event(conn, &Event::course_created(&course)).await?;
+    notify_cache(conn, &[CacheKey::CoursesForTutor(course.tutor_id)]).await?;
+    Ok(course.course_id)
+}
+
+#[instrument(level = "debug", skip(pool))]
 pub async fn delete_course_db(
     pool: &PgPool,
     tutor_id: i32,
     course_id: i32,
 ) -> Result<String, EzyTutorError> {
+    let _timer = query_timer("delete_course");
+    let mut tx = pool.begin().await?;
+    //Locked so the course is still there when it's deleted, nothing is
+    //queued for a course of another tutor or one that doesn't exist
+    let course = sqlx::query!(
+        "SELECT course_id FROM ezy_course_c6 WHERE tutor_id = $1 AND course_id = $2 FOR UPDATE",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    //While the enrollments are still there
+    let event = Event::course_deleted(tutor_id, course_id);
+    if course.is_some() {
+        queue_notifications_db(&mut tx, &event).await?;
+    }
     //Prepare SQL Statement
     let course_row = sqlx::query!(
         "DELETE FROM ezy_course_c6 WHERE tutor_id = $1 AND course_id = $2",
         tutor_id,
         course_id,
     )
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
+    if course_row.rows_affected() > 0 {
+        record_event(&mut tx, &event).await?;
+        notify_cache(&mut tx, &[CacheKey::CoursesForTutor(tutor_id)]).await?;
+    }
+    tx.commit().await?;
     Ok(format!("Deleted {:?} record", course_row))
 }
 
+#[instrument(level = "debug", skip(pool, update_course))]
 pub async fn update_course_details_db(
     pool: &PgPool,
     tutor_id: i32,
     course_id: i32,
     update_course: UpdateCourse,
 ) -> Result<Course, EzyTutorError> {
+    let _timer = query_timer("update_course_details");
     //Retrieve current record
 
     let current_course_row = sqlx::query_as!(
@@ -145,6 +353,7 @@ pub async fn update_course_details_db(
         current_course_row.course_price.unwrap_or_default()
     };
 
+    let mut tx = pool.begin().await?;
     //Prepare SQL Statement
     let course_row = sqlx::query_as!(
         Course,
@@ -168,11 +377,80 @@ pub async fn update_course_details_db(
         tutor_id,
         course_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *tx)
     .await;
     if let Ok(course) = course_row {
+        let event = Event::course_updated(&course);
+        record_event(&mut tx, &event).await?;
+        queue_notifications_db(&mut tx, &event).await?;
+        notify_cache(&mut tx, &[CacheKey::CoursesForTutor(tutor_id)]).await?;
+        tx.commit().await?;
         Ok(course)
     } else {
         Err(EzyTutorError::NotFound("Course id not found".into()))
     }
 }
+
+//Moves the given courses of a tutor to another one, or all of them if none
+//are given. Either every course moves or none does. Enrollments stay, the
+//students hear about it like about any other change. Only tutorctl does this.
+#[allow(dead_code)]
+#[instrument(level = "debug", skip(pool))]
+pub async fn reassign_courses_db(
+    pool: &PgPool,
+    from_tutor_id: i32,
+    to_tutor_id: i32,
+    course_ids: Option<&[i32]>,
+) -> Result<Vec<Course>, EzyTutorError> {
+    let _timer = query_timer("reassign_courses");
+    if from_tutor_id == to_tutor_id {
+        return Err(EzyTutorError::InvalidInput(
+            "courses can only be reassigned to another tutor".into(),
+        ));
+    }
+    let mut tx = pool.begin().await?;
+    let target = sqlx::query_scalar!(
+        "SELECT tutor_id FROM ezy_tutor_c6 WHERE tutor_id = $1 FOR SHARE",
+        to_tutor_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if target.is_none() {
+        return Err(EzyTutorError::NotFound("Tutor id not found".into()));
+    }
+    let course_rows = sqlx::query_as!(
+        Course,
+        "UPDATE ezy_course_c6 SET tutor_id = $2
+        WHERE tutor_id = $1 AND ($3::int[] IS NULL OR course_id = ANY($3))
+        RETURNING course_id, tutor_id, course_name, course_description, course_format,
+        course_structure, course_duration, course_price, course_language, course_level,
+        posted_time",
+        from_tutor_id,
+        to_tutor_id,
+        course_ids
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let missing = course_ids.is_some_and(|ids| {
+        ids.iter()
+            .any(|id| !course_rows.iter().any(|course| course.course_id == *id))
+    });
+    if missing {
+        return Err(EzyTutorError::NotFound("Course id not found".into()));
+    }
+    for course in &course_rows {
+        let event = Event::course_updated(course);
+        record_event(&mut tx, &event).await?;
+        queue_notifications_db(&mut tx, &event).await?;
+    }
+    notify_cache(
+        &mut tx,
+        &[
+            CacheKey::CoursesForTutor(from_tutor_id),
+            CacheKey::CoursesForTutor(to_tutor_id),
+        ],
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(course_rows)
+}
@@ -0,0 +1,145 @@
+use crate::dbaccess::course::contains_pattern;
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::course::{Course, CourseFilter};
+use crate::models::export::TutorFilter;
+use crate::models::tutor::Tutor;
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::{FromRow, Postgres, Row, Transaction};
+use std::marker::PhantomData;
+use std::time::Duration;
+use tracing::instrument;
+
+//A reader that takes no rows for this long is given up on. The cursor holds
+//a pooled connection in an open transaction until it is dropped.
+pub const CURSOR_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+//Server side cursor inside a read only transaction. Rows are pulled in batches,
+//so exports of large tables never hold more than one batch in memory.
+pub struct ExportCursor<T> {
+    tx: Transaction<'static, Postgres>,
+    fetch_sql: String,
+    row_type: PhantomData<T>,
+}
+
+impl<T> ExportCursor<T>
+where
+    T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+{
+    async fn declare(
+        pool: &PgPool,
+        name: &str,
+        batch_size: u32,
+        declare: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments>,
+    ) -> Result<Self, EzyTutorError> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("SET TRANSACTION READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        //Ends the session should a reader stall without the cursor being dropped
+        sqlx::query(&format!(
+            "SET LOCAL idle_in_transaction_session_timeout = {}",
+            2 * CURSOR_STALL_TIMEOUT.as_millis()
+        ))
+        .execute(&mut *tx)
+        .await?;
+        declare.execute(&mut *tx).await?;
+        Ok(ExportCursor {
+            tx,
+            fetch_sql: format!("FETCH FORWARD {} FROM {}", batch_size, name),
+            row_type: PhantomData,
+        })
+    }
+
+    //An empty batch means the cursor is exhausted
+    pub async fn next_batch(&mut self) -> Result<Vec<T>, EzyTutorError> {
+        let rows = sqlx::query_as::<_, T>(&self.fetch_sql)
+            .fetch_all(&mut *self.tx)
+            .await?;
+        Ok(rows)
+    }
+}
+
+//Unset filters are passed as NULL and match everything
+const COURSE_FILTER: &str = "WHERE ($1::INT IS NULL OR tutor_id = $1)
+    AND ($2::VARCHAR IS NULL OR course_name ILIKE $2)
+    AND ($3::VARCHAR IS NULL OR lower(course_level) = lower($3))
+    AND ($4::VARCHAR IS NULL OR course_language = $4)
+    AND ($5::VARCHAR IS NULL OR course_format = $5)
+    AND ($6::INT IS NULL OR course_price >= $6)
+    AND ($7::INT IS NULL OR course_price <= $7)
+    AND ($8::TIMESTAMP IS NULL OR posted_time >= $8)";
+
+const TUTOR_FILTER: &str = "WHERE ($1::VARCHAR IS NULL OR tutor_name ILIKE $1)";
+
+fn bind_course_filter<'q>(
+    query: sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments>,
+    filter: &CourseFilter,
+) -> sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments> {
+    query
+        .bind(filter.tutor_id)
+        .bind(filter.course_name.as_deref().map(contains_pattern))
+        .bind(filter.course_level.clone())
+        .bind(filter.course_language.clone())
+        .bind(filter.course_format.clone())
+        .bind(filter.min_price)
+        .bind(filter.max_price)
+        .bind(filter.posted_after)
+}
+
+#[instrument(level = "debug", skip(pool, filter, batch_size))]
+pub async fn open_course_cursor_db(
+    pool: &PgPool,
+    filter: &CourseFilter,
+    batch_size: u32,
+) -> Result<ExportCursor<Course>, EzyTutorError> {
+    let _timer = query_timer("open_course_cursor");
+    //Prepare SQL statement
+    let sql = format!(
+        "DECLARE course_export NO SCROLL CURSOR FOR
+        SELECT * FROM ezy_course_c6 {} ORDER BY course_id",
+        COURSE_FILTER
+    );
+    let declare = bind_course_filter(sqlx::query(&sql), filter);
+    ExportCursor::declare(pool, "course_export", batch_size, declare).await
+}
+
+//Rows an export with the filter would have
+#[instrument(level = "debug", skip(pool, filter))]
+pub async fn count_courses_db(pool: &PgPool, filter: &CourseFilter) -> Result<i64, EzyTutorError> {
+    let _timer = query_timer("count_courses");
+    let sql = format!("SELECT count(*) FROM ezy_course_c6 {}", COURSE_FILTER);
+    let row = bind_course_filter(sqlx::query(&sql), filter)
+        .fetch_one(pool)
+        .await?;
+    Ok(row.try_get(0)?)
+}
+
+#[instrument(level = "debug", skip(pool, filter, batch_size))]
+pub async fn open_tutor_cursor_db(
+    pool: &PgPool,
+    filter: &TutorFilter,
+    batch_size: u32,
+) -> Result<ExportCursor<Tutor>, EzyTutorError> {
+    let _timer = query_timer("open_tutor_cursor");
+    //Prepare SQL statement
+    let sql = format!(
+        "DECLARE tutor_export NO SCROLL CURSOR FOR
+        SELECT tutor_id, tutor_name, tutor_pic_url, tutor_profile FROM ezy_tutor_c6
+        {} ORDER BY tutor_id",
+        TUTOR_FILTER
+    );
+    let declare = sqlx::query(&sql).bind(filter.tutor_name.as_deref().map(contains_pattern));
+    ExportCursor::declare(pool, "tutor_export", batch_size, declare).await
+}
+
+#[instrument(level = "debug", skip(pool, filter))]
+pub async fn count_tutors_db(pool: &PgPool, filter: &TutorFilter) -> Result<i64, EzyTutorError> {
+    let _timer = query_timer("count_tutors");
+    let sql = format!("SELECT count(*) FROM ezy_tutor_c6 {}", TUTOR_FILTER);
+    let row = sqlx::query(&sql)
+        .bind(filter.tutor_name.as_deref().map(contains_pattern))
+        .fetch_one(pool)
+        .await?;
+    Ok(row.try_get(0)?)
+}
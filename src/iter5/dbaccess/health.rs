@@ -0,0 +1,119 @@
+use crate::errors::EzyTutorError;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgPool;
+use tracing::instrument;
+
+//Migrations compiled into the binary, the database has to be at least this far
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn ping_db(pool: &PgPool) -> Result<(), EzyTutorError> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+//Versions of the embedded migrations that were not applied successfully
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_pending_migrations_db(pool: &PgPool) -> Result<Vec<i64>, EzyTutorError> {
+    //The table is created by sqlx when migrations are first run, every
+    //migration is pending before
+    let migrated: Option<bool> =
+        sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await?;
+    let applied: Vec<i64> = if migrated == Some(true) {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect();
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotenv::dotenv;
+    use sqlx::postgres::PgConnectOptions;
+    use std::env;
+    use std::str::FromStr;
+
+    fn all_versions() -> Vec<i64> {
+        MIGRATOR
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| migration.version)
+            .collect()
+    }
+
+    //Unqualified tables are looked up in the schema only
+    async fn connect_to_schema(schema: &str) -> PgPool {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file!");
+        let options = PgConnectOptions::from_str(&database_url)
+            .unwrap()
+            .options([("search_path", schema)]);
+        PgPool::connect_with(options).await.unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn all_migrations_are_pending_before_the_first_run() {
+        //A schema that doesn't exist, as in a database sqlx never migrated
+        let pool = connect_to_schema("ezy_not_migrated").await;
+        assert_eq!(
+            get_pending_migrations_db(&pool).await.unwrap(),
+            all_versions()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn nothing_is_pending_in_the_migrated_database() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file!");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        assert_eq!(
+            get_pending_migrations_db(&pool).await.unwrap(),
+            Vec::<i64>::new()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn failed_and_missing_migrations_are_pending() {
+        let all = all_versions();
+        let pool = connect_to_schema("ezy_partly_migrated").await;
+        sqlx::query("DROP SCHEMA IF EXISTS ezy_partly_migrated CASCADE")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("CREATE SCHEMA ezy_partly_migrated")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE _sqlx_migrations (version BIGINT PRIMARY KEY, success BOOLEAN)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        //The first one failed, the last one never ran
+        for (i, version) in all[..all.len() - 1].iter().enumerate() {
+            sqlx::query("INSERT INTO _sqlx_migrations (version, success) VALUES ($1, $2)")
+                .bind(version)
+                .bind(i > 0)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let pending = get_pending_migrations_db(&pool).await;
+        sqlx::query("DROP SCHEMA ezy_partly_migrated CASCADE")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(pending.unwrap(), [all[0], all[all.len() - 1]]);
+    }
+}
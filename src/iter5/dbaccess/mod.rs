@@ -1,2 +1,17 @@
+pub mod attachment;
+//Only tutorctl loads in bulk
+#[allow(dead_code)]
+pub mod bulk;
+pub mod cache;
+pub mod chat;
 pub mod course;
+pub mod export;
+pub mod health;
+pub mod notification;
+pub mod outbox;
+pub mod pool;
+pub mod ratelimit;
+pub mod replica;
+pub mod stats;
 pub mod tutor;
+pub mod webhook;
@@ -0,0 +1,265 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::chat::Participant;
+use crate::models::event::{Event, EventType};
+use crate::models::notification::*;
+use sqlx::postgres::{PgConnection, PgPool};
+use std::time::Duration;
+use tracing::instrument;
+
+//Send errors are cut to the column size
+const MAX_ERROR_LENGTH: usize = 500;
+
+//Queues the notifications of a change in its transaction, for the recipients
+//who want them. Deletions queue theirs before the rows are gone, the
+//enrollments are deleted with the course.
+pub async fn queue_notifications_db(
+    conn: &mut PgConnection,
+    event: &Event,
+) -> Result<u64, sqlx::Error> {
+    let _timer = query_timer("queue_notifications");
+    let id = |field: &str| event.data[field].as_i64().map(|id| id as i32);
+    let (Some(tutor_id), course_id) = (id("tutor_id"), id("course_id")) else {
+        return Ok(0);
+    };
+    let queued = match event.event_type {
+        EventType::CourseCreated => {
+            queue_for_tutor(conn, NotificationKind::CoursePublished, tutor_id, course_id).await?
+        }
+        EventType::CourseUpdated => {
+            queue_for_students(
+                conn,
+                NotificationKind::CourseUpdated,
+                course_id,
+                Some(tutor_id),
+            )
+            .await?
+        }
+        EventType::CourseDeleted => {
+            queue_for_students(
+                conn,
+                NotificationKind::CourseCancelled,
+                course_id,
+                Some(tutor_id),
+            )
+            .await?
+        }
+        EventType::TutorCreated => 0,
+        EventType::TutorUpdated => {
+            queue_for_tutor(conn, NotificationKind::ProfileUpdated, tutor_id, None).await?
+        }
+        EventType::TutorDeleted => {
+            let students = queue_for_students(
+                conn,
+                NotificationKind::CourseCancelled,
+                None,
+                Some(tutor_id),
+            )
+            .await?;
+            let tutor =
+                queue_for_tutor(conn, NotificationKind::AccountClosed, tutor_id, None).await?;
+            //The queued notifications keep the address
+            sqlx::query!(
+                "DELETE FROM ezy_notification_preference_c6
+                WHERE recipient_role = 'tutor' AND recipient_id = $1",
+                tutor_id
+            )
+            .execute(&mut *conn)
+            .await?;
+            students + tutor
+        }
+    };
+
+    Ok(queued)
+}
+
+async fn queue_for_tutor(
+    conn: &mut PgConnection,
+    kind: NotificationKind,
+    tutor_id: i32,
+    course_id: Option<i32>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO ezy_notification_c6
+        (kind, recipient_role, recipient_id, email, locale, context)
+        SELECT $1::text, p.recipient_role, p.recipient_id, p.email, p.locale,
+        json_build_object('tutor_id', t.tutor_id, 'tutor_name', t.tutor_name,
+        'course_id', c.course_id, 'course_name', c.course_name)::text
+        FROM ezy_tutor_c6 t
+        LEFT JOIN ezy_course_c6 c ON c.tutor_id = t.tutor_id AND c.course_id = $3
+        JOIN ezy_notification_preference_c6 p
+        ON p.recipient_role = 'tutor' AND p.recipient_id = t.tutor_id
+        WHERE t.tutor_id = $2 AND NOT ($1::text = ANY(p.muted))",
+        kind.as_str(),
+        tutor_id,
+        course_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//Students enrolled in the course, or in any course of the tutor
+async fn queue_for_students(
+    conn: &mut PgConnection,
+    kind: NotificationKind,
+    course_id: Option<i32>,
+    tutor_id: Option<i32>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO ezy_notification_c6
+        (kind, recipient_role, recipient_id, email, locale, context)
+        SELECT $1::text, p.recipient_role, p.recipient_id, p.email, p.locale,
+        json_build_object('tutor_id', t.tutor_id, 'tutor_name', t.tutor_name,
+        'course_id', c.course_id, 'course_name', c.course_name)::text
+        FROM ezy_enrollment_c6 e
+        JOIN ezy_course_c6 c ON c.course_id = e.course_id
+        JOIN ezy_tutor_c6 t ON t.tutor_id = c.tutor_id
+        JOIN ezy_notification_preference_c6 p
+        ON p.recipient_role = 'student' AND p.recipient_id = e.student_id
+        WHERE ($2::int IS NULL OR c.course_id = $2) AND ($3::int IS NULL OR c.tutor_id = $3)
+        AND NOT ($1::text = ANY(p.muted))
+        ORDER BY c.course_id, e.student_id",
+        kind.as_str(),
+        course_id,
+        tutor_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//Due notifications, oldest first. Claiming counts the attempt and moves the
+//next one past the lease, so other instances skip the notification while it
+//is sent and pick it up again if this one dies.
+#[instrument(level = "debug", skip(pool))]
+pub async fn claim_due_notifications_db(
+    pool: &PgPool,
+    batch_size: i64,
+    lease: Duration,
+) -> Result<Vec<DueNotification>, EzyTutorError> {
+    let _timer = query_timer("claim_due_notifications");
+    let notification_rows = sqlx::query_as!(
+        DueNotification,
+        "UPDATE ezy_notification_c6 n SET attempts = n.attempts + 1,
+        next_attempt_time = now() + make_interval(secs => $2)
+        FROM (
+            SELECT notification_id FROM ezy_notification_c6
+            WHERE status = 'pending' AND next_attempt_time <= now()
+            ORDER BY next_attempt_time, notification_id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        ) due
+        WHERE n.notification_id = due.notification_id
+        RETURNING n.notification_id, n.kind, n.email, n.locale, n.context, n.attempts",
+        batch_size,
+        lease.as_secs_f64()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(notification_rows)
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn record_send_outcome_db(
+    pool: &PgPool,
+    notification_id: i32,
+    outcome: &SendOutcome,
+) -> Result<(), EzyTutorError> {
+    let _timer = query_timer("record_send_outcome");
+    let (status, retry_in, error) = match outcome {
+        SendOutcome::Sent => (NotificationStatus::Sent, Duration::ZERO, None),
+        SendOutcome::RetryIn(retry_in, error) => {
+            (NotificationStatus::Pending, *retry_in, Some(error))
+        }
+        SendOutcome::Dead(error) => (NotificationStatus::Dead, Duration::ZERO, Some(error)),
+    };
+    let error = error.map(|error| error.chars().take(MAX_ERROR_LENGTH).collect::<String>());
+    sqlx::query!(
+        "UPDATE ezy_notification_c6 SET status = $2::text,
+        next_attempt_time = now() + make_interval(secs => $3),
+        last_error = COALESCE($4, last_error),
+        sent_time = CASE WHEN $2::text = 'sent' THEN now() ELSE sent_time END
+        WHERE notification_id = $1",
+        notification_id,
+        status.as_str(),
+        retry_in.as_secs_f64(),
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_preferences_db(
+    pool: &PgPool,
+    recipient: Participant,
+) -> Result<NotificationPreferences, EzyTutorError> {
+    let _timer = query_timer("get_notification_preferences");
+    let preferences_row = sqlx::query_as!(
+        NotificationPreferences,
+        "SELECT email, locale, muted, updated_time FROM ezy_notification_preference_c6
+        WHERE recipient_role = $1 AND recipient_id = $2",
+        recipient.role.as_str(),
+        recipient.id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    preferences_row.ok_or_else(|| EzyTutorError::NotFound("No notification preferences set".into()))
+}
+
+#[instrument(level = "debug", skip(pool, preferences))]
+pub async fn set_preferences_db(
+    pool: &PgPool,
+    recipient: Participant,
+    preferences: &SetPreferences,
+) -> Result<NotificationPreferences, EzyTutorError> {
+    let _timer = query_timer("set_notification_preferences");
+    let preferences_row = sqlx::query_as!(
+        NotificationPreferences,
+        "INSERT INTO ezy_notification_preference_c6
+        (recipient_role, recipient_id, email, locale, muted) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (recipient_role, recipient_id) DO UPDATE
+        SET email = EXCLUDED.email, locale = EXCLUDED.locale, muted = EXCLUDED.muted,
+        updated_time = now()
+        RETURNING email, locale, muted, updated_time",
+        recipient.role.as_str(),
+        recipient.id,
+        preferences.email,
+        preferences.locale.as_deref().unwrap_or(LOCALES[0]),
+        preferences.muted.as_deref().unwrap_or_default()
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(preferences_row)
+}
+
+//Notifications queued already are still sent
+#[instrument(level = "debug", skip(pool))]
+pub async fn delete_preferences_db(
+    pool: &PgPool,
+    recipient: Participant,
+) -> Result<String, EzyTutorError> {
+    let _timer = query_timer("delete_notification_preferences");
+    let result = sqlx::query!(
+        "DELETE FROM ezy_notification_preference_c6
+        WHERE recipient_role = $1 AND recipient_id = $2",
+        recipient.role.as_str(),
+        recipient.id
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound(
+            "No notification preferences set".into(),
+        ));
+    }
+    Ok(format!("Deleted {:?} record", result))
+}
@@ -0,0 +1,118 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::event::{Event, OutboxEvent};
+use sqlx::postgres::{PgConnection, PgPool, Postgres};
+use sqlx::Transaction;
+use std::time::Duration;
+use tracing::instrument;
+
+//Advisory lock held by the relay publishing, "ezyoutbx" as a number
+const RELAY_LOCK_KEY: i64 = 0x657a_796f_7574_6278;
+
+//Has to run in the transaction of the change, the event is committed or
+//rolled back with it
+#[instrument(level = "debug", skip(conn, event), fields(event_type = event.event_type.as_str()))]
+pub async fn record_event(conn: &mut PgConnection, event: &Event) -> Result<(), sqlx::Error> {
+    let _timer = query_timer("record_event");
+    let event_id = uuid::Uuid::new_v4().to_string();
+    sqlx::query!(
+        "INSERT INTO ezy_outbox_c6 (event_id, event_type, partition_key, payload)
+        VALUES ($1, $2, $3, $4)",
+        event_id,
+        event.event_type.as_str(),
+        event.partition_key(),
+        event.payload(&event_id)
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+//Transaction holding the relay lock, None while another relay has it. One
+//relay at a time keeps the events in order across instances.
+#[instrument(level = "debug", skip(pool))]
+pub async fn lock_relay_db(
+    pool: &PgPool,
+) -> Result<Option<Transaction<'static, Postgres>>, EzyTutorError> {
+    let _timer = query_timer("lock_relay");
+    let mut tx = pool.begin().await?;
+    let locked = sqlx::query_scalar!("SELECT pg_try_advisory_xact_lock($1)", RELAY_LOCK_KEY)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    Ok(locked.unwrap_or(false).then_some(tx))
+}
+
+//Oldest unpublished events. A transaction that committed late can still add
+//one below the last published event_seq, it is taken by the next batch. Events
+//of the same tutor can't overtake each other, their changes lock the same rows.
+#[instrument(level = "debug", skip(conn))]
+pub async fn get_unpublished_events_db(
+    conn: &mut PgConnection,
+    limit: i64,
+) -> Result<Vec<OutboxEvent>, EzyTutorError> {
+    let _timer = query_timer("get_unpublished_events");
+    let event_rows = sqlx::query_as!(
+        OutboxEvent,
+        "SELECT event_seq, event_id, event_type, partition_key, payload
+        FROM ezy_outbox_c6 WHERE published_time IS NULL ORDER BY event_seq LIMIT $1",
+        limit
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(event_rows)
+}
+
+//Published or not, for the live streams notified of it
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_outbox_event_db(
+    pool: &PgPool,
+    event_seq: i64,
+) -> Result<Option<OutboxEvent>, EzyTutorError> {
+    let _timer = query_timer("get_outbox_event");
+    let event_row = sqlx::query_as!(
+        OutboxEvent,
+        "SELECT event_seq, event_id, event_type, partition_key, payload
+        FROM ezy_outbox_c6 WHERE event_seq = $1",
+        event_seq
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(event_row)
+}
+
+#[instrument(level = "debug", skip(conn, event_seqs))]
+pub async fn mark_events_published_db(
+    conn: &mut PgConnection,
+    event_seqs: &[i64],
+) -> Result<(), EzyTutorError> {
+    let _timer = query_timer("mark_events_published");
+    sqlx::query!(
+        "UPDATE ezy_outbox_c6 SET published_time = now() WHERE event_seq = ANY($1)",
+        event_seqs
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn prune_published_events_db(
+    pool: &PgPool,
+    retention: Duration,
+) -> Result<u64, EzyTutorError> {
+    let _timer = query_timer("prune_published_events");
+    let result = sqlx::query!(
+        "DELETE FROM ezy_outbox_c6
+        WHERE published_time < now() - make_interval(secs => $1)",
+        retention.as_secs_f64()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,56 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use sqlx::postgres::PgPool;
+use tracing::instrument;
+
+pub struct BucketState {
+    pub tokens: f64,
+    pub allowed: bool,
+}
+
+//Refills the bucket for the time since its last update and takes one token
+//if there is one, in a single statement so concurrent instances can't both
+//take the last token. A missing bucket starts out full.
+#[instrument(level = "debug", skip(pool))]
+pub async fn take_token_db(
+    pool: &PgPool,
+    bucket_key: &str,
+    capacity: f64,
+    refill_per_sec: f64,
+) -> Result<BucketState, EzyTutorError> {
+    let _timer = query_timer("take_token");
+    //Prepare SQL statement
+    let bucket = sqlx::query_as!(
+        BucketState,
+        r#"INSERT INTO ezy_rate_limit_c6 AS bucket (bucket_key, tokens, last_allowed, updated_time)
+        VALUES ($1, $2::float8 - 1, true, now())
+        ON CONFLICT (bucket_key) DO UPDATE SET
+            tokens = CASE
+                WHEN LEAST($2::float8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_time)::float8 * $3::float8) >= 1
+                THEN LEAST($2::float8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_time)::float8 * $3::float8) - 1
+                ELSE LEAST($2::float8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_time)::float8 * $3::float8)
+            END,
+            last_allowed = LEAST($2::float8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_time)::float8 * $3::float8) >= 1,
+            updated_time = now()
+        RETURNING tokens, last_allowed AS allowed"#,
+        bucket_key,
+        capacity,
+        refill_per_sec
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(bucket)
+}
+
+//Buckets untouched for longer than it takes to refill them are full anyway
+#[instrument(level = "debug", skip(pool))]
+pub async fn prune_buckets_db(pool: &PgPool, idle_secs: f64) -> Result<u64, EzyTutorError> {
+    let _timer = query_timer("prune_buckets");
+    let result = sqlx::query!(
+        "DELETE FROM ezy_rate_limit_c6 WHERE updated_time < now() - make_interval(secs => $1)",
+        idle_secs
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
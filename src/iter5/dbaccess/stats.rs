@@ -0,0 +1,29 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use sqlx::postgres::PgPool;
+use tracing::instrument;
+
+pub struct Totals {
+    pub tutors: i64,
+    pub courses: i64,
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_totals_db(pool: &PgPool) -> Result<Totals, EzyTutorError> {
+    let _timer = query_timer("get_totals");
+    //Counting every scrape would scan both tables, the planner's estimate is
+    //close enough. Tables never analyzed have no estimate yet and are counted.
+    let totals = sqlx::query_as!(
+        Totals,
+        r#"SELECT
+        (SELECT CASE WHEN reltuples < 0 THEN (SELECT COUNT(*) FROM ezy_tutor_c6)
+            ELSE reltuples::BIGINT END
+            FROM pg_class WHERE oid = 'ezy_tutor_c6'::regclass) AS "tutors!",
+        (SELECT CASE WHEN reltuples < 0 THEN (SELECT COUNT(*) FROM ezy_course_c6)
+            ELSE reltuples::BIGINT END
+            FROM pg_class WHERE oid = 'ezy_course_c6'::regclass) AS "courses!""#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(totals)
+}
@@ -1,8 +1,18 @@
+use crate::dbaccess::cache::notify_cache;
+use crate::dbaccess::course::contains_pattern;
+use crate::dbaccess::notification::queue_notifications_db;
+use crate::dbaccess::outbox::record_event;
 use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::cache::CacheKey;
+use crate::models::event::Event;
 use crate::models::tutor::{NewTutor, Tutor, UpdateTutor};
 use sqlx::postgres::PgPool;
+use tracing::instrument;
 
+#[instrument(level = "debug", skip(pool))]
 pub async fn get_all_tutors_db(pool: &PgPool) -> Result<Vec<Tutor>, EzyTutorError> {
+    let _timer = query_timer("get_all_tutors");
     //Prepare SQL Statement -> manually aproach with query! instead of query_as!
     //just for learning reasons, if a struct has e.g. more fields than the database table
     //which is sometimes true in real world apps
@@ -27,27 +37,77 @@ pub async fn get_all_tutors_db(pool: &PgPool) -> Result<Vec<Tutor>, EzyTutorErro
     }
 }
 
+//Keyset page of tutors ordered by id, limit + 1 rows tell whether there are more
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_tutors_page_db(
+    pool: &PgPool,
+    name_contains: Option<&str>,
+    after: Option<i32>,
+    limit: i64,
+) -> Result<Vec<Tutor>, EzyTutorError> {
+    let _timer = query_timer("get_tutors_page");
+    let tutor_rows = sqlx::query_as!(
+        Tutor,
+        "SELECT tutor_id, tutor_name, tutor_pic_url, tutor_profile FROM ezy_tutor_c6
+        WHERE ($1::text IS NULL OR tutor_name ILIKE $1)
+        AND ($2::int IS NULL OR tutor_id > $2)
+        ORDER BY tutor_id LIMIT $3",
+        name_contains.map(contains_pattern),
+        after,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tutor_rows)
+}
+
+//Tutors of several ids with one query, missing ids are left out
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_tutors_by_ids_db(
+    pool: &PgPool,
+    tutor_ids: &[i32],
+) -> Result<Vec<Tutor>, EzyTutorError> {
+    let _timer = query_timer("get_tutors_by_ids");
+    let tutor_rows = sqlx::query_as!(
+        Tutor,
+        "SELECT tutor_id, tutor_name, tutor_pic_url, tutor_profile FROM ezy_tutor_c6
+        WHERE tutor_id = ANY($1)",
+        tutor_ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tutor_rows)
+}
+
+#[instrument(level = "debug", skip(pool))]
 pub async fn get_tutor_details_db(pool: &PgPool, tutor_id: i32) -> Result<Tutor, EzyTutorError> {
+    let _timer = query_timer("get_tutor_details");
     //Prepare SQL statement
     let tutor_row = sqlx::query!(
         "SELECT tutor_id, tutor_name, tutor_pic_url, tutor_profile
         FROM ezy_tutor_c6 WHERE tutor_id = $1",
         tutor_id
     )
-    .fetch_one(pool)
-    .await
+    .fetch_optional(pool)
+    .await?
     .map(|tutor_row| Tutor {
         tutor_id: tutor_row.tutor_id,
         tutor_name: tutor_row.tutor_name,
         tutor_pic_url: tutor_row.tutor_pic_url,
         tutor_profile: tutor_row.tutor_profile,
     })
-    .map_err(|_err| EzyTutorError::NotFound("Tutor id not found".into()))?;
+    //Only a missing row is not found, connection failures are passed on
+    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".into()))?;
 
     Ok(tutor_row)
 }
 
+#[instrument(level = "debug", skip(pool, new_tutor))]
 pub async fn post_new_tutor_db(pool: &PgPool, new_tutor: NewTutor) -> Result<Tutor, EzyTutorError> {
+    let _timer = query_timer("post_new_tutor");
+    let mut tx = pool.begin().await?;
     let tutor_row = sqlx::query!(
         "INSERT INTO ezy_tutor_c6 (tutor_name, tutor_pic_url, tutor_profile) 
         VALUES ($1,$2,$3) RETURNING tutor_id, tutor_name, tutor_pic_url, tutor_profile",
@@ -55,22 +115,28 @@ pub async fn post_new_tutor_db(pool: &PgPool, new_tutor: NewTutor) -> Result<Tut
         new_tutor.tutor_pic_url,
         new_tutor.tutor_profile
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *tx)
     .await?;
     //Retrieve result
-    Ok(Tutor {
+    let tutor = Tutor {
         tutor_id: tutor_row.tutor_id,
         tutor_name: tutor_row.tutor_name,
         tutor_pic_url: tutor_row.tutor_pic_url,
         tutor_profile: tutor_row.tutor_profile,
-    })
+    };
+    record_event(&mut tx, &Event::tutor_created(&tutor)).await?;
+    notify_cache(&mut tx, &[CacheKey::AllTutors]).await?;
+    tx.commit().await?;
+    Ok(tutor)
 }
 
+#[instrument(level = "debug", skip(pool, update_tutor))]
 pub async fn update_tutor_details_db(
     pool: &PgPool,
     tutor_id: i32,
     update_tutor: UpdateTutor,
 ) -> Result<Tutor, EzyTutorError> {
+    let _timer = query_timer("update_tutor_details");
     //Retrieve current tutor_row
     let current_tutor_row = sqlx::query_as!(
         Tutor,
@@ -97,24 +163,78 @@ pub async fn update_tutor_details_db(
     } else {
         current_tutor_row.tutor_profile
     };
+    let mut tx = pool.begin().await?;
     //Prepare SQL Statement
     let tutor_row = sqlx::query_as!(Tutor,"UPDATE ezy_tutor_c6 SET tutor_name = $1,
                             tutor_pic_url = $2, tutor_profile = $3 WHERE tutor_id = $4 RETURNING tutor_id, tutor_name,
                             tutor_pic_url, tutor_profile", name, picurl, profile, tutor_id)
-                            .fetch_one(pool)
+                            .fetch_one(&mut *tx)
                             .await;
 
     if let Ok(tutor) = tutor_row {
+        let event = Event::tutor_updated(&tutor);
+        record_event(&mut tx, &event).await?;
+        queue_notifications_db(&mut tx, &event).await?;
+        notify_cache(&mut tx, &[CacheKey::AllTutors, CacheKey::Tutor(tutor_id)]).await?;
+        tx.commit().await?;
         Ok(tutor)
     } else {
         Err(EzyTutorError::NotFound("Tutor not found".into()))
     }
 }
 
+#[instrument(level = "debug", skip(pool, pic_url))]
+pub async fn update_tutor_pic_url_db(
+    pool: &PgPool,
+    tutor_id: i32,
+    pic_url: &str,
+) -> Result<Tutor, EzyTutorError> {
+    let _timer = query_timer("update_tutor_pic_url");
+    let mut tx = pool.begin().await?;
+    //Prepare SQL Statement
+    let tutor_row = sqlx::query_as!(
+        Tutor,
+        "UPDATE ezy_tutor_c6 SET tutor_pic_url = $1 WHERE tutor_id = $2
+        RETURNING tutor_id, tutor_name, tutor_pic_url, tutor_profile",
+        pic_url,
+        tutor_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".into()))?;
+    let event = Event::tutor_updated(&tutor_row);
+    record_event(&mut tx, &event).await?;
+    queue_notifications_db(&mut tx, &event).await?;
+    notify_cache(&mut tx, &[CacheKey::AllTutors, CacheKey::Tutor(tutor_id)]).await?;
+    tx.commit().await?;
+
+    Ok(tutor_row)
+}
+
+#[instrument(level = "debug", skip(pool))]
 pub async fn delete_tutor_db(pool: &PgPool, tutor_id: i32) -> Result<String, EzyTutorError> {
+    let _timer = query_timer("delete_tutor");
+    let mut tx = pool.begin().await?;
+    //While the tutor's courses and their enrollments are still there
+    let event = Event::tutor_deleted(tutor_id);
+    queue_notifications_db(&mut tx, &event).await?;
     //Prepare SQL Statement
     let tutor_row = sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+    if tutor_row.rows_affected() > 0 {
+        record_event(&mut tx, &event).await?;
+        //Courses of the tutor are deleted with it
+        notify_cache(
+            &mut tx,
+            &[
+                CacheKey::AllTutors,
+                CacheKey::Tutor(tutor_id),
+                CacheKey::CoursesForTutor(tutor_id),
+            ],
+        )
+        .await?;
+    }
+    tx.commit().await?;
     Ok(format!("Deleted {:?} record", tutor_row))
 }
@@ -0,0 +1,285 @@
+use crate::errors::EzyTutorError;
+use crate::metrics::query_timer;
+use crate::models::event::OutboxEvent;
+use crate::models::webhook::*;
+use sqlx::postgres::PgPool;
+use std::time::Duration;
+use tracing::instrument;
+
+//Attempt errors are cut to the column size
+const MAX_ERROR_LENGTH: usize = 500;
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_webhooks_db(pool: &PgPool) -> Result<Vec<Webhook>, EzyTutorError> {
+    let _timer = query_timer("get_webhooks");
+    let webhook_rows = sqlx::query_as!(
+        Webhook,
+        "SELECT webhook_id, url, event_types, active, created_time
+        FROM ezy_webhook_c6 ORDER BY webhook_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(webhook_rows)
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_webhook_db(pool: &PgPool, webhook_id: i32) -> Result<Webhook, EzyTutorError> {
+    let _timer = query_timer("get_webhook");
+    let webhook_row = sqlx::query_as!(
+        Webhook,
+        "SELECT webhook_id, url, event_types, active, created_time
+        FROM ezy_webhook_c6 WHERE webhook_id = $1",
+        webhook_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    webhook_row.ok_or_else(|| EzyTutorError::NotFound("Webhook id not found".into()))
+}
+
+#[instrument(level = "debug", skip(pool, new_webhook, secret))]
+pub async fn post_new_webhook_db(
+    pool: &PgPool,
+    new_webhook: NewWebhook,
+    secret: &str,
+) -> Result<Webhook, EzyTutorError> {
+    let _timer = query_timer("post_new_webhook");
+    let webhook_row = sqlx::query_as!(
+        Webhook,
+        "INSERT INTO ezy_webhook_c6 (url, event_types, secret) VALUES ($1, $2, $3)
+        RETURNING webhook_id, url, event_types, active, created_time",
+        new_webhook.url,
+        &new_webhook.event_types,
+        secret
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(webhook_row)
+}
+
+#[instrument(level = "debug", skip(pool, update_webhook))]
+pub async fn update_webhook_db(
+    pool: &PgPool,
+    webhook_id: i32,
+    update_webhook: UpdateWebhook,
+) -> Result<Webhook, EzyTutorError> {
+    let _timer = query_timer("update_webhook");
+    let webhook_row = sqlx::query_as!(
+        Webhook,
+        "UPDATE ezy_webhook_c6 SET url = COALESCE($1, url),
+        event_types = COALESCE($2, event_types), secret = COALESCE($3, secret),
+        active = COALESCE($4, active)
+        WHERE webhook_id = $5
+        RETURNING webhook_id, url, event_types, active, created_time",
+        update_webhook.url,
+        update_webhook.event_types.as_deref(),
+        update_webhook.secret,
+        update_webhook.active,
+        webhook_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    webhook_row.ok_or_else(|| EzyTutorError::NotFound("Webhook id not found".into()))
+}
+
+//Deletes the delivery log of the subscription with it
+#[instrument(level = "debug", skip(pool))]
+pub async fn delete_webhook_db(pool: &PgPool, webhook_id: i32) -> Result<String, EzyTutorError> {
+    let _timer = query_timer("delete_webhook");
+    let result = sqlx::query!(
+        "DELETE FROM ezy_webhook_c6 WHERE webhook_id = $1",
+        webhook_id
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound("Webhook id not found".into()));
+    }
+    Ok(format!("Deleted {:?} record", result))
+}
+
+//One pending delivery per active subscription to the event type, in the
+//order of the events. Deliveries an earlier publication already added are kept.
+#[instrument(level = "debug", skip(pool, events))]
+pub async fn insert_deliveries_db(
+    pool: &PgPool,
+    events: &[OutboxEvent],
+) -> Result<u64, EzyTutorError> {
+    let _timer = query_timer("insert_webhook_deliveries");
+    let column = |field: fn(&OutboxEvent) -> &String| -> Vec<String> {
+        events.iter().map(|event| field(event).clone()).collect()
+    };
+    let event_ids = column(|event| &event.event_id);
+    let event_types = column(|event| &event.event_type);
+    let payloads = column(|event| &event.payload);
+    let result = sqlx::query!(
+        "INSERT INTO ezy_webhook_delivery_c6 (webhook_id, event_id, event_type, payload)
+        SELECT w.webhook_id, e.event_id, e.event_type, e.payload
+        FROM unnest($1::text[], $2::text[], $3::text[])
+            WITH ORDINALITY AS e(event_id, event_type, payload, position)
+        JOIN ezy_webhook_c6 w ON w.active AND e.event_type = ANY(w.event_types)
+        ORDER BY e.position, w.webhook_id
+        ON CONFLICT (webhook_id, event_id) DO NOTHING",
+        &event_ids,
+        &event_types,
+        &payloads
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//Due deliveries of active subscriptions, oldest first. Claiming counts the
+//attempt and moves the next one past the lease, so other instances skip the
+//delivery while it is sent and pick it up again if this one dies.
+#[instrument(level = "debug", skip(pool))]
+pub async fn claim_due_deliveries_db(
+    pool: &PgPool,
+    batch_size: i64,
+    lease: Duration,
+) -> Result<Vec<DueDelivery>, EzyTutorError> {
+    let _timer = query_timer("claim_due_deliveries");
+    let delivery_rows = sqlx::query_as!(
+        DueDelivery,
+        r#"UPDATE ezy_webhook_delivery_c6 d SET attempts = d.attempts + 1,
+        next_attempt_time = now() + make_interval(secs => $2)
+        FROM (
+            SELECT d.delivery_id, w.url, w.secret
+            FROM ezy_webhook_delivery_c6 d
+            JOIN ezy_webhook_c6 w ON w.webhook_id = d.webhook_id
+            WHERE d.status = 'pending' AND d.next_attempt_time <= now() AND w.active
+            ORDER BY d.next_attempt_time, d.delivery_id
+            LIMIT $1
+            FOR UPDATE OF d SKIP LOCKED
+        ) due
+        WHERE d.delivery_id = due.delivery_id
+        RETURNING d.delivery_id, d.event_id, d.event_type, d.payload, d.attempts,
+        due.url AS "url!", due.secret AS "secret!""#,
+        batch_size,
+        lease.as_secs_f64()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(delivery_rows)
+}
+
+#[instrument(level = "debug", skip(pool, attempt))]
+pub async fn record_attempt_db(
+    pool: &PgPool,
+    delivery_id: i32,
+    attempt: &NewAttempt,
+    outcome: AttemptOutcome,
+) -> Result<(), EzyTutorError> {
+    let _timer = query_timer("record_webhook_attempt");
+    let error = attempt
+        .error
+        .as_deref()
+        .map(|error| error.chars().take(MAX_ERROR_LENGTH).collect::<String>());
+    let (status, retry_in) = match outcome {
+        AttemptOutcome::Delivered => (DeliveryStatus::Delivered, Duration::ZERO),
+        AttemptOutcome::RetryIn(retry_in) => (DeliveryStatus::Pending, retry_in),
+        AttemptOutcome::Dead => (DeliveryStatus::Dead, Duration::ZERO),
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        "INSERT INTO ezy_webhook_attempt_c6 (delivery_id, status_code, error, duration_ms)
+        VALUES ($1, $2, $3, $4)",
+        delivery_id,
+        attempt.status_code,
+        error,
+        attempt.duration_ms
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE ezy_webhook_delivery_c6 SET status = $2::text,
+        next_attempt_time = now() + make_interval(secs => $3),
+        delivered_time = CASE WHEN $2::text = 'delivered' THEN now() ELSE delivered_time END
+        WHERE delivery_id = $1",
+        delivery_id,
+        status.as_str(),
+        retry_in.as_secs_f64()
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_deliveries_db(
+    pool: &PgPool,
+    webhook_id: i32,
+    status: Option<DeliveryStatus>,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, EzyTutorError> {
+    let _timer = query_timer("get_webhook_deliveries");
+    let delivery_rows = sqlx::query_as!(
+        WebhookDelivery,
+        "SELECT * FROM ezy_webhook_delivery_c6
+        WHERE webhook_id = $1 AND ($2::text IS NULL OR status = $2)
+        ORDER BY delivery_id DESC LIMIT $3",
+        webhook_id,
+        status.map(|status| status.as_str()),
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(delivery_rows)
+}
+
+#[instrument(level = "debug", skip(pool))]
+pub async fn get_delivery_details_db(
+    pool: &PgPool,
+    webhook_id: i32,
+    delivery_id: i32,
+) -> Result<DeliveryDetails, EzyTutorError> {
+    let _timer = query_timer("get_webhook_delivery_details");
+    let delivery = sqlx::query_as!(
+        WebhookDelivery,
+        "SELECT * FROM ezy_webhook_delivery_c6 WHERE webhook_id = $1 AND delivery_id = $2",
+        webhook_id,
+        delivery_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Delivery id not found".into()))?;
+    let attempts = sqlx::query_as!(
+        WebhookAttempt,
+        "SELECT * FROM ezy_webhook_attempt_c6 WHERE delivery_id = $1 ORDER BY attempt_id",
+        delivery_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(DeliveryDetails { delivery, attempts })
+}
+
+//Sends the delivery again with a fresh set of attempts, whatever its status
+#[instrument(level = "debug", skip(pool))]
+pub async fn redeliver_db(
+    pool: &PgPool,
+    webhook_id: i32,
+    delivery_id: i32,
+) -> Result<WebhookDelivery, EzyTutorError> {
+    let _timer = query_timer("redeliver_webhook");
+    let delivery_row = sqlx::query_as!(
+        WebhookDelivery,
+        "UPDATE ezy_webhook_delivery_c6 SET status = 'pending', attempts = 0,
+        next_attempt_time = now(), delivered_time = NULL
+        WHERE webhook_id = $1 AND delivery_id = $2 RETURNING *",
+        webhook_id,
+        delivery_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    delivery_row.ok_or_else(|| EzyTutorError::NotFound("Delivery id not found".into()))
+}
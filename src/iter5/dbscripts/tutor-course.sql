@@ -50,3 +50,7 @@ values(1, 1, 'First course', 'Beginner' , '2021-04-12 05:40:00');
 insert into ezy_course_c6
     (course_id, tutor_id, course_name, course_format, posted_time)
 values(2, 1, 'Second course', 'ebook', '2021-04-12 05:45:00');
+
+/* Seed rows use explicit ids, move the sequences past them */
+select setval('ezy_tutor_c6_tutor_id_seq', (select max(tutor_id) from ezy_tutor_c6));
+select setval('ezy_course_c6_course_id_seq', (select max(course_id) from ezy_course_c6));
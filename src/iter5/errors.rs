@@ -1,7 +1,9 @@
-use actix_web::{error, http::StatusCode, HttpResponse};
+use crate::telemetry::current_request_id;
+use actix_web::{error, http::header, http::StatusCode, HttpResponse};
 use serde::Serialize;
 use sqlx::error::Error as SQLxError;
 use std::fmt;
+use utoipa::ToSchema;
 
 #[derive(Debug, Serialize)]
 pub enum EzyTutorError {
@@ -9,61 +11,129 @@ pub enum EzyTutorError {
     ActixError(String),
     NotFound(String),
     InvalidInput(String),
+    Forbidden(String),
+    PayloadTooLarge(String),
+    StorageError(String),
+    ServiceUnavailable(String),
+    //Message and seconds until the client may try again
+    TooManyRequests(String, u64),
 }
+
+//Seconds a client should wait before retrying a 503
+pub const RETRY_AFTER_SECS: u64 = 5;
 impl EzyTutorError {
-    fn error_response(&self) -> String {
+    //Server side failures are logged as errors with the full message,
+    //client errors as warnings
+    pub fn log(&self) {
         match self {
-            EzyTutorError::DBError(msg) => {
-                println!("Database error occured: {:?}", msg);
-                "Database error".into()
-            }
+            EzyTutorError::DBError(msg) => tracing::error!(error = %msg, "database error occurred"),
             EzyTutorError::ActixError(msg) => {
-                println!("Server error occured: {:?}", msg);
-                "Internal server error".into()
-            }
-            EzyTutorError::NotFound(msg) => {
-                println!("Not found error occured: {:?}", msg);
-                msg.into()
+                tracing::error!(error = %msg, "server error occurred")
             }
+            EzyTutorError::NotFound(msg) => tracing::info!(error = %msg, "not found"),
             EzyTutorError::InvalidInput(msg) => {
-                println!("Invalid input received {:?}", msg);
-                msg.into()
+                tracing::warn!(error = %msg, "invalid input received")
+            }
+            EzyTutorError::Forbidden(msg) => tracing::warn!(error = %msg, "forbidden request"),
+            EzyTutorError::PayloadTooLarge(msg) => {
+                tracing::warn!(error = %msg, "payload too large")
+            }
+            EzyTutorError::StorageError(msg) => {
+                tracing::error!(error = %msg, "storage error occurred")
+            }
+            EzyTutorError::ServiceUnavailable(msg) => {
+                tracing::warn!(error = %msg, "service unavailable")
+            }
+            EzyTutorError::TooManyRequests(msg, _) => {
+                tracing::info!(error = %msg, "rate limit exceeded")
             }
         }
     }
+
+    //The message sent back, it hides internals. Also for responses that carry
+    //errors in their body, like GraphQL.
+    pub fn client_message(&self) -> String {
+        match self {
+            EzyTutorError::DBError(_) => "Database error".into(),
+            EzyTutorError::ActixError(_) => "Internal server error".into(),
+            EzyTutorError::StorageError(_) => "Storage error".into(),
+            EzyTutorError::ServiceUnavailable(_) => {
+                "Service temporarily unavailable, please retry later".into()
+            }
+            EzyTutorError::NotFound(msg)
+            | EzyTutorError::InvalidInput(msg)
+            | EzyTutorError::Forbidden(msg)
+            | EzyTutorError::PayloadTooLarge(msg)
+            | EzyTutorError::TooManyRequests(msg, _) => msg.into(),
+        }
+    }
 }
 
 impl error::ResponseError for EzyTutorError {
     fn status_code(&self) -> StatusCode {
         match self {
-            EzyTutorError::DBError(msg) | EzyTutorError::ActixError(msg) => {
-                println!("Internal Server Error Statuscode: 500: {:?}", msg);
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
-            EzyTutorError::NotFound(msg) => {
-                println!("Not found error, Statuscode: 400: {:?}", msg);
-                StatusCode::NOT_FOUND
-            }
-            EzyTutorError::InvalidInput(msg) => {
-                println!("Invalid Input error, Bad Request: {:?}", msg);
-                StatusCode::BAD_REQUEST
-            }
+            EzyTutorError::DBError(_)
+            | EzyTutorError::ActixError(_)
+            | EzyTutorError::StorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            EzyTutorError::NotFound(_) => StatusCode::NOT_FOUND,
+            EzyTutorError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            EzyTutorError::Forbidden(_) => StatusCode::FORBIDDEN,
+            EzyTutorError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            EzyTutorError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            EzyTutorError::TooManyRequests(..) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-        HttpResponse::build(self.status_code()).json(MyErrorResponse {
-            error_message: self.error_response(),
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            EzyTutorError::ServiceUnavailable(_) => {
+                response.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS));
+            }
+            EzyTutorError::TooManyRequests(_, retry_after) => {
+                response.insert_header((header::RETRY_AFTER, *retry_after));
+            }
+            _ => {}
+        }
+        self.log();
+        response.json(MyErrorResponse {
+            error_message: self.client_message(),
+            request_id: current_request_id(),
         })
     }
 }
 
+//Status codes of the gRPC API, matching the HTTP ones
+impl From<EzyTutorError> for tonic::Status {
+    fn from(err: EzyTutorError) -> Self {
+        err.log();
+        let message = err.client_message();
+        match err {
+            EzyTutorError::DBError(_)
+            | EzyTutorError::ActixError(_)
+            | EzyTutorError::StorageError(_) => tonic::Status::internal(message),
+            EzyTutorError::NotFound(_) => tonic::Status::not_found(message),
+            EzyTutorError::InvalidInput(_) => tonic::Status::invalid_argument(message),
+            EzyTutorError::Forbidden(_) => tonic::Status::permission_denied(message),
+            EzyTutorError::PayloadTooLarge(_) | EzyTutorError::TooManyRequests(..) => {
+                tonic::Status::resource_exhausted(message)
+            }
+            EzyTutorError::ServiceUnavailable(_) => tonic::Status::unavailable(message),
+        }
+    }
+}
+
 impl fmt::Display for EzyTutorError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            EzyTutorError::ActixError(s) => write!(f,"Actix Error {}",s),
-            EzyTutorError::DBError(s) => write!(f,"Database Error {}",s),
-            EzyTutorError::NotFound(s) => write!(f,"Error: Not Found {}",s),
-            EzyTutorError::InvalidInput(s) => write!(f,"Invalid Input Error {}",s)
+            EzyTutorError::ActixError(s) => write!(f, "Actix Error {}", s),
+            EzyTutorError::DBError(s) => write!(f, "Database Error {}", s),
+            EzyTutorError::NotFound(s) => write!(f, "Error: Not Found {}", s),
+            EzyTutorError::InvalidInput(s) => write!(f, "Invalid Input Error {}", s),
+            EzyTutorError::Forbidden(s) => write!(f, "Forbidden Error {}", s),
+            EzyTutorError::PayloadTooLarge(s) => write!(f, "Payload Too Large Error {}", s),
+            EzyTutorError::StorageError(s) => write!(f, "Storage Error {}", s),
+            EzyTutorError::ServiceUnavailable(s) => write!(f, "Service Unavailable {}", s),
+            EzyTutorError::TooManyRequests(s, _) => write!(f, "Too Many Requests {}", s),
         }
         //write!(f, "{}", self)
     }
@@ -75,13 +145,34 @@ impl From<actix_web::error::Error> for EzyTutorError {
     }
 }
 
+impl From<actix_multipart::MultipartError> for EzyTutorError {
+    fn from(err: actix_multipart::MultipartError) -> Self {
+        EzyTutorError::InvalidInput(format!("invalid multipart upload: {}", err))
+    }
+}
+
+//Postgres error code of a statement cancelled by statement_timeout
+const QUERY_CANCELED: &str = "57014";
+
 impl From<SQLxError> for EzyTutorError {
     fn from(err: SQLxError) -> Self {
-        EzyTutorError::DBError(err.to_string())
+        match &err {
+            //The database is down or overloaded, the request may succeed later
+            SQLxError::PoolTimedOut | SQLxError::PoolClosed | SQLxError::Io(_) => {
+                EzyTutorError::ServiceUnavailable(err.to_string())
+            }
+            SQLxError::Database(db_err) if db_err.code().as_deref() == Some(QUERY_CANCELED) => {
+                EzyTutorError::ServiceUnavailable(err.to_string())
+            }
+            _ => EzyTutorError::DBError(err.to_string()),
+        }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct MyErrorResponse {
     error_message: String,
+    //Same value as the X-Request-Id response header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
@@ -0,0 +1,276 @@
+use crate::errors::EzyTutorError;
+use crate::models::course::Course;
+use crate::models::export::ExportFormat;
+use crate::models::tutor::Tutor;
+use actix_web::web::Bytes;
+use rust_xlsxwriter::Workbook;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Seek;
+
+const XLSX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+//Rows of an XLSX sheet, less the header row
+const XLSX_MAX_ROWS: i64 = 1_048_575;
+
+pub enum ExportValue {
+    Int(i64),
+    Text(String),
+    Null,
+}
+
+//A record that can be exported. JSON Lines uses the serde representation,
+//the tabular formats use HEADERS and values() so the column order is stable.
+pub trait ExportRow: Serialize {
+    const HEADERS: &'static [&'static str];
+    fn values(&self) -> Vec<ExportValue>;
+}
+
+fn text(value: &Option<String>) -> ExportValue {
+    value
+        .as_ref()
+        .map(|value| ExportValue::Text(value.clone()))
+        .unwrap_or(ExportValue::Null)
+}
+
+impl ExportRow for Course {
+    const HEADERS: &'static [&'static str] = &[
+        "course_id",
+        "tutor_id",
+        "course_name",
+        "course_description",
+        "course_format",
+        "course_structure",
+        "course_duration",
+        "course_price",
+        "course_language",
+        "course_level",
+        "posted_time",
+    ];
+
+    fn values(&self) -> Vec<ExportValue> {
+        vec![
+            ExportValue::Int(self.course_id.into()),
+            ExportValue::Int(self.tutor_id.into()),
+            ExportValue::Text(self.course_name.clone()),
+            text(&self.course_description),
+            text(&self.course_format),
+            text(&self.course_structure),
+            text(&self.course_duration),
+            self.course_price
+                .map(|price| ExportValue::Int(price.into()))
+                .unwrap_or(ExportValue::Null),
+            text(&self.course_language),
+            text(&self.course_level),
+            text(&self.posted_time.map(|time| time.to_string())),
+        ]
+    }
+}
+
+impl ExportRow for Tutor {
+    const HEADERS: &'static [&'static str] =
+        &["tutor_id", "tutor_name", "tutor_pic_url", "tutor_profile"];
+
+    fn values(&self) -> Vec<ExportValue> {
+        vec![
+            ExportValue::Int(self.tutor_id.into()),
+            ExportValue::Text(self.tutor_name.clone()),
+            ExportValue::Text(self.tutor_pic_url.clone()),
+            ExportValue::Text(self.tutor_profile.clone()),
+        ]
+    }
+}
+
+impl ExportFormat {
+    //An explicit format parameter wins, otherwise the first supported Accept entry
+    pub fn negotiate(explicit: Option<ExportFormat>, accept: Option<&str>) -> ExportFormat {
+        if let Some(format) = explicit {
+            return format;
+        }
+        accept
+            .into_iter()
+            .flat_map(|accept| accept.split(','))
+            .filter_map(|entry| entry.split(';').next())
+            .find_map(|media_type| match media_type.trim() {
+                "text/csv" | "text/*" | "*/*" => Some(ExportFormat::Csv),
+                "application/x-ndjson" | "application/jsonl" | "application/json-lines" => {
+                    Some(ExportFormat::Jsonl)
+                }
+                XLSX_CONTENT_TYPE => Some(ExportFormat::Xlsx),
+                _ => None,
+            })
+            .unwrap_or(ExportFormat::Csv)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Xlsx => XLSX_CONTENT_TYPE,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+//Checked before the response starts, a sheet that overflows halfway could
+//only be cut off
+pub fn check_xlsx_rows(rows: i64) -> Result<(), EzyTutorError> {
+    if rows > XLSX_MAX_ROWS {
+        return Err(EzyTutorError::PayloadTooLarge(format!(
+            "the export has {} rows, an XLSX sheet holds at most {}, please use csv or jsonl",
+            rows, XLSX_MAX_ROWS
+        )));
+    }
+    Ok(())
+}
+
+pub enum ExportEncoder {
+    Csv {
+        wrote_headers: bool,
+    },
+    Jsonl,
+    //xlsx is a zip archive, rows are spooled to temp files by the
+    //constant memory worksheet and the archive is assembled at the end
+    Xlsx {
+        workbook: Box<Workbook>,
+        next_row: u32,
+    },
+}
+
+pub enum ExportTail {
+    Bytes(Bytes),
+    File(File),
+}
+
+fn export_error(err: impl std::fmt::Display) -> EzyTutorError {
+    EzyTutorError::ActixError(format!("export failed: {}", err))
+}
+
+impl ExportEncoder {
+    pub fn new(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Csv => ExportEncoder::Csv {
+                wrote_headers: false,
+            },
+            ExportFormat::Jsonl => ExportEncoder::Jsonl,
+            ExportFormat::Xlsx => {
+                let mut workbook = Workbook::new();
+                workbook.add_worksheet_with_constant_memory();
+                ExportEncoder::Xlsx {
+                    workbook: Box::new(workbook),
+                    next_row: 0,
+                }
+            }
+        }
+    }
+
+    //Returns the bytes that can be sent right away, if any
+    pub fn encode_batch<T: ExportRow>(
+        &mut self,
+        rows: &[T],
+    ) -> Result<Option<Bytes>, EzyTutorError> {
+        match self {
+            ExportEncoder::Csv { wrote_headers } => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                if !*wrote_headers {
+                    writer.write_record(T::HEADERS).map_err(export_error)?;
+                    *wrote_headers = true;
+                }
+                for row in rows {
+                    writer
+                        .write_record(row.values().into_iter().map(|value| match value {
+                            ExportValue::Int(value) => value.to_string(),
+                            ExportValue::Text(value) => value,
+                            ExportValue::Null => String::new(),
+                        }))
+                        .map_err(export_error)?;
+                }
+                let data = writer.into_inner().map_err(export_error)?;
+                Ok(Some(Bytes::from(data)))
+            }
+            ExportEncoder::Jsonl => {
+                let mut data = Vec::new();
+                for row in rows {
+                    serde_json::to_writer(&mut data, row).map_err(export_error)?;
+                    data.push(b'\n');
+                }
+                Ok(Some(Bytes::from(data)))
+            }
+            ExportEncoder::Xlsx { workbook, next_row } => {
+                let worksheet = workbook.worksheet_from_index(0).map_err(export_error)?;
+                if *next_row == 0 {
+                    for (column, header) in T::HEADERS.iter().enumerate() {
+                        worksheet
+                            .write_string(0, column as u16, *header)
+                            .map_err(export_error)?;
+                    }
+                    *next_row = 1;
+                }
+                for row in rows {
+                    for (column, value) in row.values().into_iter().enumerate() {
+                        match value {
+                            ExportValue::Int(value) => {
+                                worksheet.write_number(*next_row, column as u16, value as f64)
+                            }
+                            ExportValue::Text(value) => {
+                                worksheet.write_string(*next_row, column as u16, value)
+                            }
+                            ExportValue::Null => continue,
+                        }
+                        .map_err(export_error)?;
+                    }
+                    *next_row += 1;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    //Whatever is left after the last batch, headers of an empty csv export
+    //or the assembled xlsx file
+    pub fn finish<T: ExportRow>(mut self) -> Result<ExportTail, EzyTutorError> {
+        match &mut self {
+            ExportEncoder::Csv {
+                wrote_headers: false,
+            }
+            | ExportEncoder::Xlsx { next_row: 0, .. } => {
+                let headers = self.encode_batch::<T>(&[])?;
+                self.finish_written(headers)
+            }
+            _ => self.finish_written(None),
+        }
+    }
+
+    fn finish_written(self, pending: Option<Bytes>) -> Result<ExportTail, EzyTutorError> {
+        match self {
+            ExportEncoder::Xlsx { mut workbook, .. } => {
+                let mut file = tempfile::tempfile().map_err(export_error)?;
+                workbook.save_to_writer(&mut file).map_err(export_error)?;
+                file.rewind().map_err(export_error)?;
+                Ok(ExportTail::File(file))
+            }
+            _ => Ok(ExportTail::Bytes(pending.unwrap_or_default())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xlsx_exports_fit_one_sheet() {
+        assert!(check_xlsx_rows(0).is_ok());
+        assert!(check_xlsx_rows(XLSX_MAX_ROWS).is_ok());
+        assert!(matches!(
+            check_xlsx_rows(XLSX_MAX_ROWS + 1),
+            Err(EzyTutorError::PayloadTooLarge(_))
+        ));
+    }
+}
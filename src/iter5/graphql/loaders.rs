@@ -0,0 +1,70 @@
+use super::resolvers::{client_error, RequestContext};
+use crate::dbaccess::course::get_courses_for_tutors_db;
+use crate::dbaccess::tutor::get_tutors_by_ids_db;
+use crate::models::course::Course;
+use crate::models::tutor::Tutor;
+use async_graphql::dataloader::{DataLoader, Loader};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+//Relations of all objects of a response are collected by the loaders and
+//read with one query per relation. Loaders live for one request, so they
+//read with its consistency and cache nothing.
+
+//Courses of tutors, by tutor id
+pub struct CoursesByTutor(pub Arc<RequestContext>);
+
+impl Loader<i32> for CoursesByTutor {
+    type Value = Vec<Course>;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, tutor_ids: &[i32]) -> Result<HashMap<i32, Vec<Course>>, Self::Error> {
+        let courses = self
+            .0
+            .read(|pool| async move { get_courses_for_tutors_db(&pool, tutor_ids).await })
+            .await
+            .map_err(client_error)?;
+        let mut by_tutor: HashMap<i32, Vec<Course>> = HashMap::new();
+        for course in courses {
+            by_tutor.entry(course.tutor_id).or_default().push(course);
+        }
+        Ok(by_tutor)
+    }
+}
+
+//Tutors by id, for the tutor of a course
+pub struct TutorsById(pub Arc<RequestContext>);
+
+impl Loader<i32> for TutorsById {
+    type Value = Tutor;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, tutor_ids: &[i32]) -> Result<HashMap<i32, Tutor>, Self::Error> {
+        let tutors = self
+            .0
+            .read(|pool| async move { get_tutors_by_ids_db(&pool, tutor_ids).await })
+            .await
+            .map_err(client_error)?;
+        Ok(tutors
+            .into_iter()
+            .map(|tutor| (tutor.tutor_id, tutor))
+            .collect())
+    }
+}
+
+//The context of a request and fresh loaders for it
+pub fn with_request_data(
+    request: async_graphql::Request,
+    context: Arc<RequestContext>,
+) -> async_graphql::Request {
+    request
+        .data(DataLoader::new(
+            CoursesByTutor(context.clone()),
+            actix_rt::spawn,
+        ))
+        .data(DataLoader::new(
+            TutorsById(context.clone()),
+            actix_rt::spawn,
+        ))
+        .data(context)
+}
@@ -0,0 +1,137 @@
+pub mod loaders;
+pub mod resolvers;
+
+use crate::config::GraphqlConfig;
+use async_graphql::parser::types::OperationType;
+use async_graphql::{EmptySubscription, Request, Response, Schema, ServerError};
+use resolvers::{MutationRoot, QueryRoot, RequestContext};
+use std::sync::Arc;
+
+pub type EzySchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+//The schema and the limits checked before a document is parsed
+pub struct Graphql {
+    pub schema: EzySchema,
+    max_nesting: usize,
+}
+
+impl Graphql {
+    //Depth and complexity are checked before anything executes, a query over
+    //them costs no database query. Arguments and input values nest as well,
+    //so the parser gets twice the depth of selection sets.
+    pub fn from_config(config: &GraphqlConfig) -> Self {
+        Graphql {
+            schema: Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+                .limit_depth(config.max_depth)
+                .limit_complexity(config.max_complexity)
+                .finish(),
+            max_nesting: 2 * config.max_depth,
+        }
+    }
+
+    //Mutations are only run when allowed, GET requests must not change anything
+    pub async fn execute(
+        &self,
+        mut request: Request,
+        context: Arc<RequestContext>,
+        allow_mutations: bool,
+    ) -> Response {
+        if nesting_depth(&request.query) > self.max_nesting {
+            return refuse(format!(
+                "query is nested deeper than {} levels",
+                self.max_nesting
+            ));
+        }
+        if !allow_mutations {
+            let operation_name = request.operation_name.clone();
+            let document = match request.parsed_query() {
+                Ok(document) => document,
+                Err(err) => return Response::from_errors(vec![err]),
+            };
+            let mutation = document.operations.iter().any(|(name, operation)| {
+                operation.node.ty == OperationType::Mutation
+                    && (operation_name.is_none()
+                        || name.map(|name| name.as_str()) == operation_name.as_deref())
+            });
+            if mutation {
+                return refuse("mutations must be sent with POST".to_string());
+            }
+        }
+        let request = loaders::with_request_data(request, context);
+        self.schema.execute(request).await
+    }
+}
+
+fn refuse(message: String) -> Response {
+    Response::from_errors(vec![ServerError::new(message, None)])
+}
+
+//Deepest nesting of braces, brackets and parentheses outside of strings and
+//comments. Found without recursion, so documents nested too deeply for the
+//limits can be refused before they are parsed.
+pub fn nesting_depth(query: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut deepest = 0;
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '[' | '(' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            '}' | ']' | ')' => depth = depth.saturating_sub(1),
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' || c == '\r' {
+                        break;
+                    }
+                }
+            }
+            //Block strings end at the next """, other strings at an unescaped "
+            '"' if chars.peek() == Some(&'"') => {
+                chars.next();
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    let mut quotes = 0;
+                    for c in chars.by_ref() {
+                        quotes = if c == '"' { quotes + 1 } else { 0 };
+                        if quotes == 3 {
+                            break;
+                        }
+                    }
+                }
+            }
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' | '\n' => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    deepest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nesting_skips_strings_and_comments() {
+        assert_eq!(nesting_depth("{ tutors { nodes { id } } }"), 3);
+        assert_eq!(
+            nesting_depth("mutation { createCourse(input: {name: \"{{{\\\"{{\"}) { id } }"),
+            3
+        );
+        assert_eq!(nesting_depth("{ a # {{{{\n { b } }"), 2);
+        assert_eq!(nesting_depth("{ a(s: \"\"\" {{{ \" \"\" \"\"\") }"), 2);
+        assert_eq!(nesting_depth(&"{a".repeat(20_000)), 20_000);
+        assert_eq!(nesting_depth("}}} { }"), 1);
+    }
+}
@@ -0,0 +1,524 @@
+use super::loaders::{CoursesByTutor, TutorsById};
+use crate::consistency::Consistency;
+use crate::dbaccess::course::*;
+use crate::dbaccess::tutor::*;
+use crate::errors::EzyTutorError;
+use crate::models::course::{Course, CourseFilter, CreateCourse, UpdateCourse};
+use crate::models::tutor::{NewTutor, Tutor, UpdateTutor};
+use crate::state::AppState;
+use actix_web::web;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{Context, InputObject, Object, SimpleObject};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+//Largest page a client may ask for
+pub const MAX_PAGE_SIZE: i32 = 100;
+
+//Errors carry the message the REST API would answer with. Never convert with
+//`?` alone, that would show the internal message of database errors.
+pub fn client_error(err: EzyTutorError) -> async_graphql::Error {
+    err.log();
+    async_graphql::Error::new(err.client_message())
+}
+
+//A page costs its fields once per requested item
+fn page_complexity(first: i32, child_complexity: usize) -> usize {
+    first.clamp(0, MAX_PAGE_SIZE) as usize * child_complexity + 1
+}
+
+//Reads go through AppState::read like the REST handlers but skip the cache,
+//its entries don't fit paged and filtered reads
+pub struct RequestContext {
+    state: web::Data<AppState>,
+    consistency: Consistency,
+    //Set by mutations, later fields of the request have to see the write
+    wrote: AtomicBool,
+}
+
+impl RequestContext {
+    pub fn new(state: web::Data<AppState>, consistency: Consistency) -> Arc<Self> {
+        Arc::new(RequestContext {
+            state,
+            consistency,
+            wrote: AtomicBool::new(false),
+        })
+    }
+
+    pub async fn read<T, F, Fut>(&self, query: F) -> Result<T, EzyTutorError>
+    where
+        F: Fn(sqlx::PgPool) -> Fut,
+        Fut: Future<Output = Result<T, EzyTutorError>>,
+    {
+        let consistency = if self.wrote.load(Ordering::Relaxed) {
+            Consistency::ReadYourWrites
+        } else {
+            self.consistency
+        };
+        self.state.read(consistency, query).await
+    }
+
+    fn wrote(&self) {
+        self.wrote.store(true, Ordering::Relaxed);
+    }
+}
+
+fn request<'a>(ctx: &Context<'a>) -> &'a Arc<RequestContext> {
+    ctx.data_unchecked::<Arc<RequestContext>>()
+}
+
+#[derive(SimpleObject)]
+pub struct PageInfo {
+    /// Pass as after to get the next page
+    end_cursor: Option<String>,
+    has_next_page: bool,
+}
+
+#[derive(SimpleObject)]
+pub struct TutorConnection {
+    nodes: Vec<TutorNode>,
+    page_info: PageInfo,
+}
+
+#[derive(SimpleObject)]
+pub struct CourseConnection {
+    nodes: Vec<CourseNode>,
+    page_info: PageInfo,
+}
+
+//Cursors are the id of the last row of a page
+fn cursor(after: Option<String>) -> Result<Option<i32>, EzyTutorError> {
+    after
+        .map(|cursor| {
+            cursor
+                .parse()
+                .map_err(|_| EzyTutorError::InvalidInput(format!("invalid cursor {:?}", cursor)))
+        })
+        .transpose()
+}
+
+//Rows were fetched with one more than the page size
+fn page<T>(mut rows: Vec<T>, first: i32, id: impl Fn(&T) -> i32) -> (Vec<T>, PageInfo) {
+    let has_next_page = rows.len() > first as usize;
+    rows.truncate(first as usize);
+    let end_cursor = rows.last().map(|row| id(row).to_string());
+    (
+        rows,
+        PageInfo {
+            end_cursor,
+            has_next_page,
+        },
+    )
+}
+
+//A missing single object is null, not an error
+fn found<T>(result: Result<T, EzyTutorError>) -> async_graphql::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(EzyTutorError::NotFound(_)) => Ok(None),
+        Err(err) => Err(client_error(err)),
+    }
+}
+
+#[derive(Default)]
+pub struct QueryRoot;
+
+/// Reads of tutors and their courses
+#[Object]
+impl QueryRoot {
+    /// Tutors ordered by id
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn tutors(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 20,
+            validator(minimum = 0, maximum = 100),
+            desc = "Page size, at most 100"
+        )]
+        first: i32,
+        #[graphql(desc = "endCursor of the previous page")] after: Option<String>,
+        #[graphql(desc = "Case-insensitive part of the name")] name_contains: Option<String>,
+    ) -> async_graphql::Result<TutorConnection> {
+        let after = cursor(after).map_err(client_error)?;
+        let tutors = request(ctx)
+            .read(|pool| {
+                let name = name_contains.clone();
+                async move {
+                    get_tutors_page_db(&pool, name.as_deref(), after, i64::from(first) + 1).await
+                }
+            })
+            .await
+            .map_err(client_error)?;
+        let (tutors, page_info) = page(tutors, first, |tutor| tutor.tutor_id);
+        Ok(TutorConnection {
+            nodes: tutors.into_iter().map(TutorNode).collect(),
+            page_info,
+        })
+    }
+
+    async fn tutor(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Option<TutorNode>> {
+        let tutor = request(ctx)
+            .read(|pool| async move { get_tutor_details_db(&pool, id).await })
+            .await;
+        Ok(found(tutor)?.map(TutorNode))
+    }
+
+    /// Courses of all tutors ordered by id
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    #[allow(clippy::too_many_arguments)]
+    async fn courses(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 20,
+            validator(minimum = 0, maximum = 100),
+            desc = "Page size, at most 100"
+        )]
+        first: i32,
+        #[graphql(desc = "endCursor of the previous page")] after: Option<String>,
+        tutor_id: Option<i32>,
+        language: Option<String>,
+        #[graphql(desc = "Case-insensitive, like Beginner")] level: Option<String>,
+        #[graphql(desc = "Case-insensitive part of the name")] name_contains: Option<String>,
+    ) -> async_graphql::Result<CourseConnection> {
+        let after = cursor(after).map_err(client_error)?;
+        let filter = CourseFilter {
+            tutor_id,
+            course_name: name_contains,
+            course_level: level,
+            course_language: language,
+            ..CourseFilter::default()
+        };
+        let courses = request(ctx)
+            .read(|pool| {
+                let filter = filter.clone();
+                async move { get_courses_page_db(&pool, &filter, after, i64::from(first) + 1).await }
+            })
+            .await
+            .map_err(client_error)?;
+        let (courses, page_info) = page(courses, first, |course| course.course_id);
+        Ok(CourseConnection {
+            nodes: courses.into_iter().map(CourseNode).collect(),
+            page_info,
+        })
+    }
+
+    async fn course(
+        &self,
+        ctx: &Context<'_>,
+        tutor_id: i32,
+        id: i32,
+    ) -> async_graphql::Result<Option<CourseNode>> {
+        let course = request(ctx)
+            .read(|pool| async move { get_course_details_db(&pool, tutor_id, id).await })
+            .await;
+        Ok(found(course)?.map(CourseNode))
+    }
+}
+
+#[derive(Default)]
+pub struct MutationRoot;
+
+//Same writes and cache invalidations as the REST handlers
+/// Writes, the same as the REST endpoints
+#[Object]
+impl MutationRoot {
+    async fn create_tutor(
+        &self,
+        ctx: &Context<'_>,
+        input: NewTutorInput,
+    ) -> async_graphql::Result<TutorNode> {
+        let request = request(ctx);
+        let tutor = post_new_tutor_db(&request.state.db, input.into())
+            .await
+            .map_err(client_error)?;
+        request.wrote();
+        Ok(TutorNode(tutor))
+    }
+
+    async fn update_tutor(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        input: UpdateTutorInput,
+    ) -> async_graphql::Result<TutorNode> {
+        let request = request(ctx);
+        let tutor = update_tutor_details_db(&request.state.db, id, input.into())
+            .await
+            .map_err(client_error)?;
+        request.wrote();
+        Ok(TutorNode(tutor))
+    }
+
+    /// Deletes the tutor with all their courses, true once they are gone
+    async fn delete_tutor(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<bool> {
+        let request = request(ctx);
+        delete_tutor_db(&request.state.db, id)
+            .await
+            .map_err(client_error)?;
+        request.wrote();
+        Ok(true)
+    }
+
+    async fn create_course(
+        &self,
+        ctx: &Context<'_>,
+        input: NewCourseInput,
+    ) -> async_graphql::Result<CourseNode> {
+        let request = request(ctx);
+        let course = CreateCourse::from(input);
+        course
+            .validate()
+            .map_err(|err| client_error(EzyTutorError::InvalidInput(err)))?;
+        let course = post_new_course_db(&request.state.db, course)
+            .await
+            .map_err(client_error)?;
+        request.wrote();
+        Ok(CourseNode(course))
+    }
+
+    async fn update_course(
+        &self,
+        ctx: &Context<'_>,
+        tutor_id: i32,
+        id: i32,
+        input: UpdateCourseInput,
+    ) -> async_graphql::Result<CourseNode> {
+        let request = request(ctx);
+        let course = update_course_details_db(&request.state.db, tutor_id, id, input.into())
+            .await
+            .map_err(client_error)?;
+        request.wrote();
+        Ok(CourseNode(course))
+    }
+
+    /// True once the course is gone
+    async fn delete_course(
+        &self,
+        ctx: &Context<'_>,
+        tutor_id: i32,
+        id: i32,
+    ) -> async_graphql::Result<bool> {
+        let request = request(ctx);
+        delete_course_db(&request.state.db, tutor_id, id)
+            .await
+            .map_err(client_error)?;
+        request.wrote();
+        Ok(true)
+    }
+}
+
+pub struct TutorNode(Tutor);
+
+#[Object(name = "Tutor")]
+impl TutorNode {
+    async fn id(&self) -> i32 {
+        self.0.tutor_id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.tutor_name
+    }
+
+    async fn picture_url(&self) -> &str {
+        &self.0.tutor_pic_url
+    }
+
+    async fn profile(&self) -> &str {
+        &self.0.tutor_profile
+    }
+
+    /// Loaded for all tutors of a response with one query
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn courses(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 50,
+            validator(minimum = 0, maximum = 100),
+            desc = "At most 100"
+        )]
+        first: i32,
+        #[graphql(desc = "Case-insensitive, like Beginner")] level: Option<String>,
+    ) -> async_graphql::Result<Vec<CourseNode>> {
+        let courses = ctx
+            .data_unchecked::<DataLoader<CoursesByTutor>>()
+            .load_one(self.0.tutor_id)
+            .await?
+            .unwrap_or_default();
+        let level = level.map(|level| level.to_lowercase());
+        Ok(courses
+            .into_iter()
+            .filter(|course| {
+                level.as_ref().is_none_or(|level| {
+                    course
+                        .course_level
+                        .as_ref()
+                        .map(|l| l.to_lowercase())
+                        .as_ref()
+                        == Some(level)
+                })
+            })
+            .take(first as usize)
+            .map(CourseNode)
+            .collect())
+    }
+}
+
+pub struct CourseNode(Course);
+
+#[Object(name = "Course")]
+impl CourseNode {
+    async fn id(&self) -> i32 {
+        self.0.course_id
+    }
+
+    async fn tutor_id(&self) -> i32 {
+        self.0.tutor_id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.course_name
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.course_description.as_deref()
+    }
+
+    async fn format(&self) -> Option<&str> {
+        self.0.course_format.as_deref()
+    }
+
+    async fn structure(&self) -> Option<&str> {
+        self.0.course_structure.as_deref()
+    }
+
+    async fn duration(&self) -> Option<&str> {
+        self.0.course_duration.as_deref()
+    }
+
+    async fn price(&self) -> Option<i32> {
+        self.0.course_price
+    }
+
+    async fn language(&self) -> Option<&str> {
+        self.0.course_language.as_deref()
+    }
+
+    async fn level(&self) -> Option<&str> {
+        self.0.course_level.as_deref()
+    }
+
+    //Formatted like serde formats it for the REST API
+    /// ISO 8601 without a time zone, like the REST API
+    async fn posted_time(&self) -> Option<String> {
+        self.0
+            .posted_time
+            .map(|time| time.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+    }
+
+    /// Loaded for all courses of a response with one query
+    async fn tutor(&self, ctx: &Context<'_>) -> async_graphql::Result<TutorNode> {
+        let tutor = ctx
+            .data_unchecked::<DataLoader<TutorsById>>()
+            .load_one(self.0.tutor_id)
+            .await?;
+        tutor
+            .map(TutorNode)
+            .ok_or_else(|| client_error(EzyTutorError::NotFound("Tutor id not found".into())))
+    }
+}
+
+#[derive(InputObject)]
+pub struct NewTutorInput {
+    name: String,
+    picture_url: String,
+    profile: String,
+}
+
+impl From<NewTutorInput> for NewTutor {
+    fn from(tutor: NewTutorInput) -> Self {
+        NewTutor {
+            tutor_name: tutor.name,
+            tutor_pic_url: tutor.picture_url,
+            tutor_profile: tutor.profile,
+        }
+    }
+}
+
+/// Fields left out keep their value
+#[derive(InputObject)]
+pub struct UpdateTutorInput {
+    name: Option<String>,
+    picture_url: Option<String>,
+    profile: Option<String>,
+}
+
+impl From<UpdateTutorInput> for UpdateTutor {
+    fn from(tutor: UpdateTutorInput) -> Self {
+        UpdateTutor {
+            tutor_name: tutor.name,
+            tutor_pic_url: tutor.picture_url,
+            tutor_profile: tutor.profile,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct NewCourseInput {
+    tutor_id: i32,
+    name: String,
+    description: Option<String>,
+    format: Option<String>,
+    structure: Option<String>,
+    duration: Option<String>,
+    price: Option<i32>,
+    language: Option<String>,
+    level: Option<String>,
+}
+
+impl From<NewCourseInput> for CreateCourse {
+    fn from(course: NewCourseInput) -> Self {
+        CreateCourse {
+            tutor_id: course.tutor_id,
+            course_name: course.name,
+            course_description: course.description,
+            course_format: course.format,
+            course_structure: course.structure,
+            course_duration: course.duration,
+            course_price: course.price,
+            course_language: course.language,
+            course_level: course.level,
+        }
+    }
+}
+
+/// Fields left out keep their value
+#[derive(InputObject)]
+pub struct UpdateCourseInput {
+    name: Option<String>,
+    description: Option<String>,
+    format: Option<String>,
+    structure: Option<String>,
+    duration: Option<String>,
+    price: Option<i32>,
+    language: Option<String>,
+    level: Option<String>,
+}
+
+impl From<UpdateCourseInput> for UpdateCourse {
+    fn from(course: UpdateCourseInput) -> Self {
+        UpdateCourse {
+            course_name: course.name,
+            course_description: course.description,
+            course_format: course.format,
+            course_structure: course.structure,
+            course_duration: course.duration,
+            course_price: course.price,
+            course_language: course.language,
+            course_level: course.level,
+        }
+    }
+}
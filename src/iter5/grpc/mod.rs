@@ -0,0 +1,28 @@
+pub mod proto;
+pub mod service;
+
+use crate::state::AppState;
+use actix_web::web;
+use proto::course_service_server::CourseServiceServer;
+use proto::tutor_service_server::TutorServiceServer;
+use service::GrpcApi;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+
+//Serves both services until shutdown resolves, then waits for the calls in
+//flight. It runs on the runtime of the caller, next to the actix workers.
+pub async fn serve(
+    listener: TcpListener,
+    state: web::Data<AppState>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    let api = Arc::new(GrpcApi::new(state));
+    tonic::transport::Server::builder()
+        .trace_fn(|request| tracing::info_span!("grpc", method = %request.uri().path()))
+        .add_service(TutorServiceServer::from_arc(api.clone()))
+        .add_service(CourseServiceServer::from_arc(api))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+        .await
+}
@@ -0,0 +1,263 @@
+use crate::models::course::{
+    Course as CourseModel, CourseFilter, CreateCourse, UpdateCourse as UpdateCourseModel,
+};
+use crate::models::tutor::{
+    NewTutor as NewTutorModel, Tutor as TutorModel, UpdateTutor as UpdateTutorModel,
+};
+
+//Messages of ezytutors.proto, tags and types have to match it. The service
+//stubs are generated by build.rs.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Tutor {
+    #[prost(int32, tag = "1")]
+    pub tutor_id: i32,
+    #[prost(string, tag = "2")]
+    pub tutor_name: String,
+    #[prost(string, tag = "3")]
+    pub tutor_pic_url: String,
+    #[prost(string, tag = "4")]
+    pub tutor_profile: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NewTutor {
+    #[prost(string, tag = "1")]
+    pub tutor_name: String,
+    #[prost(string, tag = "2")]
+    pub tutor_pic_url: String,
+    #[prost(string, tag = "3")]
+    pub tutor_profile: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpdateTutor {
+    #[prost(int32, tag = "1")]
+    pub tutor_id: i32,
+    #[prost(string, optional, tag = "2")]
+    pub tutor_name: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub tutor_pic_url: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub tutor_profile: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TutorId {
+    #[prost(int32, tag = "1")]
+    pub tutor_id: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListTutorsRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TutorList {
+    #[prost(message, repeated, tag = "1")]
+    pub tutors: Vec<Tutor>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Course {
+    #[prost(int32, tag = "1")]
+    pub course_id: i32,
+    #[prost(int32, tag = "2")]
+    pub tutor_id: i32,
+    #[prost(string, tag = "3")]
+    pub course_name: String,
+    #[prost(string, optional, tag = "4")]
+    pub course_description: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub course_format: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub course_structure: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    pub course_duration: Option<String>,
+    #[prost(int32, optional, tag = "8")]
+    pub course_price: Option<i32>,
+    #[prost(string, optional, tag = "9")]
+    pub course_language: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub course_level: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub posted_time: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NewCourse {
+    #[prost(int32, tag = "1")]
+    pub tutor_id: i32,
+    #[prost(string, tag = "2")]
+    pub course_name: String,
+    #[prost(string, optional, tag = "3")]
+    pub course_description: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub course_format: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub course_structure: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub course_duration: Option<String>,
+    #[prost(int32, optional, tag = "7")]
+    pub course_price: Option<i32>,
+    #[prost(string, optional, tag = "8")]
+    pub course_language: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub course_level: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpdateCourse {
+    #[prost(int32, tag = "1")]
+    pub tutor_id: i32,
+    #[prost(int32, tag = "2")]
+    pub course_id: i32,
+    #[prost(string, optional, tag = "3")]
+    pub course_name: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub course_description: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub course_format: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub course_structure: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    pub course_duration: Option<String>,
+    #[prost(int32, optional, tag = "8")]
+    pub course_price: Option<i32>,
+    #[prost(string, optional, tag = "9")]
+    pub course_language: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub course_level: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CourseId {
+    #[prost(int32, tag = "1")]
+    pub tutor_id: i32,
+    #[prost(int32, tag = "2")]
+    pub course_id: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CatalogueRequest {
+    #[prost(int32, optional, tag = "1")]
+    pub tutor_id: Option<i32>,
+    #[prost(string, optional, tag = "2")]
+    pub course_name: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub course_level: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub course_language: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub course_format: Option<String>,
+    #[prost(int32, optional, tag = "6")]
+    pub min_price: Option<i32>,
+    #[prost(int32, optional, tag = "7")]
+    pub max_price: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Deleted {
+    #[prost(string, tag = "1")]
+    pub message: String,
+}
+
+include!(concat!(env!("OUT_DIR"), "/ezytutors.v1.TutorService.rs"));
+include!(concat!(env!("OUT_DIR"), "/ezytutors.v1.CourseService.rs"));
+
+impl From<TutorModel> for Tutor {
+    fn from(tutor: TutorModel) -> Self {
+        Tutor {
+            tutor_id: tutor.tutor_id,
+            tutor_name: tutor.tutor_name,
+            tutor_pic_url: tutor.tutor_pic_url,
+            tutor_profile: tutor.tutor_profile,
+        }
+    }
+}
+
+impl From<NewTutor> for NewTutorModel {
+    fn from(tutor: NewTutor) -> Self {
+        NewTutorModel {
+            tutor_name: tutor.tutor_name,
+            tutor_pic_url: tutor.tutor_pic_url,
+            tutor_profile: tutor.tutor_profile,
+        }
+    }
+}
+
+impl From<UpdateTutor> for UpdateTutorModel {
+    fn from(tutor: UpdateTutor) -> Self {
+        UpdateTutorModel {
+            tutor_name: tutor.tutor_name,
+            tutor_pic_url: tutor.tutor_pic_url,
+            tutor_profile: tutor.tutor_profile,
+        }
+    }
+}
+
+impl From<CourseModel> for Course {
+    fn from(course: CourseModel) -> Self {
+        Course {
+            course_id: course.course_id,
+            tutor_id: course.tutor_id,
+            course_name: course.course_name,
+            course_description: course.course_description,
+            course_format: course.course_format,
+            course_structure: course.course_structure,
+            course_duration: course.course_duration,
+            course_price: course.course_price,
+            course_language: course.course_language,
+            course_level: course.course_level,
+            //Formatted like serde formats it for the REST API
+            posted_time: course
+                .posted_time
+                .map(|time| time.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+        }
+    }
+}
+
+impl From<NewCourse> for CreateCourse {
+    fn from(course: NewCourse) -> Self {
+        CreateCourse {
+            tutor_id: course.tutor_id,
+            course_name: course.course_name,
+            course_description: course.course_description,
+            course_format: course.course_format,
+            course_structure: course.course_structure,
+            course_duration: course.course_duration,
+            course_price: course.course_price,
+            course_language: course.course_language,
+            course_level: course.course_level,
+        }
+    }
+}
+
+impl From<UpdateCourse> for UpdateCourseModel {
+    fn from(course: UpdateCourse) -> Self {
+        UpdateCourseModel {
+            course_name: course.course_name,
+            course_description: course.course_description,
+            course_format: course.course_format,
+            course_structure: course.course_structure,
+            course_duration: course.course_duration,
+            course_price: course.course_price,
+            course_language: course.course_language,
+            course_level: course.course_level,
+        }
+    }
+}
+
+impl From<CatalogueRequest> for CourseFilter {
+    fn from(request: CatalogueRequest) -> Self {
+        CourseFilter {
+            tutor_id: request.tutor_id,
+            course_name: request.course_name,
+            course_level: request.course_level,
+            course_language: request.course_language,
+            course_format: request.course_format,
+            min_price: request.min_price,
+            max_price: request.max_price,
+            posted_after: None,
+        }
+    }
+}
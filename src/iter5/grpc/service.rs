@@ -0,0 +1,349 @@
+use super::proto::course_service_server::CourseService;
+use super::proto::tutor_service_server::TutorService;
+use super::proto::*;
+use crate::cache::CacheKey;
+use crate::consistency::Consistency;
+use crate::dbaccess::course::*;
+use crate::dbaccess::export::{open_course_cursor_db, CURSOR_STALL_TIMEOUT};
+use crate::dbaccess::tutor::*;
+use crate::errors::EzyTutorError;
+use crate::models::course::CreateCourse;
+use crate::state::AppState;
+use actix_web::rt::time::timeout;
+use actix_web::web;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::Instrument;
+
+//Courses the catalogue stream reads from its cursor at a time
+const CATALOGUE_BATCH_SIZE: u32 = 500;
+
+type CourseStream = BoxStream<'static, Result<Course, Status>>;
+
+//Same reads and writes as the REST handlers. There is no
+//read-your-writes cookie, reads right after a write through this instance
+//still go to the primary (see AppState::read).
+pub struct GrpcApi {
+    state: web::Data<AppState>,
+}
+
+impl GrpcApi {
+    pub fn new(state: web::Data<AppState>) -> Self {
+        GrpcApi { state }
+    }
+}
+
+#[tonic::async_trait]
+impl TutorService for GrpcApi {
+    async fn list_tutors(
+        &self,
+        _request: Request<ListTutorsRequest>,
+    ) -> Result<Response<TutorList>, Status> {
+        let tutors = self
+            .state
+            .cached_read(
+                CacheKey::AllTutors,
+                Consistency::Eventual,
+                |pool| async move { get_all_tutors_db(&pool).await },
+            )
+            .await?;
+        Ok(Response::new(TutorList {
+            tutors: tutors.into_iter().map(Tutor::from).collect(),
+        }))
+    }
+
+    async fn get_tutor(&self, request: Request<TutorId>) -> Result<Response<Tutor>, Status> {
+        let tutor_id = request.into_inner().tutor_id;
+        let tutor = self
+            .state
+            .cached_read(
+                CacheKey::Tutor(tutor_id),
+                Consistency::Eventual,
+                |pool| async move { get_tutor_details_db(&pool, tutor_id).await },
+            )
+            .await?;
+        Ok(Response::new(tutor.into()))
+    }
+
+    async fn create_tutor(&self, request: Request<NewTutor>) -> Result<Response<Tutor>, Status> {
+        let tutor = post_new_tutor_db(&self.state.db, request.into_inner().into()).await?;
+        Ok(Response::new(tutor.into()))
+    }
+
+    async fn update_tutor(&self, request: Request<UpdateTutor>) -> Result<Response<Tutor>, Status> {
+        let update = request.into_inner();
+        let tutor_id = update.tutor_id;
+        let tutor = update_tutor_details_db(&self.state.db, tutor_id, update.into()).await?;
+        Ok(Response::new(tutor.into()))
+    }
+
+    async fn delete_tutor(&self, request: Request<TutorId>) -> Result<Response<Deleted>, Status> {
+        let tutor_id = request.into_inner().tutor_id;
+        let message = delete_tutor_db(&self.state.db, tutor_id).await?;
+        Ok(Response::new(Deleted { message }))
+    }
+}
+
+#[tonic::async_trait]
+impl CourseService for GrpcApi {
+    type ListTutorCoursesStream = CourseStream;
+    type ListCatalogueStream = CourseStream;
+
+    //A tutor has few courses, they come from the cache like on the REST API
+    async fn list_tutor_courses(
+        &self,
+        request: Request<TutorId>,
+    ) -> Result<Response<CourseStream>, Status> {
+        let tutor_id = request.into_inner().tutor_id;
+        let courses = self
+            .state
+            .cached_read(
+                CacheKey::CoursesForTutor(tutor_id),
+                Consistency::Eventual,
+                |pool| async move { get_courses_for_tutor_db(&pool, tutor_id).await },
+            )
+            .await?;
+        let courses = stream::iter(courses.into_iter().map(Course::from).map(Ok));
+        Ok(Response::new(courses.boxed()))
+    }
+
+    //The cursor is opened before responding so connection errors fail the
+    //call. Batches are only read as fast as the client takes the courses, a
+    //client that stops taking them loses the stream.
+    async fn list_catalogue(
+        &self,
+        request: Request<CatalogueRequest>,
+    ) -> Result<Response<CourseStream>, Status> {
+        let filter = request.into_inner().into();
+        let mut cursor =
+            open_course_cursor_db(&self.state.db, &filter, CATALOGUE_BATCH_SIZE).await?;
+        let (sender, receiver) = mpsc::channel::<Result<Vec<Course>, Status>>(1);
+        tokio::spawn(
+            async move {
+                loop {
+                    let batch = match cursor.next_batch().await {
+                        Ok(batch) if batch.is_empty() => break,
+                        Ok(batch) => Ok(batch.into_iter().map(Course::from).collect()),
+                        Err(err) => Err(err.into()),
+                    };
+                    let failed = batch.is_err();
+                    match timeout(CURSOR_STALL_TIMEOUT, sender.send(batch)).await {
+                        Ok(Ok(())) if !failed => {}
+                        //Dropping the cursor rolls back its transaction
+                        Ok(_) => break,
+                        Err(_) => {
+                            tracing::warn!(
+                                "catalogue stream abandoned, the client read nothing for {}s",
+                                CURSOR_STALL_TIMEOUT.as_secs()
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+        let courses = ReceiverStream::new(receiver).flat_map(|batch| {
+            let courses: Vec<Result<Course, Status>> = match batch {
+                Ok(batch) => batch.into_iter().map(Ok).collect(),
+                Err(status) => vec![Err(status)],
+            };
+            stream::iter(courses)
+        });
+        Ok(Response::new(courses.boxed()))
+    }
+
+    async fn get_course(&self, request: Request<CourseId>) -> Result<Response<Course>, Status> {
+        let CourseId {
+            tutor_id,
+            course_id,
+        } = request.into_inner();
+        let course = self
+            .state
+            .read(Consistency::Eventual, |pool| async move {
+                get_course_details_db(&pool, tutor_id, course_id).await
+            })
+            .await?;
+        Ok(Response::new(course.into()))
+    }
+
+    //Unlike JSON bodies, messages can't be checked while they are decoded
+    async fn create_course(&self, request: Request<NewCourse>) -> Result<Response<Course>, Status> {
+        let course = CreateCourse::from(request.into_inner());
+        course.validate().map_err(EzyTutorError::InvalidInput)?;
+        let course = post_new_course_db(&self.state.db, course).await?;
+        Ok(Response::new(course.into()))
+    }
+
+    async fn update_course(
+        &self,
+        request: Request<UpdateCourse>,
+    ) -> Result<Response<Course>, Status> {
+        let update = request.into_inner();
+        let (tutor_id, course_id) = (update.tutor_id, update.course_id);
+        let course =
+            update_course_details_db(&self.state.db, tutor_id, course_id, update.into()).await?;
+        Ok(Response::new(course.into()))
+    }
+
+    async fn delete_course(&self, request: Request<CourseId>) -> Result<Response<Deleted>, Status> {
+        let CourseId {
+            tutor_id,
+            course_id,
+        } = request.into_inner();
+        let message = delete_course_db(&self.state.db, tutor_id, course_id).await?;
+        Ok(Response::new(Deleted { message }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{start_listening, ReadCache};
+    use crate::grpc::proto::course_service_client::CourseServiceClient;
+    use crate::grpc::proto::tutor_service_client::TutorServiceClient;
+    use crate::grpc::serve;
+    use dotenv::dotenv;
+    use futures_util::TryStreamExt;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    async fn channel(state: web::Data<AppState>) -> Channel {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        actix_rt::spawn(serve(listener, state, std::future::pending()));
+        Channel::from_shared(format!("http://{}", address))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap()
+    }
+
+    fn new_course(tutor_id: i32, course_name: &str) -> NewCourse {
+        NewCourse {
+            tutor_id,
+            course_name: course_name.to_string(),
+            course_level: Some("Beginner".to_string()),
+            ..NewCourse::default()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn services_mirror_the_rest_api() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool.clone(),
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let cache_listener = start_listening(state.clone(), pool.clone()).await;
+        let channel = channel(state).await;
+        let mut tutors = TutorServiceClient::new(channel.clone());
+        let mut courses = CourseServiceClient::new(channel);
+
+        let tutor = tutors
+            .create_tutor(NewTutor {
+                tutor_name: "gRPC tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic4".into(),
+                tutor_profile: "Streams courses".into(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let tutor_id = tutor.tutor_id;
+        let first = courses
+            .create_course(new_course(tutor_id, "gRPC first"))
+            .await
+            .unwrap()
+            .into_inner();
+        let second = courses
+            .create_course(new_course(tutor_id, "gRPC second"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(first.posted_time.is_some());
+
+        let invalid = courses
+            .create_course(new_course(tutor_id, " "))
+            .await
+            .unwrap_err();
+        assert_eq!(invalid.code(), Code::InvalidArgument);
+        assert_eq!(invalid.message(), "course_name must not be empty");
+        let missing = tutors
+            .get_tutor(TutorId { tutor_id: -1 })
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+
+        let streamed: Vec<Course> = courses
+            .list_tutor_courses(TutorId { tutor_id })
+            .await
+            .unwrap()
+            .into_inner()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(streamed.len(), 2);
+        let catalogue: Vec<Course> = courses
+            .list_catalogue(CatalogueRequest {
+                tutor_id: Some(tutor_id),
+                course_name: Some("SECOND".into()),
+                ..CatalogueRequest::default()
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(catalogue, vec![second.clone()]);
+
+        //The cached tutor is dropped once the update is committed
+        tutors.get_tutor(TutorId { tutor_id }).await.unwrap();
+        tutors
+            .update_tutor(UpdateTutor {
+                tutor_id,
+                tutor_name: Some("gRPC tutor renamed".into()),
+                ..UpdateTutor::default()
+            })
+            .await
+            .unwrap();
+        let mut tutor = Tutor::default();
+        for _ in 0..100 {
+            tutor = tutors
+                .get_tutor(TutorId { tutor_id })
+                .await
+                .unwrap()
+                .into_inner();
+            if tutor.tutor_name == "gRPC tutor renamed" {
+                break;
+            }
+            actix_rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(tutor.tutor_name, "gRPC tutor renamed");
+        assert_eq!(tutor.tutor_profile, "Streams courses");
+
+        for course in [first, second] {
+            courses
+                .delete_course(CourseId {
+                    tutor_id,
+                    course_id: course.course_id,
+                })
+                .await
+                .unwrap();
+        }
+        tutors.delete_tutor(TutorId { tutor_id }).await.unwrap();
+        //Stops the listener like at shutdown
+        pool.close().await;
+        cache_listener.await.unwrap();
+    }
+}
@@ -0,0 +1,604 @@
+use crate::dbaccess::attachment::*;
+use crate::dbaccess::course::get_course_details_db;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::identity::Requester;
+use crate::media::MediaState;
+use crate::models::attachment::{Attachment, NewAttachment};
+use crate::openapi::AttachmentUpload;
+use crate::state::AppState;
+use actix_multipart::Multipart;
+use actix_web::http::header::{self, ContentDisposition};
+use actix_web::web::BytesMut;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
+
+#[utoipa::path(
+    post,
+    path = "/v1/courses/{tutor_id}/{course_id}/attachments",
+    tag = "attachments",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course"),
+        ("X-Tutor-Id" = i32, Header, description = "Authenticated tutor, must own the course")
+    ),
+    request_body(content = AttachmentUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The stored attachment", body = Attachment),
+        (status = 400, description = "Missing file or already attached", body = MyErrorResponse),
+        (status = 403, description = "Not the owning tutor", body = MyErrorResponse),
+        (status = 413, description = "File too large or quota exceeded", body = MyErrorResponse)
+    )
+)]
+pub async fn post_new_attachment(
+    app_state: web::Data<AppState>,
+    media_state: web::Data<MediaState>,
+    path: web::Path<(i32, i32)>,
+    requester: Requester,
+    mut payload: Multipart,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = path.into_inner();
+    requester.require_tutor(tutor_id)?;
+    get_course_details_db(&app_state.db, tutor_id, course_id).await?;
+
+    //Read the "file" field, hashing and enforcing the size limit while streaming
+    let mut upload = None;
+    while let Some(mut field) = payload.try_next().await? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let file_name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .map(sanitize_file_name)
+            .unwrap_or_else(|| "attachment".into());
+        let mime_type = field
+            .content_type()
+            .map(|mime| mime.essence_str().to_string())
+            .filter(|mime| mime.len() <= 100)
+            .unwrap_or_else(|| "application/octet-stream".into());
+        let mut hasher = Sha256::new();
+        let mut data = BytesMut::new();
+        while let Some(chunk) = field.try_next().await? {
+            if data.len() + chunk.len() > media_state.attachment_max_bytes {
+                return Err(EzyTutorError::PayloadTooLarge(format!(
+                    "attachment must not be larger than {} bytes",
+                    media_state.attachment_max_bytes
+                )));
+            }
+            hasher.update(&chunk);
+            data.extend_from_slice(&chunk);
+        }
+        upload = Some((
+            file_name,
+            mime_type,
+            hex::encode(hasher.finalize()),
+            data.freeze(),
+        ));
+    }
+    let (file_name, mime_type, checksum, data) = upload
+        .ok_or_else(|| EzyTutorError::InvalidInput("multipart field \"file\" is missing".into()))?;
+
+    let storage_key = format!("attachments/{}/{}", course_id, checksum);
+    //The content of a duplicate is already stored and must stay untouched
+    if storage_key_in_use_db(&app_state.db, &storage_key).await? {
+        return Err(EzyTutorError::InvalidInput(
+            "this file is already attached to the course".into(),
+        ));
+    }
+    let size_bytes = data.len() as i64;
+    media_state
+        .store
+        .put(&storage_key, &mime_type, data)
+        .await?;
+    let new_attachment = NewAttachment {
+        course_id,
+        tutor_id,
+        file_name,
+        mime_type,
+        size_bytes,
+        checksum,
+        storage_key: storage_key.clone(),
+    };
+    match post_new_attachment_db(
+        &app_state.db,
+        new_attachment,
+        media_state.attachment_quota_bytes,
+    )
+    .await
+    {
+        Ok(attachment) => Ok(HttpResponse::Ok().json(attachment)),
+        Err(err) => {
+            remove_unreferenced(&app_state, &media_state, &storage_key).await;
+            Err(err)
+        }
+    }
+}
+
+//A concurrent upload of the same file may have attached the content in the
+//meantime. If that can't be told, the content is kept.
+async fn remove_unreferenced(app_state: &AppState, media_state: &MediaState, storage_key: &str) {
+    match storage_key_in_use_db(&app_state.db, storage_key).await {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(cleanup_err) = media_state.store.delete(storage_key).await {
+                tracing::warn!(
+                    key = %storage_key,
+                    error = %cleanup_err,
+                    "could not remove orphaned attachment"
+                );
+            }
+        }
+        Err(check_err) => tracing::warn!(
+            key = %storage_key,
+            error = %check_err,
+            "could not check whether the attachment is orphaned"
+        ),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/courses/{tutor_id}/{course_id}/attachments",
+    tag = "attachments",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course"),
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    responses(
+        (status = 200, description = "Attachments of the course", body = [Attachment]),
+        (status = 403, description = "Neither owning tutor nor enrolled student", body = MyErrorResponse)
+    )
+)]
+pub async fn get_attachments_for_course(
+    app_state: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    requester: Requester,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = path.into_inner();
+    get_course_details_db(&app_state.db, tutor_id, course_id).await?;
+    check_course_access(&app_state, requester, tutor_id, course_id).await?;
+    get_attachments_for_course_db(&app_state.db, course_id)
+        .await
+        .map(|attachments| HttpResponse::Ok().json(attachments))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/courses/{tutor_id}/{course_id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course"),
+        ("attachment_id" = i32, Path, description = "Id of the attachment"),
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student"),
+        ("Range" = Option<String>, Header, description = "Single byte range, e.g. bytes=0-1023")
+    ),
+    responses(
+        (status = 200, description = "The file content"),
+        (status = 206, description = "The requested byte range"),
+        (status = 403, description = "Neither owning tutor nor enrolled student", body = MyErrorResponse),
+        (status = 404, description = "Attachment not found", body = MyErrorResponse),
+        (status = 416, description = "Range not satisfiable")
+    )
+)]
+pub async fn download_attachment(
+    app_state: web::Data<AppState>,
+    media_state: web::Data<MediaState>,
+    path: web::Path<(i32, i32, i32)>,
+    requester: Requester,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, attachment_id) = path.into_inner();
+    get_course_details_db(&app_state.db, tutor_id, course_id).await?;
+    check_course_access(&app_state, requester, tutor_id, course_id).await?;
+    let attachment = get_attachment_details_db(&app_state.db, course_id, attachment_id).await?;
+    let content = media_state.store.get(&attachment.storage_key).await?.data;
+
+    let etag = format!("\"{}\"", attachment.checksum.trim());
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(attachment.mime_type.as_str())
+        .insert_header(ContentDisposition::attachment(attachment.file_name.clone()))
+        .insert_header((header::ETAG, etag.clone()))
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CACHE_CONTROL, "private, no-cache"));
+
+    //A stale If-Range means the client's partial copy is outdated, send everything
+    let if_range_matches = req
+        .headers()
+        .get(header::IF_RANGE)
+        .map(|value| value.to_str().map(|value| value == etag).unwrap_or(false))
+        .unwrap_or(true);
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range_matches);
+    let len = content.len() as u64;
+    match range.map(|range| parse_byte_range(range, len)) {
+        Some(ByteRange::Satisfiable(start, end)) => Ok(response
+            .status(actix_web::http::StatusCode::PARTIAL_CONTENT)
+            .insert_header((
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, len),
+            ))
+            .body(content.slice(start as usize..=end as usize))),
+        Some(ByteRange::Unsatisfiable) => Ok(HttpResponse::RangeNotSatisfiable()
+            .insert_header((header::CONTENT_RANGE, format!("bytes */{}", len)))
+            .finish()),
+        Some(ByteRange::Ignored) | None => Ok(response.body(content)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/courses/{tutor_id}/{course_id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course"),
+        ("attachment_id" = i32, Path, description = "Id of the attachment"),
+        ("X-Tutor-Id" = i32, Header, description = "Authenticated tutor, must own the course")
+    ),
+    responses(
+        (status = 200, description = "Deletion summary", body = String),
+        (status = 403, description = "Not the owning tutor", body = MyErrorResponse),
+        (status = 404, description = "Attachment not found", body = MyErrorResponse)
+    )
+)]
+pub async fn delete_attachment(
+    app_state: web::Data<AppState>,
+    media_state: web::Data<MediaState>,
+    path: web::Path<(i32, i32, i32)>,
+    requester: Requester,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, attachment_id) = path.into_inner();
+    requester.require_tutor(tutor_id)?;
+    get_course_details_db(&app_state.db, tutor_id, course_id).await?;
+    let attachment = delete_attachment_db(&app_state.db, course_id, attachment_id).await?;
+    media_state.store.delete(&attachment.storage_key).await?;
+    Ok(HttpResponse::Ok().json(format!("Deleted attachment {}", attachment.attachment_id)))
+}
+
+//Course materials are visible to the owning tutor and to enrolled students only
+async fn check_course_access(
+    app_state: &AppState,
+    requester: Requester,
+    tutor_id: i32,
+    course_id: i32,
+) -> Result<(), EzyTutorError> {
+    let allowed = match requester {
+        Requester::Tutor(id) => id == tutor_id,
+        Requester::Student(student_id) => {
+            is_student_enrolled_db(&app_state.db, course_id, student_id).await?
+        }
+        Requester::Anonymous => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(EzyTutorError::Forbidden(
+            "course materials are only available to the tutor and enrolled students".into(),
+        ))
+    }
+}
+
+//Keep only the last path component and drop control characters
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(255)
+        .collect();
+    if name.trim().is_empty() {
+        "attachment".into()
+    } else {
+        name
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    //inclusive start and end offsets
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    //malformed or multi-range requests are answered with the full content
+    Ignored,
+}
+
+fn parse_byte_range(header: &str, len: u64) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Ignored;
+    };
+    if spec.contains(',') {
+        return ByteRange::Ignored;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Ignored;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return ByteRange::Ignored,
+        //suffix range: the last n bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(suffix) => (len.saturating_sub(suffix), len.saturating_sub(1)),
+            Err(_) => return ByteRange::Ignored,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, len.saturating_sub(1)),
+            Err(_) => return ByteRange::Ignored,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            _ => return ByteRange::Ignored,
+        },
+    };
+    if len == 0 || start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Satisfiable(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::dbaccess::course::post_new_course_db;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::media::local::LocalMediaStore;
+    use crate::models::attachment::Attachment;
+    use crate::models::course::CreateCourse;
+    use crate::models::tutor::NewTutor;
+    use crate::routes::course_routes;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    async fn test_states() -> (web::Data<AppState>, web::Data<MediaState>) {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let media_state = web::Data::new(MediaState {
+            store: Arc::new(LocalMediaStore::new(
+                env::temp_dir().join("tutor-db-media-test"),
+            )),
+            public_base_url: "http://localhost:3000".into(),
+            max_upload_bytes: 1024,
+            attachment_max_bytes: 1024,
+            attachment_quota_bytes: 1024 * 1024 * 1024,
+        });
+        (app_state, media_state)
+    }
+
+    fn upload_request(uri: &str, tutor_header: &str, content: &[u8]) -> test::TestRequest {
+        let boundary = "tutordbboundary";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"../notes.txt\"\r\nContent-Type: text/plain\r\n\r\n",
+            boundary
+        )
+        .into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("X-Tutor-Id", tutor_header))
+            .insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            ))
+            .set_payload(body)
+    }
+
+    fn unique_content() -> Vec<u8> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        format!("course notes generated at {}", nanos).into_bytes()
+    }
+
+    #[actix_rt::test]
+    async fn attachment_lifecycle_test() {
+        let (app_state, media_state) = test_states().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(course_routes),
+        )
+        .await;
+        let content = unique_content();
+        let req = upload_request("/courses/1/1/attachments", "1", &content).to_request();
+        let attachment: Attachment = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(attachment.file_name, "notes.txt");
+        assert_eq!(attachment.size_bytes, content.len() as i64);
+
+        let req = test::TestRequest::get()
+            .uri("/courses/1/1/attachments")
+            .insert_header(("X-Tutor-Id", "1"))
+            .to_request();
+        let attachments: Vec<Attachment> = test::call_and_read_body_json(&app, req).await;
+        assert!(attachments
+            .iter()
+            .any(|listed| listed.attachment_id == attachment.attachment_id));
+
+        let download_uri = format!("/courses/1/1/attachments/{}", attachment.attachment_id);
+        let req = test::TestRequest::get()
+            .uri(&download_uri)
+            .insert_header(("X-Tutor-Id", "1"))
+            .insert_header((header::RANGE, "bytes=0-5"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(test::read_body(resp).await, content[..6]);
+
+        let req = test::TestRequest::delete()
+            .uri(&download_uri)
+            .insert_header(("X-Tutor-Id", "1"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn duplicate_upload_keeps_the_stored_content() {
+        let (app_state, media_state) = test_states().await;
+        let pool = app_state.db.clone();
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Attaching tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic9".into(),
+                tutor_profile: "Shares notes".into(),
+            },
+        )
+        .await
+        .unwrap();
+        let course = post_new_course_db(
+            &pool,
+            CreateCourse {
+                tutor_id: tutor.tutor_id,
+                course_name: "Attached notes".into(),
+                course_description: None,
+                course_format: None,
+                course_structure: None,
+                course_duration: None,
+                course_price: None,
+                course_language: None,
+                course_level: None,
+            },
+        )
+        .await
+        .unwrap();
+        //The first upload fills the quota, a second copy would exceed it
+        let content = unique_content();
+        let media_state = web::Data::new(MediaState {
+            store: media_state.store.clone(),
+            public_base_url: media_state.public_base_url.clone(),
+            max_upload_bytes: media_state.max_upload_bytes,
+            attachment_max_bytes: media_state.attachment_max_bytes,
+            attachment_quota_bytes: content.len() as i64,
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state.clone())
+                .configure(course_routes),
+        )
+        .await;
+        let uri = format!(
+            "/courses/{}/{}/attachments",
+            tutor.tutor_id, course.course_id
+        );
+        let tutor_header = tutor.tutor_id.to_string();
+        let req = upload_request(&uri, &tutor_header, &content).to_request();
+        let attachment: Attachment = test::call_and_read_body_json(&app, req).await;
+
+        let req = upload_request(&uri, &tutor_header, &content).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::get()
+            .uri(&format!("{}/{}", uri, attachment.attachment_id))
+            .insert_header(("X-Tutor-Id", tutor_header.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(test::read_body(resp).await, content);
+
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+        let storage_key = format!("attachments/{}/{}", course.course_id, attachment.checksum);
+        media_state.store.delete(&storage_key).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn post_attachment_other_tutor_forbidden() {
+        let (app_state, media_state) = test_states().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(course_routes),
+        )
+        .await;
+        let req = upload_request("/courses/1/1/attachments", "2", &unique_content()).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn get_attachments_student_access() {
+        let (app_state, media_state) = test_states().await;
+        sqlx::query!(
+            "INSERT INTO ezy_enrollment_c6 (course_id, student_id) VALUES (1, 501)
+            ON CONFLICT DO NOTHING"
+        )
+        .execute(&app_state.db)
+        .await
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(course_routes),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/courses/1/1/attachments")
+            .insert_header(("X-Student-Id", "501"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/courses/1/1/attachments")
+            .insert_header(("X-Student-Id", "502"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn parse_byte_range_test() {
+        assert_eq!(
+            parse_byte_range("bytes=0-9", 100),
+            ByteRange::Satisfiable(0, 9)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=90-", 100),
+            ByteRange::Satisfiable(90, 99)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=-10", 100),
+            ByteRange::Satisfiable(90, 99)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=50-500", 100),
+            ByteRange::Satisfiable(50, 99)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=100-", 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_byte_range("bytes=0-1,5-6", 100), ByteRange::Ignored);
+        assert_eq!(parse_byte_range("items=0-1", 100), ByteRange::Ignored);
+    }
+}
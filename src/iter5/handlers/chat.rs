@@ -0,0 +1,502 @@
+use crate::chat::{run_session, ws, ChatHub};
+use crate::dbaccess::chat::*;
+use crate::dbaccess::course::get_course_details_db;
+use crate::dbaccess::tutor::get_tutor_details_db;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::identity::Requester;
+use crate::lifecycle::Lifecycle;
+use crate::models::chat::{
+    ChatMessage, Conversation, ConversationSummary, HistoryParams, NewConversation, Participant,
+};
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+const MAX_HISTORY_LIMIT: i64 = 100;
+
+fn participant(requester: Requester) -> Result<Participant, EzyTutorError> {
+    requester.participant().ok_or_else(|| {
+        EzyTutorError::Forbidden("chat is only available to tutors and students".into())
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/chat/conversations",
+    tag = "chat",
+    params(("X-Student-Id" = i32, Header, description = "Authenticated student")),
+    request_body = NewConversation,
+    responses(
+        (status = 200, description = "The conversation of the student with the tutor, \
+            started if there was none", body = Conversation),
+        (status = 403, description = "Not a student", body = MyErrorResponse),
+        (status = 404, description = "Tutor or course not found", body = MyErrorResponse)
+    )
+)]
+pub async fn post_new_conversation(
+    app_state: web::Data<AppState>,
+    requester: Requester,
+    new_conversation: web::Json<NewConversation>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let Requester::Student(student_id) = requester else {
+        return Err(EzyTutorError::Forbidden(
+            "only students may start conversations".into(),
+        ));
+    };
+    let new_conversation = new_conversation.into_inner();
+    get_tutor_details_db(&app_state.db, new_conversation.tutor_id).await?;
+    if let Some(course_id) = new_conversation.course_id {
+        get_course_details_db(&app_state.db, new_conversation.tutor_id, course_id).await?;
+    }
+    start_conversation_db(&app_state.db, student_id, &new_conversation)
+        .await
+        .map(|conversation| HttpResponse::Ok().json(conversation))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chat/conversations",
+    tag = "chat",
+    params(
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    responses(
+        (status = 200, description = "Conversations of the caller, latest first",
+            body = [ConversationSummary]),
+        (status = 403, description = "Neither tutor nor student", body = MyErrorResponse)
+    )
+)]
+pub async fn get_conversations(
+    app_state: web::Data<AppState>,
+    requester: Requester,
+) -> Result<HttpResponse, EzyTutorError> {
+    let participant = participant(requester)?;
+    get_conversations_db(&app_state.db, participant)
+        .await
+        .map(|conversations| HttpResponse::Ok().json(conversations))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chat/conversations/{conversation_id}/messages",
+    tag = "chat",
+    params(
+        ("conversation_id" = i32, Path, description = "Id of the conversation"),
+        HistoryParams,
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    responses(
+        (status = 200, description = "Messages newest first. The messages to the caller \
+            count as delivered.", body = [ChatMessage]),
+        (status = 400, description = "Invalid limit", body = MyErrorResponse),
+        (status = 403, description = "Not a participant", body = MyErrorResponse),
+        (status = 404, description = "Conversation not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_chat_messages(
+    app_state: web::Data<AppState>,
+    requester: Requester,
+    path: web::Path<i32>,
+    params: web::Query<HistoryParams>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let participant = participant(requester)?;
+    let limit = params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    if !(1..=MAX_HISTORY_LIMIT).contains(&limit) {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "limit must be between 1 and {}",
+            MAX_HISTORY_LIMIT
+        )));
+    }
+    let conversation = get_conversation_db(&app_state.db, path.into_inner()).await?;
+    if !conversation.includes(participant) {
+        return Err(EzyTutorError::Forbidden(
+            "only its participants may read a conversation".into(),
+        ));
+    }
+    let messages = get_chat_messages_db(
+        &app_state.db,
+        conversation.conversation_id,
+        params.before,
+        limit,
+    )
+    .await?;
+    if let Some(newest) = messages.first() {
+        mark_delivered_db(
+            &app_state.db,
+            &conversation,
+            participant.role,
+            newest.message_id,
+        )
+        .await?;
+    }
+    Ok(HttpResponse::Ok().json(messages))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chat/ws",
+    tag = "chat",
+    params(
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    responses(
+        (status = 101, description = "WebSocket carrying JSON text frames for all \
+            conversations of the caller. Clients send send, typing and read frames and get \
+            message, delivered, read, typing, resync and error frames."),
+        (status = 400, description = "Not a WebSocket handshake", body = MyErrorResponse),
+        (status = 403, description = "Neither tutor nor student", body = MyErrorResponse)
+    )
+)]
+pub async fn get_chat_socket(
+    app_state: web::Data<AppState>,
+    hub: web::Data<ChatHub>,
+    lifecycle: web::Data<Lifecycle>,
+    requester: Requester,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, EzyTutorError> {
+    let participant = participant(requester)?;
+    let (response, sender, receiver) = ws::upgrade(&req, payload, hub.max_frame_bytes())?;
+    actix_rt::spawn(run_session(
+        hub,
+        app_state.db.clone(),
+        lifecycle,
+        participant,
+        sender,
+        receiver,
+    ));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::chat::listen;
+    use crate::config::ChatConfig;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::identity::{STUDENT_ID_HEADER, TUTOR_ID_HEADER};
+    use crate::models::chat::{ChatRole, ServerFrame};
+    use crate::models::tutor::NewTutor;
+    use crate::routes::chat_routes;
+    use actix_codec::{Decoder, Encoder};
+    use actix_http::ws::{Codec, Frame, Message};
+    use actix_http::{Payload, Request};
+    use actix_web::body::{BoxBody, MessageBody};
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::web::{Bytes, BytesMut};
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use serde_json::json;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    //Just enough of a WebSocket client for the tests, its frames are the
+    //request payload and the server's come in the response body
+    struct Client {
+        payload: mpsc::UnboundedSender<Bytes>,
+        body: BoxBody,
+        codec: Codec,
+        buffer: BytesMut,
+    }
+
+    impl Client {
+        async fn connect(
+            app: &impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+            header: (&str, i32),
+        ) -> Client {
+            let (payload, frames) = mpsc::unbounded_channel::<Bytes>();
+            let frames = futures_util::stream::unfold(frames, |mut frames| async move {
+                let chunk = frames.recv().await?;
+                Some((Ok(chunk), frames))
+            });
+            let (req, _) = test::TestRequest::get()
+                .uri("/chat/ws")
+                .insert_header(("Upgrade", "websocket"))
+                .insert_header(("Connection", "Upgrade"))
+                .insert_header(("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="))
+                .insert_header(("Sec-WebSocket-Version", "13"))
+                .insert_header(header)
+                .to_request()
+                .replace_payload(Payload::from(
+                    Box::pin(frames) as actix_http::BoxedPayloadStream
+                ));
+            let resp = test::call_service(app, req).await;
+            assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+            let mut client = Client {
+                payload,
+                body: resp.into_body().boxed(),
+                codec: Codec::new().client_mode(),
+                buffer: BytesMut::new(),
+            };
+            //Answered by the session loop, so the session is registered
+            client.write(Message::Ping(Bytes::from_static(b"ready")));
+            loop {
+                if let Frame::Pong(_) = client.read().await {
+                    return client;
+                }
+            }
+        }
+
+        fn write(&mut self, message: Message) {
+            let mut encoded = BytesMut::new();
+            self.codec.encode(message, &mut encoded).unwrap();
+            self.payload.send(encoded.freeze()).unwrap();
+        }
+
+        fn send(&mut self, frame: serde_json::Value) {
+            self.write(Message::Text(frame.to_string().into()));
+        }
+
+        async fn read(&mut self) -> Frame {
+            loop {
+                if let Some(frame) = self.codec.decode(&mut self.buffer).unwrap() {
+                    return frame;
+                }
+                let body = &mut self.body;
+                let chunk = actix_rt::time::timeout(
+                    Duration::from_secs(10),
+                    futures_util::future::poll_fn(|cx| {
+                        std::pin::Pin::new(&mut *body).poll_next(cx)
+                    }),
+                )
+                .await
+                .expect("no frame within 10s")
+                .expect("socket closed")
+                .unwrap_or_else(|_| panic!("socket failed"));
+                self.buffer.extend_from_slice(&chunk);
+            }
+        }
+
+        //Skips pings and frames of other types
+        async fn next(&mut self, frame_type: &str) -> ServerFrame {
+            loop {
+                if let Frame::Text(text) = self.read().await {
+                    let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+                    if value["type"] == frame_type {
+                        return serde_json::from_value(value).unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    async fn listener_ready(pool: &PgPool) {
+        for _ in 0..50 {
+            let listening = sqlx::query_scalar!(
+                "SELECT count(*) FROM pg_stat_activity WHERE query LIKE 'LISTEN %ezy_chat%'"
+            )
+            .fetch_one(pool)
+            .await
+            .unwrap();
+            if listening > Some(0) {
+                return;
+            }
+            actix_rt::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the chat listener did not start");
+    }
+
+    #[actix_rt::test]
+    async fn chat_carries_messages_receipts_and_typing_between_participants() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let hub = web::Data::new(ChatHub::from_config(&ChatConfig::default()));
+        let listener = actix_rt::spawn(listen(hub.clone(), pool.clone()));
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool.clone(),
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(hub)
+                .app_data(web::Data::new(Lifecycle::default()))
+                .configure(chat_routes),
+        )
+        .await;
+
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Chatting tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic8".into(),
+                tutor_profile: "Answers questions".into(),
+            },
+        )
+        .await
+        .unwrap();
+        let student_id = 700_000 + tutor.tutor_id;
+        let start = |header: (&str, i32), new_conversation: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/chat/conversations")
+                .insert_header(header)
+                .set_json(new_conversation)
+                .to_request()
+        };
+        let req = start(
+            (TUTOR_ID_HEADER, tutor.tutor_id),
+            json!({"tutor_id": tutor.tutor_id}),
+        );
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let req = start(
+            (STUDENT_ID_HEADER, student_id),
+            json!({"tutor_id": tutor.tutor_id, "course_id": -1}),
+        );
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let req = start(
+            (STUDENT_ID_HEADER, student_id),
+            json!({"tutor_id": tutor.tutor_id}),
+        );
+        let conversation: Conversation = test::call_and_read_body_json(&app, req).await;
+        let conversation_id = conversation.conversation_id;
+
+        listener_ready(&pool).await;
+        let mut student = Client::connect(&app, (STUDENT_ID_HEADER, student_id)).await;
+        let mut tutor_socket = Client::connect(&app, (TUTOR_ID_HEADER, tutor.tutor_id)).await;
+
+        //The sender gets its message back as acknowledgement, the connected
+        //recipient gets it delivered
+        let send = |body: &str, client_message_id: &str| {
+            json!({
+                "type": "send",
+                "conversation_id": conversation_id,
+                "body": body,
+                "client_message_id": client_message_id,
+            })
+        };
+        student.send(send("Hello", "c1"));
+        let ServerFrame::Message { message: sent } = student.next("message").await else {
+            unreachable!()
+        };
+        assert_eq!(sent.client_message_id.as_deref(), Some("c1"));
+        assert_eq!(sent.sender, "student");
+        let ServerFrame::Message { message: received } = tutor_socket.next("message").await else {
+            unreachable!()
+        };
+        assert_eq!(received, sent);
+        assert_eq!(
+            student.next("delivered").await,
+            ServerFrame::Delivered {
+                conversation_id,
+                message_ids: vec![sent.message_id],
+            }
+        );
+
+        //Resent after a lost acknowledgement, stored once
+        student.send(send("Hello", "c1"));
+        let ServerFrame::Message { message: resent } = student.next("message").await else {
+            unreachable!()
+        };
+        assert_eq!(resent.message_id, sent.message_id);
+
+        tutor_socket.send(json!({"type": "typing", "conversation_id": conversation_id}));
+        assert_eq!(
+            student.next("typing").await,
+            ServerFrame::Typing {
+                conversation_id,
+                sender: ChatRole::Tutor,
+            }
+        );
+        tutor_socket.send(json!({
+            "type": "read",
+            "conversation_id": conversation_id,
+            "message_id": sent.message_id,
+        }));
+        assert_eq!(
+            student.next("read").await,
+            ServerFrame::Read {
+                conversation_id,
+                message_ids: vec![sent.message_id],
+            }
+        );
+
+        student.send(send("Still there?", "c2"));
+        student.send(send("Anyone?", "c3"));
+        student.send(json!({
+            "type": "send",
+            "conversation_id": -1,
+            "body": "Lost",
+            "client_message_id": "c4",
+        }));
+        assert_eq!(
+            student.next("error").await,
+            ServerFrame::Error {
+                error: "Conversation id not found".into(),
+                client_message_id: Some("c4".into()),
+            }
+        );
+        tutor_socket.send(json!({"type": "wave"}));
+        assert!(matches!(
+            tutor_socket.next("error").await,
+            ServerFrame::Error {
+                client_message_id: None,
+                ..
+            }
+        ));
+        drop(student);
+        drop(tutor_socket);
+
+        let history = |query: &str, header: (&str, i32)| {
+            test::TestRequest::get()
+                .uri(&format!(
+                    "/chat/conversations/{}/messages{}",
+                    conversation_id, query
+                ))
+                .insert_header(header)
+                .to_request()
+        };
+        let req = history("?limit=2", (TUTOR_ID_HEADER, tutor.tutor_id));
+        let page: Vec<ChatMessage> = test::call_and_read_body_json(&app, req).await;
+        let bodies: Vec<&str> = page.iter().map(|message| message.body.as_str()).collect();
+        assert_eq!(bodies, ["Anyone?", "Still there?"]);
+        let req = history(
+            &format!("?limit=2&before={}", page[1].message_id),
+            (TUTOR_ID_HEADER, tutor.tutor_id),
+        );
+        let older: Vec<ChatMessage> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(older.len(), 1);
+        assert!(older[0].read_time.is_some());
+        let req = history("", (STUDENT_ID_HEADER, student_id + 1));
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let req = history("?limit=0", (TUTOR_ID_HEADER, tutor.tutor_id));
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        //Fetching the history delivered the rest
+        let req = test::TestRequest::get()
+            .uri("/chat/conversations")
+            .insert_header((STUDENT_ID_HEADER, student_id))
+            .to_request();
+        let conversations: Vec<ConversationSummary> =
+            test::call_and_read_body_json(&app, req).await;
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].unread, 0);
+        let undelivered = sqlx::query_scalar!(
+            "SELECT count(*) FROM ezy_chat_message_c6
+            WHERE conversation_id = $1 AND delivered_time IS NULL",
+            conversation_id
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(undelivered, Some(0));
+
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+        //Stops the listener like at shutdown
+        pool.close().await;
+        listener.await.unwrap();
+    }
+}
@@ -1,29 +1,87 @@
+use crate::cache::CacheKey;
+use crate::consistency::Consistency;
 use crate::dbaccess::course::*;
-use crate::errors::EzyTutorError;
-use crate::models::course::{CreateCourse, UpdateCourse};
+use crate::dbaccess::tutor::get_tutor_details_db;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::models::course::{
+    Course, CreateCourse, ImportCourse, ImportMode, ImportParams, ImportReport, UpdateCourse,
+};
 use crate::state::AppState;
-use actix_web::{web, HttpResponse};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
 
+pub const MAX_IMPORT_ROWS: usize = 1000;
+
+#[utoipa::path(
+    get,
+    path = "/v1/courses/{tutor_id}",
+    tag = "courses",
+    params(("tutor_id" = i32, Path, description = "Id of the tutor")),
+    responses(
+        (status = 200, description = "All courses of the tutor", body = [Course]),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
 pub async fn get_courses_for_tutor(
     app_state: web::Data<AppState>,
     path: web::Path<i32>,
+    consistency: Consistency,
 ) -> Result<HttpResponse, EzyTutorError> {
     let tutor_id = path.into_inner();
-    get_courses_for_tutor_db(&app_state.db, tutor_id)
-        .await
-        .map(|courses| HttpResponse::Ok().json(courses))
+    let courses = app_state
+        .cached_read(
+            CacheKey::CoursesForTutor(tutor_id),
+            consistency,
+            |pool| async move { get_courses_for_tutor_db(&pool, tutor_id).await },
+        )
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(app_state.cache.cache_control())
+        .json(courses))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/courses/{tutor_id}/{course_id}",
+    tag = "courses",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course")
+    ),
+    responses(
+        (status = 200, description = "The course", body = Course),
+        (status = 404, description = "Course not found", body = MyErrorResponse)
+    )
+)]
 pub async fn get_course_details(
     app_state: web::Data<AppState>,
     path: web::Path<(i32, i32)>,
+    consistency: Consistency,
 ) -> Result<HttpResponse, EzyTutorError> {
     let (tutor_id, course_id) = path.into_inner();
-    get_course_details_db(&app_state.db, tutor_id, course_id)
+    app_state
+        .read(consistency, |pool| async move {
+            get_course_details_db(&pool, tutor_id, course_id).await
+        })
         .await
         .map(|course| HttpResponse::Ok().json(course))
 }
 
+#[utoipa::path(
+    put,
+    path = "/v1/courses/{tutor_id}/{course_id}",
+    tag = "courses",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course")
+    ),
+    request_body = UpdateCourse,
+    responses(
+        (status = 200, description = "The updated course", body = Course),
+        (status = 400, description = "Invalid JSON input", body = MyErrorResponse),
+        (status = 404, description = "Course not found", body = MyErrorResponse)
+    )
+)]
 pub async fn update_course_details(
     app_state: web::Data<AppState>,
     update_course: web::Json<UpdateCourse>,
@@ -35,6 +93,17 @@ pub async fn update_course_details(
         .map(|course| HttpResponse::Ok().json(course))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/courses/",
+    tag = "courses",
+    request_body = CreateCourse,
+    responses(
+        (status = 200, description = "The created course", body = Course),
+        (status = 400, description = "Invalid JSON input", body = MyErrorResponse),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
 pub async fn post_new_course(
     new_course: web::Json<CreateCourse>,
     app_state: web::Data<AppState>,
@@ -44,6 +113,19 @@ pub async fn post_new_course(
         .map(|course| HttpResponse::Ok().json(course))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/v1/courses/{tutor_id}/{course_id}",
+    tag = "courses",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course")
+    ),
+    responses(
+        (status = 200, description = "Deletion summary", body = String),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
 pub async fn delete_course(
     app_state: web::Data<AppState>,
     path: web::Path<(i32, i32)>,
@@ -54,15 +136,113 @@ pub async fn delete_course(
         .map(|resp| HttpResponse::Ok().json(resp))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/courses/{tutor_id}/import",
+    tag = "courses",
+    params(("tutor_id" = i32, Path, description = "Id of the tutor"), ImportParams),
+    request_body(
+        content((Vec<ImportCourse> = "application/json"), (String = "text/csv")),
+        description = "Courses as a JSON array or CSV with a header row"
+    ),
+    responses(
+        (status = 200, description = "Import report", body = ImportReport),
+        (status = 400, description = "Atomic import rejected, nothing was created", body = ImportReport),
+        (status = 404, description = "Tutor not found", body = MyErrorResponse),
+        (status = 413, description = "Too many courses", body = MyErrorResponse)
+    )
+)]
+pub async fn import_courses(
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    params: web::Query<ImportParams>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    get_tutor_details_db(&app_state.db, tutor_id).await?;
+    let records = parse_import_records(&req, &body)?;
+    if records.is_empty() {
+        return Err(EzyTutorError::InvalidInput(
+            "import contains no courses".into(),
+        ));
+    }
+    if records.len() > MAX_IMPORT_ROWS {
+        return Err(EzyTutorError::PayloadTooLarge(format!(
+            "an import may contain at most {} courses",
+            MAX_IMPORT_ROWS
+        )));
+    }
+    let rows = records
+        .into_iter()
+        .map(|record| record.and_then(|course| course.into_create_course(tutor_id)))
+        .collect();
+    let report = import_courses_db(&app_state.db, rows, &params).await?;
+    tracing::info!(
+        tutor_id,
+        created = report.created,
+        failed = report.failed,
+        dry_run = report.dry_run,
+        "course import finished"
+    );
+    if report.mode == ImportMode::Atomic && report.failed > 0 {
+        Ok(HttpResponse::BadRequest().json(report))
+    } else {
+        Ok(HttpResponse::Ok().json(report))
+    }
+}
+
+//Parse every record on its own, so one bad row doesn't hide the errors of the others
+fn parse_import_records(
+    req: &HttpRequest,
+    body: &[u8],
+) -> Result<Vec<Result<ImportCourse, String>>, EzyTutorError> {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    match content_type.as_str() {
+        "text/csv" => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(body);
+            Ok(reader
+                .deserialize::<ImportCourse>()
+                .map(|record| record.map_err(|err| err.to_string()))
+                .collect())
+        }
+        "application/json" => {
+            let values: Vec<serde_json::Value> = serde_json::from_slice(body).map_err(|err| {
+                EzyTutorError::InvalidInput(format!("expected a JSON array of courses: {}", err))
+            })?;
+            Ok(values
+                .into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|err| err.to_string()))
+                .collect())
+        }
+        _ => Err(EzyTutorError::InvalidInput(
+            "imports must be sent as text/csv or application/json".into(),
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::ReadCache;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::models::course::ImportReport;
+    use crate::models::tutor::NewTutor;
+    use actix_web::body;
     use actix_web::http::StatusCode;
+    use actix_web::test;
     use actix_web::ResponseError;
     use dotenv::dotenv;
     use sqlx::postgres::PgPool;
     use std::env;
-    use std::sync::Mutex;
 
     #[actix_rt::test]
     async fn get_all_courses_success() {
@@ -71,11 +251,14 @@ mod tests {
         let db_pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let tutor_id = web::Path::from(1);
-        let resp = get_courses_for_tutor(app_state, tutor_id).await.unwrap();
+        let resp = get_courses_for_tutor(app_state, tutor_id, Consistency::Eventual)
+            .await
+            .unwrap();
         assert_eq!(resp.status(), StatusCode::OK)
     }
 
@@ -86,11 +269,14 @@ mod tests {
         let db_pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let params = web::Path::from((1, 2));
-        let resp = get_course_details(app_state, params).await.unwrap();
+        let resp = get_course_details(app_state, params, Consistency::Eventual)
+            .await
+            .unwrap();
         assert_eq!(resp.status(), StatusCode::OK)
     }
 
@@ -101,11 +287,12 @@ mod tests {
         let db_pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let params = web::Path::from((1, 21)); //set false params
-        let resp = get_course_details(app_state, params).await;
+        let resp = get_course_details(app_state, params, Consistency::Eventual).await;
         match resp {
             Ok(_) => println!("Something wrong"),
             Err(err) => assert_eq!(err.status_code(), StatusCode::NOT_FOUND),
@@ -120,8 +307,9 @@ mod tests {
         let db_pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let new_course_msg = CreateCourse {
             tutor_id: 1,
@@ -146,8 +334,9 @@ mod tests {
         let pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let update_course_msg = UpdateCourse {
             course_name: Some("Course name changed".into()),
@@ -175,8 +364,9 @@ mod tests {
         let pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let params = web::Path::from((1, 3));
         let resp = delete_course(app_state, params).await.unwrap();
@@ -190,8 +380,9 @@ mod tests {
         let pool = PgPool::connect(&database_url).await.unwrap();
         let app_state = web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             db: pool,
+            replica: None,
+            cache: ReadCache::default(),
         });
         let params = web::Path::from((1, 21)); //provide invalid params
         let resp = delete_course(app_state, params).await;
@@ -200,4 +391,126 @@ mod tests {
             Err(err) => assert_eq!(err.status_code(), StatusCode::NOT_FOUND),
         }
     }
+
+    #[actix_rt::test]
+    async fn import_courses_dry_run_test() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let req = test::TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "text/csv"))
+            .to_http_request();
+        let csv = "course_name,course_level,course_price\nImported course,Beginner,100\nSecond import,,\n";
+        let params = web::Query(ImportParams {
+            mode: ImportMode::Atomic,
+            dry_run: true,
+        });
+        let resp = import_courses(
+            app_state,
+            web::Path::from(1),
+            params,
+            req,
+            web::Bytes::from(csv),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let report: ImportReport =
+            serde_json::from_slice(&body::to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.created, 0);
+        assert_eq!(report.failed, 0);
+    }
+
+    #[actix_rt::test]
+    async fn import_courses_atomic_failure_test() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let req = test::TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .to_http_request();
+        let json = r#"[{"course_name": "Valid course"}, {"course_name": ""}, {"course_level": "no name"}]"#;
+        let params = web::Query(ImportParams::default());
+        let resp = import_courses(
+            app_state,
+            web::Path::from(1),
+            params,
+            req,
+            web::Bytes::from(json),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let report: ImportReport =
+            serde_json::from_slice(&body::to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(report.created, 0);
+        assert_eq!(report.failed, 2);
+        assert!(report.rows[0].error.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn import_courses_best_effort_test() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Importing tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic9".into(),
+                tutor_profile: "Brings courses along".into(),
+            },
+        )
+        .await
+        .unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool.clone(),
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let req = test::TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .to_http_request();
+        let json =
+            r#"[{"course_name": "Best effort course"}, {"course_name": "x", "course_price": -1}]"#;
+        let params = web::Query(ImportParams {
+            mode: ImportMode::BestEffort,
+            dry_run: false,
+        });
+        let resp = import_courses(
+            app_state,
+            web::Path::from(tutor.tutor_id),
+            params,
+            req,
+            web::Bytes::from(json),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let report: ImportReport =
+            serde_json::from_slice(&body::to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        let courses = get_courses_for_tutor_db(&pool, tutor.tutor_id)
+            .await
+            .unwrap();
+        //Courses go with their tutor
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+        assert_eq!(report.created, 1);
+        assert_eq!(report.failed, 1);
+        assert_eq!(courses.len(), 1);
+        assert_eq!(courses[0].course_name, "Best effort course");
+    }
 }
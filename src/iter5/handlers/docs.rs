@@ -0,0 +1,54 @@
+use crate::openapi::ApiDoc;
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use utoipa::OpenApi;
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+//Swagger UI is compiled into the binary and served below /docs/, its
+//relative asset paths need the trailing slash
+pub async fn api_docs() -> HttpResponse {
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, "/docs/"))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::docs_routes;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn docs_are_served_without_a_cdn() {
+        let app = test::init_service(App::new().configure(docs_routes)).await;
+
+        let req = test::TestRequest::get().uri("/docs").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(resp.headers().get(header::LOCATION).unwrap(), "/docs/");
+
+        let req = test::TestRequest::get().uri("/docs/").to_request();
+        let page = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(page.contains("./swagger-ui-bundle.js"));
+        assert!(!page.contains("https://"));
+
+        let req = test::TestRequest::get()
+            .uri("/docs/swagger-ui-bundle.js")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/docs/swagger-initializer.js")
+            .to_request();
+        let script = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(script.contains("\"/openapi.json\""));
+
+        let req = test::TestRequest::get().uri("/openapi.json").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
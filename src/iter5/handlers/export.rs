@@ -0,0 +1,373 @@
+use crate::dbaccess::export::*;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::export::{check_xlsx_rows, ExportEncoder, ExportRow, ExportTail};
+use crate::identity::Admin;
+use crate::lifecycle::Lifecycle;
+use crate::models::course::CourseFilter;
+use crate::models::export::{ExportFormat, ExportParams, TutorFilter};
+use crate::state::AppState;
+use actix_web::http::header::{self, ContentDisposition};
+use actix_web::rt::time::timeout;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::postgres::PgRow;
+use sqlx::FromRow;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+const EXPORT_BATCH_SIZE: u32 = 500;
+//Number of encoded batches buffered ahead of a slow client
+const EXPORT_CHANNEL_CAPACITY: usize = 4;
+
+#[utoipa::path(
+    get,
+    path = "/admin/export/courses",
+    tag = "admin",
+    params(
+        ExportParams,
+        CourseFilter,
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "All matching courses as CSV, JSON Lines or XLSX"),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 413, description = "Too many rows for an XLSX sheet", body = MyErrorResponse)
+    )
+)]
+pub async fn export_courses(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    lifecycle: web::Data<Lifecycle>,
+    params: web::Query<ExportParams>,
+    filter: web::Query<CourseFilter>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let format = negotiate_format(&params, &req);
+    if format == ExportFormat::Xlsx {
+        check_xlsx_rows(count_courses_db(&app_state.db, &filter).await?)?;
+    }
+    let cursor = open_course_cursor_db(&app_state.db, &filter, EXPORT_BATCH_SIZE).await?;
+    Ok(stream_export(&lifecycle, cursor, format, "courses"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/export/tutors",
+    tag = "admin",
+    params(
+        ExportParams,
+        TutorFilter,
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "All matching tutors as CSV, JSON Lines or XLSX"),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 413, description = "Too many rows for an XLSX sheet", body = MyErrorResponse)
+    )
+)]
+pub async fn export_tutors(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    lifecycle: web::Data<Lifecycle>,
+    params: web::Query<ExportParams>,
+    filter: web::Query<TutorFilter>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let format = negotiate_format(&params, &req);
+    if format == ExportFormat::Xlsx {
+        check_xlsx_rows(count_tutors_db(&app_state.db, &filter).await?)?;
+    }
+    let cursor = open_tutor_cursor_db(&app_state.db, &filter, EXPORT_BATCH_SIZE).await?;
+    Ok(stream_export(&lifecycle, cursor, format, "tutors"))
+}
+
+fn negotiate_format(params: &ExportParams, req: &HttpRequest) -> ExportFormat {
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    ExportFormat::negotiate(params.format, accept)
+}
+
+//The cursor is opened before responding so connection errors still produce a
+//proper error response. Encoding runs in a separate task feeding a bounded
+//channel, which keeps the database reads in step with the client. The
+//encoding itself is CPU and file bound and runs on the blocking pool.
+fn stream_export<T>(
+    lifecycle: &Lifecycle,
+    cursor: ExportCursor<T>,
+    format: ExportFormat,
+    name: &str,
+) -> HttpResponse
+where
+    T: ExportRow + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+{
+    let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+    //The producer outlives the handler, keep it in the request's span and
+    //let shutdown wait for it
+    lifecycle.spawn(
+        async move {
+            if let Err(err) = produce_export(cursor, format, &sender).await {
+                tracing::error!(error = %err, "export failed");
+                let _ = sender.send(Err(err)).await;
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|chunk| (chunk.map_err(actix_web::Error::from), receiver))
+    });
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition::attachment(format!(
+            "{}.{}",
+            name,
+            format.extension()
+        )))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .streaming(body)
+}
+
+async fn produce_export<T>(
+    mut cursor: ExportCursor<T>,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Result<Bytes, EzyTutorError>>,
+) -> Result<(), EzyTutorError>
+where
+    T: ExportRow + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+{
+    let mut encoder = ExportEncoder::new(format);
+    loop {
+        let batch = cursor.next_batch().await?;
+        if batch.is_empty() {
+            break;
+        }
+        let (chunk, returned) = web::block(move || {
+            let chunk = encoder.encode_batch(&batch);
+            (chunk, encoder)
+        })
+        .await
+        .map_err(|err| EzyTutorError::ActixError(err.to_string()))?;
+        encoder = returned;
+        if let Some(chunk) = chunk? {
+            if !deliver(sender, chunk).await {
+                //Dropping the cursor rolls back its transaction
+                return Ok(());
+            }
+        }
+    }
+    //The connection isn't needed to assemble the file
+    drop(cursor);
+    let tail = web::block(move || encoder.finish::<T>())
+        .await
+        .map_err(|err| EzyTutorError::ActixError(err.to_string()))??;
+    match tail {
+        ExportTail::Bytes(chunk) => {
+            if !chunk.is_empty() {
+                deliver(sender, chunk).await;
+            }
+        }
+        ExportTail::File(file) => {
+            let mut file = tokio::fs::File::from_std(file);
+            loop {
+                let mut chunk = vec![0; 64 * 1024];
+                let read = file
+                    .read(&mut chunk)
+                    .await
+                    .map_err(|err| EzyTutorError::ActixError(err.to_string()))?;
+                if read == 0 {
+                    break;
+                }
+                chunk.truncate(read);
+                if !deliver(sender, Bytes::from(chunk)).await {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+//False once the client went away or stopped reading
+async fn deliver(sender: &mpsc::Sender<Result<Bytes, EzyTutorError>>, chunk: Bytes) -> bool {
+    match timeout(CURSOR_STALL_TIMEOUT, sender.send(Ok(chunk))).await {
+        Ok(sent) => sent.is_ok(),
+        Err(_) => {
+            tracing::warn!(
+                "export abandoned, the client read nothing for {}s",
+                CURSOR_STALL_TIMEOUT.as_secs()
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::dbaccess::course::{get_courses_page_db, post_new_course_db};
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::identity::AdminToken;
+    use crate::models::course::CreateCourse;
+    use crate::models::tutor::{NewTutor, Tutor};
+    use crate::routes::admin_routes;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use sqlx::postgres::PgPool;
+    use std::env;
+
+    async fn test_app_state() -> web::Data<AppState> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool,
+            replica: None,
+            cache: ReadCache::default(),
+        })
+    }
+
+    #[actix_rt::test]
+    async fn export_courses_csv_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_state().await)
+                .app_data(web::Data::new(AdminToken(Some("secret".into()))))
+                .app_data(web::Data::new(Lifecycle::default()))
+                .configure(admin_routes),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/export/courses?tutor_id=1&format=csv")
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let mut lines = body.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("course_id,tutor_id,course_name"));
+        assert!(lines.all(|line| line.split(',').nth(1) == Some("1")));
+    }
+
+    #[actix_rt::test]
+    async fn export_tutors_jsonl_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_state().await)
+                .app_data(web::Data::new(AdminToken(Some("secret".into()))))
+                .app_data(web::Data::new(Lifecycle::default()))
+                .configure(admin_routes),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/export/tutors")
+            .insert_header(("X-Admin-Token", "secret"))
+            .insert_header((header::ACCEPT, "application/x-ndjson"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        let tutors: Vec<Tutor> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(!tutors.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn export_courses_xlsx_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_state().await)
+                .app_data(web::Data::new(AdminToken(Some("secret".into()))))
+                .app_data(web::Data::new(Lifecycle::default()))
+                .configure(admin_routes),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/export/courses?format=xlsx")
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        //xlsx files are zip archives
+        assert!(body.starts_with(b"PK"));
+    }
+
+    #[actix_rt::test]
+    async fn export_without_admin_token_forbidden() {
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_state().await)
+                .app_data(web::Data::new(AdminToken(Some("secret".into()))))
+                .app_data(web::Data::new(Lifecycle::default()))
+                .configure(admin_routes),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/export/courses")
+            .insert_header(("X-Admin-Token", "wrong"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn exports_filter_like_the_course_search() {
+        let app_state = test_app_state().await;
+        let pool = &app_state.db;
+        let tutor = post_new_tutor_db(
+            pool,
+            NewTutor {
+                tutor_name: "Filtered tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic9".into(),
+                tutor_profile: "Teaches percentages".into(),
+            },
+        )
+        .await
+        .unwrap();
+        for (course_name, course_level) in [("Rust 100%", "Beginner"), ("Rust 1000", "Beginner")] {
+            post_new_course_db(
+                pool,
+                CreateCourse {
+                    tutor_id: tutor.tutor_id,
+                    course_name: course_name.into(),
+                    course_description: None,
+                    course_format: None,
+                    course_structure: None,
+                    course_duration: None,
+                    course_price: None,
+                    course_language: None,
+                    course_level: Some(course_level.into()),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        //The wildcard matches itself, the level any case
+        let filter = CourseFilter {
+            tutor_id: Some(tutor.tutor_id),
+            course_name: Some("100%".into()),
+            course_level: Some("beginner".into()),
+            ..CourseFilter::default()
+        };
+        let found = get_courses_page_db(pool, &filter, None, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].course_name, "Rust 100%");
+        assert_eq!(count_courses_db(pool, &filter).await.unwrap(), 1);
+        delete_tutor_db(pool, tutor.tutor_id).await.unwrap();
+    }
+}
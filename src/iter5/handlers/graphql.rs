@@ -0,0 +1,413 @@
+use crate::consistency::Consistency;
+use crate::errors::EzyTutorError;
+use crate::graphql::resolvers::RequestContext;
+use crate::graphql::Graphql;
+use crate::state::AppState;
+use actix_web::error::JsonPayloadError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_graphql::Variables;
+use serde::Deserialize;
+
+//A small query console served by the API itself, it needs no CDN and no
+//inline script or style
+const PLAYGROUND_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>EzyTutors GraphQL</title>
+    <meta charset="utf-8"/>
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <link rel="stylesheet" href="/graphql/playground.css"/>
+  </head>
+  <body>
+    <header>
+      <h1>EzyTutors GraphQL</h1>
+      <button id="run" title="Ctrl+Enter">Run</button>
+      <a href="/graphql/schema.graphql" target="_blank">Schema</a>
+    </header>
+    <main>
+      <section>
+        <textarea id="query" spellcheck="false">{
+  tutors(first: 5) {
+    nodes { id name courses { id name } }
+    pageInfo { hasNextPage endCursor }
+  }
+}</textarea>
+        <textarea id="variables" spellcheck="false" placeholder="Variables as JSON"></textarea>
+      </section>
+      <pre id="result"></pre>
+    </main>
+    <script src="/graphql/playground.js"></script>
+  </body>
+</html>
+"#;
+
+const PLAYGROUND_STYLE: &str = r#"body { margin: 0; font-family: sans-serif; height: 100vh; display: flex; flex-direction: column; }
+header { display: flex; align-items: center; gap: 1em; padding: 0.5em 1em; background: #263238; color: #fff; }
+header h1 { font-size: 1.1em; margin: 0; flex: 1; }
+header a { color: #fff; }
+main { flex: 1; display: flex; min-height: 0; }
+section { flex: 1; display: flex; flex-direction: column; }
+textarea, pre { font-family: monospace; font-size: 0.9em; margin: 0; padding: 0.75em; border: 0; }
+#query { flex: 3; border-bottom: 1px solid #ccc; resize: none; }
+#variables { flex: 1; resize: none; }
+pre { flex: 1; overflow: auto; background: #f5f5f5; border-left: 1px solid #ccc; }
+"#;
+
+const PLAYGROUND_SCRIPT: &str = r#"const query = document.getElementById("query");
+const variables = document.getElementById("variables");
+const result = document.getElementById("result");
+
+async function run() {
+  let vars = null;
+  if (variables.value.trim() !== "") {
+    try {
+      vars = JSON.parse(variables.value);
+    } catch (err) {
+      result.textContent = "Variables are not valid JSON: " + err.message;
+      return;
+    }
+  }
+  result.textContent = "...";
+  try {
+    const resp = await fetch("/graphql", {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ query: query.value, variables: vars }),
+    });
+    const text = await resp.text();
+    try {
+      result.textContent = JSON.stringify(JSON.parse(text), null, 2);
+    } catch (err) {
+      result.textContent = resp.status + " " + text;
+    }
+  } catch (err) {
+    result.textContent = err.message;
+  }
+}
+
+document.getElementById("run").addEventListener("click", run);
+document.addEventListener("keydown", (event) => {
+  if (event.key === "Enter" && (event.ctrlKey || event.metaKey)) {
+    event.preventDefault();
+    run();
+  }
+});
+"#;
+
+//Bodies over the limit of the route are 413, everything else that is not a
+//GraphQL request is 400
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Overflow { limit }
+        | JsonPayloadError::OverflowKnownLength { limit, .. } => EzyTutorError::PayloadTooLarge(
+            format!("GraphQL requests may be at most {} bytes", limit),
+        )
+        .into(),
+        _ => {
+            EzyTutorError::InvalidInput("please provide a valid GraphQL request".to_string()).into()
+        }
+    }
+}
+
+pub async fn graphql_post(
+    app_state: web::Data<AppState>,
+    graphql: web::Data<Graphql>,
+    consistency: Consistency,
+    request: web::Json<async_graphql::Request>,
+) -> HttpResponse {
+    let context = RequestContext::new(app_state, consistency);
+    let response = graphql.execute(request.into_inner(), context, true).await;
+    HttpResponse::Ok().json(response)
+}
+
+//Queries by GET, variables are JSON like in a POST body
+#[derive(Deserialize, Debug)]
+pub struct GraphqlParams {
+    pub query: String,
+    #[serde(rename = "operationName")]
+    pub operation_name: Option<String>,
+    pub variables: Option<String>,
+}
+
+pub async fn graphql_get(
+    app_state: web::Data<AppState>,
+    graphql: web::Data<Graphql>,
+    consistency: Consistency,
+    params: web::Query<GraphqlParams>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let params = params.into_inner();
+    let mut request = async_graphql::Request::new(params.query);
+    if let Some(operation_name) = params.operation_name {
+        request = request.operation_name(operation_name);
+    }
+    if let Some(variables) = params.variables.as_deref() {
+        let variables = serde_json::from_str(variables).map_err(|_| {
+            EzyTutorError::InvalidInput("variables must be a JSON object".to_string())
+        })?;
+        request = request.variables(Variables::from_json(variables));
+    }
+    let context = RequestContext::new(app_state, consistency);
+    let response = graphql.execute(request, context, false).await;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn graphql_schema(graphql: web::Data<Graphql>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(graphql.schema.sdl())
+}
+
+pub async fn graphql_playground() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(PLAYGROUND_PAGE)
+}
+
+pub async fn graphql_playground_script() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        .body(PLAYGROUND_SCRIPT)
+}
+
+pub async fn graphql_playground_style() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .body(PLAYGROUND_STYLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::config::GraphqlConfig;
+    use crate::metrics::metrics;
+    use crate::routes::graphql_routes;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use serde_json::{json, Value};
+    use sqlx::postgres::PgPool;
+    use std::env;
+
+    async fn app_state() -> web::Data<AppState> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let db_pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
+        })
+    }
+
+    fn graphql() -> web::Data<Graphql> {
+        web::Data::new(Graphql::from_config(&GraphqlConfig::default()))
+    }
+
+    fn routes(cfg: &mut web::ServiceConfig) {
+        graphql_routes(cfg, GraphqlConfig::default().max_body_bytes);
+    }
+
+    //Enough of percent-encoding for the queries below
+    fn encode(query: &str) -> String {
+        query
+            .replace(' ', "%20")
+            .replace('{', "%7B")
+            .replace('}', "%7D")
+            .replace(',', "%2C")
+    }
+
+    fn query_count(query: &str) -> u64 {
+        metrics()
+            .db_query_duration
+            .with_label_values(&[query])
+            .get_sample_count()
+    }
+
+    #[actix_rt::test]
+    async fn relations_are_loaded_in_batches() {
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state().await)
+                .app_data(graphql())
+                .configure(routes),
+        )
+        .await;
+        let courses_before = query_count("get_courses_for_tutors");
+        let tutors_before = query_count("get_tutors_by_ids");
+
+        let query = "{ tutors(first: 20) { nodes { id courses { id tutor { id } } } } }";
+        let req = test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({ "query": query }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.get("errors").is_none(), "{}", resp);
+        let tutors = resp["data"]["tutors"]["nodes"].as_array().unwrap();
+        assert!(tutors.len() > 1);
+        for tutor in tutors {
+            for course in tutor["courses"].as_array().unwrap() {
+                assert_eq!(course["tutor"]["id"], tutor["id"]);
+            }
+        }
+        //One query per relation, not one per tutor or course
+        assert_eq!(query_count("get_courses_for_tutors"), courses_before + 1);
+        assert_eq!(query_count("get_tutors_by_ids"), tutors_before + 1);
+    }
+
+    #[actix_rt::test]
+    async fn mutations_mirror_the_rest_api() {
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state().await)
+                .app_data(graphql())
+                .configure(routes),
+        )
+        .await;
+        let post = |query: &str, variables: Value| {
+            test::TestRequest::post()
+                .uri("/graphql")
+                .set_json(json!({ "query": query, "variables": variables }))
+                .to_request()
+        };
+
+        let req = post(
+            "mutation($input: NewTutorInput!) { createTutor(input: $input) { id name } }",
+            json!({"input": {"name": "GraphQL tutor", "pictureUrl": "", "profile": "Tests"}}),
+        );
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let tutor_id = resp["data"]["createTutor"]["id"].as_i64().unwrap();
+        assert_eq!(resp["data"]["createTutor"]["name"], "GraphQL tutor");
+
+        let req = post(
+            "mutation($input: NewCourseInput!) { createCourse(input: $input) { id price } }",
+            json!({"input": {"tutorId": tutor_id, "name": "Schemas", "price": 120}}),
+        );
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let course_id = resp["data"]["createCourse"]["id"].as_i64().unwrap();
+        assert_eq!(resp["data"]["createCourse"]["price"], 120);
+
+        let req = post(
+            "mutation($t: Int!, $c: Int!) {
+              updateCourse(tutorId: $t, id: $c, input: {level: \"Beginner\"}) { name level }
+            }",
+            json!({"t": tutor_id, "c": course_id}),
+        );
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp["data"]["updateCourse"],
+            json!({"name": "Schemas", "level": "Beginner"})
+        );
+
+        let req = post(
+            "mutation { createCourse(input: {tutorId: 1, name: \" \"}) { id } }",
+            json!({}),
+        );
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["data"], json!(null));
+        assert_eq!(
+            resp["errors"][0]["message"],
+            "course_name must not be empty"
+        );
+        assert_eq!(resp["errors"][0]["path"], json!(["createCourse"]));
+
+        //Queries also work with GET, mutations don't
+        let uri = format!(
+            "/graphql?query={}",
+            encode(&format!(
+                "{{ course(tutorId: {}, id: {}) {{ name }} }}",
+                tutor_id, course_id
+            ))
+        );
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["data"]["course"]["name"], "Schemas");
+        let uri = format!(
+            "/graphql?query={}",
+            encode(&format!("mutation {{ deleteTutor(id: {}) }}", tutor_id))
+        );
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(resp["data"].is_null());
+        assert_eq!(
+            resp["errors"][0]["message"],
+            "mutations must be sent with POST"
+        );
+
+        let req = post(
+            "mutation($t: Int!, $c: Int!) { deleteCourse(tutorId: $t, id: $c) deleteTutor(id: $t) }",
+            json!({"t": tutor_id, "c": course_id}),
+        );
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp["data"],
+            json!({"deleteCourse": true, "deleteTutor": true})
+        );
+        let req = post(
+            "query($id: Int!) { tutor(id: $id) { id } }",
+            json!({"id": tutor_id}),
+        );
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["data"], json!({"tutor": null}));
+    }
+
+    #[actix_rt::test]
+    async fn deep_and_large_queries_are_refused() {
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state().await)
+                .app_data(graphql())
+                .configure(routes),
+        )
+        .await;
+        let post = |query: String| {
+            test::TestRequest::post()
+                .uri("/graphql")
+                .set_json(json!({ "query": query }))
+                .to_request()
+        };
+
+        //Refused before parsing, however deep the document is
+        let query = format!("query{}", "{a".repeat(20_000));
+        let resp: Value = test::call_and_read_body_json(&app, post(query)).await;
+        assert_eq!(
+            resp["errors"][0]["message"],
+            "query is nested deeper than 20 levels"
+        );
+        let query = format!(
+            "{{ tutors(first: {}1{}) {{ nodes {{ id }} }} }}",
+            "[".repeat(30),
+            "]".repeat(30)
+        );
+        let resp: Value = test::call_and_read_body_json(&app, post(query)).await;
+        assert!(resp["data"].is_null());
+
+        //Parsed, but over the depth and complexity limits
+        let query = format!(
+            "{{ tutors {{ nodes {{ {} id {} }} }} }}",
+            "courses(first: 1) { tutor { ".repeat(4),
+            "} } ".repeat(4)
+        );
+        let resp: Value = test::call_and_read_body_json(&app, post(query)).await;
+        assert!(resp["data"].is_null());
+        assert!(resp["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nested too deep"));
+        let query = "{ a: tutors(first: 100) { nodes { courses(first: 100) { id } } } }";
+        let resp: Value = test::call_and_read_body_json(&app, post(query.to_string())).await;
+        assert!(resp["data"].is_null());
+        assert!(resp["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("too complex"));
+
+        let query = format!(
+            "{{ tutors {{ nodes {{ id }} }} }} #{}",
+            "x".repeat(64 * 1024)
+        );
+        let resp = test::call_service(&app, post(query)).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::dbaccess::tutor::{get_tutor_details_db, update_tutor_pic_url_db};
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::identity::Requester;
+use crate::media::picture::{original_key, process_picture, thumbnail_key};
+use crate::media::{validate_key, MediaState};
+use crate::models::tutor::TutorPicture;
+use crate::openapi::PictureUpload;
+use crate::state::AppState;
+use actix_multipart::Multipart;
+use actix_web::http::header;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+//Stored media never changes under a given key, so clients may cache it for good
+const MEDIA_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+const PUBLIC_MEDIA_PREFIX: &str = "tutors/";
+
+#[utoipa::path(
+    post,
+    path = "/v1/tutors/{tutor_id}/picture",
+    tag = "tutors",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("X-Tutor-Id" = i32, Header, description = "Authenticated tutor, must be the same tutor")
+    ),
+    request_body(content = PictureUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Stored picture and thumbnail URLs", body = TutorPicture),
+        (status = 400, description = "Not a supported image", body = MyErrorResponse),
+        (status = 403, description = "Not the same tutor", body = MyErrorResponse),
+        (status = 404, description = "Tutor not found", body = MyErrorResponse),
+        (status = 413, description = "Picture too large", body = MyErrorResponse)
+    )
+)]
+pub async fn upload_tutor_picture(
+    app_state: web::Data<AppState>,
+    media_state: web::Data<MediaState>,
+    path: web::Path<i32>,
+    requester: Requester,
+    mut payload: Multipart,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    requester.require_tutor(tutor_id)?;
+    let tutor = get_tutor_details_db(&app_state.db, tutor_id).await?;
+
+    //Read the "picture" field, enforcing the size limit while streaming
+    let mut upload: Option<(Option<String>, Bytes)> = None;
+    while let Some(mut field) = payload.try_next().await? {
+        if field.name() != Some("picture") {
+            continue;
+        }
+        let declared_type = field
+            .content_type()
+            .map(|mime| mime.essence_str().to_string());
+        let mut data = BytesMut::new();
+        while let Some(chunk) = field.try_next().await? {
+            if data.len() + chunk.len() > media_state.max_upload_bytes {
+                return Err(EzyTutorError::PayloadTooLarge(format!(
+                    "picture must not be larger than {} bytes",
+                    media_state.max_upload_bytes
+                )));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        upload = Some((declared_type, data.freeze()));
+    }
+    let (declared_type, data) = upload.ok_or_else(|| {
+        EzyTutorError::InvalidInput("multipart field \"picture\" is missing".into())
+    })?;
+
+    //Decoding and resizing is CPU bound, keep it off the async workers
+    let picture = web::block(move || process_picture(declared_type.as_deref(), data))
+        .await
+        .map_err(|err| EzyTutorError::ActixError(err.to_string()))??;
+
+    let store = &media_state.store;
+    let key = original_key(tutor_id, &picture.digest, picture.extension);
+    store
+        .put(&key, picture.content_type, picture.original.clone())
+        .await?;
+    let mut thumbnails = BTreeMap::new();
+    for (size, data) in &picture.thumbnails {
+        let thumb_key = thumbnail_key(tutor_id, &picture.digest, *size);
+        store.put(&thumb_key, "image/png", data.clone()).await?;
+        thumbnails.insert(*size, media_state.public_url(&thumb_key));
+    }
+
+    let tutor_pic_url = media_state.public_url(&key);
+    update_tutor_pic_url_db(&app_state.db, tutor_id, &tutor_pic_url).await?;
+
+    //Remove the previous upload, unless the same picture was uploaded again
+    if let Some(old_key) = media_state.key_from_url(&tutor.tutor_pic_url) {
+        if old_key != key {
+            delete_picture_files(&media_state, &old_key).await;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(TutorPicture {
+        tutor_id,
+        tutor_pic_url,
+        thumbnails,
+    }))
+}
+
+//Best effort cleanup of an original and its thumbnails
+async fn delete_picture_files(media_state: &MediaState, original: &str) {
+    let Some((dir, _)) = original.rsplit_once('/') else {
+        return;
+    };
+    let mut keys = vec![original.to_string()];
+    for size in crate::media::picture::THUMBNAIL_SIZES {
+        keys.push(format!("{}/thumb_{}.png", dir, size));
+    }
+    for key in keys {
+        if let Err(err) = media_state.store.delete(&key).await {
+            tracing::warn!(key = %key, error = %err, "could not delete old media");
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/media/{key}",
+    tag = "media",
+    params(("key" = String, Path, description = "Storage key, may contain slashes")),
+    responses(
+        (status = 200, description = "The stored file"),
+        (status = 304, description = "Not modified"),
+        (status = 404, description = "Media not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_media(
+    media_state: web::Data<MediaState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let key = path.into_inner();
+    validate_key(&key)?;
+    //Attachments share the store but are access controlled, only pictures are public
+    if !key.starts_with(PUBLIC_MEDIA_PREFIX) {
+        return Err(EzyTutorError::NotFound("Media not found".into()));
+    }
+    let etag = format!("\"{}\"", &hex::encode(Sha256::digest(key.as_bytes()))[..16]);
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        })
+        .unwrap_or(false);
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CACHE_CONTROL, MEDIA_CACHE_CONTROL))
+            .finish());
+    }
+
+    let media = media_state.store.get(&key).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(media.content_type)
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, MEDIA_CACHE_CONTROL))
+        .body(media.data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::identity::TUTOR_ID_HEADER;
+    use crate::media::local::LocalMediaStore;
+    use crate::models::tutor::NewTutor;
+    use crate::routes::{media_routes, tutor_routes};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use image::{ImageFormat, RgbImage};
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    async fn test_states(max_upload_bytes: usize) -> (web::Data<AppState>, web::Data<MediaState>) {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let media_state = web::Data::new(MediaState {
+            store: Arc::new(LocalMediaStore::new(
+                env::temp_dir().join("tutor-db-media-test"),
+            )),
+            public_base_url: "http://localhost:3000".into(),
+            max_upload_bytes,
+            attachment_max_bytes: max_upload_bytes,
+            attachment_quota_bytes: 10 * max_upload_bytes as i64,
+        });
+        (app_state, media_state)
+    }
+
+    //Uploads replace the picture, so each test changes a tutor of its own
+    async fn own_tutor(pool: &PgPool) -> i32 {
+        post_new_tutor_db(
+            pool,
+            NewTutor {
+                tutor_name: "Pictured tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic8".into(),
+                tutor_profile: "Uploads pictures".into(),
+            },
+        )
+        .await
+        .unwrap()
+        .tutor_id
+    }
+
+    fn multipart_body(content_type: &str, data: &[u8]) -> (String, Vec<u8>) {
+        let boundary = "tutordbboundary";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"picture\"; filename=\"pic\"\r\n\
+             Content-Type: {}\r\n\r\n",
+            boundary, content_type
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        (format!("multipart/form-data; boundary={}", boundary), body)
+    }
+
+    fn png_bytes() -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        RgbImage::from_pixel(300, 200, image::Rgb([10, 120, 200]))
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    #[actix_rt::test]
+    async fn upload_tutor_picture_success() {
+        let (app_state, media_state) = test_states(1024 * 1024).await;
+        let pool = app_state.db.clone();
+        let tutor_id = own_tutor(&pool).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(tutor_routes)
+                .configure(media_routes),
+        )
+        .await;
+        let (content_type, body) = multipart_body("image/png", &png_bytes());
+        let req = test::TestRequest::post()
+            .uri(&format!("/tutors/{}/picture", tutor_id))
+            .insert_header((TUTOR_ID_HEADER, tutor_id.to_string()))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let picture: TutorPicture = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(picture.thumbnails.len(), 2);
+
+        let media_path = picture
+            .tutor_pic_url
+            .strip_prefix("http://localhost:3000")
+            .unwrap()
+            .to_string();
+        let req = test::TestRequest::get().uri(&media_path).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            MEDIA_CACHE_CONTROL
+        );
+        delete_tutor_db(&pool, tutor_id).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn upload_tutor_picture_of_another_tutor() {
+        let (app_state, media_state) = test_states(1024 * 1024).await;
+        let pool = app_state.db.clone();
+        let tutor_id = own_tutor(&pool).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(tutor_routes),
+        )
+        .await;
+        for requester in [None, Some(tutor_id + 1)] {
+            let (content_type, body) = multipart_body("image/png", &png_bytes());
+            let mut req = test::TestRequest::post()
+                .uri(&format!("/tutors/{}/picture", tutor_id))
+                .insert_header((header::CONTENT_TYPE, content_type));
+            if let Some(requester) = requester {
+                req = req.insert_header((TUTOR_ID_HEADER, requester.to_string()));
+            }
+            let resp = test::call_service(&app, req.set_payload(body).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        }
+        let tutor = get_tutor_details_db(&pool, tutor_id).await.unwrap();
+        assert_eq!(tutor.tutor_pic_url, "http://s3.amazon.aws.com/pic8");
+        delete_tutor_db(&pool, tutor_id).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn upload_tutor_picture_invalid_type() {
+        let (app_state, media_state) = test_states(1024 * 1024).await;
+        let pool = app_state.db.clone();
+        let tutor_id = own_tutor(&pool).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(tutor_routes),
+        )
+        .await;
+        let (content_type, body) = multipart_body("image/png", b"definitely not a picture");
+        let req = test::TestRequest::post()
+            .uri(&format!("/tutors/{}/picture", tutor_id))
+            .insert_header((TUTOR_ID_HEADER, tutor_id.to_string()))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        delete_tutor_db(&pool, tutor_id).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn upload_tutor_picture_too_large() {
+        let (app_state, media_state) = test_states(64).await;
+        let pool = app_state.db.clone();
+        let tutor_id = own_tutor(&pool).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(media_state)
+                .configure(tutor_routes),
+        )
+        .await;
+        let (content_type, body) = multipart_body("image/png", &png_bytes());
+        let req = test::TestRequest::post()
+            .uri(&format!("/tutors/{}/picture", tutor_id))
+            .insert_header((TUTOR_ID_HEADER, tutor_id.to_string()))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        delete_tutor_db(&pool, tutor_id).await.unwrap();
+    }
+}
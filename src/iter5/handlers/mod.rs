@@ -1,3 +1,14 @@
+pub mod attachment;
+pub mod chat;
 pub mod course;
+pub mod docs;
+pub mod export;
 pub mod general;
+pub mod graphql;
+pub mod media;
+pub mod metrics;
+pub mod notification;
+pub mod stream;
 pub mod tutor;
+pub mod v2;
+pub mod webhook;
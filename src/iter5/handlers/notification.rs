@@ -0,0 +1,192 @@
+use crate::dbaccess::notification::*;
+use crate::dbaccess::tutor::get_tutor_details_db;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::identity::Requester;
+use crate::models::chat::{ChatRole, Participant};
+use crate::models::notification::{NotificationPreferences, SetPreferences};
+use crate::state::AppState;
+use actix_web::{web, HttpResponse};
+
+fn recipient(requester: Requester) -> Result<Participant, EzyTutorError> {
+    requester.participant().ok_or_else(|| {
+        EzyTutorError::Forbidden("notifications are only sent to tutors and students".into())
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/notifications/preferences",
+    tag = "notifications",
+    params(
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    responses(
+        (status = 200, description = "Notification preferences of the caller",
+            body = NotificationPreferences),
+        (status = 403, description = "Neither tutor nor student", body = MyErrorResponse),
+        (status = 404, description = "No preferences set, the caller gets no emails",
+            body = MyErrorResponse)
+    )
+)]
+pub async fn get_notification_preferences(
+    app_state: web::Data<AppState>,
+    requester: Requester,
+) -> Result<HttpResponse, EzyTutorError> {
+    let preferences = get_preferences_db(&app_state.db, recipient(requester)?).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+#[utoipa::path(
+    put,
+    path = "/notifications/preferences",
+    tag = "notifications",
+    params(
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    request_body = SetPreferences,
+    responses(
+        (status = 200, description = "The preferences, emails go to their address from now on",
+            body = NotificationPreferences),
+        (status = 400, description = "Invalid preferences", body = MyErrorResponse),
+        (status = 403, description = "Neither tutor nor student", body = MyErrorResponse),
+        (status = 404, description = "Tutor not found", body = MyErrorResponse)
+    )
+)]
+pub async fn put_notification_preferences(
+    app_state: web::Data<AppState>,
+    requester: Requester,
+    preferences: web::Json<SetPreferences>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let recipient = recipient(requester)?;
+    let preferences = preferences.into_inner();
+    preferences
+        .validate()
+        .map_err(EzyTutorError::InvalidInput)?;
+    //Students have no records here, tutors do
+    if recipient.role == ChatRole::Tutor {
+        get_tutor_details_db(&app_state.db, recipient.id).await?;
+    }
+    let preferences = set_preferences_db(&app_state.db, recipient, &preferences).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/notifications/preferences",
+    tag = "notifications",
+    params(
+        ("X-Tutor-Id" = Option<i32>, Header, description = "Authenticated tutor"),
+        ("X-Student-Id" = Option<i32>, Header, description = "Authenticated student")
+    ),
+    responses(
+        (status = 200, description = "Deletion message, the caller gets no more emails",
+            body = String),
+        (status = 403, description = "Neither tutor nor student", body = MyErrorResponse),
+        (status = 404, description = "No preferences set", body = MyErrorResponse)
+    )
+)]
+pub async fn delete_notification_preferences(
+    app_state: web::Data<AppState>,
+    requester: Requester,
+) -> Result<HttpResponse, EzyTutorError> {
+    let deleted = delete_preferences_db(&app_state.db, recipient(requester)?).await?;
+    Ok(HttpResponse::Ok().json(deleted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::identity::{STUDENT_ID_HEADER, TUTOR_ID_HEADER};
+    use crate::routes::notification_routes;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use serde_json::json;
+    use sqlx::postgres::PgPool;
+    use std::env;
+
+    #[actix_rt::test]
+    async fn preferences_are_set_read_and_deleted() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .configure(notification_routes),
+        )
+        .await;
+        let student = (STUDENT_ID_HEADER, "920001");
+        let preferences = || test::TestRequest::default().uri("/notifications/preferences");
+
+        let resp = test::call_service(&app, preferences().to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(
+            &app,
+            preferences()
+                .method(Method::PUT)
+                .insert_header((TUTOR_ID_HEADER, "-7"))
+                .set_json(json!({ "email": "nobody@example.com" }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        for invalid in [
+            json!({ "email": "not an address" }),
+            json!({ "email": "ada@example.com", "locale": "fr" }),
+            json!({ "email": "ada@example.com", "muted": ["newsletter"] }),
+        ] {
+            let resp = test::call_service(
+                &app,
+                preferences()
+                    .method(Method::PUT)
+                    .insert_header(student)
+                    .set_json(invalid)
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let resp = test::call_service(
+            &app,
+            preferences()
+                .method(Method::PUT)
+                .insert_header(student)
+                .set_json(json!({
+                    "email": "ada@example.com",
+                    "locale": "de",
+                    "muted": ["course_updated"],
+                }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp =
+            test::call_service(&app, preferences().insert_header(student).to_request()).await;
+        let stored: NotificationPreferences = test::read_body_json(resp).await;
+        assert_eq!(stored.email, "ada@example.com");
+        assert_eq!(stored.locale, "de");
+        assert_eq!(stored.muted, ["course_updated"]);
+
+        let delete = || {
+            preferences()
+                .method(Method::DELETE)
+                .insert_header(student)
+                .to_request()
+        };
+        let resp = test::call_service(&app, delete()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, delete()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
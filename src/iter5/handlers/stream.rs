@@ -0,0 +1,234 @@
+use crate::dbaccess::tutor::get_tutor_details_db;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::lifecycle::Lifecycle;
+use crate::state::AppState;
+use crate::streams::{event_stream, EventHub, StreamFilter};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+#[utoipa::path(
+    get,
+    path = "/events/courses",
+    tag = "events",
+    params(
+        ("Last-Event-ID" = Option<String>, Header, description = "Id of the last event received, to resume after it")
+    ),
+    responses(
+        (status = 200, description = "Server-sent course.created, course.updated and course.deleted events \
+            of every tutor as they are committed. A reset event means events were missed.",
+            content_type = "text/event-stream", body = String)
+    )
+)]
+pub async fn get_course_events(
+    hub: web::Data<EventHub>,
+    lifecycle: web::Data<Lifecycle>,
+    req: HttpRequest,
+) -> HttpResponse {
+    stream_events(&hub, StreamFilter::Courses, lifecycle, &req)
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/tutors/{tutor_id}",
+    tag = "events",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("Last-Event-ID" = Option<String>, Header, description = "Id of the last event received, to resume after it")
+    ),
+    responses(
+        (status = 200, description = "Server-sent events of the tutor and its courses as they are committed. \
+            A reset event means events were missed.",
+            content_type = "text/event-stream", body = String),
+        (status = 404, description = "Tutor not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_tutor_events(
+    app_state: web::Data<AppState>,
+    hub: web::Data<EventHub>,
+    lifecycle: web::Data<Lifecycle>,
+    path: web::Path<i32>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    get_tutor_details_db(&app_state.db, tutor_id).await?;
+    Ok(stream_events(
+        &hub,
+        StreamFilter::Tutor(tutor_id),
+        lifecycle,
+        &req,
+    ))
+}
+
+fn stream_events(
+    hub: &EventHub,
+    filter: StreamFilter,
+    lifecycle: web::Data<Lifecycle>,
+    req: &HttpRequest,
+) -> HttpResponse {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok());
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        //Proxies such as nginx would hold the events back otherwise
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(event_stream(hub, filter, last_event_id, lifecycle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::config::StreamConfig;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db, update_tutor_details_db};
+    use crate::models::tutor::{NewTutor, UpdateTutor};
+    use crate::routes::stream_routes;
+    use crate::streams::listen;
+    use actix_web::body::MessageBody;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use dotenv::dotenv;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::time::Duration;
+
+    //Reads the body until it has an event of the type, returns its id (empty
+    //for resets) and data
+    async fn next_event(
+        body: &mut (impl MessageBody + Unpin),
+        event_type: &str,
+    ) -> (String, serde_json::Value) {
+        let mut text = String::new();
+        loop {
+            let chunk = actix_rt::time::timeout(
+                Duration::from_secs(10),
+                futures_util::future::poll_fn(|cx| std::pin::Pin::new(&mut *body).poll_next(cx)),
+            )
+            .await
+            .expect("no event within 10s")
+            .expect("stream ended")
+            .unwrap_or_else(|_| panic!("stream failed"));
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+            for event in text.split("\n\n") {
+                let field = |name: &str| {
+                    event
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(str::to_string)
+                };
+                if field("event: ").as_deref() == Some(event_type) {
+                    let data = serde_json::from_str(&field("data: ").unwrap()).unwrap();
+                    return (field("id: ").unwrap_or_default(), data);
+                }
+            }
+        }
+    }
+
+    async fn listener_ready(pool: &PgPool) {
+        for _ in 0..50 {
+            let listening = sqlx::query_scalar!(
+                "SELECT count(*) FROM pg_stat_activity WHERE query LIKE 'LISTEN %ezy_outbox%'"
+            )
+            .fetch_one(pool)
+            .await
+            .unwrap();
+            if listening > Some(0) {
+                return;
+            }
+            actix_rt::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the outbox listener did not start");
+    }
+
+    #[actix_rt::test]
+    async fn tutor_stream_pushes_changes_and_resumes_after_last_event_id() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let hub = web::Data::new(EventHub::from_config(&StreamConfig::default()));
+        let listener = actix_rt::spawn(listen(hub.clone(), pool.clone()));
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool.clone(),
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(hub)
+                .app_data(web::Data::new(Lifecycle::default()))
+                .configure(stream_routes),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/events/tutors/-1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Streamed tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic7".into(),
+                tutor_profile: "Watched live".into(),
+            },
+        )
+        .await
+        .unwrap();
+        listener_ready(&pool).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/events/tutors/{}", tutor.tutor_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        let mut body = resp.into_body();
+
+        let rename = |name: &str| UpdateTutor {
+            tutor_name: Some(name.to_string()),
+            tutor_pic_url: None,
+            tutor_profile: None,
+        };
+        update_tutor_details_db(&pool, tutor.tutor_id, rename("Renamed once"))
+            .await
+            .unwrap();
+        let (first_id, data) = next_event(&mut body, "tutor.updated").await;
+        assert_eq!(data["data"]["tutor_name"], "Renamed once");
+        assert_eq!(data["id"], first_id);
+        update_tutor_details_db(&pool, tutor.tutor_id, rename("Renamed twice"))
+            .await
+            .unwrap();
+        let (second_id, _) = next_event(&mut body, "tutor.updated").await;
+        drop(body);
+
+        //Reconnecting after the first update gets the second one again
+        let req = test::TestRequest::get()
+            .uri(&format!("/events/tutors/{}", tutor.tutor_id))
+            .insert_header(("Last-Event-ID", first_id.as_str()))
+            .to_request();
+        let mut body = test::call_service(&app, req).await.into_body();
+        let (replayed_id, data) = next_event(&mut body, "tutor.updated").await;
+        assert_eq!(replayed_id, second_id);
+        assert_eq!(data["data"]["tutor_name"], "Renamed twice");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/events/tutors/{}", tutor.tutor_id))
+            .insert_header(("Last-Event-ID", "not-buffered"))
+            .to_request();
+        let mut body = test::call_service(&app, req).await.into_body();
+        next_event(&mut body, "reset").await;
+        drop(body);
+
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+        //Stops the listener like at shutdown
+        pool.close().await;
+        listener.await.unwrap();
+    }
+}
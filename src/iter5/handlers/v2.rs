@@ -0,0 +1,368 @@
+use crate::cache::CacheKey;
+use crate::consistency::Consistency;
+use crate::dbaccess::course::*;
+use crate::dbaccess::tutor::*;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::models::course::Course;
+use crate::models::tutor::Tutor;
+use crate::models::v2::{
+    CourseV2, CreateCourseV2, NewTutorV2, PriceCurrency, TutorV2, UpdateCourseV2, UpdateTutorV2,
+};
+use crate::state::AppState;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+
+//Same reads and writes as v1, only the representations differ. Operation ids
+//get a suffix, the v1 handlers of the same name are in the spec as well.
+
+#[utoipa::path(
+    get,
+    path = "/v2/courses/{tutor_id}",
+    operation_id = "get_courses_for_tutor_v2",
+    tag = "courses",
+    params(("tutor_id" = i32, Path, description = "Id of the tutor")),
+    responses(
+        (status = 200, description = "All courses of the tutor", body = [CourseV2]),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
+pub async fn get_courses_for_tutor(
+    app_state: web::Data<AppState>,
+    currency: web::Data<PriceCurrency>,
+    path: web::Path<i32>,
+    consistency: Consistency,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    let courses: Vec<Course> = app_state
+        .cached_read(
+            CacheKey::CoursesForTutor(tutor_id),
+            consistency,
+            |pool| async move { get_courses_for_tutor_db(&pool, tutor_id).await },
+        )
+        .await?;
+    let courses: Vec<CourseV2> = courses
+        .into_iter()
+        .map(|course| CourseV2::from_course(course, &currency))
+        .collect();
+    Ok(HttpResponse::Ok()
+        .insert_header(app_state.cache.cache_control())
+        .json(courses))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/courses/{tutor_id}/{course_id}",
+    operation_id = "get_course_details_v2",
+    tag = "courses",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course")
+    ),
+    responses(
+        (status = 200, description = "The course", body = CourseV2),
+        (status = 404, description = "Course not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_course_details(
+    app_state: web::Data<AppState>,
+    currency: web::Data<PriceCurrency>,
+    path: web::Path<(i32, i32)>,
+    consistency: Consistency,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = path.into_inner();
+    let course = app_state
+        .read(consistency, |pool| async move {
+            get_course_details_db(&pool, tutor_id, course_id).await
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(CourseV2::from_course(course, &currency)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/courses/",
+    operation_id = "post_new_course_v2",
+    tag = "courses",
+    request_body = CreateCourseV2,
+    responses(
+        (status = 201, description = "The created course", body = CourseV2),
+        (status = 400, description = "Invalid input", body = MyErrorResponse),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
+pub async fn post_new_course(
+    app_state: web::Data<AppState>,
+    currency: web::Data<PriceCurrency>,
+    new_course: web::Json<CreateCourseV2>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let new_course = new_course
+        .into_inner()
+        .into_create_course(&currency)
+        .map_err(EzyTutorError::InvalidInput)?;
+    let course = post_new_course_db(&app_state.db, new_course).await?;
+    Ok(HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            format!("/v2/courses/{}/{}", course.tutor_id, course.course_id),
+        ))
+        .json(CourseV2::from_course(course, &currency)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v2/courses/{tutor_id}/{course_id}",
+    operation_id = "update_course_details_v2",
+    tag = "courses",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course")
+    ),
+    request_body = UpdateCourseV2,
+    responses(
+        (status = 200, description = "The updated course", body = CourseV2),
+        (status = 400, description = "Invalid input", body = MyErrorResponse),
+        (status = 404, description = "Course not found", body = MyErrorResponse)
+    )
+)]
+pub async fn update_course_details(
+    app_state: web::Data<AppState>,
+    currency: web::Data<PriceCurrency>,
+    update_course: web::Json<UpdateCourseV2>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = path.into_inner();
+    let update_course = update_course
+        .into_inner()
+        .into_update_course(&currency)
+        .map_err(EzyTutorError::InvalidInput)?;
+    let course =
+        update_course_details_db(&app_state.db, tutor_id, course_id, update_course).await?;
+    Ok(HttpResponse::Ok().json(CourseV2::from_course(course, &currency)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v2/courses/{tutor_id}/{course_id}",
+    operation_id = "delete_course_v2",
+    tag = "courses",
+    params(
+        ("tutor_id" = i32, Path, description = "Id of the tutor"),
+        ("course_id" = i32, Path, description = "Id of the course")
+    ),
+    responses(
+        (status = 204, description = "The course is gone"),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
+pub async fn delete_course(
+    app_state: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = path.into_inner();
+    delete_course_db(&app_state.db, tutor_id, course_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/tutors/",
+    operation_id = "get_all_tutors_v2",
+    tag = "tutors",
+    responses(
+        (status = 200, description = "All tutors", body = [TutorV2]),
+        (status = 404, description = "No tutors found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_all_tutors(
+    app_state: web::Data<AppState>,
+    consistency: Consistency,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutors: Vec<Tutor> = app_state
+        .cached_read(CacheKey::AllTutors, consistency, |pool| async move {
+            get_all_tutors_db(&pool).await
+        })
+        .await?;
+    let tutors: Vec<TutorV2> = tutors.into_iter().map(TutorV2::from).collect();
+    Ok(HttpResponse::Ok()
+        .insert_header(app_state.cache.cache_control())
+        .json(tutors))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/tutors/{tutor_id}",
+    operation_id = "get_tutor_details_v2",
+    tag = "tutors",
+    params(("tutor_id" = i32, Path, description = "Id of the tutor")),
+    responses(
+        (status = 200, description = "The tutor", body = TutorV2),
+        (status = 404, description = "Tutor not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_tutor_details(
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    consistency: Consistency,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    let tutor: Tutor = app_state
+        .cached_read(CacheKey::Tutor(tutor_id), consistency, |pool| async move {
+            get_tutor_details_db(&pool, tutor_id).await
+        })
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(app_state.cache.cache_control())
+        .json(TutorV2::from(tutor)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/tutors/",
+    operation_id = "post_new_tutor_v2",
+    tag = "tutors",
+    request_body = NewTutorV2,
+    responses(
+        (status = 201, description = "The created tutor", body = TutorV2),
+        (status = 400, description = "Invalid JSON input", body = MyErrorResponse),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
+pub async fn post_new_tutor(
+    app_state: web::Data<AppState>,
+    new_tutor: web::Json<NewTutorV2>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor = post_new_tutor_db(&app_state.db, new_tutor.into_inner().into()).await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/v2/tutors/{}", tutor.tutor_id)))
+        .json(TutorV2::from(tutor)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v2/tutors/{tutor_id}",
+    operation_id = "update_tutor_details_v2",
+    tag = "tutors",
+    params(("tutor_id" = i32, Path, description = "Id of the tutor")),
+    request_body = UpdateTutorV2,
+    responses(
+        (status = 200, description = "The updated tutor", body = TutorV2),
+        (status = 400, description = "Invalid JSON input", body = MyErrorResponse),
+        (status = 404, description = "Tutor not found", body = MyErrorResponse)
+    )
+)]
+pub async fn update_tutor_details(
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    update_tutor: web::Json<UpdateTutorV2>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    let tutor =
+        update_tutor_details_db(&app_state.db, tutor_id, update_tutor.into_inner().into()).await?;
+    Ok(HttpResponse::Ok().json(TutorV2::from(tutor)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v2/tutors/{tutor_id}",
+    operation_id = "delete_tutor_v2",
+    tag = "tutors",
+    params(("tutor_id" = i32, Path, description = "Id of the tutor")),
+    responses(
+        (status = 204, description = "The tutor and their courses are gone"),
+        (status = 500, description = "Database error", body = MyErrorResponse)
+    )
+)]
+pub async fn delete_tutor(
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = path.into_inner();
+    delete_tutor_db(&app_state.db, tutor_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::models::v2::Money;
+    use crate::routes::api_routes;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App, ResponseError};
+    use dotenv::dotenv;
+    use sqlx::postgres::PgPool;
+    use std::env;
+
+    #[actix_rt::test]
+    async fn v1_and_v2_read_the_same_course() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let db_pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(web::Data::new(PriceCurrency("USD".to_string())))
+                .configure(api_routes),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/v1/courses/1/1").to_request();
+        let v1: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get().uri("/v2/courses/1/1").to_request();
+        let v2: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(v1["course_name"], v2["name"]);
+        assert_eq!(v1["course_id"], v2["id"]);
+        assert_eq!(v1["course_price"], v2["price"]["amount"]);
+        assert_eq!(v2["price"]["currency"], "USD");
+        assert!(v2.get("course_name").is_none());
+
+        let req = test::TestRequest::get().uri("/v2/tutors/1").to_request();
+        let tutor: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(tutor["id"], 1);
+
+        //Endpoints without a new representation are only in v1
+        let req = test::TestRequest::post()
+            .uri("/v2/courses/1/import")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn v2_rejects_prices_in_other_currencies() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let db_pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: db_pool,
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let new_course = web::Json(CreateCourseV2 {
+            tutor_id: 1,
+            name: "Priced course".into(),
+            description: None,
+            format: None,
+            structure: None,
+            duration: None,
+            price: Some(Money {
+                amount: 100,
+                currency: "EUR".into(),
+            }),
+            language: None,
+            level: None,
+        });
+        let currency = web::Data::new(PriceCurrency("USD".to_string()));
+        let err = post_new_course(app_state, currency, new_course)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
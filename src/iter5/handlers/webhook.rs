@@ -0,0 +1,483 @@
+use crate::dbaccess::webhook::*;
+use crate::errors::{EzyTutorError, MyErrorResponse};
+use crate::identity::Admin;
+use crate::models::webhook::*;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse};
+
+const DEFAULT_DELIVERY_LIMIT: i64 = 50;
+const MAX_DELIVERY_LIMIT: i64 = 500;
+
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "admin",
+    params(("X-Admin-Token" = String, Header, description = "Admin token")),
+    request_body = NewWebhook,
+    responses(
+        (status = 200, description = "The subscription with its secret", body = CreatedWebhook),
+        (status = 400, description = "Invalid subscription", body = MyErrorResponse),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse)
+    )
+)]
+pub async fn post_new_webhook(
+    _admin: Admin,
+    new_webhook: web::Json<NewWebhook>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let new_webhook = new_webhook.into_inner();
+    new_webhook
+        .validate()
+        .map_err(EzyTutorError::InvalidInput)?;
+    let secret = new_webhook.secret.clone().unwrap_or_else(generate_secret);
+    let webhook = post_new_webhook_db(&app_state.db, new_webhook, &secret).await?;
+    Ok(HttpResponse::Ok().json(CreatedWebhook { webhook, secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    params(("X-Admin-Token" = String, Header, description = "Admin token")),
+    responses(
+        (status = 200, description = "All subscriptions", body = [Webhook]),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse)
+    )
+)]
+pub async fn get_webhooks(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let webhooks = get_webhooks_db(&app_state.db).await?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/{webhook_id}",
+    tag = "admin",
+    params(
+        ("webhook_id" = i32, Path, description = "Id of the subscription"),
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "The subscription", body = Webhook),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 404, description = "Subscription not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_webhook(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let webhook = get_webhook_db(&app_state.db, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/webhooks/{webhook_id}",
+    tag = "admin",
+    params(
+        ("webhook_id" = i32, Path, description = "Id of the subscription"),
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    request_body = UpdateWebhook,
+    responses(
+        (status = 200, description = "The updated subscription", body = Webhook),
+        (status = 400, description = "Invalid subscription", body = MyErrorResponse),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 404, description = "Subscription not found", body = MyErrorResponse)
+    )
+)]
+pub async fn update_webhook(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    update_webhook: web::Json<UpdateWebhook>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let update_webhook = update_webhook.into_inner();
+    update_webhook
+        .validate()
+        .map_err(EzyTutorError::InvalidInput)?;
+    let webhook = update_webhook_db(&app_state.db, path.into_inner(), update_webhook).await?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{webhook_id}",
+    tag = "admin",
+    params(
+        ("webhook_id" = i32, Path, description = "Id of the subscription"),
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "Deletion message, the delivery log goes with it", body = String),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 404, description = "Subscription not found", body = MyErrorResponse)
+    )
+)]
+pub async fn delete_webhook(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let message = delete_webhook_db(&app_state.db, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(message))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/{webhook_id}/deliveries",
+    tag = "admin",
+    params(
+        ("webhook_id" = i32, Path, description = "Id of the subscription"),
+        DeliveryFilter,
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "Deliveries of the subscription, newest first", body = [WebhookDelivery]),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 404, description = "Subscription not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_webhook_deliveries(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    filter: web::Query<DeliveryFilter>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let webhook_id = path.into_inner();
+    //An unknown subscription is not found rather than without deliveries
+    get_webhook_db(&app_state.db, webhook_id).await?;
+    let limit = filter
+        .limit
+        .unwrap_or(DEFAULT_DELIVERY_LIMIT)
+        .clamp(1, MAX_DELIVERY_LIMIT);
+    let deliveries = get_deliveries_db(&app_state.db, webhook_id, filter.status, limit).await?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/{webhook_id}/deliveries/{delivery_id}",
+    tag = "admin",
+    params(
+        ("webhook_id" = i32, Path, description = "Id of the subscription"),
+        ("delivery_id" = i32, Path, description = "Id of the delivery"),
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "The delivery with all its attempts", body = DeliveryDetails),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 404, description = "Delivery not found", body = MyErrorResponse)
+    )
+)]
+pub async fn get_webhook_delivery(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (webhook_id, delivery_id) = path.into_inner();
+    let delivery = get_delivery_details_db(&app_state.db, webhook_id, delivery_id).await?;
+    Ok(HttpResponse::Ok().json(delivery))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks/{webhook_id}/deliveries/{delivery_id}/redeliver",
+    tag = "admin",
+    params(
+        ("webhook_id" = i32, Path, description = "Id of the subscription"),
+        ("delivery_id" = i32, Path, description = "Id of the delivery"),
+        ("X-Admin-Token" = String, Header, description = "Admin token")
+    ),
+    responses(
+        (status = 200, description = "The delivery, pending again with a fresh set of attempts", body = WebhookDelivery),
+        (status = 403, description = "Missing or wrong admin token", body = MyErrorResponse),
+        (status = 404, description = "Delivery not found", body = MyErrorResponse)
+    )
+)]
+pub async fn redeliver_webhook_delivery(
+    _admin: Admin,
+    app_state: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (webhook_id, delivery_id) = path.into_inner();
+    let delivery = redeliver_db(&app_state.db, webhook_id, delivery_id).await?;
+    Ok(HttpResponse::Ok().json(delivery))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::config::WebhookConfig;
+    use crate::dbaccess::course::{delete_course_db, post_new_course_db};
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::identity::AdminToken;
+    use crate::models::course::CreateCourse;
+    use crate::models::event::OutboxEvent;
+    use crate::models::tutor::NewTutor;
+    use crate::outbox::webhook::WebhookSink;
+    use crate::outbox::EventSink;
+    use crate::poller::process_due;
+    use crate::routes::webhook_routes;
+    use crate::webhooks::*;
+    use actix_web::http::header::HeaderMap;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App, HttpRequest, HttpServer};
+    use dotenv::dotenv;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::Mutex;
+
+    //Partner system answering every request with the status set by the test
+    struct Receiver {
+        status: AtomicU16,
+        requests: Mutex<Vec<(HeaderMap, String)>>,
+    }
+
+    async fn receive(
+        receiver: web::Data<Receiver>,
+        req: HttpRequest,
+        body: String,
+    ) -> HttpResponse {
+        receiver
+            .requests
+            .lock()
+            .unwrap()
+            .push((req.headers().clone(), body));
+        let status = StatusCode::from_u16(receiver.status.load(Ordering::SeqCst)).unwrap();
+        HttpResponse::build(status).finish()
+    }
+
+    async fn start_receiver() -> (web::Data<Receiver>, String) {
+        let receiver = web::Data::new(Receiver {
+            status: AtomicU16::new(500),
+            requests: Mutex::new(Vec::new()),
+        });
+        let app_receiver = receiver.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_receiver.clone())
+                .default_service(web::to(receive))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let url = format!("http://{}/hooks", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        (receiver, url)
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+        headers.get(name).unwrap().to_str().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn failed_deliveries_are_retried_then_dead_until_redelivered() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            db: pool.clone(),
+            replica: None,
+            cache: ReadCache::default(),
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .app_data(web::Data::new(AdminToken(Some("secret".into()))))
+                .configure(webhook_routes),
+        )
+        .await;
+        let (receiver, url) = start_receiver().await;
+        let secret = "whsec_receiver_test_secret";
+
+        let req = test::TestRequest::post()
+            .uri("/admin/webhooks")
+            .insert_header(("X-Admin-Token", "secret"))
+            .set_json(serde_json::json!({ "url": url, "event_types": ["course.creatd"] }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::post()
+            .uri("/admin/webhooks")
+            .insert_header(("X-Admin-Token", "secret"))
+            .set_json(serde_json::json!({
+                "url": url,
+                "event_types": ["course.created", "course.deleted"],
+                "secret": secret,
+            }))
+            .to_request();
+        let created: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(created["secret"], secret);
+        let webhook_id = created["webhook_id"].as_i64().unwrap();
+
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Webhook tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic5".into(),
+                tutor_profile: "Has subscribers".into(),
+            },
+        )
+        .await
+        .unwrap();
+        let course = post_new_course_db(
+            &pool,
+            CreateCourse {
+                tutor_id: tutor.tutor_id,
+                course_name: "Webhook course".into(),
+                course_description: None,
+                course_format: None,
+                course_structure: None,
+                course_duration: None,
+                course_price: None,
+                course_language: None,
+                course_level: None,
+            },
+        )
+        .await
+        .unwrap();
+        //Published by hand, the relay is not running in tests
+        let events = sqlx::query_as!(
+            OutboxEvent,
+            "SELECT event_seq, event_id, event_type, partition_key, payload
+            FROM ezy_outbox_c6 WHERE partition_key = $1 ORDER BY event_seq",
+            format!("tutor:{}", tutor.tutor_id)
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let sink = WebhookSink::new(pool.clone());
+        sink.publish(&events).await.unwrap();
+        //Publishing again doesn't deliver twice
+        sink.publish(&events).await.unwrap();
+
+        //Other tests create courses at the same time, their events can be
+        //delivered to this subscription as well
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/admin/webhooks/{}/deliveries?limit=500",
+                webhook_id
+            ))
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let deliveries: Vec<WebhookDelivery> = test::call_and_read_body_json(&app, req).await;
+        let course_deliveries: Vec<WebhookDelivery> = deliveries
+            .into_iter()
+            .filter(|delivery| {
+                let payload: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap();
+                payload["type"] == "course.created"
+                    && payload["data"]["course_id"] == course.course_id
+            })
+            .collect();
+        assert_eq!(course_deliveries.len(), 1);
+        let delivery = course_deliveries.into_iter().next().unwrap();
+        assert_eq!(delivery.status, "pending");
+        let details_uri = format!(
+            "/admin/webhooks/{}/deliveries/{}",
+            webhook_id, delivery.delivery_id
+        );
+
+        let sender = WebhookSender::new(&WebhookConfig {
+            max_attempts: 2,
+            backoff_initial_secs: 600,
+            timeout_secs: 5,
+            batch_size: 1000,
+            ..WebhookConfig::default()
+        })
+        .unwrap();
+        process_due(&sender, &pool).await.unwrap();
+        let req = test::TestRequest::get()
+            .uri(&details_uri)
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let details: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(details["status"], "pending");
+        assert_eq!(details["attempts"].as_array().unwrap().len(), 1);
+        //Not due for another ten minutes
+        process_due(&sender, &pool).await.unwrap();
+        sqlx::query!(
+            "UPDATE ezy_webhook_delivery_c6 SET next_attempt_time = now() WHERE delivery_id = $1",
+            delivery.delivery_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        process_due(&sender, &pool).await.unwrap();
+        let req = test::TestRequest::get()
+            .uri(&details_uri)
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let details: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(details["status"], "dead");
+        let attempts = details["attempts"].as_array().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts.iter().all(|attempt| attempt["status_code"] == 500));
+
+        receiver.status.store(200, Ordering::SeqCst);
+        let req = test::TestRequest::post()
+            .uri(&format!("{}/redeliver", details_uri))
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let redelivered: WebhookDelivery = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(redelivered.status, "pending");
+        assert_eq!(redelivered.attempts, 0);
+        process_due(&sender, &pool).await.unwrap();
+        let req = test::TestRequest::get()
+            .uri(&details_uri)
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        let details: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(details["status"], "delivered");
+        assert!(details["delivered_time"].is_string());
+        assert_eq!(details["attempts"][2]["status_code"], 200);
+
+        let delivery_header = delivery.delivery_id.to_string();
+        let received: Vec<(HeaderMap, String)> = receiver
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(headers, _)| header(headers, DELIVERY_HEADER) == delivery_header)
+            .cloned()
+            .collect();
+        assert_eq!(received.len(), 3);
+        for (headers, body) in &received {
+            assert_eq!(body, &delivery.payload);
+            assert_eq!(header(headers, EVENT_HEADER), "course.created");
+            assert_eq!(header(headers, EVENT_ID_HEADER), delivery.event_id);
+            let timestamp = header(headers, TIMESTAMP_HEADER);
+            assert_eq!(
+                header(headers, SIGNATURE_HEADER),
+                sign(secret, timestamp, body)
+            );
+        }
+
+        delete_course_db(&pool, tutor.tutor_id, course.course_id)
+            .await
+            .unwrap();
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/webhooks/{}", webhook_id))
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::get()
+            .uri(&details_uri)
+            .insert_header(("X-Admin-Token", "secret"))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
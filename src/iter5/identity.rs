@@ -0,0 +1,117 @@
+use crate::errors::EzyTutorError;
+use crate::models::chat::{ChatRole, Participant};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+
+//Identity of the caller. Authentication happens in front of this service,
+//the gateway forwards the authenticated id in X-Tutor-Id or X-Student-Id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requester {
+    Tutor(i32),
+    Student(i32),
+    Anonymous,
+}
+
+pub const TUTOR_ID_HEADER: &str = "X-Tutor-Id";
+pub const STUDENT_ID_HEADER: &str = "X-Student-Id";
+
+impl Requester {
+    pub fn from_request_headers(req: &HttpRequest) -> Result<Self, EzyTutorError> {
+        let header_id = |name: &str| -> Result<Option<i32>, EzyTutorError> {
+            req.headers()
+                .get(name)
+                .map(|value| {
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|value| value.trim().parse().ok())
+                        .ok_or_else(|| {
+                            EzyTutorError::InvalidInput(format!("invalid {} header", name))
+                        })
+                })
+                .transpose()
+        };
+        match (header_id(TUTOR_ID_HEADER)?, header_id(STUDENT_ID_HEADER)?) {
+            (Some(_), Some(_)) => Err(EzyTutorError::InvalidInput(format!(
+                "only one of {} and {} may be set",
+                TUTOR_ID_HEADER, STUDENT_ID_HEADER
+            ))),
+            (Some(tutor_id), None) => Ok(Requester::Tutor(tutor_id)),
+            (None, Some(student_id)) => Ok(Requester::Student(student_id)),
+            (None, None) => Ok(Requester::Anonymous),
+        }
+    }
+
+    //Tutors and students, anonymous callers are nobody
+    pub fn participant(&self) -> Option<Participant> {
+        match *self {
+            Requester::Tutor(id) => Some(Participant {
+                role: ChatRole::Tutor,
+                id,
+            }),
+            Requester::Student(id) => Some(Participant {
+                role: ChatRole::Student,
+                id,
+            }),
+            Requester::Anonymous => None,
+        }
+    }
+
+    pub fn is_tutor(&self, tutor_id: i32) -> bool {
+        *self == Requester::Tutor(tutor_id)
+    }
+
+    pub fn require_tutor(&self, tutor_id: i32) -> Result<(), EzyTutorError> {
+        if self.is_tutor(tutor_id) {
+            Ok(())
+        } else {
+            Err(EzyTutorError::Forbidden(
+                "only the owning tutor may do this".into(),
+            ))
+        }
+    }
+}
+
+impl FromRequest for Requester {
+    type Error = EzyTutorError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Requester::from_request_headers(req))
+    }
+}
+
+pub const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+//Shared secret for the admin routes, admin access is disabled when unset
+pub struct AdminToken(pub Option<String>);
+
+//Extracting Admin succeeds only for requests carrying the admin token
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = EzyTutorError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let expected = req
+            .app_data::<web::Data<AdminToken>>()
+            .and_then(|token| token.0.clone());
+        let provided = req
+            .headers()
+            .get(ADMIN_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok());
+        ready(match (expected, provided) {
+            (Some(expected), Some(provided)) if constant_time_eq(&expected, provided) => Ok(Admin),
+            _ => Err(EzyTutorError::Forbidden("admin access required".into())),
+        })
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
@@ -0,0 +1,186 @@
+use actix_web::dev::ServerHandle;
+use actix_web::rt::time::{sleep, timeout};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+//Shared between the workers and the shutdown task. Once draining starts,
+//readiness fails so the orchestrator stops routing new traffic here while
+//requests in flight are finished.
+#[derive(Debug)]
+pub struct Lifecycle {
+    draining: AtomicBool,
+    //Number of background jobs still running
+    jobs: watch::Sender<usize>,
+    //End of the grace period, everything shutdown waits for shares it
+    deadline: OnceLock<Instant>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Lifecycle {
+            draining: AtomicBool::new(false),
+            jobs: watch::Sender::new(0),
+            deadline: OnceLock::new(),
+        }
+    }
+}
+
+impl Lifecycle {
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    //Runs a background job that shutdown waits for. Jobs run on the main
+    //arbiter, the worker threads are gone once the server has stopped.
+    pub fn spawn(&self, job: impl Future<Output = ()> + Send + 'static) {
+        self.jobs.send_modify(|jobs| *jobs += 1);
+        let jobs = self.jobs.clone();
+        actix_rt::System::current().arbiter().spawn(async move {
+            job.await;
+            jobs.send_modify(|jobs| *jobs -= 1);
+        });
+    }
+
+    //The grace period starts with the first call, later calls get the same end
+    pub fn deadline(&self, grace: Duration) -> Instant {
+        *self.deadline.get_or_init(|| Instant::now() + grace)
+    }
+
+    //Time left until the deadline, none if the grace period hasn't started
+    pub fn remaining(&self) -> Duration {
+        self.deadline
+            .get()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    //False if jobs were still running when the grace period ended
+    pub async fn wait_for_jobs(&self) -> bool {
+        let mut jobs = self.jobs.subscribe();
+        let finished = timeout(self.remaining(), jobs.wait_for(|jobs| *jobs == 0)).await;
+        finished.is_ok()
+    }
+}
+
+//Readiness fails first and load balancers get the delay to notice. Then actix
+//stops accepting connections and waits up to its shutdown timeout for requests
+//in flight, and background jobs get the rest of the grace period.
+pub async fn shutdown(
+    lifecycle: &Lifecycle,
+    server: ServerHandle,
+    delay: Duration,
+    grace: Duration,
+) {
+    tracing::info!(
+        delay_secs = delay.as_secs(),
+        grace_secs = grace.as_secs(),
+        "shutdown requested, draining"
+    );
+    lifecycle.start_draining();
+    sleep(delay).await;
+    lifecycle.deadline(grace);
+    server.stop(true).await;
+    if !lifecycle.wait_for_jobs().await {
+        tracing::warn!("background jobs still running at the end of the grace period");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn slow_handler() -> HttpResponse {
+        actix_web::rt::time::sleep(Duration::from_millis(800)).await;
+        HttpResponse::Ok().body("finished")
+    }
+
+    async fn get(address: std::net::SocketAddr, path: &str) -> std::io::Result<String> {
+        let mut stream = TcpStream::connect(address).await?;
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    #[actix_rt::test]
+    async fn slow_request_completes_during_shutdown() {
+        let lifecycle = web::Data::new(Lifecycle::default());
+        let server = HttpServer::new(|| App::new().route("/slow", web::get().to(slow_handler)))
+            .workers(1)
+            .disable_signals()
+            .shutdown_timeout(5)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        let running = actix_rt::spawn(server);
+
+        //A background job outliving the request has to finish as well
+        let (done_sender, mut done) = tokio::sync::oneshot::channel();
+        lifecycle.spawn(async move {
+            actix_web::rt::time::sleep(Duration::from_millis(1200)).await;
+            let _ = done_sender.send(());
+        });
+
+        let slow = actix_rt::spawn(async move { get(address, "/slow").await });
+        actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        let delay = Duration::from_millis(100);
+        shutdown(&lifecycle, handle, delay, Duration::from_secs(5)).await;
+        //shutdown only returns once the job has finished
+        assert!(done.try_recv().is_ok());
+
+        let response = slow.await.unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("finished"));
+        assert!(lifecycle.is_draining());
+        assert!(get(address, "/slow").await.is_err());
+        running.await.unwrap().unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn connections_are_accepted_during_the_delay() {
+        let lifecycle = web::Data::new(Lifecycle::default());
+        let server = HttpServer::new(|| App::new().route("/", web::get().to(HttpResponse::Ok)))
+            .workers(1)
+            .disable_signals()
+            .shutdown_timeout(5)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        let running = actix_rt::spawn(server);
+
+        let stopping = lifecycle.clone();
+        let stopped = actix_rt::spawn(async move {
+            let delay = Duration::from_millis(500);
+            shutdown(&stopping, handle, delay, Duration::from_secs(5)).await;
+        });
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+        assert!(lifecycle.is_draining());
+        let response = get(address, "/").await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        stopped.await.unwrap();
+        assert!(get(address, "/").await.is_err());
+        running.await.unwrap().unwrap();
+        //Waits after the shutdown share its deadline
+        let deadline = lifecycle.deadline(Duration::from_secs(60));
+        assert!(deadline < Instant::now() + Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,72 @@
+use super::{content_type_for_key, validate_key, MediaStore, StoredMedia};
+use crate::errors::EzyTutorError;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+
+//Stores media as plain files below a root directory
+pub struct LocalMediaStore {
+    root: PathBuf,
+    tmp_counter: AtomicU64,
+}
+
+impl LocalMediaStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalMediaStore {
+            root: root.into(),
+            tmp_counter: AtomicU64::new(0),
+        }
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf, EzyTutorError> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+fn storage_error(err: std::io::Error) -> EzyTutorError {
+    if err.kind() == ErrorKind::NotFound {
+        EzyTutorError::NotFound("Media not found".into())
+    } else {
+        EzyTutorError::StorageError(err.to_string())
+    }
+}
+
+#[async_trait]
+impl MediaStore for LocalMediaStore {
+    async fn put(&self, key: &str, _content_type: &str, data: Bytes) -> Result<(), EzyTutorError> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await.map_err(storage_error)?;
+        }
+        //write to a temporary file first so readers never see a partial file
+        let tmp_path = path.with_extension(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            self.tmp_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp_path, &data).await.map_err(storage_error)?;
+        fs::rename(&tmp_path, &path).await.map_err(storage_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<StoredMedia, EzyTutorError> {
+        let path = self.path_for(key)?;
+        let data = fs::read(&path).await.map_err(storage_error)?;
+        Ok(StoredMedia {
+            content_type: content_type_for_key(key).to_string(),
+            data: Bytes::from(data),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), EzyTutorError> {
+        let path = self.path_for(key)?;
+        match fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(storage_error(err)),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+pub mod local;
+pub mod picture;
+pub mod s3;
+
+use crate::config::{MediaBackend, MediaConfig};
+use crate::errors::EzyTutorError;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+//Storage backend for uploaded media (tutor pictures, thumbnails).
+//Keys are relative, slash separated paths such as "tutors/1/<hash>/original.png"
+#[async_trait]
+pub trait MediaStore: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, data: Bytes) -> Result<(), EzyTutorError>;
+    async fn get(&self, key: &str) -> Result<StoredMedia, EzyTutorError>;
+    async fn delete(&self, key: &str) -> Result<(), EzyTutorError>;
+}
+
+pub struct StoredMedia {
+    pub content_type: String,
+    pub data: Bytes,
+}
+
+//Shared media settings, registered next to AppState
+pub struct MediaState {
+    pub store: Arc<dyn MediaStore>,
+    pub public_base_url: String,
+    pub max_upload_bytes: usize,
+    pub attachment_max_bytes: usize,
+    pub attachment_quota_bytes: i64,
+}
+
+impl MediaState {
+    //Public URL under which a stored key is served by the media routes
+    pub fn public_url(&self, key: &str) -> String {
+        format!(
+            "{}/media/{}",
+            self.public_base_url.trim_end_matches('/'),
+            key
+        )
+    }
+
+    //Reverse of public_url, used to clean up files we stored earlier
+    pub fn key_from_url(&self, url: &str) -> Option<String> {
+        let prefix = format!("{}/media/", self.public_base_url.trim_end_matches('/'));
+        url.strip_prefix(&prefix)
+            .filter(|key| validate_key(key).is_ok())
+            .map(|key| key.to_string())
+    }
+}
+
+//Build the media store selected in the configuration
+pub fn media_state_from_config(
+    config: &MediaConfig,
+    default_base_url: &str,
+) -> Result<MediaState, EzyTutorError> {
+    let store: Arc<dyn MediaStore> = match config.backend {
+        MediaBackend::Local => Arc::new(local::LocalMediaStore::new(config.root.clone())),
+        MediaBackend::S3 => Arc::new(s3::S3MediaStore::new(
+            config.s3_bucket.as_deref().unwrap_or_default(),
+            config.s3_endpoint.as_deref(),
+            &config.s3_prefix,
+        )?),
+    };
+    Ok(MediaState {
+        store,
+        public_base_url: config
+            .base_url
+            .clone()
+            .unwrap_or_else(|| default_base_url.into()),
+        max_upload_bytes: config.max_upload_bytes,
+        attachment_max_bytes: config.attachment_max_bytes,
+        attachment_quota_bytes: config.attachment_quota_bytes,
+    })
+}
+
+pub fn validate_key(key: &str) -> Result<(), EzyTutorError> {
+    let valid = !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('.')
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(EzyTutorError::NotFound("Media not found".into()))
+    }
+}
+
+pub fn content_type_for_key(key: &str) -> &'static str {
+    match key.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
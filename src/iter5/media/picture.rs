@@ -0,0 +1,85 @@
+use crate::errors::EzyTutorError;
+use actix_web::web::Bytes;
+use image::{ImageFormat, ImageReader, Limits};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+//Edge lengths (in pixels) of the square boxes thumbnails are fitted into
+pub const THUMBNAIL_SIZES: [u32; 2] = [64, 256];
+//Guards against decompression bombs, a 5MB file can still claim huge dimensions
+const MAX_DIMENSION: u32 = 8000;
+
+pub struct ProcessedPicture {
+    pub digest: String,
+    pub extension: &'static str,
+    pub content_type: &'static str,
+    pub original: Bytes,
+    //(size, png encoded thumbnail)
+    pub thumbnails: Vec<(u32, Bytes)>,
+}
+
+fn supported_format(format: ImageFormat) -> Option<(&'static str, &'static str)> {
+    match format {
+        ImageFormat::Png => Some(("png", "image/png")),
+        ImageFormat::Jpeg => Some(("jpg", "image/jpeg")),
+        ImageFormat::Gif => Some(("gif", "image/gif")),
+        ImageFormat::WebP => Some(("webp", "image/webp")),
+        _ => None,
+    }
+}
+
+//Validate an uploaded picture against its declared content type and
+//render the thumbnails. The declared type is only trusted if the bytes agree.
+pub fn process_picture(
+    declared_type: Option<&str>,
+    data: Bytes,
+) -> Result<ProcessedPicture, EzyTutorError> {
+    let unsupported =
+        || EzyTutorError::InvalidInput("picture must be a PNG, JPEG, GIF or WebP image".into());
+    let format = image::guess_format(&data).map_err(|_| unsupported())?;
+    let (extension, content_type) = supported_format(format).ok_or_else(unsupported)?;
+    if let Some(declared) = declared_type {
+        if declared != content_type && !(declared == "image/jpg" && content_type == "image/jpeg") {
+            return Err(EzyTutorError::InvalidInput(format!(
+                "declared content type {} does not match uploaded {} image",
+                declared, content_type
+            )));
+        }
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    let mut reader = ImageReader::with_format(Cursor::new(&data), format);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|err| EzyTutorError::InvalidInput(format!("could not decode picture: {}", err)))?;
+
+    let mut thumbnails = Vec::with_capacity(THUMBNAIL_SIZES.len());
+    for size in THUMBNAIL_SIZES {
+        let mut encoded = Cursor::new(Vec::new());
+        image
+            .thumbnail(size, size)
+            .write_to(&mut encoded, ImageFormat::Png)
+            .map_err(|err| EzyTutorError::StorageError(err.to_string()))?;
+        thumbnails.push((size, Bytes::from(encoded.into_inner())));
+    }
+
+    let digest = hex::encode(Sha256::digest(&data));
+    Ok(ProcessedPicture {
+        digest: digest[..32].to_string(),
+        extension,
+        content_type,
+        original: data,
+        thumbnails,
+    })
+}
+
+pub fn original_key(tutor_id: i32, digest: &str, extension: &str) -> String {
+    format!("tutors/{}/{}/original.{}", tutor_id, digest, extension)
+}
+
+pub fn thumbnail_key(tutor_id: i32, digest: &str, size: u32) -> String {
+    format!("tutors/{}/{}/thumb_{}.png", tutor_id, digest, size)
+}
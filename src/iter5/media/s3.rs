@@ -0,0 +1,89 @@
+use super::{content_type_for_key, validate_key, MediaStore, StoredMedia};
+use crate::errors::EzyTutorError;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path;
+use object_store::{Attribute, Attributes, ObjectStore, PutOptions, PutPayload};
+
+//Stores media in an S3 compatible bucket (AWS S3, MinIO, Ceph, ...)
+pub struct S3MediaStore {
+    store: AmazonS3,
+    prefix: String,
+}
+
+impl S3MediaStore {
+    //Credentials and region are read from the usual AWS_* variables,
+    //the endpoint is only needed for stores other than AWS
+    pub fn new(bucket: &str, endpoint: Option<&str>, prefix: &str) -> Result<Self, EzyTutorError> {
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        if let Some(endpoint) = endpoint {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
+        }
+        let store = builder.build().map_err(s3_error)?;
+        Ok(S3MediaStore {
+            store,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn path_for(&self, key: &str) -> Result<Path, EzyTutorError> {
+        validate_key(key)?;
+        if self.prefix.is_empty() {
+            Ok(Path::from(key))
+        } else {
+            Ok(Path::from(format!(
+                "{}/{}",
+                self.prefix.trim_end_matches('/'),
+                key
+            )))
+        }
+    }
+}
+
+fn s3_error(err: object_store::Error) -> EzyTutorError {
+    match err {
+        object_store::Error::NotFound { .. } => EzyTutorError::NotFound("Media not found".into()),
+        err => EzyTutorError::StorageError(err.to_string()),
+    }
+}
+
+#[async_trait]
+impl MediaStore for S3MediaStore {
+    async fn put(&self, key: &str, content_type: &str, data: Bytes) -> Result<(), EzyTutorError> {
+        let path = self.path_for(key)?;
+        let mut attributes = Attributes::new();
+        attributes.insert(Attribute::ContentType, content_type.to_string().into());
+        let options = PutOptions {
+            attributes,
+            ..Default::default()
+        };
+        self.store
+            .put_opts(&path, PutPayload::from(data), options)
+            .await
+            .map_err(s3_error)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<StoredMedia, EzyTutorError> {
+        let path = self.path_for(key)?;
+        let result = self.store.get(&path).await.map_err(s3_error)?;
+        let content_type = result
+            .attributes
+            .get(&Attribute::ContentType)
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| content_type_for_key(key).to_string());
+        let data = result.bytes().await.map_err(s3_error)?;
+        Ok(StoredMedia { content_type, data })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), EzyTutorError> {
+        let path = self.path_for(key)?;
+        match self.store.delete(&path).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(err) => Err(s3_error(err)),
+        }
+    }
+}
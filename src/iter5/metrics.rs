@@ -0,0 +1,195 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+//Counters have to survive across workers and requests, so there is one
+//registry for the whole process instead of a field in AppState
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub db_query_duration: HistogramVec,
+    pub db_pool_size: IntGauge,
+    pub db_pool_idle: IntGauge,
+    pub db_pool_max_size: IntGauge,
+    pub db_pool_acquire_wait: Gauge,
+    pub tutors_total: IntGauge,
+    pub courses_total: IntGauge,
+    pub health_checks: IntCounter,
+    pub rate_limited: IntCounterVec,
+    pub cache_requests: IntCounterVec,
+    pub db_reads: IntCounterVec,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+//Buckets from 1ms to 10s, the defaults start at 5ms which hides most queries
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("ezy_http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "ezy_http_request_duration_seconds",
+                "Time from receiving a request until the response head is ready",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "ezy_db_query_duration_seconds",
+                "Duration of database access functions, including waiting for a connection",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["query"],
+        )
+        .unwrap();
+        let db_pool_size =
+            IntGauge::new("ezy_db_pool_size", "Open connections in the pool").unwrap();
+        let db_pool_idle =
+            IntGauge::new("ezy_db_pool_idle", "Idle connections in the pool").unwrap();
+        let db_pool_max_size = IntGauge::new(
+            "ezy_db_pool_max_size",
+            "Maximum number of connections in the pool",
+        )
+        .unwrap();
+        let db_pool_acquire_wait = Gauge::new(
+            "ezy_db_pool_acquire_wait_seconds",
+            "Time needed to acquire a connection at the last scrape",
+        )
+        .unwrap();
+        let tutors_total =
+            IntGauge::new("ezy_tutors_total", "Registered tutors, estimated").unwrap();
+        let courses_total =
+            IntGauge::new("ezy_courses_total", "Offered courses, estimated").unwrap();
+        let health_checks =
+            IntCounter::new("ezy_health_checks_total", "Calls of the health endpoint").unwrap();
+        let rate_limited = IntCounterVec::new(
+            Opts::new(
+                "ezy_rate_limited_total",
+                "Requests rejected because the client exceeded its quota",
+            ),
+            &["scope", "access"],
+        )
+        .unwrap();
+        let cache_requests = IntCounterVec::new(
+            Opts::new(
+                "ezy_cache_requests_total",
+                "Read cache lookups by entry and result",
+            ),
+            &["entry", "result"],
+        )
+        .unwrap();
+        let db_reads = IntCounterVec::new(
+            Opts::new(
+                "ezy_db_reads_total",
+                "Routed reads by target: primary, replica or fallback to the primary",
+            ),
+            &["target"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(db_pool_size.clone())).unwrap();
+        registry.register(Box::new(db_pool_idle.clone())).unwrap();
+        registry
+            .register(Box::new(db_pool_max_size.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_acquire_wait.clone()))
+            .unwrap();
+        registry.register(Box::new(tutors_total.clone())).unwrap();
+        registry.register(Box::new(courses_total.clone())).unwrap();
+        registry.register(Box::new(health_checks.clone())).unwrap();
+        registry.register(Box::new(rate_limited.clone())).unwrap();
+        registry.register(Box::new(cache_requests.clone())).unwrap();
+        registry.register(Box::new(db_reads.clone())).unwrap();
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_query_duration,
+            db_pool_size,
+            db_pool_idle,
+            db_pool_max_size,
+            db_pool_acquire_wait,
+            tutors_total,
+            courses_total,
+            health_checks,
+            rate_limited,
+            cache_requests,
+            db_reads,
+        }
+    }
+
+    //Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics can't be encoded");
+        String::from_utf8(buffer).expect("metrics are not valid utf-8")
+    }
+}
+
+//Observes the query duration when dropped, keep it alive for the whole function
+pub fn query_timer(query: &str) -> HistogramTimer {
+    metrics()
+        .db_query_duration
+        .with_label_values(&[query])
+        .start_timer()
+}
+
+//Counts requests per route pattern, not per path, so ids don't create new series
+pub async fn metrics_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let result = next.call(req).await;
+    let status = match &result {
+        Ok(res) => res.status(),
+        Err(err) => err.as_response_error().status_code(),
+    };
+    let status = status.as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    let metrics = metrics();
+    metrics.http_requests.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    result
+}
@@ -0,0 +1,31 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//attachment id and uploaded time are generated by the db,
+//the content itself is kept in the media store under storage_key
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct Attachment {
+    pub attachment_id: i32,
+    pub course_id: i32,
+    pub tutor_id: i32,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub checksum: String,
+    #[serde(skip)]
+    #[schema(ignore)]
+    pub storage_key: String,
+    pub uploaded_time: NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAttachment {
+    pub course_id: i32,
+    pub tutor_id: i32,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub checksum: String,
+    pub storage_key: String,
+}
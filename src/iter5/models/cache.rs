@@ -0,0 +1,40 @@
+use std::fmt;
+
+//Cached reads. Changes send the keys they touch to every instance, named
+//like they are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKey {
+    AllTutors,
+    Tutor(i32),
+    CoursesForTutor(i32),
+}
+
+impl CacheKey {
+    //Used for the hit and miss metrics
+    pub fn label(&self) -> &'static str {
+        match self {
+            CacheKey::AllTutors => "all_tutors",
+            CacheKey::Tutor(_) => "tutor",
+            CacheKey::CoursesForTutor(_) => "courses_for_tutor",
+        }
+    }
+
+    pub fn parse(key: &str) -> Option<CacheKey> {
+        match key.split_once('/') {
+            None if key == "tutors" => Some(CacheKey::AllTutors),
+            Some(("tutors", tutor_id)) => tutor_id.parse().ok().map(CacheKey::Tutor),
+            Some(("courses", tutor_id)) => tutor_id.parse().ok().map(CacheKey::CoursesForTutor),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheKey::AllTutors => write!(f, "tutors"),
+            CacheKey::Tutor(tutor_id) => write!(f, "tutors/{}", tutor_id),
+            CacheKey::CoursesForTutor(tutor_id) => write!(f, "courses/{}", tutor_id),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+//Fits a whole message frame into one notification, which is limited to 8000 bytes
+pub const MAX_MESSAGE_CHARS: usize = 1000;
+const MAX_CLIENT_MESSAGE_ID_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    Tutor,
+    Student,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::Tutor => "tutor",
+            ChatRole::Student => "student",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<ChatRole> {
+        match role {
+            "tutor" => Some(ChatRole::Tutor),
+            "student" => Some(ChatRole::Student),
+            _ => None,
+        }
+    }
+
+    pub fn other(&self) -> ChatRole {
+        match self {
+            ChatRole::Tutor => ChatRole::Student,
+            ChatRole::Student => ChatRole::Tutor,
+        }
+    }
+}
+
+//Student or tutor taking part in conversations, with their id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Participant {
+    pub role: ChatRole,
+    pub id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct Conversation {
+    pub conversation_id: i32,
+    pub tutor_id: i32,
+    pub student_id: i32,
+    pub course_id: Option<i32>,
+    pub created_time: NaiveDateTime,
+    pub last_message_time: Option<NaiveDateTime>,
+}
+
+impl Conversation {
+    pub fn participant(&self, role: ChatRole) -> Participant {
+        match role {
+            ChatRole::Tutor => Participant {
+                role,
+                id: self.tutor_id,
+            },
+            ChatRole::Student => Participant {
+                role,
+                id: self.student_id,
+            },
+        }
+    }
+
+    pub fn includes(&self, participant: Participant) -> bool {
+        self.participant(participant.role) == participant
+    }
+}
+
+//A conversation as listed for one of its participants
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct ConversationSummary {
+    pub conversation_id: i32,
+    pub tutor_id: i32,
+    pub student_id: i32,
+    pub course_id: Option<i32>,
+    pub created_time: NaiveDateTime,
+    pub last_message_time: Option<NaiveDateTime>,
+    //Messages of the other participant not read yet
+    pub unread: i64,
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct NewConversation {
+    pub tutor_id: i32,
+    //Must be one of the tutor's courses
+    pub course_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, sqlx::FromRow, ToSchema)]
+pub struct ChatMessage {
+    pub message_id: i64,
+    pub conversation_id: i32,
+    //"tutor" or "student"
+    pub sender: String,
+    pub body: String,
+    pub client_message_id: Option<String>,
+    pub sent_time: NaiveDateTime,
+    //Set once the recipient was connected or fetched it
+    pub delivered_time: Option<NaiveDateTime>,
+    pub read_time: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Debug, Clone, IntoParams)]
+pub struct HistoryParams {
+    //Messages older than this message_id, for the next page
+    pub before: Option<i64>,
+    //At most 100, 50 if not given
+    pub limit: Option<i64>,
+}
+
+//Frames a client sends over the socket, JSON text tagged by "type"
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ClientFrame {
+    Send {
+        conversation_id: i32,
+        body: String,
+        client_message_id: Option<String>,
+    },
+    Typing {
+        conversation_id: i32,
+    },
+    //Everything the other participant sent up to message_id was read
+    Read {
+        conversation_id: i32,
+        message_id: i64,
+    },
+}
+
+//Frames sent to clients. The sender gets its own messages as well, with its
+//client_message_id as acknowledgement.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    Message {
+        message: ChatMessage,
+    },
+    Delivered {
+        conversation_id: i32,
+        message_ids: Vec<i64>,
+    },
+    Read {
+        conversation_id: i32,
+        message_ids: Vec<i64>,
+    },
+    Typing {
+        conversation_id: i32,
+        sender: ChatRole,
+    },
+    //Frames may have been missed, clients reload the history
+    Resync,
+    Error {
+        error: String,
+        client_message_id: Option<String>,
+    },
+}
+
+//Frame of a conversation as it goes to every instance, which passes it to
+//the participants connected there
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatNotice {
+    pub tutor_id: i32,
+    pub student_id: i32,
+    //Only this participant gets it, both if None
+    pub recipient: Option<ChatRole>,
+    pub frame: ServerFrame,
+}
+
+impl ChatNotice {
+    pub fn to_both(conversation: &Conversation, frame: ServerFrame) -> Self {
+        ChatNotice {
+            tutor_id: conversation.tutor_id,
+            student_id: conversation.student_id,
+            recipient: None,
+            frame,
+        }
+    }
+
+    pub fn recipients(&self) -> Vec<Participant> {
+        let conversation = [
+            Participant {
+                role: ChatRole::Tutor,
+                id: self.tutor_id,
+            },
+            Participant {
+                role: ChatRole::Student,
+                id: self.student_id,
+            },
+        ];
+        conversation
+            .into_iter()
+            .filter(|participant| self.recipient.is_none_or(|role| role == participant.role))
+            .collect()
+    }
+}
+
+pub fn validate_message(body: &str, client_message_id: Option<&str>) -> Result<(), String> {
+    if body.trim().is_empty() {
+        return Err("body must not be empty".into());
+    }
+    if body.chars().count() > MAX_MESSAGE_CHARS {
+        return Err(format!(
+            "body is longer than {} characters",
+            MAX_MESSAGE_CHARS
+        ));
+    }
+    if client_message_id.is_some_and(|id| id.is_empty() || id.len() > MAX_CLIENT_MESSAGE_ID_LENGTH)
+    {
+        return Err(format!(
+            "client_message_id must have between 1 and {} bytes",
+            MAX_CLIENT_MESSAGE_ID_LENGTH
+        ));
+    }
+    Ok(())
+}
@@ -1,12 +1,13 @@
 use actix_web::web;
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 //use std::convert::TryFrom;
 //use super::super::errors::EzyTutorError;
 
 //course id will be auto generated for db
 //posted time will be auto generated for db
-#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+#[derive(Deserialize, Serialize, Debug, Clone, sqlx::FromRow, ToSchema)]
 pub struct Course {
     pub course_id: i32,
     pub tutor_id: i32,
@@ -21,7 +22,23 @@ pub struct Course {
     pub posted_time: Option<NaiveDateTime>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+//Filters for course listings, every field is optional and they combine with AND
+#[derive(Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CourseFilter {
+    pub tutor_id: Option<i32>,
+    //Part of the name, compared case-insensitively
+    pub course_name: Option<String>,
+    //Compared case-insensitively
+    pub course_level: Option<String>,
+    pub course_language: Option<String>,
+    pub course_format: Option<String>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    pub posted_after: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
 pub struct CreateCourse {
     pub tutor_id: i32,
     pub course_name: String,
@@ -34,7 +51,7 @@ pub struct CreateCourse {
     pub course_level: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct UpdateCourse {
     pub course_name: Option<String>,
     pub course_description: Option<String>,
@@ -45,6 +62,115 @@ pub struct UpdateCourse {
     pub course_language: Option<String>,
     pub course_level: Option<String>,
 }
+impl CreateCourse {
+    //Mirrors the column limits of ezy_course_c6
+    pub fn validate(&self) -> Result<(), String> {
+        if self.course_name.trim().is_empty() {
+            return Err("course_name must not be empty".into());
+        }
+        let limits = [
+            ("course_name", Some(&self.course_name), 140),
+            ("course_description", self.course_description.as_ref(), 2000),
+            ("course_format", self.course_format.as_ref(), 30),
+            ("course_structure", self.course_structure.as_ref(), 200),
+            ("course_duration", self.course_duration.as_ref(), 30),
+            ("course_language", self.course_language.as_ref(), 30),
+            ("course_level", self.course_level.as_ref(), 30),
+        ];
+        for (field, value, max) in limits {
+            if value
+                .map(|value| value.chars().count() > max)
+                .unwrap_or(false)
+            {
+                return Err(format!(
+                    "{} must not be longer than {} characters",
+                    field, max
+                ));
+            }
+        }
+        if self.course_price.map(|price| price < 0).unwrap_or(false) {
+            return Err("course_price must not be negative".into());
+        }
+        Ok(())
+    }
+}
+
+//One record of a bulk import, the tutor id defaults to the one in the path
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct ImportCourse {
+    pub tutor_id: Option<i32>,
+    pub course_name: String,
+    pub course_description: Option<String>,
+    pub course_format: Option<String>,
+    pub course_structure: Option<String>,
+    pub course_duration: Option<String>,
+    pub course_price: Option<i32>,
+    pub course_language: Option<String>,
+    pub course_level: Option<String>,
+}
+
+impl ImportCourse {
+    pub fn into_create_course(self, tutor_id: i32) -> Result<CreateCourse, String> {
+        if let Some(record_tutor_id) = self.tutor_id {
+            if record_tutor_id != tutor_id {
+                return Err(format!(
+                    "tutor_id {} does not match tutor {} of the import",
+                    record_tutor_id, tutor_id
+                ));
+            }
+        }
+        let course = CreateCourse {
+            tutor_id,
+            course_name: self.course_name,
+            course_description: self.course_description,
+            course_format: self.course_format,
+            course_structure: self.course_structure,
+            course_duration: self.course_duration,
+            course_price: self.course_price,
+            course_language: self.course_language,
+            course_level: self.course_level,
+        };
+        course.validate()?;
+        Ok(course)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    //all rows are inserted or none
+    #[default]
+    Atomic,
+    //valid rows are inserted, failing rows are reported
+    BestEffort,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+    #[serde(default)]
+    pub mode: ImportMode,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+//row numbers are 1 based and count records, not lines
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ImportRowResult {
+    pub row: usize,
+    pub course_id: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub dry_run: bool,
+    pub created: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
 impl From<web::Json<CreateCourse>> for CreateCourse {
     fn from(new_course: web::Json<CreateCourse>) -> Self {
         CreateCourse {
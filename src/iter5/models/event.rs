@@ -0,0 +1,127 @@
+use crate::models::course::Course;
+use crate::models::tutor::Tutor;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+//Lifecycle events of courses and tutors, named like they appear in payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EventType {
+    #[serde(rename = "course.created")]
+    CourseCreated,
+    #[serde(rename = "course.updated")]
+    CourseUpdated,
+    #[serde(rename = "course.deleted")]
+    CourseDeleted,
+    #[serde(rename = "tutor.created")]
+    TutorCreated,
+    #[serde(rename = "tutor.updated")]
+    TutorUpdated,
+    #[serde(rename = "tutor.deleted")]
+    TutorDeleted,
+}
+
+impl EventType {
+    pub const ALL: [EventType; 6] = [
+        EventType::CourseCreated,
+        EventType::CourseUpdated,
+        EventType::CourseDeleted,
+        EventType::TutorCreated,
+        EventType::TutorUpdated,
+        EventType::TutorDeleted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::CourseCreated => "course.created",
+            EventType::CourseUpdated => "course.updated",
+            EventType::CourseDeleted => "course.deleted",
+            EventType::TutorCreated => "tutor.created",
+            EventType::TutorUpdated => "tutor.updated",
+            EventType::TutorDeleted => "tutor.deleted",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<EventType> {
+        EventType::ALL
+            .into_iter()
+            .find(|event_type| event_type.as_str() == name)
+    }
+}
+
+//Created and updated events carry the whole resource as it was written,
+//deleted ones only its ids
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub event_type: EventType,
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    pub fn course_created(course: &Course) -> Self {
+        Event::with_data(EventType::CourseCreated, course)
+    }
+
+    pub fn course_updated(course: &Course) -> Self {
+        Event::with_data(EventType::CourseUpdated, course)
+    }
+
+    pub fn course_deleted(tutor_id: i32, course_id: i32) -> Self {
+        Event {
+            event_type: EventType::CourseDeleted,
+            data: json!({ "tutor_id": tutor_id, "course_id": course_id }),
+        }
+    }
+
+    pub fn tutor_created(tutor: &Tutor) -> Self {
+        Event::with_data(EventType::TutorCreated, tutor)
+    }
+
+    pub fn tutor_updated(tutor: &Tutor) -> Self {
+        Event::with_data(EventType::TutorUpdated, tutor)
+    }
+
+    //The tutor's courses go with it, there are no course.deleted events for them
+    pub fn tutor_deleted(tutor_id: i32) -> Self {
+        Event {
+            event_type: EventType::TutorDeleted,
+            data: json!({ "tutor_id": tutor_id }),
+        }
+    }
+
+    fn with_data(event_type: EventType, data: &impl Serialize) -> Self {
+        Event {
+            event_type,
+            data: serde_json::to_value(data).expect("models serialize to JSON"),
+        }
+    }
+
+    //Events of a tutor and its courses share the key, partitioned sinks such
+    //as a broker keep them in order
+    pub fn partition_key(&self) -> String {
+        format!("tutor:{}", self.data["tutor_id"])
+    }
+
+    //Body sent to subscribers. Redeliveries keep the id, receivers use it to
+    //drop events they already handled.
+    pub fn payload(&self, event_id: &str) -> String {
+        json!({
+            "id": event_id,
+            "type": self.event_type.as_str(),
+            "created_at": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "data": self.data,
+        })
+        .to_string()
+    }
+}
+
+//Event recorded in the outbox. The payload is what every sink publishes,
+//its id is the same on every (re)publication for consumers to drop duplicates.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OutboxEvent {
+    pub event_seq: i64,
+    pub event_id: String,
+    pub event_type: String,
+    pub partition_key: String,
+    pub payload: String,
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+    Draining,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct DependencyStatus {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+//Overall status is up only if every dependency is up and the server isn't draining
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub checks: BTreeMap<String, DependencyStatus>,
+}
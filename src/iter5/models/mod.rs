@@ -1,2 +1,11 @@
+pub mod attachment;
+pub mod cache;
+pub mod chat;
 pub mod course;
+pub mod event;
+pub mod export;
+pub mod health;
+pub mod notification;
 pub mod tutor;
+pub mod v2;
+pub mod webhook;
@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+//Locales with templates for every kind, others get the default locale
+pub const LOCALES: [&str; 2] = ["en", "de"];
+const MAX_EMAIL_LENGTH: usize = 254;
+
+//Emails sent to tutors and students, named like their templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    //To the tutor, once a new course is listed
+    CoursePublished,
+    //To the students enrolled in a course that changed
+    CourseUpdated,
+    //To the students enrolled in a course that was removed, also when its
+    //tutor was
+    CourseCancelled,
+    //To the tutor, after a change of their profile
+    ProfileUpdated,
+    //To the tutor, once their profile is removed
+    AccountClosed,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 5] = [
+        NotificationKind::CoursePublished,
+        NotificationKind::CourseUpdated,
+        NotificationKind::CourseCancelled,
+        NotificationKind::ProfileUpdated,
+        NotificationKind::AccountClosed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::CoursePublished => "course_published",
+            NotificationKind::CourseUpdated => "course_updated",
+            NotificationKind::CourseCancelled => "course_cancelled",
+            NotificationKind::ProfileUpdated => "profile_updated",
+            NotificationKind::AccountClosed => "account_closed",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<NotificationKind> {
+        NotificationKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct NotificationPreferences {
+    pub email: String,
+    pub locale: String,
+    //Kinds such as "course_updated" the user doesn't want
+    pub muted: Vec<String>,
+    pub updated_time: NaiveDateTime,
+}
+
+//Replaces the preferences of the caller
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct SetPreferences {
+    pub email: String,
+    //"en" if not given
+    pub locale: Option<String>,
+    //Nothing is muted if not given
+    pub muted: Option<Vec<String>>,
+}
+
+impl SetPreferences {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_email(&self.email)?;
+        if let Some(locale) = &self.locale {
+            if !LOCALES.contains(&locale.as_str()) {
+                return Err(format!("locale must be one of {}", LOCALES.join(", ")));
+            }
+        }
+        if let Some(unknown) = self
+            .muted
+            .iter()
+            .flatten()
+            .find(|name| NotificationKind::parse(name).is_none())
+        {
+            let known: Vec<&str> = NotificationKind::ALL
+                .iter()
+                .map(NotificationKind::as_str)
+                .collect();
+            return Err(format!(
+                "unknown notification kind {:?}, expected one of {}",
+                unknown,
+                known.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+//Only the shape is checked, the mail server decides whether it exists
+fn validate_email(email: &str) -> Result<(), String> {
+    let valid = email.len() <= MAX_EMAIL_LENGTH
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        && email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && !domain.is_empty() && !domain.contains('@')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err("email must be an address such as name@example.com".into())
+    }
+}
+
+//Queued notification claimed for sending. The context holds the values its
+//template shows, taken when the change happened.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DueNotification {
+    pub notification_id: i32,
+    pub kind: String,
+    pub email: String,
+    pub locale: String,
+    pub context: String,
+    //Including the one being made
+    pub attempts: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationStatus {
+    Pending,
+    Sent,
+    Dead,
+}
+
+impl NotificationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationStatus::Pending => "pending",
+            NotificationStatus::Sent => "sent",
+            NotificationStatus::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendOutcome {
+    Sent,
+    RetryIn(Duration, String),
+    //Failed for the last time or can't be rendered
+    Dead(String),
+}
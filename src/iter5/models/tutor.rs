@@ -1,7 +1,9 @@
 use actix_web::web;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Deserialize, Serialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Deserialize, Serialize, sqlx::FromRow, ToSchema)]
 pub struct Tutor {
     pub tutor_id: i32,
     pub tutor_name: String,
@@ -9,20 +11,28 @@ pub struct Tutor {
     pub tutor_profile: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct NewTutor {
     pub tutor_name: String,
     pub tutor_pic_url: String,
     pub tutor_profile: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct UpdateTutor {
     pub tutor_name: Option<String>,
     pub tutor_pic_url: Option<String>,
     pub tutor_profile: Option<String>,
 }
 
+//Returned after a picture upload, thumbnails are keyed by their edge length
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct TutorPicture {
+    pub tutor_id: i32,
+    pub tutor_pic_url: String,
+    pub thumbnails: BTreeMap<u32, String>,
+}
+
 impl From<web::Json<NewTutor>> for NewTutor {
     fn from(new_tutor: web::Json<NewTutor>) -> Self {
         NewTutor {
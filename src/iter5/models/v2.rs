@@ -0,0 +1,261 @@
+use super::course::{Course, CreateCourse, UpdateCourse};
+use super::tutor::{NewTutor, Tutor, UpdateTutor};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//Representations of /v2. The dbaccess layer keeps working on the v1 models,
+//these are mapped from and to them at the handler boundary.
+
+//Currency of the stored prices, from api.price_currency
+#[derive(Debug, Clone)]
+pub struct PriceCurrency(pub String);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct Money {
+    pub amount: i32,
+    //ISO 4217 code
+    pub currency: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CourseLevel {
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+impl CourseLevel {
+    //v1 stores free text, anything else is left out of v2
+    fn parse(level: &str) -> Option<CourseLevel> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "beginner" => Some(CourseLevel::Beginner),
+            "intermediate" => Some(CourseLevel::Intermediate),
+            "advanced" => Some(CourseLevel::Advanced),
+            _ => None,
+        }
+    }
+
+    //Spelled like the levels v1 clients write
+    fn as_stored(&self) -> String {
+        match self {
+            CourseLevel::Beginner => "Beginner",
+            CourseLevel::Intermediate => "Intermediate",
+            CourseLevel::Advanced => "Advanced",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CourseV2 {
+    pub id: i32,
+    pub tutor_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub format: Option<String>,
+    pub structure: Option<String>,
+    pub duration: Option<String>,
+    pub price: Option<Money>,
+    pub language: Option<String>,
+    pub level: Option<CourseLevel>,
+    pub posted_time: Option<NaiveDateTime>,
+}
+
+impl CourseV2 {
+    pub fn from_course(course: Course, currency: &PriceCurrency) -> Self {
+        CourseV2 {
+            id: course.course_id,
+            tutor_id: course.tutor_id,
+            name: course.course_name,
+            description: course.course_description,
+            format: course.course_format,
+            structure: course.course_structure,
+            duration: course.course_duration,
+            price: course.course_price.map(|amount| Money {
+                amount,
+                currency: currency.0.clone(),
+            }),
+            language: course.course_language,
+            level: course.course_level.as_deref().and_then(CourseLevel::parse),
+            posted_time: course.posted_time,
+        }
+    }
+}
+
+//Prices are stored without their currency, only the configured one is accepted
+fn stored_price(price: Option<Money>, currency: &PriceCurrency) -> Result<Option<i32>, String> {
+    match price {
+        Some(price) if price.currency != currency.0 => Err(format!(
+            "price.currency must be {}, got {}",
+            currency.0, price.currency
+        )),
+        price => Ok(price.map(|price| price.amount)),
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct CreateCourseV2 {
+    pub tutor_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub format: Option<String>,
+    pub structure: Option<String>,
+    pub duration: Option<String>,
+    pub price: Option<Money>,
+    pub language: Option<String>,
+    pub level: Option<CourseLevel>,
+}
+
+impl CreateCourseV2 {
+    pub fn into_create_course(self, currency: &PriceCurrency) -> Result<CreateCourse, String> {
+        let course = CreateCourse {
+            tutor_id: self.tutor_id,
+            course_name: self.name,
+            course_description: self.description,
+            course_format: self.format,
+            course_structure: self.structure,
+            course_duration: self.duration,
+            course_price: stored_price(self.price, currency)?,
+            course_language: self.language,
+            course_level: self.level.map(|level| level.as_stored()),
+        };
+        course.validate()?;
+        Ok(course)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct UpdateCourseV2 {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub format: Option<String>,
+    pub structure: Option<String>,
+    pub duration: Option<String>,
+    pub price: Option<Money>,
+    pub language: Option<String>,
+    pub level: Option<CourseLevel>,
+}
+
+impl UpdateCourseV2 {
+    pub fn into_update_course(self, currency: &PriceCurrency) -> Result<UpdateCourse, String> {
+        if self.price.as_ref().is_some_and(|price| price.amount < 0) {
+            return Err("price.amount must not be negative".into());
+        }
+        Ok(UpdateCourse {
+            course_name: self.name,
+            course_description: self.description,
+            course_format: self.format,
+            course_structure: self.structure,
+            course_duration: self.duration,
+            course_price: stored_price(self.price, currency)?,
+            course_language: self.language,
+            course_level: self.level.map(|level| level.as_stored()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TutorV2 {
+    pub id: i32,
+    pub name: String,
+    pub picture_url: String,
+    pub profile: String,
+}
+
+impl From<Tutor> for TutorV2 {
+    fn from(tutor: Tutor) -> Self {
+        TutorV2 {
+            id: tutor.tutor_id,
+            name: tutor.tutor_name,
+            picture_url: tutor.tutor_pic_url,
+            profile: tutor.tutor_profile,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct NewTutorV2 {
+    pub name: String,
+    pub picture_url: String,
+    pub profile: String,
+}
+
+impl From<NewTutorV2> for NewTutor {
+    fn from(tutor: NewTutorV2) -> Self {
+        NewTutor {
+            tutor_name: tutor.name,
+            tutor_pic_url: tutor.picture_url,
+            tutor_profile: tutor.profile,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct UpdateTutorV2 {
+    pub name: Option<String>,
+    pub picture_url: Option<String>,
+    pub profile: Option<String>,
+}
+
+impl From<UpdateTutorV2> for UpdateTutor {
+    fn from(tutor: UpdateTutorV2) -> Self {
+        UpdateTutor {
+            tutor_name: tutor.name,
+            tutor_pic_url: tutor.picture_url,
+            tutor_profile: tutor.profile,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn courses_map_between_versions() {
+        let currency = PriceCurrency("USD".to_string());
+        let course = Course {
+            course_id: 7,
+            tutor_id: 1,
+            course_name: "Rust basics".into(),
+            course_description: None,
+            course_format: None,
+            course_structure: None,
+            course_duration: None,
+            course_price: Some(300),
+            course_language: None,
+            course_level: Some("intermediate".into()),
+            posted_time: None,
+        };
+        let json = serde_json::to_value(CourseV2::from_course(course, &currency)).unwrap();
+        assert_eq!(json["price"]["amount"], 300);
+        assert_eq!(json["price"]["currency"], "USD");
+        assert_eq!(json["level"], "intermediate");
+        assert_eq!(json["name"], "Rust basics");
+
+        let new_course: CreateCourseV2 = serde_json::from_value(serde_json::json!({
+            "tutor_id": 1,
+            "name": "Rust basics",
+            "price": {"amount": 300, "currency": "EUR"},
+            "level": "advanced"
+        }))
+        .unwrap();
+        let err = new_course
+            .clone()
+            .into_create_course(&currency)
+            .unwrap_err();
+        assert!(err.contains("price.currency must be USD"));
+        let course = new_course
+            .into_create_course(&PriceCurrency("EUR".to_string()))
+            .unwrap();
+        assert_eq!(course.course_price, Some(300));
+        assert_eq!(course.course_level.as_deref(), Some("Advanced"));
+
+        let unknown_level = serde_json::from_value::<CreateCourseV2>(serde_json::json!({
+            "tutor_id": 1, "name": "Rust basics", "level": "expert"
+        }));
+        assert!(unknown_level.is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::models::event::EventType;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::{IntoParams, ToSchema};
+
+const MIN_SECRET_LENGTH: usize = 16;
+
+//Subscription of a partner system. The secret signs the deliveries, it is
+//only returned once, when the subscription is created.
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct Webhook {
+    pub webhook_id: i32,
+    pub url: String,
+    //Names such as "course.created"
+    pub event_types: Vec<String>,
+    pub active: bool,
+    pub created_time: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    //Keep it, it can't be read again, only replaced
+    pub secret: String,
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct NewWebhook {
+    pub url: String,
+    pub event_types: Vec<String>,
+    //Generated if not given
+    pub secret: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct UpdateWebhook {
+    pub url: Option<String>,
+    pub event_types: Option<Vec<String>>,
+    pub secret: Option<String>,
+    //Inactive subscriptions get no new deliveries and pending ones wait
+    pub active: Option<bool>,
+}
+
+impl NewWebhook {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_url(&self.url)?;
+        validate_event_types(&self.event_types)?;
+        self.secret.as_deref().map_or(Ok(()), validate_secret)
+    }
+}
+
+impl UpdateWebhook {
+    pub fn validate(&self) -> Result<(), String> {
+        self.url.as_deref().map_or(Ok(()), validate_url)?;
+        self.event_types
+            .as_deref()
+            .map_or(Ok(()), validate_event_types)?;
+        self.secret.as_deref().map_or(Ok(()), validate_secret)
+    }
+}
+
+fn validate_url(url: &str) -> Result<(), String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err("url must start with http:// or https://".into());
+    }
+    if url.chars().count() > 2000 {
+        return Err("url is longer than 2000 characters".into());
+    }
+    Ok(())
+}
+
+fn validate_event_types(event_types: &[String]) -> Result<(), String> {
+    if event_types.is_empty() {
+        return Err("event_types must name at least one event type".into());
+    }
+    if let Some(unknown) = event_types
+        .iter()
+        .find(|name| EventType::parse(name).is_none())
+    {
+        let known: Vec<&str> = EventType::ALL.iter().map(EventType::as_str).collect();
+        return Err(format!(
+            "unknown event type {:?}, expected one of {}",
+            unknown,
+            known.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn validate_secret(secret: &str) -> Result<(), String> {
+    let length = secret.chars().count();
+    if !(MIN_SECRET_LENGTH..=200).contains(&length) {
+        return Err(format!(
+            "secret must have between {} and 200 characters",
+            MIN_SECRET_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+pub fn generate_secret() -> String {
+    format!("whsec_{}", uuid::Uuid::new_v4().simple())
+}
+
+//pending until the receiver answered with 2xx (delivered) or every attempt
+//failed (dead). Manual redelivery makes any delivery pending again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct WebhookDelivery {
+    pub delivery_id: i32,
+    pub webhook_id: i32,
+    //Same for every subscription and every redelivery of the event
+    pub event_id: String,
+    pub event_type: String,
+    //The exact body that is signed and sent
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_time: NaiveDateTime,
+    pub created_time: NaiveDateTime,
+    pub delivered_time: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow, ToSchema)]
+pub struct WebhookAttempt {
+    pub attempt_id: i32,
+    pub delivery_id: i32,
+    //None if no answer was received
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+    pub attempted_time: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct DeliveryDetails {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub attempts: Vec<WebhookAttempt>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryFilter {
+    pub status: Option<DeliveryStatus>,
+    //Newest deliveries first, 50 if not given
+    pub limit: Option<i64>,
+}
+
+//Delivery claimed by a sender, with what it needs to send it
+#[derive(Debug, Clone)]
+pub struct DueDelivery {
+    pub delivery_id: i32,
+    pub event_id: String,
+    pub event_type: String,
+    pub payload: String,
+    //Including the one being made
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAttempt {
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+//What happens to a delivery after an attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptOutcome {
+    Delivered,
+    RetryIn(Duration),
+    Dead,
+}
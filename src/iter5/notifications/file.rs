@@ -0,0 +1,46 @@
+use super::{Email, Mailer};
+use crate::errors::EzyTutorError;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+//Appends every email as a JSON line instead of sending it. The file is opened
+//for every email, so it can be rotated while the notifier runs.
+pub struct FileMailer {
+    path: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileMailer { path: path.into() }
+    }
+
+    fn storage_error(&self, err: std::io::Error) -> EzyTutorError {
+        EzyTutorError::StorageError(format!("email file {}: {}", self.path.display(), err))
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send(&self, email: &Email) -> Result<(), EzyTutorError> {
+        let mut line = serde_json::to_string(email).expect("emails can be serialized");
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|err| self.storage_error(err))?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(|err| self.storage_error(err))?;
+        file.sync_data()
+            .await
+            .map_err(|err| self.storage_error(err))
+    }
+}
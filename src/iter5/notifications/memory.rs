@@ -0,0 +1,49 @@
+use super::{Email, Mailer};
+use crate::errors::EzyTutorError;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+//Keeps the emails for tests to look at, the first failures given are
+//answered like an unreachable server
+#[derive(Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<Email>>,
+    failures: AtomicUsize,
+}
+
+impl MemoryMailer {
+    pub fn failing(failures: usize) -> Self {
+        MemoryMailer {
+            failures: AtomicUsize::new(failures),
+            ..MemoryMailer::default()
+        }
+    }
+
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn send(&self, email: &Email) -> Result<(), EzyTutorError> {
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if failing {
+            return Err(EzyTutorError::ServiceUnavailable(
+                "memory: connection refused".into(),
+            ));
+        }
+        self.sent.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
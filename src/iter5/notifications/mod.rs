@@ -0,0 +1,512 @@
+pub mod file;
+#[cfg(test)]
+pub mod memory;
+pub mod smtp;
+pub mod templates;
+
+use crate::config::NotificationConfig;
+use crate::dbaccess::notification::{claim_due_notifications_db, record_send_outcome_db};
+use crate::errors::EzyTutorError;
+use crate::models::notification::{DueNotification, NotificationKind, SendOutcome};
+use crate::poller::{backoff, Queue};
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use templates::Templates;
+
+//Time a claimed notification is left to its sender before others may retry
+//it, longer than an SMTP timeout
+const LEASE: Duration = Duration::from_secs(120);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+//Transport of the rendered emails. InvalidInput is for emails the transport
+//will never take, every other error is retried.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, email: &Email) -> Result<(), EzyTutorError>;
+}
+
+//The file if configured, for tests and development, SMTP otherwise
+pub fn mailer_from_config(config: &NotificationConfig) -> Result<Arc<dyn Mailer>, EzyTutorError> {
+    match (&config.file, &config.smtp) {
+        (Some(path), _) => Ok(Arc::new(file::FileMailer::new(path))),
+        (None, Some(smtp)) => Ok(Arc::new(smtp::SmtpMailer::new(smtp)?)),
+        (None, None) => Err(EzyTutorError::InvalidInput(
+            "notifications need a file or smtp".into(),
+        )),
+    }
+}
+
+pub struct Notifier {
+    mailer: Arc<dyn Mailer>,
+    templates: Templates,
+    config: NotificationConfig,
+}
+
+impl Notifier {
+    //Links in the emails point to the site URL
+    pub fn new(
+        mailer: Arc<dyn Mailer>,
+        config: &NotificationConfig,
+        site_url: &str,
+    ) -> Result<Self, EzyTutorError> {
+        let templates = Templates::load(&config.default_locale, site_url)
+            .map_err(EzyTutorError::InvalidInput)?;
+        Ok(Notifier {
+            mailer,
+            templates,
+            config: config.clone(),
+        })
+    }
+
+    async fn send(&self, notification: &DueNotification) -> SendOutcome {
+        let email = match self.render(notification) {
+            Ok(email) => email,
+            //Rendering it again won't help
+            Err(err) => return SendOutcome::Dead(err),
+        };
+        match self.mailer.send(&email).await {
+            Ok(()) => SendOutcome::Sent,
+            Err(err @ EzyTutorError::InvalidInput(_)) => SendOutcome::Dead(err.to_string()),
+            Err(err) if notification.attempts.max(0) as u32 >= self.config.max_attempts => {
+                SendOutcome::Dead(err.to_string())
+            }
+            Err(err) => SendOutcome::RetryIn(
+                backoff(
+                    self.config.backoff_initial_secs,
+                    self.config.backoff_max_secs,
+                    notification.attempts.max(0) as u32,
+                ),
+                err.to_string(),
+            ),
+        }
+    }
+
+    fn render(&self, notification: &DueNotification) -> Result<Email, String> {
+        let kind = NotificationKind::parse(&notification.kind)
+            .ok_or_else(|| format!("unknown notification kind {:?}", notification.kind))?;
+        let context: serde_json::Value =
+            serde_json::from_str(&notification.context).map_err(|err| err.to_string())?;
+        let rendered = self
+            .templates
+            .render(kind, &notification.locale, &context)
+            .map_err(|err| err.to_string())?;
+        Ok(Email {
+            from: self.config.from.clone(),
+            to: notification.email.clone(),
+            subject: rendered.subject,
+            text: rendered.text,
+            html: rendered.html,
+        })
+    }
+}
+
+#[async_trait]
+impl Queue for Notifier {
+    type Item = DueNotification;
+    const NAME: &'static str = "notifications";
+
+    fn batch_size(&self) -> u32 {
+        self.config.batch_size
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.config.poll_interval_ms)
+    }
+
+    async fn claim(
+        &self,
+        pool: &PgPool,
+        limit: i64,
+    ) -> Result<Vec<DueNotification>, EzyTutorError> {
+        claim_due_notifications_db(pool, limit, LEASE).await
+    }
+
+    async fn dispatch(
+        &self,
+        pool: &PgPool,
+        notification: &DueNotification,
+    ) -> Result<(), EzyTutorError> {
+        let outcome = self.send(notification).await;
+        if let SendOutcome::Dead(error) = &outcome {
+            tracing::warn!(
+                notification_id = notification.notification_id,
+                attempts = notification.attempts,
+                mailer = self.mailer.name(),
+                error,
+                "notification is dead"
+            );
+        }
+        record_send_outcome_db(pool, notification.notification_id, &outcome).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::memory::MemoryMailer;
+    use super::*;
+    use crate::dbaccess::course::*;
+    use crate::dbaccess::notification::set_preferences_db;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::models::chat::{ChatRole, Participant};
+    use crate::models::course::{CreateCourse, UpdateCourse};
+    use crate::models::notification::SetPreferences;
+    use crate::models::tutor::NewTutor;
+    use crate::poller::process_due;
+    use dotenv::dotenv;
+    use std::env;
+
+    fn notifier(mailer: Arc<MemoryMailer>) -> Notifier {
+        Notifier::new(
+            mailer,
+            &NotificationConfig::default(),
+            "https://ezytutors.example",
+        )
+        .unwrap()
+    }
+
+    fn due(kind: &str, attempts: i32) -> DueNotification {
+        DueNotification {
+            notification_id: 1,
+            kind: kind.to_string(),
+            email: "ada@example.com".into(),
+            locale: "en".into(),
+            context: r#"{"tutor_id": 1, "tutor_name": "Ada"}"#.into(),
+            attempts,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn failures_are_retried_until_the_last_attempt() {
+        let mailer = Arc::new(MemoryMailer::failing(2));
+        let notifier = notifier(mailer.clone());
+        assert_eq!(
+            notifier.send(&due("profile_updated", 1)).await,
+            SendOutcome::RetryIn(
+                Duration::from_secs(60),
+                "Service Unavailable memory: connection refused".into()
+            )
+        );
+        assert!(matches!(
+            notifier.send(&due("profile_updated", 8)).await,
+            SendOutcome::Dead(_)
+        ));
+        assert_eq!(
+            notifier.send(&due("profile_updated", 9)).await,
+            SendOutcome::Sent
+        );
+        //Rendering them again won't help
+        assert!(matches!(
+            notifier.send(&due("newsletter", 1)).await,
+            SendOutcome::Dead(error) if error.contains("unknown notification kind")
+        ));
+        assert!(matches!(
+            notifier.send(&due("course_updated", 1)).await,
+            SendOutcome::Dead(_)
+        ));
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].from, "EzyTutors <no-reply@ezytutors.local>");
+        assert_eq!(sent[0].subject, "Your tutor profile was changed");
+    }
+
+    #[actix_rt::test]
+    async fn changes_notify_the_recipients_who_want_it() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Notified tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic7".into(),
+                tutor_profile: "Reads their email".into(),
+            },
+        )
+        .await
+        .unwrap();
+        //Ids no other test enrolls
+        let students = [900_000 + tutor.tutor_id, 910_000 + tutor.tutor_id];
+        let recipients = [
+            (
+                ChatRole::Tutor,
+                tutor.tutor_id,
+                "tutor@example.com",
+                "en",
+                vec![],
+            ),
+            (
+                ChatRole::Student,
+                students[0],
+                "first@example.com",
+                "de",
+                vec![],
+            ),
+            (
+                ChatRole::Student,
+                students[1],
+                "second@example.com",
+                "en",
+                vec!["course_updated".to_string()],
+            ),
+        ];
+        for (role, id, email, locale, muted) in recipients {
+            set_preferences_db(
+                &pool,
+                Participant { role, id },
+                &SetPreferences {
+                    email: email.into(),
+                    locale: Some(locale.into()),
+                    muted: Some(muted),
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let course = post_new_course_db(
+            &pool,
+            CreateCourse {
+                tutor_id: tutor.tutor_id,
+                course_name: "Notified course".into(),
+                course_description: None,
+                course_format: None,
+                course_structure: None,
+                course_duration: None,
+                course_price: None,
+                course_language: None,
+                course_level: None,
+            },
+        )
+        .await
+        .unwrap();
+        for student_id in students {
+            sqlx::query!(
+                "INSERT INTO ezy_enrollment_c6 (course_id, student_id) VALUES ($1, $2)",
+                course.course_id,
+                student_id
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        update_course_details_db(
+            &pool,
+            tutor.tutor_id,
+            course.course_id,
+            UpdateCourse {
+                course_name: None,
+                course_description: None,
+                course_format: None,
+                course_structure: None,
+                course_duration: None,
+                course_price: Some(100),
+                course_language: None,
+                course_level: None,
+            },
+        )
+        .await
+        .unwrap();
+        delete_course_db(&pool, tutor.tutor_id, course.course_id)
+            .await
+            .unwrap();
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+
+        let ids = [tutor.tutor_id, students[0], students[1]];
+        let queued = || {
+            sqlx::query!(
+                "SELECT notification_id, kind, email, status, attempts, last_error
+                FROM ezy_notification_c6 WHERE recipient_id = ANY($1)
+                AND ((recipient_role = 'tutor' AND recipient_id = $2)
+                OR (recipient_role = 'student' AND recipient_id <> $2))
+                ORDER BY notification_id",
+                &ids[..],
+                tutor.tutor_id
+            )
+            .fetch_all(&pool)
+        };
+        let notifications = queued().await.unwrap();
+        let kinds: Vec<(&str, &str)> = notifications
+            .iter()
+            .map(|row| (row.kind.as_str(), row.email.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("course_published", "tutor@example.com"),
+                ("course_updated", "first@example.com"),
+                ("course_cancelled", "first@example.com"),
+                ("course_cancelled", "second@example.com"),
+                ("account_closed", "tutor@example.com"),
+            ]
+        );
+
+        //The first email fails and waits for its retry, the others are sent
+        let mailer = Arc::new(MemoryMailer::failing(1));
+        let notifier = notifier(mailer.clone());
+        while process_due(&notifier, &pool).await.unwrap() > 0 {}
+        let notifications = queued().await.unwrap();
+        let pending: Vec<_> = notifications
+            .iter()
+            .filter(|row| row.status == "pending")
+            .collect();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 1);
+        assert!(pending[0]
+            .last_error
+            .as_deref()
+            .unwrap()
+            .contains("connection refused"));
+        sqlx::query!(
+            "UPDATE ezy_notification_c6 SET next_attempt_time = now()
+            WHERE notification_id = $1",
+            pending[0].notification_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        while process_due(&notifier, &pool).await.unwrap() > 0 {}
+        let notifications = queued().await.unwrap();
+        assert!(notifications.iter().all(|row| row.status == "sent"));
+
+        let sent = mailer.sent();
+        let german = sent
+            .iter()
+            .find(|email| email.to == "first@example.com" && email.subject.contains("abgesagt"))
+            .unwrap();
+        assert_eq!(german.subject, "Notified course wurde abgesagt");
+        assert!(german.text.contains("Notified tutor"));
+        assert!(german.html.contains("<strong>Notified course</strong>"));
+
+        //The tutor's preferences went with the profile
+        sqlx::query!(
+            "DELETE FROM ezy_notification_preference_c6
+            WHERE recipient_role = 'student' AND recipient_id = ANY($1)",
+            &students[..]
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let preferences = sqlx::query_scalar!(
+            "SELECT count(*) FROM ezy_notification_preference_c6
+            WHERE recipient_role = 'tutor' AND recipient_id = $1",
+            tutor.tutor_id
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(preferences, Some(0));
+        let notification_ids: Vec<i32> = notifications
+            .iter()
+            .map(|row| row.notification_id)
+            .collect();
+        sqlx::query!(
+            "DELETE FROM ezy_notification_c6 WHERE notification_id = ANY($1)",
+            &notification_ids[..]
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn deleting_a_course_of_another_tutor_notifies_nobody() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let mut tutors = Vec::new();
+        for tutor_name in ["Course owner", "Other tutor"] {
+            let tutor = post_new_tutor_db(
+                &pool,
+                NewTutor {
+                    tutor_name: tutor_name.into(),
+                    tutor_pic_url: "http://s3.amazon.aws.com/pic7".into(),
+                    tutor_profile: "Teaches".into(),
+                },
+            )
+            .await
+            .unwrap();
+            tutors.push(tutor.tutor_id);
+        }
+        let (owner, other) = (tutors[0], tutors[1]);
+        //An id no other test enrolls
+        let student = 920_000 + owner;
+        set_preferences_db(
+            &pool,
+            Participant {
+                role: ChatRole::Student,
+                id: student,
+            },
+            &SetPreferences {
+                email: "enrolled@example.com".into(),
+                locale: None,
+                muted: None,
+            },
+        )
+        .await
+        .unwrap();
+        let course = post_new_course_db(
+            &pool,
+            CreateCourse {
+                tutor_id: owner,
+                course_name: "Someone else's course".into(),
+                course_description: None,
+                course_format: None,
+                course_structure: None,
+                course_duration: None,
+                course_price: None,
+                course_language: None,
+                course_level: None,
+            },
+        )
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO ezy_enrollment_c6 (course_id, student_id) VALUES ($1, $2)",
+            course.course_id,
+            student
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        delete_course_db(&pool, other, course.course_id)
+            .await
+            .unwrap();
+        let queued = sqlx::query_scalar!(
+            "SELECT count(*) FROM ezy_notification_c6
+            WHERE recipient_role = 'student' AND recipient_id = $1",
+            student
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(queued, Some(0));
+        let courses = get_courses_for_tutor_db(&pool, owner).await.unwrap();
+        assert_eq!(courses.len(), 1);
+
+        //Without preferences the tutors' deletions queue nothing either
+        sqlx::query!(
+            "DELETE FROM ezy_notification_preference_c6
+            WHERE recipient_role = 'student' AND recipient_id = $1",
+            student
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        for tutor_id in tutors {
+            delete_tutor_db(&pool, tutor_id).await.unwrap();
+        }
+    }
+}
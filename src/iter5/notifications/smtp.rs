@@ -0,0 +1,68 @@
+use super::{Email, Mailer};
+use crate::config::{SmtpConfig, SmtpTls};
+use crate::errors::EzyTutorError;
+use async_trait::async_trait;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::time::Duration;
+
+//Sends through a relay, keeping a few connections to it open
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &SmtpConfig) -> Result<Self, EzyTutorError> {
+        let builder = match config.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host),
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &config.host,
+            )),
+        }
+        .map_err(|err| EzyTutorError::InvalidInput(format!("notifications.smtp.host: {}", err)))?;
+        let mut builder = builder
+            .port(config.port)
+            .timeout(Some(Duration::from_secs(config.timeout_secs)));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(SmtpMailer {
+            transport: builder.build(),
+        })
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, EzyTutorError> {
+    address
+        .parse()
+        .map_err(|err| EzyTutorError::InvalidInput(format!("{:?}: {}", address, err)))
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, email: &Email) -> Result<(), EzyTutorError> {
+        let message = Message::builder()
+            .from(mailbox(&email.from)?)
+            .to(mailbox(&email.to)?)
+            .subject(&email.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                email.text.clone(),
+                email.html.clone(),
+            ))
+            .map_err(|err| EzyTutorError::InvalidInput(err.to_string()))?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            //Rejected for good, such as an unknown mailbox
+            Err(err) if err.is_permanent() => {
+                Err(EzyTutorError::InvalidInput(format!("smtp: {}", err)))
+            }
+            Err(err) => Err(EzyTutorError::ServiceUnavailable(format!("smtp: {}", err))),
+        }
+    }
+}
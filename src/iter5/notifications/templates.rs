@@ -0,0 +1,186 @@
+use crate::models::notification::{NotificationKind, LOCALES};
+use minijinja::{Environment, UndefinedBehavior, Value};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+//One file per locale in LOCALES, with the same kinds
+const SOURCES: [(&str, &str); 2] = [
+    ("en", include_str!("templates/en.toml")),
+    ("de", include_str!("templates/de.toml")),
+];
+
+#[derive(Deserialize)]
+struct LocaleFile {
+    //Wraps every HTML body, given as body
+    layout: String,
+    #[serde(flatten)]
+    kinds: HashMap<String, KindTemplates>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KindTemplates {
+    subject: String,
+    text: String,
+    html: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendered {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+//Templates are named "<locale>/<kind>.<part>", the .html ones are escaped
+pub struct Templates {
+    env: Environment<'static>,
+    default_locale: String,
+}
+
+impl Templates {
+    //Fails if a locale lacks a kind or a template doesn't parse
+    pub fn load(default_locale: &str, site_url: &str) -> Result<Self, String> {
+        let mut env = Environment::new();
+        //A value missing from the context is a bug, not an empty string
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.add_global("site_url", site_url.trim_end_matches('/'));
+        for (locale, source) in SOURCES {
+            let file: LocaleFile =
+                toml::from_str(source).map_err(|err| format!("{} templates: {}", locale, err))?;
+            let mut templates = vec![(format!("{}/layout.html", locale), file.layout)];
+            let mut kinds = file.kinds;
+            for kind in NotificationKind::ALL {
+                let KindTemplates {
+                    subject,
+                    text,
+                    html,
+                } = kinds
+                    .remove(kind.as_str())
+                    .ok_or_else(|| format!("{} templates: {} is missing", locale, kind.as_str()))?;
+                let name = format!("{}/{}", locale, kind.as_str());
+                templates.push((format!("{}.subject", name), subject));
+                templates.push((format!("{}.txt", name), text));
+                templates.push((format!("{}.html", name), html));
+            }
+            if let Some(unknown) = kinds.keys().next() {
+                return Err(format!("{} templates: unknown kind {}", locale, unknown));
+            }
+            for (name, template) in templates {
+                env.add_template_owned(name, template)
+                    .map_err(|err| format!("{} templates: {}", locale, err))?;
+            }
+        }
+        Ok(Templates {
+            env,
+            default_locale: default_locale.to_string(),
+        })
+    }
+
+    //In the default locale if there are no templates for the one given
+    pub fn render(
+        &self,
+        kind: NotificationKind,
+        locale: &str,
+        context: &serde_json::Value,
+    ) -> Result<Rendered, minijinja::Error> {
+        let locale = if LOCALES.contains(&locale) {
+            locale
+        } else {
+            &self.default_locale
+        };
+        let name = format!("{}/{}", locale, kind.as_str());
+        let render = |name: &str| self.env.get_template(name)?.render(context);
+        let subject = render(&format!("{}.subject", name))?;
+        let text = render(&format!("{}.txt", name))?;
+        let body = render(&format!("{}.html", name))?;
+        let html = self
+            .env
+            .get_template(&format!("{}/layout.html", locale))?
+            .render(minijinja::context! { body => Value::from_safe_string(body) })?;
+        Ok(Rendered {
+            //A header, it can't span lines
+            subject: subject.split_whitespace().collect::<Vec<_>>().join(" "),
+            text,
+            html,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn templates() -> Templates {
+        Templates::load("en", "https://ezytutors.example/").unwrap()
+    }
+
+    fn course_context() -> serde_json::Value {
+        json!({
+            "tutor_id": 7,
+            "tutor_name": "Ada",
+            "course_id": 42,
+            "course_name": "Rust <for> \"everyone\" & more",
+        })
+    }
+
+    #[test]
+    fn every_kind_renders_in_every_locale() {
+        let templates = templates();
+        for locale in LOCALES {
+            for kind in NotificationKind::ALL {
+                let rendered = templates.render(kind, locale, &course_context()).unwrap();
+                assert!(!rendered.subject.is_empty(), "{} {:?}", locale, kind);
+                assert!(rendered.text.contains("Ada"), "{} {:?}", locale, kind);
+                assert!(rendered.html.contains("Ada"), "{} {:?}", locale, kind);
+                assert!(rendered.html.starts_with("<!DOCTYPE html>"));
+            }
+        }
+    }
+
+    #[test]
+    fn html_is_escaped_and_text_is_not() {
+        let rendered = templates()
+            .render(NotificationKind::CoursePublished, "en", &course_context())
+            .unwrap();
+        assert_eq!(
+            rendered.subject,
+            "Your course Rust <for> \"everyone\" & more is listed"
+        );
+        assert!(rendered.text.contains("\"Rust <for> \"everyone\" & more\""));
+        assert!(rendered
+            .text
+            .contains("https://ezytutors.example/v1/courses/7/42"));
+        assert!(rendered
+            .html
+            .contains("<strong>Rust &lt;for&gt; &quot;everyone&quot; &amp; more</strong>"));
+        assert!(!rendered.html.contains("<for>"));
+    }
+
+    #[test]
+    fn unknown_locales_get_the_default_one() {
+        let templates = templates();
+        let german = templates
+            .render(NotificationKind::AccountClosed, "de", &course_context())
+            .unwrap();
+        assert_eq!(german.subject, "Ihr Profil wurde entfernt");
+        assert!(german.html.contains("<html lang=\"de\">"));
+        let fallback = templates
+            .render(NotificationKind::AccountClosed, "fr", &course_context())
+            .unwrap();
+        assert_eq!(fallback.subject, "Your tutor profile was removed");
+    }
+
+    #[test]
+    fn missing_values_fail_the_rendering() {
+        let err = templates()
+            .render(
+                NotificationKind::CourseUpdated,
+                "en",
+                &json!({ "tutor_name": "Ada" }),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), minijinja::ErrorKind::UndefinedError);
+    }
+}
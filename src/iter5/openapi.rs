@@ -0,0 +1,331 @@
+use crate::errors::MyErrorResponse;
+use crate::handlers::{
+    attachment, chat, course, export, general, media, metrics, notification, stream, tutor, v2,
+    webhook,
+};
+use crate::models::attachment::Attachment;
+use crate::models::chat::{
+    ChatMessage, ChatRole, Conversation, ConversationSummary, NewConversation,
+};
+use crate::models::course::{
+    Course, CreateCourse, ImportCourse, ImportMode, ImportReport, ImportRowResult, UpdateCourse,
+};
+use crate::models::export::ExportFormat;
+use crate::models::health::{DependencyStatus, HealthReport, HealthStatus};
+use crate::models::notification::{NotificationPreferences, SetPreferences};
+use crate::models::tutor::{NewTutor, Tutor, TutorPicture, UpdateTutor};
+use crate::models::v2::{
+    CourseLevel, CourseV2, CreateCourseV2, Money, NewTutorV2, TutorV2, UpdateCourseV2,
+    UpdateTutorV2,
+};
+use crate::models::webhook::{
+    CreatedWebhook, DeliveryDetails, DeliveryStatus, NewWebhook, UpdateWebhook, Webhook,
+    WebhookAttempt, WebhookDelivery,
+};
+use utoipa::{OpenApi, ToSchema};
+
+//Request body of a picture upload, only used for the specification
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct PictureUpload {
+    //PNG, JPEG, GIF or WebP image
+    #[schema(value_type = String, format = Binary)]
+    picture: Vec<u8>,
+}
+
+//Request body of an attachment upload, only used for the specification
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct AttachmentUpload {
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+//Every handler mounted in routes.rs has to be listed here,
+//tests::spec_covers_all_routes fails otherwise
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "EzyTutors API",
+        description = "Tutors, their courses and course materials. The unprefixed \
+            /courses and /tutors paths are deprecated aliases of /v1."
+    ),
+    paths(
+        general::health_check_handler,
+        general::liveness_handler,
+        general::readiness_handler,
+        metrics::metrics_handler,
+        course::post_new_course,
+        course::import_courses,
+        course::get_courses_for_tutor,
+        course::get_course_details,
+        course::update_course_details,
+        course::delete_course,
+        attachment::post_new_attachment,
+        attachment::get_attachments_for_course,
+        attachment::download_attachment,
+        attachment::delete_attachment,
+        tutor::post_new_tutor,
+        tutor::get_all_tutors,
+        tutor::get_tutor_details,
+        tutor::update_tutor_details,
+        tutor::delete_tutor,
+        media::upload_tutor_picture,
+        media::get_media,
+        export::export_courses,
+        export::export_tutors,
+        webhook::post_new_webhook,
+        webhook::get_webhooks,
+        webhook::get_webhook,
+        webhook::update_webhook,
+        webhook::delete_webhook,
+        webhook::get_webhook_deliveries,
+        webhook::get_webhook_delivery,
+        webhook::redeliver_webhook_delivery,
+        stream::get_course_events,
+        stream::get_tutor_events,
+        chat::post_new_conversation,
+        chat::get_conversations,
+        chat::get_chat_messages,
+        chat::get_chat_socket,
+        notification::get_notification_preferences,
+        notification::put_notification_preferences,
+        notification::delete_notification_preferences,
+        v2::post_new_course,
+        v2::get_courses_for_tutor,
+        v2::get_course_details,
+        v2::update_course_details,
+        v2::delete_course,
+        v2::post_new_tutor,
+        v2::get_all_tutors,
+        v2::get_tutor_details,
+        v2::update_tutor_details,
+        v2::delete_tutor,
+    ),
+    components(schemas(
+        Course,
+        CreateCourse,
+        UpdateCourse,
+        ImportCourse,
+        ImportMode,
+        ImportReport,
+        ImportRowResult,
+        Tutor,
+        NewTutor,
+        UpdateTutor,
+        TutorPicture,
+        Attachment,
+        ExportFormat,
+        HealthReport,
+        HealthStatus,
+        DependencyStatus,
+        MyErrorResponse,
+        PictureUpload,
+        AttachmentUpload,
+        CourseV2,
+        CreateCourseV2,
+        UpdateCourseV2,
+        CourseLevel,
+        Money,
+        TutorV2,
+        NewTutorV2,
+        UpdateTutorV2,
+        Webhook,
+        CreatedWebhook,
+        NewWebhook,
+        UpdateWebhook,
+        WebhookDelivery,
+        WebhookAttempt,
+        DeliveryDetails,
+        DeliveryStatus,
+        Conversation,
+        ConversationSummary,
+        NewConversation,
+        ChatMessage,
+        ChatRole,
+        NotificationPreferences,
+        SetPreferences,
+    )),
+    tags(
+        (name = "general", description = "Service health"),
+        (name = "courses", description = "Courses offered by tutors"),
+        (name = "attachments", description = "Downloadable course materials"),
+        (name = "tutors", description = "Tutor profiles"),
+        (name = "media", description = "Publicly served tutor pictures"),
+        (name = "events", description = "Server-sent streams of changes"),
+        (name = "chat", description = "Conversations between students and tutors"),
+        (name = "notifications", description = "Emails about changes to courses and tutors"),
+        (name = "admin", description = "Operations, requires the admin token")
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::*;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+    use std::collections::BTreeSet;
+
+    //Full patterns of the resources in the debug output of a resource map.
+    //Nodes are nested by indentation, prefix nodes are scopes.
+    fn resource_patterns(resource_map: &str) -> Vec<String> {
+        let lines: Vec<&str> = resource_map.lines().collect();
+        let mut scopes: Vec<(usize, String)> = Vec::new();
+        let mut patterns = Vec::new();
+        let mut node = (0, String::new());
+        for (i, line) in lines.iter().enumerate() {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            if trimmed == "ResourceMap {" {
+                while scopes.last().is_some_and(|(scope, _)| *scope >= indent) {
+                    scopes.pop();
+                }
+                node.0 = indent;
+            } else if trimmed == "patterns: Single(" {
+                let pattern = lines[i + 1].trim().trim_end_matches(',').trim_matches('"');
+                let scope = scopes.last().map(|(_, path)| path.as_str()).unwrap_or("");
+                node.1 = format!("{}{}", scope, pattern);
+            } else if trimmed == "is_prefix: true," {
+                scopes.push(node.clone());
+            } else if trimmed == "is_prefix: false," {
+                patterns.push(node.1.clone());
+            }
+        }
+        patterns
+    }
+
+    //Path parameters as in the spec, without their regex
+    fn spec_path(pattern: &str) -> String {
+        let mut path = String::new();
+        let mut in_regex = false;
+        for c in pattern.chars() {
+            match c {
+                '{' => in_regex = false,
+                ':' if path.rfind('{') > path.rfind('}') => in_regex = true,
+                '}' => in_regex = false,
+                _ => {}
+            }
+            if !in_regex {
+                path.push(c);
+            }
+        }
+        path
+    }
+
+    //A request path the pattern matches, every parameter is 1
+    fn example_uri(path: &str) -> String {
+        path.split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    "1"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    //(method, path) of every route the app registers. actix can't list its
+    //routes, so the patterns come from its resource map and every method is
+    //tried on them: only unregistered ones are answered with 404 or 405. The
+    //handlers fail on the missing app data before doing anything.
+    async fn registered_routes(
+        configure: impl FnOnce(&mut web::ServiceConfig),
+    ) -> BTreeSet<(String, String)> {
+        let app = test::init_service(App::new().configure(configure).default_service(web::to(
+            |req: HttpRequest| async move {
+                HttpResponse::NotFound().body(format!("{:#?}", req.resource_map()))
+            },
+        )))
+        .await;
+        let req = test::TestRequest::get().uri("/unrouted/").to_request();
+        let resource_map = test::call_and_read_body(&app, req).await;
+        let mut routes = BTreeSet::new();
+        for pattern in resource_patterns(std::str::from_utf8(&resource_map).unwrap()) {
+            let path = spec_path(&pattern);
+            let uri = example_uri(&path);
+            for method in [
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::DELETE,
+                Method::PATCH,
+            ] {
+                let req = test::TestRequest::default()
+                    .method(method.clone())
+                    .uri(&uri)
+                    .to_request();
+                let status = test::call_service(&app, req).await.status();
+                if status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED {
+                    routes.insert((method.as_str().to_lowercase(), path.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    fn spec_operations() -> BTreeSet<(String, String)> {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let mut operations = BTreeSet::new();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                operations.insert((method.clone(), path.clone()));
+            }
+        }
+        operations
+    }
+
+    #[actix_rt::test]
+    async fn spec_covers_all_routes() {
+        //The REST routes in the order of the server, without the deprecated
+        //aliases, the docs and GraphQL
+        let registered = registered_routes(|cfg| {
+            general_routes(cfg);
+            metrics_routes(cfg);
+            stream_routes(cfg);
+            chat_routes(cfg);
+            webhook_routes(cfg);
+            admin_routes(cfg);
+            notification_routes(cfg);
+            api_routes(cfg);
+            media_routes(cfg);
+        })
+        .await;
+        assert!(registered.contains(&("get".to_string(), "/v1/courses/{tutor_id}".to_string())));
+        let documented = spec_operations();
+        let undocumented: Vec<_> = registered.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "routes missing in the OpenAPI spec: {:?}",
+            undocumented
+        );
+        let unrouted: Vec<_> = documented.difference(&registered).collect();
+        assert!(
+            unrouted.is_empty(),
+            "operations in the OpenAPI spec without a route: {:?}",
+            unrouted
+        );
+    }
+
+    #[actix_rt::test]
+    async fn spec_is_openapi_3_1() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+        let schemas = &spec["components"]["schemas"];
+        for schema in ["Course", "CreateCourse", "UpdateCourse", "Tutor"] {
+            assert!(schemas[schema].is_object(), "{} schema missing", schema);
+        }
+        for schema in [
+            "NewTutor",
+            "UpdateTutor",
+            "MyErrorResponse",
+            "CourseV2",
+            "TutorV2",
+        ] {
+            assert!(schemas[schema].is_object(), "{} schema missing", schema);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::EventSink;
+use crate::config::BrokerSinkConfig;
+use crate::errors::EzyTutorError;
+use crate::models::event::OutboxEvent;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+const CONTENT_TYPE: &str = "application/vnd.kafka.json.v2+json";
+const ACCEPT: &str = "application/vnd.kafka.v2+json";
+
+//Produces a batch with one request to the Kafka REST Proxy. Records with the
+//same key go to the same partition in the order they were sent.
+pub struct BrokerSink {
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct ProduceResponse {
+    offsets: Vec<RecordOffset>,
+}
+
+#[derive(Deserialize)]
+struct RecordOffset {
+    error: Option<String>,
+}
+
+impl BrokerSink {
+    pub fn new(config: &BrokerSinkConfig) -> Result<Self, EzyTutorError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|err| EzyTutorError::ServiceUnavailable(format!("broker client: {}", err)))?;
+        Ok(BrokerSink {
+            client,
+            endpoint: format!(
+                "{}/topics/{}",
+                config.url.trim_end_matches('/'),
+                config.topic
+            ),
+        })
+    }
+}
+
+fn broker_error(err: impl std::fmt::Display) -> EzyTutorError {
+    EzyTutorError::ServiceUnavailable(format!("broker: {}", err))
+}
+
+#[async_trait]
+impl EventSink for BrokerSink {
+    fn name(&self) -> &'static str {
+        "broker"
+    }
+
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), EzyTutorError> {
+        let records = events
+            .iter()
+            .map(|event| {
+                let value: serde_json::Value = serde_json::from_str(&event.payload)?;
+                Ok(json!({ "key": event.partition_key, "value": value }))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()
+            .map_err(broker_error)?;
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
+            .header(reqwest::header::ACCEPT, ACCEPT)
+            .body(json!({ "records": records }).to_string())
+            .send()
+            .await
+            .map_err(broker_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(broker_error(format!("proxy answered {}", status)));
+        }
+        //Records are accepted one by one, any failed one fails the batch
+        let body = response.bytes().await.map_err(broker_error)?;
+        let produced: ProduceResponse = serde_json::from_slice(&body).map_err(broker_error)?;
+        match produced
+            .offsets
+            .iter()
+            .find_map(|offset| offset.error.as_ref())
+        {
+            Some(error) => Err(broker_error(error)),
+            None => Ok(()),
+        }
+    }
+}
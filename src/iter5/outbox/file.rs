@@ -0,0 +1,51 @@
+use super::EventSink;
+use crate::errors::EzyTutorError;
+use crate::models::event::OutboxEvent;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+//Appends one payload per line. The file is opened for every batch, so it
+//can be rotated while the relay runs.
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileSink { path: path.into() }
+    }
+
+    fn storage_error(&self, err: std::io::Error) -> EzyTutorError {
+        EzyTutorError::StorageError(format!("outbox file {}: {}", self.path.display(), err))
+    }
+}
+
+#[async_trait]
+impl EventSink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), EzyTutorError> {
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&event.payload);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|err| self.storage_error(err))?;
+        file.write_all(lines.as_bytes())
+            .await
+            .map_err(|err| self.storage_error(err))?;
+        //On disk before the events are marked published
+        file.sync_data()
+            .await
+            .map_err(|err| self.storage_error(err))
+    }
+}
@@ -0,0 +1,265 @@
+pub mod broker;
+pub mod file;
+pub mod webhook;
+
+use crate::config::OutboxConfig;
+use crate::dbaccess::outbox::*;
+use crate::errors::EzyTutorError;
+use crate::lifecycle::Lifecycle;
+use crate::models::event::OutboxEvent;
+use actix_web::web;
+use async_trait::async_trait;
+use sqlx::postgres::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+//Longest wait between rounds while a sink keeps failing
+const MAX_FAILURE_BACKOFF: Duration = Duration::from_secs(30);
+
+//Destination of the published events. A batch that failed in any sink is
+//published again in full, sinks get events more than once and in order.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), EzyTutorError>;
+}
+
+pub struct Relay {
+    sinks: Vec<Arc<dyn EventSink>>,
+    config: OutboxConfig,
+}
+
+//Webhook subscriptions always get the events, the other sinks if configured
+pub fn relay_from_config(config: &OutboxConfig, pool: PgPool) -> Result<Relay, EzyTutorError> {
+    let mut sinks: Vec<Arc<dyn EventSink>> = vec![Arc::new(webhook::WebhookSink::new(pool))];
+    if let Some(path) = &config.file {
+        sinks.push(Arc::new(file::FileSink::new(path)));
+    }
+    if let Some(broker) = &config.broker {
+        sinks.push(Arc::new(broker::BrokerSink::new(broker)?));
+    }
+    Ok(Relay::new(sinks, config.clone()))
+}
+
+impl Relay {
+    pub fn new(sinks: Vec<Arc<dyn EventSink>>, config: OutboxConfig) -> Self {
+        Relay { sinks, config }
+    }
+
+    //Publishes the oldest batch of unpublished events, returns its size. The
+    //events are only marked published once every sink took them.
+    pub async fn relay_once(&self, pool: &PgPool) -> Result<usize, EzyTutorError> {
+        let Some(mut tx) = lock_relay_db(pool).await? else {
+            return Ok(0);
+        };
+        let events = get_unpublished_events_db(&mut tx, self.config.batch_size as i64).await?;
+        if events.is_empty() {
+            return Ok(0);
+        }
+        for sink in &self.sinks {
+            sink.publish(&events).await.inspect_err(|err| {
+                tracing::warn!(sink = sink.name(), error = %err, "outbox sink failed");
+            })?;
+        }
+        let event_seqs: Vec<i64> = events.iter().map(|event| event.event_seq).collect();
+        mark_events_published_db(&mut tx, &event_seqs).await?;
+        tx.commit().await?;
+        Ok(events.len())
+    }
+
+    //Relays until shutdown starts, a batch in flight is finished first
+    pub async fn run(self, pool: PgPool, lifecycle: web::Data<Lifecycle>) {
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+        let retention = Duration::from_secs(self.config.retention_hours * 3600);
+        let mut failures = 0;
+        let mut last_pruned: Option<Instant> = None;
+        while !lifecycle.is_draining() {
+            if last_pruned.is_none_or(|pruned| pruned.elapsed() >= PRUNE_INTERVAL) {
+                match prune_published_events_db(&pool, retention).await {
+                    Ok(pruned) => tracing::debug!(pruned, "published outbox events removed"),
+                    Err(err) => tracing::warn!(error = %err, "outbox not pruned"),
+                }
+                last_pruned = Some(Instant::now());
+            }
+            let wait = match self.relay_once(&pool).await {
+                //More may be waiting already
+                Ok(relayed) if relayed == self.config.batch_size as usize => {
+                    failures = 0;
+                    continue;
+                }
+                Ok(_) => {
+                    failures = 0;
+                    poll_interval
+                }
+                Err(err) => {
+                    failures += 1;
+                    tracing::warn!(error = %err, failures, "outbox relay failed");
+                    (poll_interval * 2u32.pow(failures.min(10))).min(MAX_FAILURE_BACKOFF)
+                }
+            };
+            actix_rt::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::file::FileSink;
+    use super::*;
+    use crate::dbaccess::course::*;
+    use crate::dbaccess::tutor::{delete_tutor_db, post_new_tutor_db};
+    use crate::models::course::{CreateCourse, UpdateCourse};
+    use crate::models::tutor::NewTutor;
+    use dotenv::dotenv;
+    use std::env;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    //Takes the first batch then fails, like a broker timing out on the ack
+    struct FlakySink {
+        failed: AtomicBool,
+    }
+
+    #[async_trait]
+    impl EventSink for FlakySink {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        async fn publish(&self, _events: &[OutboxEvent]) -> Result<(), EzyTutorError> {
+            if self.failed.swap(true, Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(EzyTutorError::ServiceUnavailable("flaky: timed out".into()))
+            }
+        }
+    }
+
+    fn new_course(tutor_id: i32) -> CreateCourse {
+        CreateCourse {
+            tutor_id,
+            course_name: "Outbox course".into(),
+            course_description: None,
+            course_format: None,
+            course_structure: None,
+            course_duration: None,
+            course_price: None,
+            course_language: None,
+            course_level: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn events_are_relayed_in_order_until_every_sink_took_them() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        let path = env::temp_dir().join(format!("outbox-{}.jsonl", uuid::Uuid::new_v4()));
+        let relay = Relay::new(
+            vec![
+                Arc::new(FileSink::new(&path)),
+                Arc::new(FlakySink {
+                    failed: AtomicBool::new(false),
+                }),
+            ],
+            OutboxConfig {
+                batch_size: 1000,
+                ..OutboxConfig::default()
+            },
+        );
+
+        let tutor = post_new_tutor_db(
+            &pool,
+            NewTutor {
+                tutor_name: "Outbox tutor".into(),
+                tutor_pic_url: "http://s3.amazon.aws.com/pic6".into(),
+                tutor_profile: "Changes a lot".into(),
+            },
+        )
+        .await
+        .unwrap();
+        let course = post_new_course_db(&pool, new_course(tutor.tutor_id))
+            .await
+            .unwrap();
+        update_course_details_db(
+            &pool,
+            tutor.tutor_id,
+            course.course_id,
+            UpdateCourse {
+                course_name: None,
+                course_description: None,
+                course_format: None,
+                course_structure: None,
+                course_duration: None,
+                course_price: Some(100),
+                course_language: None,
+                course_level: None,
+            },
+        )
+        .await
+        .unwrap();
+        delete_course_db(&pool, tutor.tutor_id, course.course_id)
+            .await
+            .unwrap();
+        delete_tutor_db(&pool, tutor.tutor_id).await.unwrap();
+
+        assert!(relay.relay_once(&pool).await.is_err());
+        let partition_key = format!("tutor:{}", tutor.tutor_id);
+        let unpublished = || {
+            sqlx::query_scalar!(
+                "SELECT count(*) FROM ezy_outbox_c6
+                WHERE partition_key = $1 AND published_time IS NULL",
+                partition_key
+            )
+            .fetch_one(&pool)
+        };
+        assert_eq!(unpublished().await.unwrap(), Some(5));
+        while unpublished().await.unwrap() != Some(0) {
+            relay.relay_once(&pool).await.unwrap();
+        }
+
+        //The file took the failed batch as well, consumers drop the copies
+        let lines = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        let payloads: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|payload| payload["data"]["tutor_id"] == tutor.tutor_id)
+            .collect();
+        assert_eq!(payloads.len(), 10);
+        let mut seen = Vec::new();
+        let mut types = Vec::new();
+        for payload in &payloads {
+            if !seen.contains(&payload["id"]) {
+                seen.push(payload["id"].clone());
+                types.push(payload["type"].as_str().unwrap().to_string());
+            }
+        }
+        assert_eq!(
+            types,
+            [
+                "tutor.created",
+                "course.created",
+                "course.updated",
+                "course.deleted",
+                "tutor.deleted"
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn failed_changes_record_no_event() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        //No such tutor, the foreign key rejects the course
+        assert!(post_new_course_db(&pool, new_course(-5)).await.is_err());
+        let recorded = sqlx::query_scalar!(
+            "SELECT count(*) FROM ezy_outbox_c6 WHERE partition_key = 'tutor:-5'"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(recorded, Some(0));
+    }
+}
@@ -0,0 +1,31 @@
+use super::EventSink;
+use crate::dbaccess::webhook::insert_deliveries_db;
+use crate::errors::EzyTutorError;
+use crate::models::event::OutboxEvent;
+use async_trait::async_trait;
+use sqlx::postgres::PgPool;
+
+//Turns events into pending deliveries of the subscriptions, which
+//webhooks::WebhookSender sends with its own retries
+pub struct WebhookSink {
+    pool: PgPool,
+}
+
+impl WebhookSink {
+    pub fn new(pool: PgPool) -> Self {
+        WebhookSink { pool }
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), EzyTutorError> {
+        let deliveries = insert_deliveries_db(&self.pool, events).await?;
+        tracing::debug!(deliveries, "webhook deliveries recorded");
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use crate::errors::EzyTutorError;
+use crate::lifecycle::Lifecycle;
+use actix_web::web;
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use sqlx::postgres::PgPool;
+use std::time::Duration;
+
+//Wait before the next attempt: the initial backoff after the first failure,
+//doubled after every further one up to the maximum
+pub fn backoff(initial_secs: u64, max_secs: u64, failed_attempts: u32) -> Duration {
+    let factor = 2u64.saturating_pow(failed_attempts.saturating_sub(1));
+    let secs = initial_secs.saturating_mul(factor);
+    Duration::from_secs(secs.min(max_secs))
+}
+
+//Work queued in a table. Due rows are claimed under a lease, so instances
+//can poll side by side, then each is dispatched and its outcome recorded.
+#[async_trait]
+pub trait Queue: Send + Sync {
+    type Item: Send + Sync;
+    //For the log
+    const NAME: &'static str;
+
+    fn batch_size(&self) -> u32;
+    fn poll_interval(&self) -> Duration;
+    async fn claim(&self, pool: &PgPool, limit: i64) -> Result<Vec<Self::Item>, EzyTutorError>;
+    async fn dispatch(&self, pool: &PgPool, item: &Self::Item) -> Result<(), EzyTutorError>;
+}
+
+//Dispatches one batch of due items side by side, returns its size
+pub async fn process_due<Q: Queue>(queue: &Q, pool: &PgPool) -> Result<usize, EzyTutorError> {
+    let items = queue.claim(pool, queue.batch_size() as i64).await?;
+    let recorded = join_all(items.iter().map(|item| queue.dispatch(pool, item))).await;
+    recorded.into_iter().collect::<Result<Vec<()>, _>>()?;
+    Ok(items.len())
+}
+
+//Polls until shutdown starts. A batch in flight is finished first, the
+//lifecycle waits for this job.
+pub async fn run<Q: Queue>(queue: Q, pool: PgPool, lifecycle: web::Data<Lifecycle>) {
+    while !lifecycle.is_draining() {
+        match process_due(&queue, &pool).await {
+            //More may be due already
+            Ok(count) if count == queue.batch_size() as usize => continue,
+            Ok(_) => {}
+            Err(err) => tracing::warn!(error = %err, queue = Q::NAME, "dispatching failed"),
+        }
+        actix_rt::time::sleep(queue.poll_interval()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let waits: Vec<u64> = (1..=9)
+            .map(|attempts| backoff(30, 3600, attempts).as_secs())
+            .collect();
+        assert_eq!(waits, [30, 60, 120, 240, 480, 960, 1920, 3600, 3600]);
+        assert_eq!(backoff(30, 3600, 0).as_secs(), 30);
+        assert_eq!(backoff(30, 3600, u32::MAX).as_secs(), 3600);
+    }
+}
@@ -0,0 +1,87 @@
+use super::{Decision, RateLimitStore};
+use crate::config::Quota;
+use crate::errors::EzyTutorError;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+//Full buckets are dropped once the map grows beyond this, a missing
+//bucket is the same as a full one
+const PRUNE_ABOVE: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant,
+}
+
+//Buckets of this process only, instances behind a load balancer each allow
+//the full quota
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryRateLimitStore {
+    fn take_at(&self, key: &str, quota: Quota, now: Instant) -> Decision {
+        let capacity = quota.burst as f64;
+        let rate = quota.refill_per_sec();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_ABOVE {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            full_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        let mut tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        let allowed = tokens >= 1.0;
+        if allowed {
+            tokens -= 1.0;
+        }
+        bucket.tokens = tokens;
+        bucket.updated = now;
+        bucket.full_at = now + Duration::from_secs_f64((capacity - tokens) / rate);
+        Decision { allowed, tokens }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, EzyTutorError> {
+        Ok(self.take_at(key, quota, Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn buckets_refill_over_time() {
+        let store = MemoryRateLimitStore::default();
+        let quota = Quota {
+            burst: 2,
+            per_minute: 60,
+        };
+        let start = Instant::now();
+        assert!(store.take_at("a", quota, start).allowed);
+        assert!(store.take_at("a", quota, start).allowed);
+        let decision = store.take_at("a", quota, start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.tokens, 0.0);
+        assert!(store.take_at("b", quota, start).allowed);
+
+        //One token per second, never more than the burst
+        let later = start + Duration::from_millis(1500);
+        assert!(store.take_at("a", quota, later).allowed);
+        assert!(!store.take_at("a", quota, later).allowed);
+        let much_later = later + Duration::from_secs(3600);
+        let decision = store.take_at("a", quota, much_later);
+        assert!(decision.allowed);
+        assert_eq!(decision.tokens, 1.0);
+    }
+}
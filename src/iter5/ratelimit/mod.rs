@@ -0,0 +1,314 @@
+pub mod memory;
+pub mod postgres;
+
+use crate::config::{Quota, RateLimitBackend, RateLimitConfig};
+use crate::errors::EzyTutorError;
+use crate::identity::Requester;
+use crate::metrics::metrics;
+use crate::versioning::unversioned_path;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, ResponseError};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgPool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+//Token buckets by key. Implementations have to refill and take atomically,
+//the same key is used concurrently by all workers.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, EzyTutorError>;
+}
+
+//Outcome of taking a token, tokens is what is left in the bucket afterwards
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub tokens: f64,
+}
+
+impl Decision {
+    //Headers of the IETF RateLimit header fields draft
+    fn insert_headers(&self, quota: Quota, headers: &mut HeaderMap) {
+        let rate = quota.refill_per_sec();
+        let reset = ((quota.burst as f64 - self.tokens) / rate).ceil().max(0.0);
+        for (name, value) in [
+            ("ratelimit-limit", quota.burst.to_string()),
+            (
+                "ratelimit-remaining",
+                (self.tokens.floor() as u64).to_string(),
+            ),
+            ("ratelimit-reset", (reset as u64).to_string()),
+            (
+                "ratelimit-policy",
+                format!("{};w={}", quota.burst, quota.window_secs().ceil() as u64),
+            ),
+        ] {
+            headers.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_str(&value).unwrap(),
+            );
+        }
+    }
+
+    //Seconds until the next token is available
+    fn retry_after(&self, quota: Quota) -> u64 {
+        (((1.0 - self.tokens) / quota.refill_per_sec()).ceil() as u64).max(1)
+    }
+}
+
+//Registered as app data, requests pass unlimited while it is missing
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    config: RateLimitConfig,
+    api_keys: HashSet<String>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, config: RateLimitConfig) -> Self {
+        let api_keys = config
+            .api_keys
+            .iter()
+            .map(|digest| digest.to_ascii_lowercase())
+            .collect();
+        RateLimiter {
+            store,
+            config,
+            api_keys,
+        }
+    }
+
+    //Longest matching scope, "/courses" covers "/courses" and "/courses/1"
+    fn quota_for(&self, path: &str, method: &Method) -> Option<(&str, &'static str, Quota)> {
+        //All versions share the quota of a scope
+        let path = unversioned_path(path);
+        let (scope, quotas) = self
+            .config
+            .scopes
+            .iter()
+            .filter(|(scope, _)| {
+                scope.as_str() == "/"
+                    || path
+                        .strip_prefix(scope.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(scope, _)| scope.len())?;
+        if [Method::GET, Method::HEAD, Method::OPTIONS].contains(method) {
+            Some((scope, "read", quotas.read))
+        } else {
+            Some((scope, "write", quotas.write))
+        }
+    }
+
+    //Authenticated callers are limited per identity, so clients sharing an
+    //address don't take from each other's quota. Only configured API keys
+    //count, made-up keys would each get a fresh bucket. Keys are hashed to
+    //keep them out of the database.
+    fn client_key(&self, req: &ServiceRequest) -> String {
+        match Requester::from_request_headers(req.request()) {
+            Ok(Requester::Tutor(tutor_id)) => return format!("tutor:{}", tutor_id),
+            Ok(Requester::Student(student_id)) => return format!("student:{}", student_id),
+            _ => {}
+        }
+        if let Some(api_key) = req.headers().get(API_KEY_HEADER) {
+            let digest = hex::encode(Sha256::digest(api_key.as_bytes()));
+            if self.api_keys.contains(&digest) {
+                return format!("key:{}", &digest[..32]);
+            }
+        }
+        let address = if self.config.trust_forwarded_for {
+            req.connection_info()
+                .realip_remote_addr()
+                .map(|address| address.to_string())
+        } else {
+            req.peer_addr().map(|address| address.ip().to_string())
+        };
+        format!("ip:{}", address.unwrap_or_else(|| "unknown".into()))
+    }
+}
+
+//Build the limiter with the store selected in the configuration
+pub fn rate_limiter_from_config(config: &RateLimitConfig, pool: PgPool) -> RateLimiter {
+    let store: Arc<dyn RateLimitStore> = match config.backend {
+        RateLimitBackend::Memory => Arc::new(memory::MemoryRateLimitStore::default()),
+        RateLimitBackend::Postgres => {
+            //A bucket idle for the longest window is full again and can be dropped
+            let longest_window = config
+                .scopes
+                .values()
+                .flat_map(|quotas| [quotas.read, quotas.write])
+                .map(|quota| quota.window_secs())
+                .fold(0.0, f64::max);
+            Arc::new(postgres::PostgresRateLimitStore::new(
+                pool,
+                Duration::from_secs_f64(longest_window.ceil().max(60.0)),
+            ))
+        }
+    };
+    RateLimiter::new(store, config.clone())
+}
+
+//Rejected requests are answered here without reaching the handler. When the
+//store fails, requests are let through, limiting is not worth an outage.
+pub async fn rate_limit_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let Some((scope, access, quota)) = limiter.quota_for(req.path(), req.method()) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let bucket = format!("{}:{}:{}", scope, access, limiter.client_key(&req));
+    let decision = match limiter.store.take(&bucket, quota).await {
+        Ok(decision) => decision,
+        Err(err) => {
+            tracing::warn!(error = %err, "rate limit store failed, request not limited");
+            return Ok(next.call(req).await?.map_into_left_body());
+        }
+    };
+    if !decision.allowed {
+        metrics()
+            .rate_limited
+            .with_label_values(&[scope, access])
+            .inc();
+        let err = EzyTutorError::TooManyRequests(
+            format!("{} limit for {} exceeded, please slow down", access, scope),
+            decision.retry_after(quota),
+        );
+        let mut response = err.error_response();
+        decision.insert_headers(quota, response.headers_mut());
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    let mut res = next.call(req).await?;
+    decision.insert_headers(quota, res.headers_mut());
+    Ok(res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScopeQuotas;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App, HttpResponse};
+    use std::collections::BTreeMap;
+
+    fn limiter() -> RateLimiter {
+        let quotas = ScopeQuotas {
+            read: Quota {
+                burst: 2,
+                per_minute: 1,
+            },
+            write: Quota {
+                burst: 1,
+                per_minute: 1,
+            },
+        };
+        let config = RateLimitConfig {
+            api_keys: vec![hex::encode(Sha256::digest(b"known-key"))],
+            scopes: BTreeMap::from([("/courses".to_string(), quotas)]),
+            ..RateLimitConfig::default()
+        };
+        RateLimiter::new(Arc::new(memory::MemoryRateLimitStore::default()), config)
+    }
+
+    #[actix_rt::test]
+    async fn scopes_match_whole_path_segments() {
+        let limiter = limiter();
+        let (scope, access, _) = limiter.quota_for("/courses/1", &Method::GET).unwrap();
+        assert_eq!((scope, access), ("/courses", "read"));
+        let (_, access, _) = limiter.quota_for("/courses", &Method::POST).unwrap();
+        assert_eq!(access, "write");
+        let (scope, _, _) = limiter.quota_for("/v2/courses/1", &Method::GET).unwrap();
+        assert_eq!(scope, "/courses");
+        assert!(limiter.quota_for("/coursesx", &Method::GET).is_none());
+        assert!(limiter.quota_for("/tutors/", &Method::GET).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn exhausted_quota_gets_429_with_headers() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(rate_limit_middleware))
+                .app_data(web::Data::new(limiter()))
+                .route("/courses/1", web::get().to(HttpResponse::Ok))
+                .route("/courses/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let get = |tutor_id: &str| {
+            test::TestRequest::get()
+                .uri("/courses/1")
+                .insert_header(("X-Tutor-Id", tutor_id))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, get("1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("ratelimit-limit").unwrap(), "2");
+        assert_eq!(resp.headers().get("ratelimit-remaining").unwrap(), "1");
+        assert_eq!(resp.headers().get("ratelimit-policy").unwrap(), "2;w=120");
+        test::call_service(&app, get("1")).await;
+
+        let resp = test::call_service(&app, get("1")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get("ratelimit-remaining").unwrap(), "0");
+        let retry_after: u64 = resp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        //Other tutors and the write quota are counted separately
+        let resp = test::call_service(&app, get("2")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::post()
+            .uri("/courses/")
+            .insert_header(("X-Tutor-Id", "1"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn only_known_api_keys_get_their_own_bucket() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(rate_limit_middleware))
+                .app_data(web::Data::new(limiter()))
+                .route("/courses/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let post = |api_key: &str| {
+            test::TestRequest::post()
+                .uri("/courses/")
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header((API_KEY_HEADER, api_key))
+                .to_request()
+        };
+
+        //Made-up keys share the quota of their address
+        let resp = test::call_service(&app, post("made-up-1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, post("made-up-2")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let resp = test::call_service(&app, post("known-key")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, post("known-key")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
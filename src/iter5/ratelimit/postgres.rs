@@ -0,0 +1,71 @@
+use super::{Decision, RateLimitStore};
+use crate::config::Quota;
+use crate::dbaccess::ratelimit::{prune_buckets_db, take_token_db};
+use crate::errors::EzyTutorError;
+use async_trait::async_trait;
+use sqlx::postgres::PgPool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+//Idle buckets are deleted on every n-th request
+const PRUNE_EVERY: u64 = 1000;
+
+//Buckets in the ezy_rate_limit_c6 table, so all instances share one quota
+pub struct PostgresRateLimitStore {
+    pool: PgPool,
+    prune_after: Duration,
+    requests: AtomicU64,
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(pool: PgPool, prune_after: Duration) -> Self {
+        PostgresRateLimitStore {
+            pool,
+            prune_after,
+            requests: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresRateLimitStore {
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, EzyTutorError> {
+        if self.requests.fetch_add(1, Ordering::Relaxed) % PRUNE_EVERY == PRUNE_EVERY - 1 {
+            let pruned = prune_buckets_db(&self.pool, self.prune_after.as_secs_f64()).await?;
+            tracing::debug!(pruned, "idle rate limit buckets removed");
+        }
+        let bucket =
+            take_token_db(&self.pool, key, quota.burst as f64, quota.refill_per_sec()).await?;
+        Ok(Decision {
+            allowed: bucket.allowed,
+            tokens: bucket.tokens,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[actix_rt::test]
+    async fn instances_share_buckets() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file!");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        //Two stores on the same table behave like two instances
+        let first = PostgresRateLimitStore::new(pool.clone(), Duration::from_secs(60));
+        let second = PostgresRateLimitStore::new(pool, Duration::from_secs(60));
+        let key = format!("test:{}", uuid::Uuid::new_v4());
+        let quota = Quota {
+            burst: 2,
+            per_minute: 1,
+        };
+        assert!(first.take(&key, quota).await.unwrap().allowed);
+        assert!(second.take(&key, quota).await.unwrap().allowed);
+        let decision = first.take(&key, quota).await.unwrap();
+        assert!(!decision.allowed);
+        assert!(decision.tokens < 1.0);
+    }
+}
@@ -1,21 +1,66 @@
-use crate::handlers::{course::*, general::*, tutor::*};
+use crate::handlers::{
+    attachment::*, chat::*, course::*, docs::*, export::*, general::*, graphql::*, media::*,
+    metrics::*, notification::*, stream::*, tutor::*, v2, webhook::*,
+};
+use crate::versioning::{legacy_alias_middleware, LegacyAliases};
+use actix_web::middleware::from_fn;
 use actix_web::web;
+use std::sync::Arc;
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+//Bulk imports of up to MAX_IMPORT_ROWS courses need more than the default 256kB
+const IMPORT_PAYLOAD_LIMIT: usize = 4 * 1024 * 1024;
 
 pub fn general_routes(cfg: &mut web::ServiceConfig) {
-    cfg.route("/health", web::get().to(health_check_handler));
+    cfg.route("/health", web::get().to(health_check_handler))
+        .route("/health/live", web::get().to(liveness_handler))
+        .route("/health/ready", web::get().to(readiness_handler));
+}
+
+pub fn metrics_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/metrics", web::get().to(metrics_handler));
+}
+
+pub fn docs_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/openapi.json", web::get().to(openapi_json))
+        .route("/docs", web::get().to(api_docs))
+        .service(SwaggerUi::new("/docs/{_:.*}").config(Config::new(["/openapi.json"])));
 }
 
+//Version 1, the JSON shapes of the first release. Frozen, changed
+//representations go into a new version.
 pub fn course_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/courses")
             .route("/", web::post().to(post_new_course))
+            .service(
+                web::resource("/{tutor_id}/import")
+                    .app_data(web::PayloadConfig::new(IMPORT_PAYLOAD_LIMIT))
+                    .route(web::post().to(import_courses)),
+            )
             .route("/{tutor_id}", web::get().to(get_courses_for_tutor))
             .route("/{tutor_id}/{course_id}", web::get().to(get_course_details))
             .route(
                 "/{tutor_id}/{course_id}",
                 web::put().to(update_course_details),
             )
-            .route("/{tutor_id}/{course_id}", web::delete().to(delete_course)),
+            .route("/{tutor_id}/{course_id}", web::delete().to(delete_course))
+            .route(
+                "/{tutor_id}/{course_id}/attachments",
+                web::post().to(post_new_attachment),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/attachments",
+                web::get().to(get_attachments_for_course),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/attachments/{attachment_id}",
+                web::get().to(download_attachment),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/attachments/{attachment_id}",
+                web::delete().to(delete_attachment),
+            ),
     );
 }
 
@@ -26,6 +71,162 @@ pub fn tutor_routes(cfg: &mut web::ServiceConfig) {
             .route("/", web::get().to(get_all_tutors))
             .route("/{tutor_id}", web::get().to(get_tutor_details))
             .route("/{tutor_id}", web::put().to(update_tutor_details))
-            .route("/{tutor_id}", web::delete().to(delete_tutor)),
+            .route("/{tutor_id}", web::delete().to(delete_tutor))
+            .route("/{tutor_id}/picture", web::post().to(upload_tutor_picture)),
+    );
+}
+
+//Version 2 only has the resources whose representation changed
+pub fn v2_course_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/courses")
+            .route("/", web::post().to(v2::post_new_course))
+            .route("/{tutor_id}", web::get().to(v2::get_courses_for_tutor))
+            .route(
+                "/{tutor_id}/{course_id}",
+                web::get().to(v2::get_course_details),
+            )
+            .route(
+                "/{tutor_id}/{course_id}",
+                web::put().to(v2::update_course_details),
+            )
+            .route(
+                "/{tutor_id}/{course_id}",
+                web::delete().to(v2::delete_course),
+            ),
+    );
+}
+
+pub fn v2_tutor_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/tutors")
+            .route("/", web::post().to(v2::post_new_tutor))
+            .route("/", web::get().to(v2::get_all_tutors))
+            .route("/{tutor_id}", web::get().to(v2::get_tutor_details))
+            .route("/{tutor_id}", web::put().to(v2::update_tutor_details))
+            .route("/{tutor_id}", web::delete().to(v2::delete_tutor)),
+    );
+}
+
+pub fn api_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/v1")
+            .configure(course_routes)
+            .configure(tutor_routes),
+    )
+    .service(
+        web::scope("/v2")
+            .configure(v2_course_routes)
+            .configure(v2_tutor_routes),
+    );
+}
+
+//The unprefixed paths from before versioning, answered like /v1. The empty
+//scope matches every path, so this has to be registered after all other routes.
+pub fn legacy_routes(cfg: &mut web::ServiceConfig, aliases: Arc<LegacyAliases>) {
+    cfg.service(
+        web::scope("")
+            .wrap(from_fn(move |req, next| {
+                legacy_alias_middleware(aliases.clone(), req, next)
+            }))
+            .configure(course_routes)
+            .configure(tutor_routes),
+    );
+}
+
+pub fn graphql_routes(cfg: &mut web::ServiceConfig, max_body_bytes: usize) {
+    cfg.service(
+        web::resource("/graphql")
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(max_body_bytes)
+                    .error_handler(json_error),
+            )
+            .route(web::post().to(graphql_post))
+            .route(web::get().to(graphql_get)),
+    );
+}
+
+//Query console and schema, for development
+pub fn graphql_playground_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/graphql/playground", web::get().to(graphql_playground))
+        .route(
+            "/graphql/playground.js",
+            web::get().to(graphql_playground_script),
+        )
+        .route(
+            "/graphql/playground.css",
+            web::get().to(graphql_playground_style),
+        )
+        .route("/graphql/schema.graphql", web::get().to(graphql_schema));
+}
+
+pub fn media_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/media/{key:.*}", web::get().to(get_media));
+}
+
+//Live changes, unversioned like the other non-resource endpoints
+pub fn stream_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/events")
+            .route("/courses", web::get().to(get_course_events))
+            .route("/tutors/{tutor_id}", web::get().to(get_tutor_events)),
+    );
+}
+
+//Conversations and the socket carrying them, unversioned like the streams
+pub fn chat_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/chat")
+            .route("/conversations", web::post().to(post_new_conversation))
+            .route("/conversations", web::get().to(get_conversations))
+            .route(
+                "/conversations/{conversation_id}/messages",
+                web::get().to(get_chat_messages),
+            )
+            .route("/ws", web::get().to(get_chat_socket)),
+    );
+}
+
+pub fn notification_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/notifications")
+            .route("/preferences", web::get().to(get_notification_preferences))
+            .route("/preferences", web::put().to(put_notification_preferences))
+            .route(
+                "/preferences",
+                web::delete().to(delete_notification_preferences),
+            ),
+    );
+}
+
+pub fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .route("/export/courses", web::get().to(export_courses))
+            .route("/export/tutors", web::get().to(export_tutors)),
+    );
+}
+
+pub fn webhook_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/webhooks")
+            .route("", web::post().to(post_new_webhook))
+            .route("", web::get().to(get_webhooks))
+            .route("/{webhook_id}", web::get().to(get_webhook))
+            .route("/{webhook_id}", web::put().to(update_webhook))
+            .route("/{webhook_id}", web::delete().to(delete_webhook))
+            .route(
+                "/{webhook_id}/deliveries",
+                web::get().to(get_webhook_deliveries),
+            )
+            .route(
+                "/{webhook_id}/deliveries/{delivery_id}",
+                web::get().to(get_webhook_delivery),
+            )
+            .route(
+                "/{webhook_id}/deliveries/{delivery_id}/redeliver",
+                web::post().to(redeliver_webhook_delivery),
+            ),
     );
 }
@@ -0,0 +1,141 @@
+use crate::config::SecurityHeadersConfig;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+//Headers added to every response. A header the handler already set wins,
+//the Content-Security-Policy can also be replaced per path in the config.
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    content_security_policy: Option<HeaderValue>,
+    csp_overrides: HashMap<String, HeaderValue>,
+}
+
+impl SecurityHeaders {
+    //Values were checked by Config::validate, empty ones leave the header out
+    pub fn new(config: &SecurityHeadersConfig) -> Self {
+        let value = |value: &str| {
+            Some(value)
+                .filter(|value| !value.is_empty())
+                .and_then(|value| HeaderValue::from_str(value).ok())
+        };
+        if !config.enabled {
+            return SecurityHeaders {
+                headers: Vec::new(),
+                content_security_policy: None,
+                csp_overrides: HashMap::new(),
+            };
+        }
+        let mut headers = vec![(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        )];
+        if config.hsts_max_age_secs > 0 {
+            let mut hsts = format!("max-age={}", config.hsts_max_age_secs);
+            if config.hsts_include_subdomains {
+                hsts.push_str("; includeSubDomains");
+            }
+            headers.push((
+                header::STRICT_TRANSPORT_SECURITY,
+                HeaderValue::from_str(&hsts).unwrap(),
+            ));
+        }
+        if let Some(policy) = value(&config.referrer_policy) {
+            headers.push((header::REFERRER_POLICY, policy));
+        }
+        SecurityHeaders {
+            headers,
+            content_security_policy: value(&config.content_security_policy),
+            csp_overrides: config
+                .csp_overrides
+                .iter()
+                .filter_map(|(path, policy)| Some((path.clone(), value(policy)?)))
+                .collect(),
+        }
+    }
+
+    fn insert_headers(&self, path: &str, headers: &mut HeaderMap) {
+        let policy = self
+            .csp_overrides
+            .get(path)
+            .or(self.content_security_policy.as_ref());
+        let csp = policy.map(|policy| (header::CONTENT_SECURITY_POLICY, policy.clone()));
+        for (name, value) in self.headers.iter().cloned().chain(csp) {
+            if !headers.contains_key(&name) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+pub async fn security_headers_middleware(
+    security_headers: Arc<SecurityHeaders>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let path = req.path().to_string();
+    match next.call(req).await {
+        Ok(mut res) => {
+            security_headers.insert_headers(&path, res.headers_mut());
+            Ok(res)
+        }
+        Err(err) => {
+            let mut response = err.error_response();
+            security_headers.insert_headers(&path, response.headers_mut());
+            Err(InternalError::from_response(err.to_string(), response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DOCS_CONTENT_SECURITY_POLICY;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn html_pages_get_their_own_policy() {
+        let security_headers = Arc::new(SecurityHeaders::new(&SecurityHeadersConfig::default()));
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(move |req, next| {
+                    security_headers_middleware(security_headers.clone(), req, next)
+                }))
+                .route("/tutors/", web::get().to(HttpResponse::Ok))
+                .route("/docs/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/tutors/").to_request();
+        let resp = test::call_service(&app, req).await;
+        let headers = resp.headers();
+        assert_eq!(
+            headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert_eq!(
+            headers.get(header::STRICT_TRANSPORT_SECURITY).unwrap(),
+            "max-age=31536000"
+        );
+        assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer");
+        assert!(headers
+            .get(header::CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("default-src 'none'"));
+
+        let req = test::TestRequest::get().uri("/docs/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get(header::CONTENT_SECURITY_POLICY).unwrap(),
+            DOCS_CONTENT_SECURITY_POLICY
+        );
+    }
+}
@@ -1,8 +1,60 @@
+use crate::cache::{CacheKey, ReadCache};
+use crate::consistency::Consistency;
+use crate::dbaccess::replica::{route_read, Replica};
+use crate::errors::EzyTutorError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use sqlx::postgres::PgPool;
-use std::sync::Mutex;
+use std::future::Future;
 
 pub struct AppState {
     pub health_check_response: String,
-    pub visit_count: Mutex<u32>,
+    //Primary, all writes and the reads that have to see them
     pub db: PgPool,
+    pub replica: Option<Replica>,
+    pub cache: ReadCache,
+}
+
+impl AppState {
+    //Plain reads go to the replica if there is one. Right after a write, by
+    //the same client or any that reached the cache (which is then filled),
+    //they go to the primary, the replica may not have the write yet.
+    pub async fn read<T, F, Fut>(
+        &self,
+        consistency: Consistency,
+        query: F,
+    ) -> Result<T, EzyTutorError>
+    where
+        F: Fn(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, EzyTutorError>>,
+    {
+        let replica = self.replica.as_ref().filter(|replica| {
+            consistency == Consistency::Eventual
+                && !self.cache.invalidated_within(replica.max_lag())
+        });
+        route_read(&self.db, replica, query).await
+    }
+
+    //A client that just wrote skips the cache, the notification of its
+    //write may not have reached this instance yet
+    pub async fn cached_read<T, F, Fut>(
+        &self,
+        key: CacheKey,
+        consistency: Consistency,
+        query: F,
+    ) -> Result<T, EzyTutorError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Fn(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, EzyTutorError>>,
+    {
+        match consistency {
+            Consistency::Eventual => {
+                self.cache
+                    .get_or_load(key, || self.read(consistency, query))
+                    .await
+            }
+            Consistency::ReadYourWrites => self.read(consistency, query).await,
+        }
+    }
 }
@@ -0,0 +1,306 @@
+use crate::config::StreamConfig;
+use crate::dbaccess::outbox::get_outbox_event_db;
+use crate::lifecycle::Lifecycle;
+use crate::models::event::OutboxEvent;
+use actix_web::web::{self, Bytes};
+use futures_util::Stream;
+use sqlx::postgres::{PgListener, PgPool};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+//Notified by the outbox trigger with the event_seq of every recorded event
+pub const OUTBOX_CHANNEL: &str = "ezy_outbox";
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+//How soon open streams end once shutdown starts
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+//Reconnection delay suggested to EventSource clients
+const RETRY_MS: u64 = 3000;
+
+#[derive(Debug, Clone)]
+pub enum Notice {
+    Event(Arc<OutboxEvent>),
+    //Events may have been missed, clients have to reload what they show
+    Reset,
+}
+
+//Latest events of this instance, in commit order, and their subscribers
+pub struct EventHub {
+    sender: broadcast::Sender<Notice>,
+    replay: Mutex<VecDeque<Arc<OutboxEvent>>>,
+    capacity: usize,
+    keepalive: Duration,
+}
+
+pub struct Subscription {
+    //Events after the client's Last-Event-ID, sent before the live ones
+    pub missed: Vec<Notice>,
+    pub receiver: broadcast::Receiver<Notice>,
+}
+
+impl EventHub {
+    //A subscriber lagging by more than the replay buffer couldn't resume either
+    pub fn from_config(config: &StreamConfig) -> Self {
+        let (sender, _) = broadcast::channel(config.replay_buffer);
+        EventHub {
+            sender,
+            replay: Mutex::new(VecDeque::with_capacity(config.replay_buffer)),
+            capacity: config.replay_buffer,
+            keepalive: Duration::from_secs(config.keepalive_secs),
+        }
+    }
+
+    pub fn publish(&self, event: OutboxEvent) {
+        let event = Arc::new(event);
+        let mut replay = self.replay.lock().unwrap();
+        if replay.len() == self.capacity {
+            replay.pop_front();
+        }
+        replay.push_back(event.clone());
+        //Nobody listening is fine
+        let _ = self.sender.send(Notice::Event(event));
+    }
+
+    //Notifications were lost, nobody can resume across the gap
+    pub fn reset(&self) {
+        let mut replay = self.replay.lock().unwrap();
+        replay.clear();
+        let _ = self.sender.send(Notice::Reset);
+    }
+
+    //Subscribes while holding the buffer, so every event is either replayed
+    //or received. An id that is no longer buffered gets a reset.
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> Subscription {
+        let replay = self.replay.lock().unwrap();
+        let missed = match last_event_id {
+            None => Vec::new(),
+            Some(last_event_id) => {
+                match replay
+                    .iter()
+                    .position(|event| event.event_id == last_event_id)
+                {
+                    Some(position) => replay
+                        .iter()
+                        .skip(position + 1)
+                        .map(|event| Notice::Event(event.clone()))
+                        .collect(),
+                    None => vec![Notice::Reset],
+                }
+            }
+        };
+        Subscription {
+            missed,
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+//Feeds the hub until the pool is closed at shutdown. Notifications arrive in
+//commit order, the events are read from the outbox one by one to keep it.
+pub async fn listen(hub: web::Data<EventHub>, pool: PgPool) {
+    let mut listener = loop {
+        match connect_listener(&pool).await {
+            Ok(listener) => break listener,
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(error = %err, "outbox notifications not available");
+                actix_rt::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    };
+    while !pool.is_closed() {
+        match listener.try_recv().await {
+            Ok(Some(notification)) => {
+                let Ok(event_seq) = notification.payload().parse::<i64>() else {
+                    tracing::warn!(payload = notification.payload(), "unexpected notification");
+                    continue;
+                };
+                match get_outbox_event_db(&pool, event_seq).await {
+                    Ok(Some(event)) => hub.publish(event),
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::warn!(error = %err, event_seq, "streamed event not read");
+                        hub.reset();
+                    }
+                }
+            }
+            //Reconnected, whatever was committed in between is lost
+            Ok(None) => {
+                tracing::warn!("outbox notifications reconnected");
+                hub.reset();
+            }
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(error = %err, "outbox notifications failed");
+                hub.reset();
+                actix_rt::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn connect_listener(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(OUTBOX_CHANNEL).await?;
+    Ok(listener)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamFilter {
+    //Course events of every tutor
+    Courses,
+    //The tutor's own events and those of its courses
+    Tutor(i32),
+}
+
+impl StreamFilter {
+    fn matches(&self, event: &OutboxEvent) -> bool {
+        match self {
+            StreamFilter::Courses => event.event_type.starts_with("course."),
+            StreamFilter::Tutor(tutor_id) => event.partition_key == format!("tutor:{}", tutor_id),
+        }
+    }
+}
+
+struct EventStream {
+    missed: VecDeque<Notice>,
+    receiver: broadcast::Receiver<Notice>,
+    filter: StreamFilter,
+    lifecycle: web::Data<Lifecycle>,
+    keepalive: Duration,
+    last_sent: Instant,
+    ticker: actix_rt::time::Interval,
+}
+
+impl EventStream {
+    //None ends the response, which clients reconnect after
+    async fn next_chunk(&mut self) -> Option<Bytes> {
+        loop {
+            if self.lifecycle.is_draining() {
+                return None;
+            }
+            let notice = match self.missed.pop_front() {
+                Some(notice) => notice,
+                None => tokio::select! {
+                    received = self.receiver.recv() => match received {
+                        Ok(notice) => notice,
+                        //Too slow, the skipped events are gone
+                        Err(RecvError::Lagged(_)) => Notice::Reset,
+                        Err(RecvError::Closed) => return None,
+                    },
+                    _ = self.ticker.tick() => {
+                        if self.last_sent.elapsed() < self.keepalive {
+                            continue;
+                        }
+                        self.last_sent = Instant::now();
+                        return Some(Bytes::from_static(b": keepalive\n\n"));
+                    }
+                },
+            };
+            if let Some(chunk) = self.encode(&notice) {
+                self.last_sent = Instant::now();
+                return Some(chunk);
+            }
+        }
+    }
+
+    //Payloads are single line JSON, so each fits one data field
+    fn encode(&self, notice: &Notice) -> Option<Bytes> {
+        match notice {
+            Notice::Event(event) if self.filter.matches(event) => Some(Bytes::from(format!(
+                "id: {}\nevent: {}\ndata: {}\n\n",
+                event.event_id, event.event_type, event.payload
+            ))),
+            Notice::Event(_) => None,
+            Notice::Reset => Some(Bytes::from_static(b"event: reset\ndata: {}\n\n")),
+        }
+    }
+}
+
+//The text/event-stream body of one client, ending when shutdown starts
+pub fn event_stream(
+    hub: &EventHub,
+    filter: StreamFilter,
+    last_event_id: Option<&str>,
+    lifecycle: web::Data<Lifecycle>,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let subscription = hub.subscribe(last_event_id);
+    let stream = EventStream {
+        missed: subscription.missed.into(),
+        receiver: subscription.receiver,
+        filter,
+        lifecycle,
+        keepalive: hub.keepalive,
+        last_sent: Instant::now(),
+        ticker: actix_rt::time::interval(DRAIN_CHECK_INTERVAL),
+    };
+    let retry = Bytes::from(format!("retry: {}\n\n", RETRY_MS));
+    let events = futures_util::stream::unfold(stream, |mut stream| async move {
+        let chunk = stream.next_chunk().await?;
+        Some((Ok(chunk), stream))
+    });
+    futures_util::StreamExt::chain(futures_util::stream::once(async { Ok(retry) }), events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_seq: i64, event_type: &str, tutor_id: i32) -> OutboxEvent {
+        OutboxEvent {
+            event_seq,
+            event_id: format!("event-{}", event_seq),
+            event_type: event_type.to_string(),
+            partition_key: format!("tutor:{}", tutor_id),
+            payload: "{}".to_string(),
+        }
+    }
+
+    fn missed_ids(subscription: &Subscription) -> Vec<String> {
+        subscription
+            .missed
+            .iter()
+            .map(|notice| match notice {
+                Notice::Event(event) => event.event_id.clone(),
+                Notice::Reset => "reset".to_string(),
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn subscribers_resume_after_their_last_event_while_it_is_buffered() {
+        let hub = EventHub::from_config(&StreamConfig {
+            replay_buffer: 2,
+            ..StreamConfig::default()
+        });
+        hub.publish(event(1, "course.created", 1));
+        hub.publish(event(2, "course.updated", 1));
+        hub.publish(event(3, "course.deleted", 1));
+
+        assert!(missed_ids(&hub.subscribe(None)).is_empty());
+        assert_eq!(missed_ids(&hub.subscribe(Some("event-2"))), ["event-3"]);
+        assert!(missed_ids(&hub.subscribe(Some("event-3"))).is_empty());
+        //Dropped from the buffer, or from before this instance started
+        assert_eq!(missed_ids(&hub.subscribe(Some("event-1"))), ["reset"]);
+
+        let mut subscription = hub.subscribe(Some("event-3"));
+        hub.reset();
+        assert!(matches!(
+            subscription.receiver.recv().await,
+            Ok(Notice::Reset)
+        ));
+        assert_eq!(missed_ids(&hub.subscribe(Some("event-3"))), ["reset"]);
+    }
+
+    #[actix_rt::test]
+    async fn filters_pick_course_events_or_those_of_a_tutor() {
+        let course_event = event(1, "course.created", 7);
+        let tutor_event = event(2, "tutor.updated", 7);
+        assert!(StreamFilter::Courses.matches(&course_event));
+        assert!(!StreamFilter::Courses.matches(&tutor_event));
+        assert!(StreamFilter::Tutor(7).matches(&course_event));
+        assert!(StreamFilter::Tutor(7).matches(&tutor_event));
+        assert!(!StreamFilter::Tutor(70).matches(&tutor_event));
+    }
+}
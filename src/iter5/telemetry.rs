@@ -0,0 +1,171 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+//Longest id accepted from a client, anything else gets a fresh id
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    #[serde(alias = "pretty")]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<LogFormat, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" | "pretty" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format {:?}, use text or json", other)),
+        }
+    }
+}
+
+//Levels are taken from RUST_LOG, e.g. RUST_LOG=info,sqlx=warn,
+//falling back to the configured filter and then to info
+pub fn init_tracing(format: LogFormat, default_filter: Option<&str>) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(default_filter.unwrap_or("info")));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
+
+//Id of the request currently being handled, None outside of a request
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+fn accepted_request_id(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?;
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value.bytes().all(|byte| byte.is_ascii_graphic());
+    valid.then(|| value.to_string())
+}
+
+//Takes the id from X-Request-Id or generates one, runs the request inside a span
+//carrying it and echoes it in the response
+pub async fn request_id_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(accepted_request_id)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+
+    let started = Instant::now();
+    let result = REQUEST_ID
+        .scope(request_id.clone(), next.call(req).instrument(span.clone()))
+        .await;
+    let header_value = HeaderValue::from_str(&request_id).ok();
+    let mut res = match result {
+        Ok(res) => res,
+        //Errors from inner middleware become responses further out,
+        //tag them with the request id as well
+        Err(err) => {
+            let mut response = err.error_response();
+            if let Some(value) = header_value {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            span.in_scope(|| {
+                tracing::warn!(status = response.status().as_u16(), error = %err, "request aborted")
+            });
+            return Err(InternalError::from_response(err.to_string(), response).into());
+        }
+    };
+    let status = res.status();
+    span.in_scope(|| {
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        if status.is_server_error() {
+            tracing::error!(status = status.as_u16(), elapsed_ms, "request failed");
+        } else {
+            tracing::info!(status = status.as_u16(), elapsed_ms, "request completed");
+        }
+    });
+    if let Some(value) = header_value {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::EzyTutorError;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn failing_handler() -> Result<HttpResponse, EzyTutorError> {
+        Err(EzyTutorError::NotFound("Course id not found".into()))
+    }
+
+    #[actix_rt::test]
+    async fn request_id_is_propagated_to_headers_and_error_body() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(request_id_middleware))
+                .route("/fail", web::get().to(failing_handler)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/fail")
+            .insert_header(("X-Request-Id", "abc-123"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        assert_eq!(resp.headers().get("x-request-id").unwrap(), "abc-123");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["request_id"], "abc-123");
+    }
+
+    #[actix_rt::test]
+    async fn request_id_is_generated_when_missing_or_invalid() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(request_id_middleware))
+                .route("/fail", web::get().to(failing_handler)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/fail")
+            .insert_header(("X-Request-Id", "has spaces"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let header = resp.headers().get("x-request-id").unwrap().to_owned();
+        let generated = header.to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["request_id"], generated);
+    }
+}
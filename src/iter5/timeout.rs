@@ -0,0 +1,70 @@
+use crate::errors::EzyTutorError;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::middleware::Next;
+use actix_web::rt::time::timeout;
+use actix_web::{Error, ResponseError};
+use std::time::Duration;
+
+//Answers with 503 when the handler hasn't produced a response in time.
+//Dropping the handler future also returns its database connection to the pool.
+//The request was moved into the handler, so the response travels as an error;
+//it is built here to carry the request id in its body.
+pub async fn request_timeout_middleware(
+    limit: Duration,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    match timeout(limit, next.call(req)).await {
+        Ok(res) => res,
+        Err(_) => {
+            let err = EzyTutorError::ServiceUnavailable(format!(
+                "no response within {}s",
+                limit.as_secs_f64()
+            ));
+            let response = err.error_response();
+            Err(InternalError::from_response(err, response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn slow_handler() -> HttpResponse {
+        actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_rt::test]
+    async fn slow_requests_get_503_with_retry_after() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(|req, next| {
+                    request_timeout_middleware(Duration::from_millis(100), req, next)
+                }))
+                .wrap(from_fn(crate::telemetry::request_id_middleware))
+                .route("/slow", web::get().to(slow_handler))
+                .route("/fast", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/slow")
+            .insert_header(("X-Request-Id", "slow-1"))
+            .to_request();
+        let resp = test::try_call_service(&app, req).await.err().unwrap();
+        let resp = resp.error_response();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.headers().contains_key(header::RETRY_AFTER));
+        assert_eq!(resp.headers().get("x-request-id").unwrap(), "slow-1");
+
+        let req = test::TestRequest::get().uri("/fast").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
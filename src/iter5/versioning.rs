@@ -0,0 +1,126 @@
+use crate::config::ApiConfig;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use chrono::{NaiveDate, NaiveTime};
+use std::sync::Arc;
+
+//Version the unprefixed paths are aliases of
+pub const LEGACY_SUCCESSOR: &str = "/v1";
+
+//Path without its version segment, "/v2/courses/1" becomes "/courses/1"
+pub fn unversioned_path(path: &str) -> &str {
+    let Some(rest) = path.strip_prefix("/v") else {
+        return path;
+    };
+    let end = rest.find('/').unwrap_or(rest.len());
+    if end == 0 || !rest[..end].bytes().all(|b| b.is_ascii_digit()) {
+        return path;
+    }
+    match &rest[end..] {
+        "" => "/",
+        rest => rest,
+    }
+}
+
+//Deprecation (RFC 9745) and Sunset (RFC 8594) headers of the unprefixed paths
+pub struct LegacyAliases {
+    deprecation: HeaderValue,
+    sunset: HeaderValue,
+}
+
+impl LegacyAliases {
+    pub fn new(config: &ApiConfig) -> Self {
+        let midnight = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+        let deprecated = midnight(config.legacy_deprecated_on);
+        let sunset = midnight(config.legacy_sunset_on);
+        LegacyAliases {
+            deprecation: HeaderValue::from_str(&format!("@{}", deprecated.timestamp())).unwrap(),
+            sunset: HeaderValue::from_str(&sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+                .unwrap(),
+        }
+    }
+}
+
+//The aliases answer like /v1, the Link header names the path to move to
+pub async fn legacy_alias_middleware(
+    aliases: Arc<LegacyAliases>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let successor = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or_else(|| req.path());
+    let link = HeaderValue::from_str(&format!(
+        "<{}{}>; rel=\"successor-version\"",
+        LEGACY_SUCCESSOR, successor
+    ));
+    let mut res = next.call(req).await?;
+    //Paths the aliases don't know are missing, not deprecated
+    if res.request().match_pattern().is_some() {
+        let headers = res.headers_mut();
+        headers.insert(
+            HeaderName::from_static("deprecation"),
+            aliases.deprecation.clone(),
+        );
+        headers.insert(HeaderName::from_static("sunset"), aliases.sunset.clone());
+        if let Ok(link) = link {
+            headers.append(header::LINK, link);
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn version_segment_is_stripped() {
+        assert_eq!(unversioned_path("/v2/courses/1"), "/courses/1");
+        assert_eq!(unversioned_path("/v1"), "/");
+        assert_eq!(unversioned_path("/courses/1"), "/courses/1");
+        assert_eq!(unversioned_path("/videos/1"), "/videos/1");
+        assert_eq!(unversioned_path("/v/courses"), "/v/courses");
+    }
+
+    #[actix_rt::test]
+    async fn aliases_announce_their_sunset() {
+        let aliases = Arc::new(LegacyAliases::new(&ApiConfig::default()));
+        let app = test::init_service(
+            App::new().service(
+                web::scope("")
+                    .wrap(from_fn(move |req, next| {
+                        legacy_alias_middleware(aliases.clone(), req, next)
+                    }))
+                    .route("/tutors/{tutor_id}", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/tutors/1?x=1").to_request();
+        let resp = test::call_service(&app, req).await;
+        let headers = resp.headers();
+        assert_eq!(headers.get("deprecation").unwrap(), "@1792281600");
+        assert_eq!(
+            headers.get("sunset").unwrap(),
+            "Sun, 18 Apr 2027 00:00:00 GMT"
+        );
+        assert_eq!(
+            headers.get(header::LINK).unwrap(),
+            "</v1/tutors/1?x=1>; rel=\"successor-version\""
+        );
+
+        let req = test::TestRequest::get().uri("/unknown").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(!resp.headers().contains_key("deprecation"));
+    }
+}
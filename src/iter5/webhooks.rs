@@ -0,0 +1,150 @@
+use crate::config::WebhookConfig;
+use crate::dbaccess::webhook::{claim_due_deliveries_db, record_attempt_db};
+use crate::errors::EzyTutorError;
+use crate::models::webhook::{AttemptOutcome, DueDelivery, NewAttempt};
+use crate::poller::{backoff, Queue};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::postgres::PgPool;
+use std::time::{Duration, Instant};
+
+pub const EVENT_HEADER: &str = "X-Ezy-Event";
+pub const EVENT_ID_HEADER: &str = "X-Ezy-Event-Id";
+pub const DELIVERY_HEADER: &str = "X-Ezy-Delivery";
+//Unix seconds, receivers should reject old ones against replays
+pub const TIMESTAMP_HEADER: &str = "X-Ezy-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Ezy-Signature";
+
+//Time a claimed delivery is left to its sender before others may retry it
+const LEASE_MARGIN: Duration = Duration::from_secs(30);
+
+//Receivers check the signature with the subscription's secret, over the
+//timestamp header, a dot and the raw body
+pub fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub struct WebhookSender {
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl WebhookSender {
+    pub fn new(config: &WebhookConfig) -> Result<Self, reqwest::Error> {
+        //A redirect is answered like any other non-2xx status
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent("ezytutors-webhooks")
+            .build()?;
+        Ok(WebhookSender {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    async fn send(&self, delivery: &DueDelivery) -> NewAttempt {
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signature = sign(&delivery.secret, &timestamp, &delivery.payload);
+        let started = Instant::now();
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(EVENT_ID_HEADER, &delivery.event_id)
+            .header(DELIVERY_HEADER, delivery.delivery_id.to_string())
+            .header(TIMESTAMP_HEADER, &timestamp)
+            .header(SIGNATURE_HEADER, signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+        let duration_ms = started.elapsed().as_millis().min(i32::MAX as u128) as i32;
+        match response {
+            Ok(response) => {
+                let status = response.status();
+                NewAttempt {
+                    status_code: Some(status.as_u16() as i32),
+                    error: (!status.is_success()).then(|| format!("receiver answered {}", status)),
+                    duration_ms,
+                }
+            }
+            Err(err) => NewAttempt {
+                status_code: None,
+                error: Some(err.to_string()),
+                duration_ms,
+            },
+        }
+    }
+
+    fn outcome(&self, delivery: &DueDelivery, attempt: &NewAttempt) -> AttemptOutcome {
+        let attempts = delivery.attempts.max(0) as u32;
+        if matches!(attempt.status_code, Some(200..=299)) {
+            AttemptOutcome::Delivered
+        } else if attempts >= self.config.max_attempts {
+            AttemptOutcome::Dead
+        } else {
+            AttemptOutcome::RetryIn(backoff(
+                self.config.backoff_initial_secs,
+                self.config.backoff_max_secs,
+                attempts,
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl Queue for WebhookSender {
+    type Item = DueDelivery;
+    const NAME: &'static str = "webhooks";
+
+    fn batch_size(&self) -> u32 {
+        self.config.batch_size
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.config.poll_interval_ms)
+    }
+
+    async fn claim(&self, pool: &PgPool, limit: i64) -> Result<Vec<DueDelivery>, EzyTutorError> {
+        let lease = Duration::from_secs(self.config.timeout_secs) + LEASE_MARGIN;
+        claim_due_deliveries_db(pool, limit, lease).await
+    }
+
+    async fn dispatch(&self, pool: &PgPool, delivery: &DueDelivery) -> Result<(), EzyTutorError> {
+        let attempt = self.send(delivery).await;
+        let outcome = self.outcome(delivery, &attempt);
+        if outcome == AttemptOutcome::Dead {
+            tracing::warn!(
+                delivery_id = delivery.delivery_id,
+                attempts = delivery.attempts,
+                "webhook delivery is dead"
+            );
+        }
+        record_attempt_db(pool, delivery.delivery_id, &attempt, outcome).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn signature_is_hmac_sha256_of_timestamp_and_body() {
+        let signature = sign("whsec_0123456789abcdef", "1760745600", r#"{"id":"42"}"#);
+        assert_eq!(
+            signature,
+            "sha256=26dc091e9ff0cd226593ae58ccd05adee96a888bc1ea126d60d0acea3fa1b2e5"
+        );
+        assert_ne!(
+            sign("whsec_0123456789abcdef", "1760745601", r#"{"id":"42"}"#),
+            signature
+        );
+    }
+}